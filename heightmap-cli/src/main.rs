@@ -0,0 +1,501 @@
+//! Headless access to the heightmap ripper and SMH's heightmap cache
+//!
+//! Run from the same directory as SMH so that `settings.json` and the `heightmaps` cache are shared.
+
+use smh_heightmap_ripper::{
+	bundle, cache,
+	catalogue::CatalogueLayer,
+	diff::DEFAULT_THRESHOLD,
+	render::{ColorRamp, RenderOptions, Shading},
+	Heightmap,
+};
+use smh_util::{anyhow::{self, bail, Context}, image, log, AnyError};
+use std::{
+	fs::File,
+	io::{BufReader, BufWriter},
+	path::{Path, PathBuf},
+};
+
+const USAGE: &str = "\
+Usage: smh-heightmaps [OPTIONS] <COMMAND> [ARGS]
+
+Commands:
+    list [--cached] [SEARCH]             List the layers in the game's paks (or just the cached ones)
+    rip [--force] <LAYER>...             Rip heightmaps into the cache, skipping layers that are already cached
+    info <LAYER | FILE>                  Show information about a cached heightmap or .smhhm file
+    export <LAYER | FILE> <OUTPUT>       Export a heightmap to PNG or raw, depending on the output's extension
+        [--rendered] [--ramp <RAMP>] [--shading <SHADING>]
+    convert <LAYER | FILE> <OUTPUT>      Convert between .smhhm and raw, upgrading old cache versions
+    import <BUNDLE>                      Import a heightmap bundle into the cache
+    diff <OLD> <NEW>                     Compare two versions of a heightmap, e.g. a cached one against a fresh rip
+        [--threshold <METERS>] [--image <OUTPUT>]
+
+Options:
+    --squad-dir <DIR>                    Overrides the Squad install directory from settings.json
+    --aes-key <KEY>                      Overrides the AES key from settings.json
+    --paks <DIR>                         Additional paks directory, e.g. for a workshop mod (repeatable)
+    -v, --verbose                        Log what the ripper is doing
+    -h, --help                           Print this message
+
+Raw files are in the format that the ripper writes to stdout: u32 width, u32 height, i32 bounds[4], f32 scale[3], then u16 heights, all little endian.";
+
+const RAW_EXTENSIONS: &[&str] = &["raw", "bin"];
+
+/// The subset of SMH's `settings.json` that we care about
+#[derive(serde::Deserialize, Default)]
+struct Settings {
+	#[serde(default)]
+	squad_dir: Option<Box<str>>,
+
+	#[serde(default)]
+	squad_pak_aes: Option<Box<str>>,
+}
+impl Settings {
+	fn load() -> Self {
+		File::open("settings.json")
+			.ok()
+			.and_then(|f| match serde_json::from_reader(BufReader::new(f)) {
+				Ok(settings) => Some(settings),
+				Err(err) => {
+					log::warn!("Error reading settings.json: {err}");
+					None
+				}
+			})
+			.unwrap_or_default()
+	}
+}
+
+struct Args {
+	squad_dir: Option<Box<str>>,
+	aes_key: Option<Box<str>>,
+	paks: Vec<Box<str>>,
+	verbose: bool,
+
+	/// Flags that belong to the subcommand
+	flags: Vec<Box<str>>,
+	options: Vec<(Box<str>, Box<str>)>,
+	positional: Vec<Box<str>>,
+}
+impl Args {
+	fn parse(args: impl Iterator<Item = String>) -> Result<Self, AnyError> {
+		let mut parsed = Args {
+			squad_dir: None,
+			aes_key: None,
+			paks: Vec::new(),
+			verbose: false,
+			flags: Vec::new(),
+			options: Vec::new(),
+			positional: Vec::new(),
+		};
+
+		let mut args = args;
+		while let Some(arg) = args.next() {
+			let mut value = |name: &str| args.next().with_context(|| format!("Missing value for {name}")).map(String::into_boxed_str);
+
+			match arg.as_str() {
+				"--squad-dir" => parsed.squad_dir = Some(value("--squad-dir")?),
+				"--aes-key" => parsed.aes_key = Some(value("--aes-key")?),
+				"--paks" => parsed.paks.push(value("--paks")?),
+				"-v" | "--verbose" => parsed.verbose = true,
+				"--ramp" | "--shading" | "--threshold" | "--image" => {
+					let value = value(&arg)?;
+					parsed.options.push((arg.into_boxed_str(), value));
+				}
+				_ if arg.starts_with('-') && arg.len() > 1 => parsed.flags.push(arg.into_boxed_str()),
+				_ => parsed.positional.push(arg.into_boxed_str()),
+			}
+		}
+
+		Ok(parsed)
+	}
+
+	/// The command is the first positional argument, so options can come before or after it
+	fn take_command(&mut self) -> Option<Box<str>> {
+		(!self.positional.is_empty()).then(|| self.positional.remove(0))
+	}
+
+	fn flag(&self, flag: &str) -> bool {
+		self.flags.iter().any(|candidate| &**candidate == flag)
+	}
+
+	fn option(&self, option: &str) -> Option<&str> {
+		self.options.iter().rev().find(|(name, _)| &**name == option).map(|(_, value)| &**value)
+	}
+
+	/// Fails on flags that the subcommand doesn't understand, so typos don't go unnoticed
+	fn check_flags(&self, known: &[&str]) -> Result<(), AnyError> {
+		if let Some(flag) = self.flags.iter().find(|flag| !known.contains(&&***flag)) {
+			bail!("Unknown option {flag}\n\n{USAGE}");
+		}
+		Ok(())
+	}
+
+	fn squad_dir(&self, settings: &Settings) -> Result<Box<str>, AnyError> {
+		self.squad_dir
+			.clone()
+			.or_else(|| settings.squad_dir.clone())
+			.or_else(|| smh_heightmap_ripper::find_squad_dir().map(|squad_dir| Box::from(squad_dir.to_string_lossy().as_ref())))
+			.context("Couldn't find your Squad install directory, please provide it with --squad-dir")
+	}
+
+	fn aes_key(&self, settings: &Settings) -> Box<str> {
+		self.aes_key
+			.clone()
+			.or_else(|| settings.squad_pak_aes.clone())
+			.unwrap_or_else(|| smh_heightmap_ripper::DEFAULT_AES_KEY.into())
+	}
+
+	/// The game's paks, followed by any additional paks
+	fn paks(&self, settings: &Settings) -> Result<Vec<Box<str>>, AnyError> {
+		let mut paks = vec![(self.squad_dir(settings)?.to_string() + "/SquadGame/Content/Paks").into_boxed_str()];
+		paks.extend(self.paks.iter().cloned());
+		Ok(paks)
+	}
+}
+
+struct Logger;
+impl log::Log for Logger {
+	fn enabled(&self, _metadata: &log::Metadata) -> bool {
+		true
+	}
+
+	fn log(&self, record: &log::Record) {
+		eprintln!("[{}] {}", record.level(), record.args());
+	}
+
+	fn flush(&self) {}
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+	path.extension()
+		.and_then(|ext| ext.to_str())
+		.map(|ext| extensions.iter().any(|candidate| ext.eq_ignore_ascii_case(candidate)))
+		.unwrap_or(false)
+}
+
+/// Heightmaps can be given either as a path to a file or as a cached layer
+fn resolve_heightmap(arg: &str) -> PathBuf {
+	let path = Path::new(arg);
+	if path.is_file() {
+		path.to_path_buf()
+	} else {
+		cache::cache_path(arg)
+	}
+}
+
+fn read_heightmap(path: &Path) -> Result<Heightmap, AnyError> {
+	if has_extension(path, RAW_EXTENSIONS) {
+		let raw = std::fs::read(path).with_context(|| format!("Error reading {}", path.display()))?;
+		return smh_heightmap_ripper::read_raw(&raw)?.with_context(|| format!("{} has no heightmap data", path.display()));
+	}
+
+	let mut r = BufReader::new(File::open(path).with_context(|| format!("Error opening {}", path.display()))?);
+	match cache::deserialize(&mut r)? {
+		Some(heightmap) => Ok(heightmap),
+		None => {
+			let version = File::open(path).map(BufReader::new).and_then(|mut r| cache::read_version(&mut r)).ok().flatten();
+			match version {
+				Some(version) => bail!("{} uses heightmap file version {version}, which isn't supported (expected {} to {})", path.display(), cache::MIN_HEIGHTMAP_FILE_VER, cache::HEIGHTMAP_FILE_VER),
+				None => bail!("{} is not a heightmap file", path.display()),
+			}
+		}
+	}
+}
+
+fn write_heightmap(path: &Path, heightmap: &Heightmap) -> Result<(), AnyError> {
+	if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+		std::fs::create_dir_all(parent)?;
+	}
+
+	let mut w = BufWriter::new(File::create(path).with_context(|| format!("Error creating {}", path.display()))?);
+	if has_extension(path, RAW_EXTENSIONS) {
+		smh_heightmap_ripper::write_raw(&mut w, heightmap)?;
+	} else {
+		cache::serialize(&mut w, heightmap)?;
+	}
+
+	Ok(())
+}
+
+fn list(args: &Args, settings: &Settings) -> Result<(), AnyError> {
+	args.check_flags(&["--cached"])?;
+
+	let mut layers = cache::cached_layers();
+	if !args.flag("--cached") {
+		let paks = args.paks(settings)?;
+		layers.extend(smh_heightmap_ripper::list_maps(paks.iter(), Some(args.aes_key(settings)))?.into_vec());
+		layers.sort_unstable();
+		layers.dedup();
+	}
+
+	let search = args.positional.first();
+	for layer in layers.iter().map(|layer| CatalogueLayer::parse(layer)) {
+		if search.map(|search| layer.matches(search)).unwrap_or(true) {
+			let cached = if cache::cache_path(&layer.path).is_file() { " (cached)" } else { "" };
+			println!("{}{cached}", layer.path);
+		}
+	}
+
+	Ok(())
+}
+
+fn rip(args: &Args, settings: &Settings) -> Result<(), AnyError> {
+	args.check_flags(&["--force"])?;
+
+	if args.positional.is_empty() {
+		bail!("No layers given\n\n{USAGE}");
+	}
+
+	let paks = args.paks(settings)?;
+	let aes_key = args.aes_key(settings);
+
+	let mut failed = 0;
+	for layer in &args.positional {
+		let cache_path = cache::cache_path(layer);
+		if !args.flag("--force") && cache_path.is_file() {
+			println!("{layer}: already cached");
+			continue;
+		}
+
+		let result = smh_heightmap_ripper::get_heightmap(paks.iter(), Some(&aes_key), &**layer)
+			.map_err(AnyError::from)
+			.and_then(|heightmap| match heightmap {
+				Some(heightmap) => write_heightmap(&cache_path, &heightmap).map(|_| Some(heightmap)),
+				None => Ok(None),
+			});
+
+		match result {
+			Ok(Some(heightmap)) => println!("{layer}: {}x{} -> {}", heightmap.width, heightmap.height, cache_path.display()),
+			Ok(None) => println!("{layer}: no heightmap"),
+			Err(err) => {
+				std::fs::remove_file(&cache_path).ok();
+				eprintln!("{layer}: {err:?}");
+				failed += 1;
+			}
+		}
+	}
+
+	if failed > 0 {
+		bail!("Failed to rip {failed} of {} layers", args.positional.len());
+	}
+
+	Ok(())
+}
+
+fn info(args: &Args) -> Result<(), AnyError> {
+	args.check_flags(&[])?;
+
+	let path = resolve_heightmap(args.positional.first().context("No heightmap given")?);
+	let heightmap = read_heightmap(&path)?;
+	let report = smh_heightmap_ripper::validation::validate(&heightmap);
+
+	println!("File: {}", path.display());
+	println!("Size: {}x{} ({:.1} x {:.1} km)", heightmap.width, heightmap.height, heightmap.width as f64 / 1000.0, heightmap.height as f64 / 1000.0);
+	println!("Bounds: {:?}", heightmap.bounds);
+	println!("Scale: {:?}", heightmap.scale);
+	println!(
+		"Elevation: {:.1}m - {:.1}m (mean {:.1}m)",
+		report.min_elevation, report.max_elevation, report.mean_elevation
+	);
+	println!("Voids: {:.2}%", report.void_fraction * 100.0);
+	if let Some(ref mask) = heightmap.mask {
+		let covered = mask.iter().filter(|valid| **valid).count();
+		println!("Coverage: {:.2}% (stitched from several landscape tiles)", covered as f64 / mask.len() as f64 * 100.0);
+	}
+
+	if report.issues.is_empty() {
+		println!("No problems found");
+	} else {
+		for issue in &report.issues {
+			println!("{:?}: {issue}", issue.severity());
+		}
+	}
+
+	Ok(())
+}
+
+fn export(args: &Args) -> Result<(), AnyError> {
+	args.check_flags(&["--rendered"])?;
+
+	let (input, output) = match &args.positional[..] {
+		[input, output] => (resolve_heightmap(input), Path::new(&**output)),
+		_ => bail!("Expected a heightmap and an output path\n\n{USAGE}"),
+	};
+
+	let heightmap = read_heightmap(&input)?;
+
+	if has_extension(output, RAW_EXTENSIONS) {
+		return write_heightmap(output, &heightmap);
+	}
+
+	if args.flag("--rendered") {
+		let mut options = RenderOptions::default();
+		if let Some(ramp) = args.option("--ramp") {
+			options.ramp = *ColorRamp::ALL
+				.iter()
+				.find(|candidate| candidate.name().eq_ignore_ascii_case(ramp))
+				.with_context(|| format!("Unknown color ramp {ramp:?}, expected one of {:?}", ColorRamp::ALL.map(ColorRamp::name)))?;
+		}
+		if let Some(shading) = args.option("--shading") {
+			options.shading = *Shading::ALL
+				.iter()
+				.find(|candidate| candidate.name().eq_ignore_ascii_case(shading))
+				.with_context(|| format!("Unknown shading {shading:?}, expected one of {:?}", Shading::ALL.map(Shading::name)))?;
+		}
+
+		smh_heightmap_ripper::render::render(&heightmap, &options).save_with_format(output, image::ImageFormat::Png)?;
+	} else {
+		heightmap.as_image().save_with_format(output, image::ImageFormat::Png)?;
+	}
+
+	println!("Exported {}", output.display());
+
+	Ok(())
+}
+
+fn convert(args: &Args) -> Result<(), AnyError> {
+	args.check_flags(&[])?;
+
+	let (input, output) = match &args.positional[..] {
+		[input, output] => (resolve_heightmap(input), Path::new(&**output)),
+		_ => bail!("Expected an input and an output path\n\n{USAGE}"),
+	};
+
+	let heightmap = read_heightmap(&input)?;
+	write_heightmap(output, &heightmap)?;
+
+	println!("Converted {} -> {}", input.display(), output.display());
+
+	Ok(())
+}
+
+fn import(args: &Args) -> Result<(), AnyError> {
+	args.check_flags(&[])?;
+
+	let path = args.positional.first().context("No bundle given")?;
+	let report = bundle::import(&mut BufReader::new(File::open(&**path).with_context(|| format!("Error opening {path}"))?))?;
+
+	for layer in &report.imported {
+		println!("{layer}: imported");
+	}
+	for layer in &report.unchanged {
+		println!("{layer}: unchanged");
+	}
+	for (layer, err) in &report.failed {
+		eprintln!("{layer}: {err}");
+	}
+
+	if !report.failed.is_empty() {
+		bail!("Failed to import {} heightmaps", report.failed.len());
+	}
+
+	Ok(())
+}
+
+fn diff(args: &Args) -> Result<(), AnyError> {
+	args.check_flags(&[])?;
+
+	let (old, new) = match &args.positional[..] {
+		[old, new] => (resolve_heightmap(old), resolve_heightmap(new)),
+		_ => bail!("Expected two heightmaps to compare\n\n{USAGE}"),
+	};
+
+	let threshold = match args.option("--threshold") {
+		Some(threshold) => threshold.parse::<f64>().with_context(|| format!("Invalid threshold {threshold:?}"))?,
+		None => DEFAULT_THRESHOLD,
+	};
+
+	let diff = smh_heightmap_ripper::diff::diff(&read_heightmap(&old)?, &read_heightmap(&new)?, threshold);
+
+	println!("Compared: {} texels", diff.stats.compared);
+	println!("Changed: {} texels ({:.2}%)", diff.stats.changed, diff.changed_fraction() * 100.0);
+	println!("Largest rise: {:.1}m", diff.stats.max_rise);
+	println!("Largest fall: {:.1}m", diff.stats.max_fall.abs());
+	println!("Mean change: {:.2}m (RMS {:.2}m)", diff.stats.mean_abs_change, diff.stats.rms_change);
+	if diff.size_changed {
+		println!("Size changed");
+	}
+	if diff.bounds_changed {
+		println!("Bounds changed");
+	}
+	if diff.scale_changed {
+		println!("Scale changed");
+	}
+
+	for region in &diff.regions {
+		let [[x0, y0], [x1, y1]] = region.bounds;
+		println!("Region: {}x{}m at ({x0}, {y0}), {} texels, {:+.1}m", x1 - x0 + 1, y1 - y0 + 1, region.texels, region.max_change);
+	}
+
+	if let Some(output) = args.option("--image") {
+		diff.to_image().save_with_format(output, image::ImageFormat::Png)?;
+		println!("Exported {output}");
+	}
+
+	if diff.is_unchanged() {
+		println!("The terrain hasn't changed");
+	}
+
+	Ok(())
+}
+
+fn run() -> Result<(), AnyError> {
+	let mut args = Args::parse(std::env::args().skip(1))?;
+	let command = match args.take_command() {
+		Some(command) if !args.flag("-h") && !args.flag("--help") => command,
+		_ => {
+			println!("{USAGE}");
+			return Ok(());
+		}
+	};
+
+	if args.verbose {
+		log::set_logger(&Logger).ok();
+		log::set_max_level(log::LevelFilter::Info);
+	}
+
+	let settings = Settings::load();
+
+	match &*command {
+		"list" => list(&args, &settings),
+		"rip" => rip(&args, &settings),
+		"info" => info(&args),
+		"export" => export(&args),
+		"convert" => convert(&args),
+		"import" => import(&args),
+		"diff" => diff(&args),
+		_ => Err(anyhow::anyhow!("Unknown command {command:?}\n\n{USAGE}")),
+	}
+}
+
+fn main() {
+	if let Err(err) = run() {
+		eprintln!("Error: {err:#}");
+		std::process::exit(1);
+	}
+}
+
+#[test]
+fn test_args() {
+	let parse = |args: &[&str]| {
+		let mut args = Args::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+		(args.take_command(), args)
+	};
+
+	// Options can come before the command
+	let (command, args) = parse(&["--squad-dir", "X", "-v", "list", "--cached", "gorodok"]);
+	assert_eq!(command.as_deref(), Some("list"));
+	assert_eq!(args.squad_dir.as_deref(), Some("X"));
+	assert!(args.verbose && args.flag("--cached"));
+	assert_eq!(args.positional, ["gorodok".into()] as [Box<str>; 1]);
+
+	// Or after it
+	let (command, args) = parse(&["diff", "old.smhhm", "--threshold", "2", "new.smhhm", "--paks", "A", "--paks", "B"]);
+	assert_eq!(command.as_deref(), Some("diff"));
+	assert_eq!(args.option("--threshold"), Some("2"));
+	assert_eq!(args.paks, ["A".into(), "B".into()] as [Box<str>; 2]);
+	assert_eq!(args.positional, ["old.smhhm".into(), "new.smhhm".into()] as [Box<str>; 2]);
+
+	let (command, args) = parse(&["--help"]);
+	assert!(command.is_none() && args.flag("--help"));
+}
@@ -0,0 +1,354 @@
+//! "SMHHB" heightmap bundle format, for sharing cached heightmaps with people who can't run the ripper
+//!
+//! * u32 `SMH_BUNDLE_MAGIC_NUMBER`
+//! * u16 `BUNDLE_FILE_VER`
+//! * u32 manifest length
+//! * JSON manifest
+//! * `.smhhm` heightmap files, one after another, in manifest order
+//!
+//! The heightmap files are already liblzma compressed, so they are stored as-is.
+
+use crate::cache::{cache_path_in, is_supported_version, CACHE_DIR, HEIGHTMAP_FILE_VER, SMH_MAGIC_NUMBER};
+use byteorder::{ReadBytesExt, WriteBytesExt, BE, LE};
+use std::{
+	fs::File,
+	io::{Read, Write},
+	path::{Component, Path},
+};
+
+pub const SMH_BUNDLE_MAGIC_NUMBER: u32 = 0xBADB0DE;
+const BUNDLE_FILE_VER: u16 = 0;
+
+/// Bundles come from untrusted sources, so sizes they claim beyond these are treated as corrupt rather than allocated
+const MAX_MANIFEST_SIZE: u64 = 16 * 1024 * 1024;
+const MAX_HEIGHTMAP_SIZE: u64 = 512 * 1024 * 1024;
+
+pub const BUNDLE_EXTENSION: &str = "smhhb";
+
+#[derive(thiserror::Error, Debug)]
+pub enum BundleError {
+	#[error("I/O error: {0}")]
+	Io(#[from] std::io::Error),
+
+	#[error("Invalid manifest: {0}")]
+	Manifest(#[from] serde_json::Error),
+
+	#[error("Not a heightmap bundle")]
+	InvalidMagic,
+
+	#[error("Unsupported bundle version {0}")]
+	UnsupportedVersion(u16),
+
+	#[error("Checksum mismatch for {0}")]
+	ChecksumMismatch(Box<str>),
+
+	#[error("{0} is not a valid heightmap file")]
+	InvalidHeightmap(Box<str>),
+
+	#[error("Invalid layer path {0:?}")]
+	InvalidLayerPath(Box<str>),
+
+	#[error("Bundle claims to contain {0} bytes of data, which is too large to be genuine")]
+	TooLarge(u64),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Manifest {
+	pub version: u16,
+	pub heightmap_version: u16,
+	pub heightmaps: Vec<ManifestEntry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ManifestEntry {
+	pub layer: Box<str>,
+	pub size: u64,
+	pub crc32: u32,
+}
+
+#[derive(Default, Debug)]
+pub struct ImportReport {
+	pub imported: Vec<Box<str>>,
+	pub unchanged: Vec<Box<str>>,
+	pub failed: Vec<(Box<str>, BundleError)>,
+}
+
+/// Layer paths come from untrusted bundles, so make sure they can't escape the heightmaps directory
+fn validate_layer_path(layer: &str) -> Result<(), BundleError> {
+	let path = std::path::Path::new(layer);
+	if layer.is_empty() || !path.components().all(|component| matches!(component, Component::Normal(_))) {
+		return Err(BundleError::InvalidLayerPath(layer.into()));
+	}
+	Ok(())
+}
+
+/// Checks that the data starts with a `.smhhm` header that we can read
+fn is_valid_heightmap(data: &[u8]) -> bool {
+	let mut r = data;
+	match (r.read_u32::<BE>(), r.read_u16::<LE>(), r.read_u32::<BE>()) {
+		(Ok(SMH_MAGIC_NUMBER), Ok(version), Ok(SMH_MAGIC_NUMBER)) => is_supported_version(version),
+		_ => false,
+	}
+}
+
+/// Reads exactly `size` bytes, without trusting `size` enough to allocate it all upfront
+fn read_sized(r: &mut impl Read, size: u64, max_size: u64) -> Result<Vec<u8>, BundleError> {
+	if size > max_size {
+		return Err(BundleError::TooLarge(size));
+	}
+
+	let mut data = Vec::new();
+	r.by_ref().take(size).read_to_end(&mut data)?;
+	if data.len() as u64 != size {
+		return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+	}
+
+	Ok(data)
+}
+
+/// Bundles the given layers from the heightmap cache
+pub fn export(w: &mut impl Write, layers: &[Box<str>]) -> Result<Manifest, BundleError> {
+	export_from(Path::new(CACHE_DIR), w, layers)
+}
+
+fn export_from(cache_dir: &Path, w: &mut impl Write, layers: &[Box<str>]) -> Result<Manifest, BundleError> {
+	let mut heightmaps = Vec::with_capacity(layers.len());
+	let mut manifest = Manifest {
+		version: BUNDLE_FILE_VER,
+		heightmap_version: HEIGHTMAP_FILE_VER,
+		heightmaps: Vec::with_capacity(layers.len()),
+	};
+
+	for layer in layers {
+		let data = std::fs::read(cache_path_in(cache_dir, layer))?;
+
+		manifest.heightmaps.push(ManifestEntry {
+			layer: layer.clone(),
+			size: data.len() as u64,
+			crc32: crc32fast::hash(&data),
+		});
+
+		heightmaps.push(data);
+	}
+
+	let manifest_json = serde_json::to_vec(&manifest)?;
+
+	w.write_u32::<BE>(SMH_BUNDLE_MAGIC_NUMBER)?;
+	w.write_u16::<LE>(BUNDLE_FILE_VER)?;
+	w.write_u32::<LE>(manifest_json.len() as u32)?;
+	w.write_all(&manifest_json)?;
+
+	for data in heightmaps {
+		w.write_all(&data)?;
+	}
+
+	w.flush()?;
+
+	Ok(manifest)
+}
+
+pub fn read_manifest(r: &mut impl Read) -> Result<Manifest, BundleError> {
+	if r.read_u32::<BE>()? != SMH_BUNDLE_MAGIC_NUMBER {
+		return Err(BundleError::InvalidMagic);
+	}
+
+	let version = r.read_u16::<LE>()?;
+	if version != BUNDLE_FILE_VER {
+		return Err(BundleError::UnsupportedVersion(version));
+	}
+
+	let manifest_size = r.read_u32::<LE>()? as u64;
+	let manifest = read_sized(r, manifest_size, MAX_MANIFEST_SIZE)?;
+
+	Ok(serde_json::from_slice(&manifest)?)
+}
+
+/// Verifies every heightmap in the bundle and merges them into the heightmap cache
+pub fn import(r: &mut impl Read) -> Result<ImportReport, BundleError> {
+	import_into(Path::new(CACHE_DIR), r)
+}
+
+fn import_into(cache_dir: &Path, r: &mut impl Read) -> Result<ImportReport, BundleError> {
+	let manifest = read_manifest(r)?;
+	let mut report = ImportReport::default();
+
+	for entry in manifest.heightmaps {
+		let data = read_sized(r, entry.size, MAX_HEIGHTMAP_SIZE)?;
+
+		let result = validate_layer_path(&entry.layer)
+			.and_then(|_| {
+				if crc32fast::hash(&data) != entry.crc32 {
+					Err(BundleError::ChecksumMismatch(entry.layer.clone()))
+				} else if !is_valid_heightmap(&data) {
+					Err(BundleError::InvalidHeightmap(entry.layer.clone()))
+				} else {
+					Ok(())
+				}
+			})
+			.and_then(|_| {
+				let cache_path = cache_path_in(cache_dir, &entry.layer);
+
+				if std::fs::read(&cache_path).map(|existing| existing == data).unwrap_or(false) {
+					return Ok(false);
+				}
+
+				std::fs::create_dir_all(cache_path.parent().unwrap())?;
+				File::create(&cache_path)?.write_all(&data)?;
+				Ok(true)
+			});
+
+		match result {
+			Ok(true) => report.imported.push(entry.layer),
+			Ok(false) => report.unchanged.push(entry.layer),
+			Err(err) => {
+				log::warn!("Error importing {} from heightmap bundle: {err}", entry.layer);
+				report.failed.push((entry.layer, err));
+			}
+		}
+	}
+
+	Ok(report)
+}
+
+#[cfg(test)]
+fn test_dir(name: &str) -> std::path::PathBuf {
+	let dir = std::env::temp_dir().join(format!("smh-bundle-test-{name}-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// The header of a `.smhhm` file followed by some made up data, which is all `import` looks at
+#[cfg(test)]
+fn test_heightmap_file(seed: u8) -> Vec<u8> {
+	let mut data = Vec::new();
+	data.write_u32::<BE>(SMH_MAGIC_NUMBER).unwrap();
+	data.write_u16::<LE>(HEIGHTMAP_FILE_VER).unwrap();
+	data.write_u32::<BE>(SMH_MAGIC_NUMBER).unwrap();
+	data.extend((0..64).map(|i| seed.wrapping_mul(31).wrapping_add(i)));
+	data
+}
+
+/// Writes a bundle by hand, so that the manifest can say things `export` never would
+#[cfg(test)]
+fn test_bundle(entries: &[(&str, &[u8])]) -> Vec<u8> {
+	let manifest = serde_json::to_vec(&Manifest {
+		version: BUNDLE_FILE_VER,
+		heightmap_version: HEIGHTMAP_FILE_VER,
+		heightmaps: entries
+			.iter()
+			.map(|(layer, data)| ManifestEntry {
+				layer: (*layer).into(),
+				size: data.len() as u64,
+				crc32: crc32fast::hash(data),
+			})
+			.collect(),
+	})
+	.unwrap();
+
+	let mut bundle = Vec::new();
+	bundle.write_u32::<BE>(SMH_BUNDLE_MAGIC_NUMBER).unwrap();
+	bundle.write_u16::<LE>(BUNDLE_FILE_VER).unwrap();
+	bundle.write_u32::<LE>(manifest.len() as u32).unwrap();
+	bundle.extend(manifest);
+	for (_, data) in entries {
+		bundle.extend_from_slice(data);
+	}
+	bundle
+}
+
+#[test]
+fn test_bundle_round_trip() {
+	let (src, dst) = (test_dir("round-trip-src"), test_dir("round-trip-dst"));
+
+	let layers: [Box<str>; 2] = ["Gorodok_RAAS_v1".into(), "Mods/Example/Example_AAS_v1".into()];
+	for (i, layer) in layers.iter().enumerate() {
+		let path = cache_path_in(&src, layer);
+		std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+		std::fs::write(path, test_heightmap_file(i as u8)).unwrap();
+	}
+
+	let mut bundle = Vec::new();
+	let manifest = export_from(&src, &mut bundle, &layers).unwrap();
+	assert_eq!(manifest.heightmaps.len(), 2);
+	assert_eq!(read_manifest(&mut bundle.as_slice()).unwrap().heightmaps.len(), 2);
+
+	let report = import_into(&dst, &mut bundle.as_slice()).unwrap();
+	assert_eq!(report.imported, layers);
+	assert!(report.unchanged.is_empty() && report.failed.is_empty(), "{report:?}");
+	for layer in &layers {
+		assert_eq!(std::fs::read(cache_path_in(&dst, layer)).unwrap(), std::fs::read(cache_path_in(&src, layer)).unwrap());
+	}
+
+	// Importing the same bundle again changes nothing
+	let report = import_into(&dst, &mut bundle.as_slice()).unwrap();
+	assert_eq!(report.unchanged, layers);
+	assert!(report.imported.is_empty() && report.failed.is_empty(), "{report:?}");
+
+	let _ = std::fs::remove_dir_all(src);
+	let _ = std::fs::remove_dir_all(dst);
+}
+
+#[test]
+fn test_bundle_checksum_mismatch() {
+	let dst = test_dir("checksum");
+
+	let (good, bad) = (test_heightmap_file(0), test_heightmap_file(1));
+	let mut bundle = test_bundle(&[("Good_v1", &good), ("Bad_v1", &bad)]);
+	*bundle.last_mut().unwrap() ^= 0xFF;
+
+	let report = import_into(&dst, &mut bundle.as_slice()).unwrap();
+	assert_eq!(report.imported, ["Good_v1".into()] as [Box<str>; 1]);
+	assert!(matches!(&*report.failed, [(layer, BundleError::ChecksumMismatch(_))] if &**layer == "Bad_v1"), "{report:?}");
+	assert!(!cache_path_in(&dst, "Bad_v1").exists());
+
+	let _ = std::fs::remove_dir_all(dst);
+}
+
+#[test]
+fn test_bundle_rejects_escaping_layer_paths() {
+	let dst = test_dir("escape");
+	let data = test_heightmap_file(0);
+
+	let absolute = if cfg!(windows) { "C:\\Windows\\Temp\\Evil_v1" } else { "/tmp/Evil_v1" };
+	let bundle = test_bundle(&[("../Evil_v1", &data), ("Gorodok/../../Evil_v1", &data), (absolute, &data), ("", &data)]);
+
+	let report = import_into(&dst, &mut bundle.as_slice()).unwrap();
+	assert!(report.imported.is_empty() && report.unchanged.is_empty(), "{report:?}");
+	assert_eq!(report.failed.len(), 4);
+	assert!(report.failed.iter().all(|(_, err)| matches!(err, BundleError::InvalidLayerPath(_))), "{report:?}");
+	assert!(!dst.parent().unwrap().join("Evil_v1.smhhm").exists());
+
+	let _ = std::fs::remove_dir_all(dst);
+}
+
+#[test]
+fn test_bundle_rejects_oversized_claims() {
+	let dst = test_dir("oversized");
+
+	// A manifest that claims to be 4 GiB
+	let mut bundle = test_bundle(&[]);
+	bundle[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+	assert!(matches!(read_manifest(&mut bundle.as_slice()), Err(BundleError::TooLarge(_))));
+
+	// A heightmap that claims to be far bigger than any heightmap could be
+	let manifest = serde_json::to_vec(&Manifest {
+		version: BUNDLE_FILE_VER,
+		heightmap_version: HEIGHTMAP_FILE_VER,
+		heightmaps: vec![ManifestEntry { layer: "Huge_v1".into(), size: u64::MAX, crc32: 0 }],
+	})
+	.unwrap();
+	let mut bundle = test_bundle(&[]);
+	bundle.truncate(6);
+	bundle.write_u32::<LE>(manifest.len() as u32).unwrap();
+	bundle.extend(manifest);
+	assert!(matches!(import_into(&dst, &mut bundle.as_slice()), Err(BundleError::TooLarge(_))));
+
+	// And one that's cut short
+	let data = test_heightmap_file(0);
+	let mut bundle = test_bundle(&[("Short_v1", &data)]);
+	bundle.truncate(bundle.len() - 1);
+	assert!(matches!(import_into(&dst, &mut bundle.as_slice()), Err(BundleError::Io(_))));
+
+	let _ = std::fs::remove_dir_all(dst);
+}
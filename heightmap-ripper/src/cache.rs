@@ -0,0 +1,171 @@
+//! "SMHHM" format
+//!
+//! * u32 `SMH_MAGIC_NUMBER`
+//! * u16 `HEIGHTMAP_FILE_VER`
+//! * u32 `SMH_MAGIC_NUMBER`
+//! * u32 width
+//! * u32 height
+//! * i32 bounds (x0, y0, x1, y1)
+//! * f32 scale (x, y, z)
+//! * liblzma compressed:
+//!   * u16 heightmap data
+//!   * u8 whether there's a validity mask (version 1+)
+//!   * u8 per texel, 1 where the landscape covers it (version 1+, if there's a mask)
+
+use std::{sync::Arc, io::{Read, Write}, path::{Path, PathBuf}};
+use crate::Heightmap;
+use byteorder::{LE, BE, ReadBytesExt, WriteBytesExt};
+
+// Disk-saved heightmap files will be forgotten when these values are changed
+pub const SMH_MAGIC_NUMBER: u32 = 0xBADFEEF;
+pub const HEIGHTMAP_FILE_VER: u16 = 1;
+
+/// The oldest heightmap file version that can still be read, from before validity masks
+pub const MIN_HEIGHTMAP_FILE_VER: u16 = 0;
+
+#[inline]
+pub fn is_supported_version(version: u16) -> bool {
+	(MIN_HEIGHTMAP_FILE_VER..=HEIGHTMAP_FILE_VER).contains(&version)
+}
+
+/// Relative to the working directory, which is shared by SMH and the CLI
+pub const CACHE_DIR: &str = "heightmaps";
+pub const HEIGHTMAP_EXTENSION: &str = "smhhm";
+
+/// Where the heightmap for a layer is cached
+pub fn cache_path(layer: &str) -> PathBuf {
+	cache_path_in(Path::new(CACHE_DIR), layer)
+}
+
+/// Where the heightmap for a layer would be cached if the cache was in `cache_dir`
+pub(crate) fn cache_path_in(cache_dir: &Path, layer: &str) -> PathBuf {
+	let mut cache_path = cache_dir.join(layer);
+	cache_path.set_extension(HEIGHTMAP_EXTENSION);
+	cache_path
+}
+
+/// Size of the cached heightmap for a layer in texels, if it has been generated
+pub fn cached_size(layer: &str) -> Option<[u32; 2]> {
+	std::fs::File::open(cache_path(layer)).ok().and_then(|mut f| read_size(&mut f).ok().flatten())
+}
+
+/// Lists the layers that have a cached heightmap, for when the ripper isn't available
+pub fn cached_layers() -> Vec<Box<str>> {
+	let mut layers = walkdir::WalkDir::new(CACHE_DIR)
+		.into_iter()
+		.filter_map(Result::ok)
+		.filter(|entry| entry.file_type().is_file() && entry.path().extension().map(|ext| ext == HEIGHTMAP_EXTENSION).unwrap_or(false))
+		.filter_map(|entry| {
+			let layer = entry.path().strip_prefix(CACHE_DIR).ok()?.with_extension("");
+			Some(layer.components().map(|component| component.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/").into_boxed_str())
+		})
+		.collect::<Vec<_>>();
+
+	layers.sort_unstable();
+	layers
+}
+
+/// Reads the file version of a heightmap file, if it is one
+pub fn read_version(r: &mut impl Read) -> Result<Option<u16>, std::io::Error> {
+	if r.read_u32::<BE>()? != SMH_MAGIC_NUMBER {
+		return Ok(None);
+	}
+
+	let version = r.read_u16::<LE>()?;
+
+	if r.read_u32::<BE>()? != SMH_MAGIC_NUMBER {
+		return Ok(None);
+	}
+
+	Ok(Some(version))
+}
+
+pub fn serialize(w: &mut impl Write, heightmap: &Heightmap) -> Result<(), std::io::Error> {
+	w.write_u32::<BE>(SMH_MAGIC_NUMBER)?;
+	w.write_u16::<LE>(HEIGHTMAP_FILE_VER)?;
+	w.write_u32::<BE>(SMH_MAGIC_NUMBER)?;
+
+	w.write_u32::<LE>(heightmap.width)?;
+	w.write_u32::<LE>(heightmap.height)?;
+
+	for bound in heightmap.bounds.iter().flatten().copied() {
+		w.write_i32::<LE>(bound)?;
+	}
+
+	for xyz in heightmap.scale {
+		w.write_f32::<LE>(xyz)?;
+	}
+
+	let mut w = xz2::write::XzEncoder::new(w, 9);
+	w.write_all(unsafe { core::slice::from_raw_parts(heightmap.data.as_ptr() as *const u8, heightmap.data.len() * 2) })?;
+	match heightmap.mask {
+		Some(ref mask) => {
+			w.write_u8(1)?;
+			w.write_all(&mask.iter().map(|valid| *valid as u8).collect::<Vec<_>>())?;
+		}
+		None => w.write_u8(0)?,
+	}
+	w.flush()?;
+
+	Ok(())
+}
+
+fn read_header(r: &mut impl Read) -> Result<Option<(u16, [u32; 2])>, std::io::Error> {
+	let version = match read_version(r)? {
+		Some(version) if is_supported_version(version) => version,
+		_ => return Ok(None),
+	};
+
+	Ok(Some((version, [r.read_u32::<LE>()?, r.read_u32::<LE>()?])))
+}
+
+/// Reads just the width and height of a heightmap file, without decompressing it
+pub fn read_size(r: &mut impl Read) -> Result<Option<[u32; 2]>, std::io::Error> {
+	Ok(read_header(r)?.map(|(_, size)| size))
+}
+
+pub fn deserialize(r: &mut impl Read) -> Result<Option<Heightmap>, std::io::Error> {
+	let (version, [width, height]) = match read_header(r)? {
+		Some(header) => header,
+		None => return Ok(None),
+	};
+
+	let bounds = [
+		[r.read_i32::<LE>()?, r.read_i32::<LE>()?],
+		[r.read_i32::<LE>()?, r.read_i32::<LE>()?]
+	];
+
+	let scale = [
+		r.read_f32::<LE>()?,
+		r.read_f32::<LE>()?,
+		r.read_f32::<LE>()?
+	];
+
+	let mut r = xz2::read::XzDecoder::new(r);
+
+	let mut data = vec![0u8; width as usize * height as usize * 2];
+	r.read_exact(&mut data)?;
+
+	let mask = if version >= 1 && r.read_u8()? != 0 {
+		let mut mask = vec![0u8; width as usize * height as usize];
+		r.read_exact(&mut mask)?;
+		Some(mask.into_iter().map(|valid| valid != 0).collect())
+	} else {
+		None
+	};
+
+	let data = unsafe {
+		let transmuted_data = Vec::from_raw_parts(data.as_mut_ptr() as *mut u16, data.len() / 2, data.capacity() / 2);
+		std::mem::forget(data);
+		transmuted_data
+	};
+
+	Ok(Some(Heightmap {
+		width,
+		height,
+		bounds,
+		scale,
+		data: Arc::from(data),
+		mask,
+	}))
+}
\ No newline at end of file
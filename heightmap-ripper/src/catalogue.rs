@@ -0,0 +1,138 @@
+//! Friendly metadata for the map paths discovered by the ripper
+
+/// Directories of sublevels that aren't useful on their own, one per line
+const SUBLEVEL_FILTERS: &str = include_str!("sublevels.txt");
+
+/// Game mode abbreviations found in layer names, and what to call them
+const GAME_MODES: &[(&str, &str)] = &[
+	("RAAS", "RAAS"),
+	("AAS", "AAS"),
+	("INVASION", "Invasion"),
+	("INV", "Invasion"),
+	("INSURGENCY", "Insurgency"),
+	("INS", "Insurgency"),
+	("SKIRMISH", "Skirmish"),
+	("TC", "Territory Control"),
+	("DESTRUCTION", "Destruction"),
+	("SEED", "Seed"),
+	("TRACKATTACK", "Track Attack"),
+	("TANKS", "Tank Battle"),
+	("TRAINING", "Training"),
+];
+
+/// Whether the map path is a lighting/sound/vfx/etc. sublevel rather than a playable level
+pub fn is_sublevel(path: &str) -> bool {
+	let path = path.to_ascii_lowercase();
+	SUBLEVEL_FILTERS
+		.lines()
+		.map(str::trim)
+		.filter(|filter| !filter.is_empty() && !filter.starts_with('#'))
+		.any(|filter| path.contains(filter))
+}
+
+/// "AlBasrah_Night" -> "Al Basrah Night"
+fn friendly_name(name: &str) -> Box<str> {
+	let mut friendly = String::with_capacity(name.len() + 4);
+	let mut prev: Option<char> = None;
+	for char in name.chars() {
+		if char == '_' || char == '-' {
+			if !friendly.ends_with(' ') {
+				friendly.push(' ');
+			}
+		} else {
+			if char.is_ascii_uppercase() && prev.map(|prev| prev.is_ascii_lowercase()).unwrap_or(false) {
+				friendly.push(' ');
+			}
+			friendly.push(char);
+		}
+		prev = Some(char);
+	}
+	friendly.trim().into()
+}
+
+#[derive(Clone, Debug)]
+pub struct CatalogueLayer {
+	/// The map path that the ripper understands
+	pub path: Box<str>,
+
+	pub map: Box<str>,
+	pub name: Box<str>,
+	pub game_mode: Option<&'static str>,
+
+	/// Name of the workshop mod (plugin) that the layer belongs to, if any
+	pub mod_name: Option<Box<str>>,
+
+	/// ID of the workshop item that the layer belongs to, if known
+	pub workshop_id: Option<u64>,
+
+	/// Size of the map in meters, if we know it
+	pub size: Option<[u32; 2]>,
+}
+impl CatalogueLayer {
+	pub fn parse(path: &str) -> Self {
+		let (prefix, map_path) = path.split_once("/Content/Maps/").unwrap_or(("", path));
+
+		let mod_name = prefix
+			.strip_prefix("SquadGame/Plugins/")
+			.map(|plugin| plugin.strip_prefix("Mods/").unwrap_or(plugin))
+			.and_then(|plugin| plugin.split('/').next())
+			.filter(|plugin| !plugin.is_empty())
+			.map(Box::from);
+
+		let stem = map_path.rsplit('/').next().unwrap_or(map_path);
+		let stem = stem.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(stem);
+
+		let map = match map_path.split_once('/') {
+			Some((map, _)) => friendly_name(map),
+			None => friendly_name(stem),
+		};
+
+		let game_mode = stem.split(['_', '-', ' ']).find_map(|token| {
+			GAME_MODES
+				.iter()
+				.find(|(abbreviation, _)| token.eq_ignore_ascii_case(abbreviation))
+				.map(|(_, game_mode)| *game_mode)
+		});
+
+		Self {
+			path: path.into(),
+			map,
+			name: friendly_name(stem),
+			game_mode,
+			mod_name,
+			workshop_id: None,
+			size: None,
+		}
+	}
+
+	/// Whether any of the layer's fields contain the search string
+	pub fn matches(&self, search: &str) -> bool {
+		let search = search.to_ascii_lowercase();
+		[Some(&*self.path), Some(&*self.map), Some(&*self.name), self.game_mode, self.mod_name.as_deref()]
+			.into_iter()
+			.flatten()
+			.any(|field| field.to_ascii_lowercase().contains(&search))
+	}
+}
+
+#[test]
+fn test_catalogue_layer() {
+	let layer = CatalogueLayer::parse("SquadGame/Content/Maps/AlBasrah/AlBasrah_Invasion_v1.umap");
+	assert_eq!(&*layer.map, "Al Basrah");
+	assert_eq!(&*layer.name, "Al Basrah Invasion v1");
+	assert_eq!(layer.game_mode, Some("Invasion"));
+	assert_eq!(layer.mod_name, None);
+
+	let layer = CatalogueLayer::parse("SquadGame/Plugins/Mods/SteelDivision/Content/Maps/Narva_Winter/Narva_Winter_RAAS.umap");
+	assert_eq!(&*layer.map, "Narva Winter");
+	assert_eq!(layer.game_mode, Some("RAAS"));
+	assert_eq!(layer.mod_name.as_deref(), Some("SteelDivision"));
+
+	let layer = CatalogueLayer::parse("SquadGame/Content/Maps/Mutaha/Mutaha.umap");
+	assert_eq!(&*layer.map, "Mutaha");
+	assert_eq!(layer.game_mode, None);
+	assert!(layer.matches("muta"));
+
+	assert!(is_sublevel("SquadGame/Content/Maps/Mutaha/Lighting_Layers/Mutaha_Dawn.umap"));
+	assert!(!is_sublevel("SquadGame/Content/Maps/Mutaha/Mutaha.umap"));
+}
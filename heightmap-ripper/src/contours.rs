@@ -0,0 +1,350 @@
+//! Contour line generation using marching squares
+
+use crate::Heightmap;
+use std::collections::HashMap;
+
+/// Heightmaps are decimated down to roughly this many cells across before contouring, otherwise we'd end up with millions of tiny segments
+const MAX_CONTOUR_GRID_SIZE: u32 = 1024;
+
+#[derive(Clone, Debug)]
+pub struct ContourLine {
+	/// Elevation of the contour line in meters
+	pub elevation: f64,
+
+	/// Index contours are drawn bolder than the rest
+	pub index: bool,
+
+	/// Polyline vertices in heightmap space (texels)
+	pub points: Box<[[f32; 2]]>,
+}
+
+#[derive(Clone, Copy)]
+struct Segment {
+	edges: [u64; 2],
+	points: [[f32; 2]; 2],
+}
+
+/// Generates contour lines every `interval` meters, with every line that is a multiple of `index_interval` marked as an index contour
+pub fn generate(heightmap: &Heightmap, interval: f64, index_interval: f64) -> Vec<ContourLine> {
+	if interval <= 0.0 || heightmap.width < 2 || heightmap.height < 2 {
+		return Vec::new();
+	}
+
+	let step = (heightmap.width.max(heightmap.height) / MAX_CONTOUR_GRID_SIZE).max(1);
+	let cols = (heightmap.width - 1) / step + 1;
+	let rows = (heightmap.height - 1) / step + 1;
+
+	// Texels outside the landscape are skipped, and so is zero, which is used for holes in the heightmap unless the whole heightmap sits at zero
+	let any_nonzero = heightmap.data.iter().any(|&raw| raw != 0);
+
+	let grid = {
+		let mut grid = Vec::with_capacity(cols as usize * rows as usize);
+		for y in 0..rows {
+			for x in 0..cols {
+				let (x, y) = ((x * step) as usize, (y * step) as usize);
+				if !heightmap.is_valid(x, y) || (any_nonzero && heightmap.data[y * heightmap.width as usize + x] == 0) {
+					grid.push(None);
+				} else {
+					grid.push(Some(heightmap.height(x, y)));
+				}
+			}
+		}
+		grid
+	};
+
+	let (min, max) = grid
+		.iter()
+		.copied()
+		.flatten()
+		.fold((f64::MAX, f64::MIN), |(min, max), height| (min.min(height), max.max(height)));
+
+	if min > max {
+		return Vec::new();
+	}
+
+	let first_level = (min / interval).ceil() as i64;
+	let last_level = (max / interval).floor() as i64;
+	if first_level > last_level {
+		return Vec::new();
+	}
+
+	let mut segments: Vec<Vec<Segment>> = vec![Vec::new(); (last_level - first_level + 1) as usize];
+
+	// Edge IDs are unique across the grid so that we can join segments from neighbouring cells
+	let h_edge = |x: u32, y: u32| (y as u64 * cols as u64 + x as u64) << 1;
+	let v_edge = |x: u32, y: u32| ((y as u64 * cols as u64 + x as u64) << 1) | 1;
+
+	for y in 0..rows - 1 {
+		for x in 0..cols - 1 {
+			let corner = |x: u32, y: u32| grid[(y * cols + x) as usize];
+
+			let (tl, tr, br, bl) = match (corner(x, y), corner(x + 1, y), corner(x + 1, y + 1), corner(x, y + 1)) {
+				(Some(tl), Some(tr), Some(br), Some(bl)) => (tl, tr, br, bl),
+				_ => continue,
+			};
+
+			let cell_min = tl.min(tr).min(br).min(bl);
+			let cell_max = tl.max(tr).max(br).max(bl);
+
+			let (x0, y0, x1, y1) = ((x * step) as f32, (y * step) as f32, ((x + 1) * step) as f32, ((y + 1) * step) as f32);
+
+			for level in ((cell_min / interval).ceil() as i64).max(first_level)..=((cell_max / interval).floor() as i64).min(last_level) {
+				let elevation = level as f64 * interval;
+
+				let above = [tl >= elevation, tr >= elevation, br >= elevation, bl >= elevation];
+				if above.iter().all(|above| *above) || !above.iter().any(|above| *above) {
+					continue;
+				}
+
+				let lerp = |a: f64, b: f64| ((elevation - a) / (b - a)) as f32;
+
+				// Top, right, bottom, left
+				let edges = [
+					(h_edge(x, y), [x0 + lerp(tl, tr) * (x1 - x0), y0]),
+					(v_edge(x + 1, y), [x1, y0 + lerp(tr, br) * (y1 - y0)]),
+					(h_edge(x, y + 1), [x0 + lerp(bl, br) * (x1 - x0), y1]),
+					(v_edge(x, y), [x0, y0 + lerp(tl, bl) * (y1 - y0)]),
+				];
+
+				let segment = |a: usize, b: usize| Segment {
+					edges: [edges[a].0, edges[b].0],
+					points: [edges[a].1, edges[b].1],
+				};
+
+				let level_segments = &mut segments[(level - first_level) as usize];
+
+				let crossings = [above[0] != above[1], above[1] != above[2], above[2] != above[3], above[3] != above[0]];
+				if crossings.iter().filter(|crossing| **crossing).count() == 2 {
+					let mut crossed = (0..4).filter(|i| crossings[*i]);
+					level_segments.push(segment(crossed.next().unwrap(), crossed.next().unwrap()));
+				} else {
+					// Saddle point, use the centre of the cell to decide which corners are cut off
+					let centre = (tl + tr + br + bl) / 4.0 >= elevation;
+					if above[0] != centre {
+						level_segments.push(segment(3, 0));
+					}
+					if above[1] != centre {
+						level_segments.push(segment(0, 1));
+					}
+					if above[2] != centre {
+						level_segments.push(segment(1, 2));
+					}
+					if above[3] != centre {
+						level_segments.push(segment(2, 3));
+					}
+				}
+			}
+		}
+	}
+
+	let mut contours = Vec::new();
+	for (level, segments) in segments.into_iter().enumerate() {
+		let elevation = (first_level + level as i64) as f64 * interval;
+		let index = index_interval > 0.0 && {
+			let index = elevation / index_interval;
+			(index - index.round()).abs() < 1e-6
+		};
+
+		for points in join_segments(&segments) {
+			let points = simplify(&points, step as f32 * 0.5);
+			if points.len() >= 2 {
+				contours.push(ContourLine {
+					elevation,
+					index,
+					points: points.into_boxed_slice(),
+				});
+			}
+		}
+	}
+
+	contours
+}
+
+fn join_segments(segments: &[Segment]) -> Vec<Vec<[f32; 2]>> {
+	let mut by_edge: HashMap<u64, [Option<usize>; 2]> = HashMap::with_capacity(segments.len() * 2);
+	for (i, segment) in segments.iter().enumerate() {
+		for edge in segment.edges {
+			let slots = by_edge.entry(edge).or_default();
+			if slots[0].is_none() {
+				slots[0] = Some(i);
+			} else {
+				slots[1] = Some(i);
+			}
+		}
+	}
+
+	// Finds the unvisited segment on the other side of `edge`, returning the point on the far side of it and its far edge
+	let next = |visited: &mut [bool], edge: u64| -> Option<(u64, [f32; 2])> {
+		let i = by_edge.get(&edge)?.iter().flatten().copied().find(|i| !visited[*i])?;
+		visited[i] = true;
+
+		let segment = &segments[i];
+		Some(if segment.edges[0] == edge {
+			(segment.edges[1], segment.points[1])
+		} else {
+			(segment.edges[0], segment.points[0])
+		})
+	};
+
+	let mut visited = vec![false; segments.len()];
+	let mut polylines = Vec::new();
+
+	for i in 0..segments.len() {
+		if visited[i] {
+			continue;
+		}
+		visited[i] = true;
+
+		let segment = &segments[i];
+
+		let mut forwards = vec![segment.points[0], segment.points[1]];
+		let mut edge = segment.edges[1];
+		while let Some((next_edge, point)) = next(&mut visited, edge) {
+			forwards.push(point);
+			edge = next_edge;
+		}
+
+		let mut backwards = Vec::new();
+		let mut edge = segment.edges[0];
+		while let Some((next_edge, point)) = next(&mut visited, edge) {
+			backwards.push(point);
+			edge = next_edge;
+		}
+
+		backwards.reverse();
+		backwards.extend(forwards);
+		polylines.push(backwards);
+	}
+
+	polylines
+}
+
+/// Ramer-Douglas-Peucker line simplification
+fn simplify(points: &[[f32; 2]], tolerance: f32) -> Vec<[f32; 2]> {
+	if points.len() < 3 {
+		return points.to_vec();
+	}
+
+	let mut keep = vec![false; points.len()];
+	keep[0] = true;
+	keep[points.len() - 1] = true;
+
+	let mut stack = vec![(0, points.len() - 1)];
+	while let Some((start, end)) = stack.pop() {
+		let ([ax, ay], [bx, by]) = (points[start], points[end]);
+		let (dx, dy) = (bx - ax, by - ay);
+		let length = (dx * dx + dy * dy).sqrt();
+
+		let mut furthest = (0.0, start);
+		for (i, [px, py]) in points.iter().copied().enumerate().take(end).skip(start + 1) {
+			let distance = if length == 0.0 {
+				((px - ax).powi(2) + (py - ay).powi(2)).sqrt()
+			} else {
+				(dy * px - dx * py + bx * ay - by * ax).abs() / length
+			};
+			if distance > furthest.0 {
+				furthest = (distance, i);
+			}
+		}
+
+		if furthest.0 > tolerance {
+			keep[furthest.1] = true;
+			stack.push((start, furthest.1));
+			stack.push((furthest.1, end));
+		}
+	}
+
+	points.iter().zip(keep).filter_map(|(point, keep)| if keep { Some(*point) } else { None }).collect()
+}
+
+#[test]
+fn test_contours_cone() {
+	use std::sync::Arc;
+
+	// A cone that rises 1 unit per texel from the edges, so every contour should be a closed loop
+	let (width, height) = (64, 64);
+	let data = (0..height)
+		.flat_map(|y: u32| (0..width).map(move |x: u32| (x.min(width - 1 - x).min(y).min(height - 1 - y) as u16 + 1) * 1000))
+		.collect::<Vec<u16>>();
+
+	let heightmap = Heightmap {
+		width,
+		height,
+		bounds: [[0, 0], [width as i32, height as i32]],
+		scale: [100.0, 100.0, 100.0],
+		data: Arc::from(data),
+		mask: None,
+	};
+
+	let contours = generate(&heightmap, 10.0, 50.0);
+	assert!(!contours.is_empty());
+
+	for contour in &contours {
+		assert_eq!(contour.index, (contour.elevation / 50.0).fract() == 0.0);
+
+		let (first, last) = (contour.points[0], contour.points[contour.points.len() - 1]);
+		assert!((first[0] - last[0]).abs() < 1e-3 && (first[1] - last[1]).abs() < 1e-3, "contour at {}m isn't closed", contour.elevation);
+	}
+}
+
+#[test]
+fn test_contours_skip_holes() {
+	use std::sync::Arc;
+
+	// A slope with a void punched out of the middle of it
+	let (width, height) = (64, 64);
+	let in_hole = |x: u32, y: u32| (24..40).contains(&x) && (24..40).contains(&y);
+	let data = (0..height)
+		.flat_map(|y: u32| (0..width).map(move |x: u32| if in_hole(x, y) { 0 } else { (x as u16 + 1) * 1000 }))
+		.collect::<Vec<u16>>();
+
+	let heightmap = Heightmap {
+		width,
+		height,
+		bounds: [[0, 0], [width as i32, height as i32]],
+		scale: [100.0, 100.0, 100.0],
+		data: Arc::from(data),
+		mask: None,
+	};
+
+	let contours = generate(&heightmap, 10.0, 50.0);
+	assert!(!contours.is_empty());
+
+	// Nothing is contoured between the void and the terrain around it, or inside the void
+	for contour in &contours {
+		for point in contour.points.iter() {
+			assert!(!in_hole(point[0].round() as u32, point[1].round() as u32), "contour at {}m runs through the hole", contour.elevation);
+		}
+	}
+}
+
+#[test]
+fn test_contours_skip_masked() {
+	use std::sync::Arc;
+
+	// A slope where the right half isn't covered by any landscape tile, but still has heights in it
+	let (width, height) = (64, 64);
+	let covered = |x: u32| x < 32;
+	let data = (0..height)
+		.flat_map(|_| (0..width).map(move |x: u32| if covered(x) { (x as u16 + 1) * 1000 } else { 5000 }))
+		.collect::<Vec<u16>>();
+	let mask = (0..height).flat_map(|_| (0..width).map(covered)).collect::<Vec<bool>>();
+
+	let heightmap = Heightmap {
+		width,
+		height,
+		bounds: [[0, 0], [width as i32, height as i32]],
+		scale: [100.0, 100.0, 100.0],
+		data: Arc::from(data),
+		mask: Some(Arc::from(mask)),
+	};
+
+	let contours = generate(&heightmap, 10.0, 50.0);
+	assert!(!contours.is_empty());
+
+	for contour in &contours {
+		for point in contour.points.iter() {
+			assert!(covered(point[0].round() as u32), "contour at {}m runs outside the landscape", contour.elevation);
+		}
+	}
+}
@@ -0,0 +1,351 @@
+//! Conversions between the coordinate spaces that SMH works in
+//!
+//! * [`Screen`]: pixels of the window (or browser) that the map is drawn in
+//! * [`MapPx`]: pixels of the captured map image
+//! * [`Minimap`]: fractions of the minimap's bounds within the map image, `0.0..=1.0` on the minimap
+//! * [`Texel`]: heightmap texels, which are the landscape's horizontal scale / 100 meters apart (1m at the usual scale of 100)
+//! * [`World`]: meters from the landscape's origin in the level
+//!
+//! Points are newtypes per space so they can't be mixed up, and a [`Transform`] converts between any two of them.
+
+use crate::{registration::HeightmapTransform, Heightmap};
+
+macro_rules! spaces {
+	($($(#[$meta:meta])* $name:ident),*) => {$(
+		$(#[$meta])*
+		#[derive(Clone, Copy, Debug, Default, PartialEq)]
+		pub struct $name(pub [f64; 2]);
+		impl $name {
+			#[inline]
+			pub fn x(&self) -> f64 {
+				self.0[0]
+			}
+
+			#[inline]
+			pub fn y(&self) -> f64 {
+				self.0[1]
+			}
+
+			#[inline]
+			pub fn to_f32(self) -> [f32; 2] {
+				[self.0[0] as f32, self.0[1] as f32]
+			}
+		}
+		impl From<[f64; 2]> for $name {
+			#[inline]
+			fn from(xy: [f64; 2]) -> Self {
+				Self(xy)
+			}
+		}
+		impl From<[f32; 2]> for $name {
+			#[inline]
+			fn from([x, y]: [f32; 2]) -> Self {
+				Self([x as f64, y as f64])
+			}
+		}
+	)*};
+}
+spaces! {
+	/// Pixels of the window that the map is drawn in
+	Screen,
+
+	/// Pixels of the captured map image
+	MapPx,
+
+	/// Fractions of the minimap's bounds within the map image
+	Minimap,
+
+	/// Heightmap texels
+	Texel,
+
+	/// Meters from the landscape's origin in the level
+	World
+}
+
+/// Where the map image is drawn on the screen, after zooming and panning
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+	/// Screen pixels per map pixel
+	pub scale: [f64; 2],
+
+	/// Screen position of the map image's top left
+	pub top_left: [f64; 2],
+}
+impl Default for Viewport {
+	fn default() -> Self {
+		Self {
+			scale: [1.0, 1.0],
+			top_left: [0.0, 0.0],
+		}
+	}
+}
+impl Viewport {
+	#[inline]
+	pub fn to_screen(&self, MapPx([x, y]): MapPx) -> Screen {
+		Screen([x * self.scale[0] + self.top_left[0], y * self.scale[1] + self.top_left[1]])
+	}
+
+	#[inline]
+	pub fn to_map(&self, Screen([x, y]): Screen) -> MapPx {
+		MapPx([(x - self.top_left[0]) / self.scale[0], (y - self.top_left[1]) / self.scale[1]])
+	}
+}
+
+/// How far the in-game map is zoomed in and panned, relative to the fully zoomed-out map
+///
+/// Zoomed-out map pixels are where the minimap's bounds were found, so they're what heightmaps line up with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapZoom {
+	/// Captured map pixels per zoomed-out map pixel
+	pub scale: f64,
+
+	/// Zoomed-out map pixel at the top left of the captured map
+	pub offset: [f64; 2],
+}
+impl Default for MapZoom {
+	fn default() -> Self {
+		Self { scale: 1.0, offset: [0.0, 0.0] }
+	}
+}
+impl MapZoom {
+	#[inline]
+	pub fn to_zoomed(&self, MapPx([x, y]): MapPx) -> MapPx {
+		MapPx([(x - self.offset[0]) * self.scale, (y - self.offset[1]) * self.scale])
+	}
+
+	#[inline]
+	pub fn to_unzoomed(&self, MapPx([x, y]): MapPx) -> MapPx {
+		MapPx([self.offset[0] + x / self.scale, self.offset[1] + y / self.scale])
+	}
+
+	/// Where the zoomed-out minimap bounds end up in the captured map, which can be well outside of it
+	#[inline]
+	pub fn minimap_bounds(&self, [top_left, bottom_right]: [[f64; 2]; 2]) -> [[f64; 2]; 2] {
+		[self.to_zoomed(MapPx(top_left)).0, self.to_zoomed(MapPx(bottom_right)).0]
+	}
+}
+
+/// How a heightmap sits on the minimap and in the level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeightmapFrame {
+	pub transform: HeightmapTransform,
+
+	/// Size of the heightmap, in texels
+	pub size: [u32; 2],
+
+	/// Start of the heightmap's bounds, in landscape quads
+	pub bounds_min: [f64; 2],
+
+	/// Landscape scale, in centimeters per quad
+	pub scale: [f64; 2],
+}
+impl HeightmapFrame {
+	pub fn new(heightmap: &Heightmap, transform: HeightmapTransform) -> Self {
+		Self {
+			transform,
+			size: [heightmap.width, heightmap.height],
+			bounds_min: [heightmap.bounds[0][0] as f64, heightmap.bounds[0][1] as f64],
+			scale: [heightmap.scale[0] as f64, heightmap.scale[1] as f64],
+		}
+	}
+
+	#[inline]
+	pub fn to_minimap(&self, Texel(texel): Texel) -> Minimap {
+		Minimap(self.transform.to_minimap(texel))
+	}
+
+	#[inline]
+	pub fn to_texel(&self, Minimap(minimap): Minimap) -> Texel {
+		Texel(self.transform.to_heightmap(minimap))
+	}
+
+	#[inline]
+	pub fn to_world(&self, Texel([x, y]): Texel) -> World {
+		World([(self.bounds_min[0] + x) * self.scale[0] / 100.0, (self.bounds_min[1] + y) * self.scale[1] / 100.0])
+	}
+
+	#[inline]
+	pub fn world_to_texel(&self, World([x, y]): World) -> Texel {
+		Texel([x * 100.0 / self.scale[0] - self.bounds_min[0], y * 100.0 / self.scale[1] - self.bounds_min[1]])
+	}
+
+	/// Whether a texel is within the heightmap
+	#[inline]
+	pub fn contains(&self, Texel([x, y]): Texel) -> bool {
+		x >= 0.0 && y >= 0.0 && x < self.size[0] as f64 && y < self.size[1] as f64
+	}
+}
+
+/// Converts between every coordinate space, given where the map is drawn, where the minimap is in the map and how the heightmap sits on it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+	pub viewport: Viewport,
+
+	/// Top left and bottom right of the minimap within the map image
+	pub minimap_bounds: [MapPx; 2],
+
+	/// Only texel and world conversions need a heightmap
+	pub heightmap: Option<HeightmapFrame>,
+}
+impl Transform {
+	pub fn new(viewport: Viewport, minimap_bounds: [[f64; 2]; 2], heightmap: Option<HeightmapFrame>) -> Self {
+		Self {
+			viewport,
+			minimap_bounds: [MapPx(minimap_bounds[0]), MapPx(minimap_bounds[1])],
+			heightmap,
+		}
+	}
+
+	#[inline]
+	fn minimap_size(&self) -> [f64; 2] {
+		let [MapPx([left, top]), MapPx([right, bottom])] = self.minimap_bounds;
+		[right - left, bottom - top]
+	}
+
+	#[inline]
+	pub fn screen_to_map(&self, screen: Screen) -> MapPx {
+		self.viewport.to_map(screen)
+	}
+
+	#[inline]
+	pub fn map_to_screen(&self, map: MapPx) -> Screen {
+		self.viewport.to_screen(map)
+	}
+
+	#[inline]
+	pub fn map_to_minimap(&self, MapPx([x, y]): MapPx) -> Minimap {
+		let [MapPx([left, top]), _] = self.minimap_bounds;
+		let [w, h] = self.minimap_size();
+		Minimap([(x - left) / w, (y - top) / h])
+	}
+
+	#[inline]
+	pub fn minimap_to_map(&self, Minimap([x, y]): Minimap) -> MapPx {
+		let [MapPx([left, top]), _] = self.minimap_bounds;
+		let [w, h] = self.minimap_size();
+		MapPx([left + x * w, top + y * h])
+	}
+
+	#[inline]
+	pub fn screen_to_minimap(&self, screen: Screen) -> Minimap {
+		self.map_to_minimap(self.screen_to_map(screen))
+	}
+
+	#[inline]
+	pub fn minimap_to_screen(&self, minimap: Minimap) -> Screen {
+		self.map_to_screen(self.minimap_to_map(minimap))
+	}
+
+	#[inline]
+	pub fn minimap_to_texel(&self, minimap: Minimap) -> Option<Texel> {
+		self.heightmap.as_ref().map(|heightmap| heightmap.to_texel(minimap))
+	}
+
+	#[inline]
+	pub fn texel_to_minimap(&self, texel: Texel) -> Option<Minimap> {
+		self.heightmap.as_ref().map(|heightmap| heightmap.to_minimap(texel))
+	}
+
+	#[inline]
+	pub fn map_to_texel(&self, map: MapPx) -> Option<Texel> {
+		self.minimap_to_texel(self.map_to_minimap(map))
+	}
+
+	#[inline]
+	pub fn texel_to_map(&self, texel: Texel) -> Option<MapPx> {
+		self.texel_to_minimap(texel).map(|minimap| self.minimap_to_map(minimap))
+	}
+
+	#[inline]
+	pub fn screen_to_texel(&self, screen: Screen) -> Option<Texel> {
+		self.map_to_texel(self.screen_to_map(screen))
+	}
+
+	#[inline]
+	pub fn texel_to_screen(&self, texel: Texel) -> Option<Screen> {
+		self.texel_to_map(texel).map(|map| self.map_to_screen(map))
+	}
+
+	#[inline]
+	pub fn texel_to_world(&self, texel: Texel) -> Option<World> {
+		self.heightmap.as_ref().map(|heightmap| heightmap.to_world(texel))
+	}
+
+	#[inline]
+	pub fn world_to_texel(&self, world: World) -> Option<Texel> {
+		self.heightmap.as_ref().map(|heightmap| heightmap.world_to_texel(world))
+	}
+
+	#[inline]
+	pub fn map_to_world(&self, map: MapPx) -> Option<World> {
+		self.map_to_texel(map).and_then(|texel| self.texel_to_world(texel))
+	}
+
+	#[inline]
+	pub fn world_to_map(&self, world: World) -> Option<MapPx> {
+		self.world_to_texel(world).and_then(|texel| self.texel_to_map(texel))
+	}
+}
+
+#[test]
+fn test_round_trips() {
+	// xorshift, so that the "random" transforms and points are the same every run
+	let mut seed = 0x2545_f491_4f6c_dd1du64;
+	let mut random = |min: f64, max: f64| {
+		seed ^= seed << 13;
+		seed ^= seed >> 7;
+		seed ^= seed << 17;
+		min + (seed >> 11) as f64 / (1u64 << 53) as f64 * (max - min)
+	};
+
+	let close = |a: [f64; 2], b: [f64; 2]| (a[0] - b[0]).abs() < 1e-6 && (a[1] - b[1]).abs() < 1e-6;
+
+	for _ in 0..1000 {
+		let size = [random(256.0, 8192.0), random(256.0, 8192.0)];
+		let heightmap_transform = match random(0.0, 3.0) as u32 {
+			0 => HeightmapTransform::fit(size),
+			1 => HeightmapTransform::offset_by_bounds(size, [random(0.0, 512.0), random(0.0, 512.0)]),
+			_ => HeightmapTransform {
+				offset: [random(-0.2, 0.2), random(-0.2, 0.2)],
+				scale: [random(0.5, 2.0) / size[0], random(0.5, 2.0) / size[1]],
+			},
+		};
+
+		let left = random(0.0, 500.0);
+		let top = random(0.0, 500.0);
+		let transform = Transform::new(
+			Viewport {
+				scale: [random(0.25, 8.0), random(0.25, 8.0)],
+				top_left: [random(-2000.0, 2000.0), random(-2000.0, 2000.0)],
+			},
+			[[left, top], [left + random(100.0, 2000.0), top + random(100.0, 2000.0)]],
+			Some(HeightmapFrame {
+				transform: heightmap_transform,
+				size: [size[0] as u32, size[1] as u32],
+				bounds_min: [random(-512.0, 512.0), random(-512.0, 512.0)],
+				scale: [random(50.0, 400.0), random(50.0, 400.0)],
+			}),
+		);
+
+		let screen = Screen([random(-1000.0, 5000.0), random(-1000.0, 5000.0)]);
+		assert!(close(transform.map_to_screen(transform.screen_to_map(screen)).0, screen.0));
+		assert!(close(transform.minimap_to_screen(transform.screen_to_minimap(screen)).0, screen.0));
+		assert!(close(transform.texel_to_screen(transform.screen_to_texel(screen).unwrap()).unwrap().0, screen.0));
+
+		let map = MapPx([random(-100.0, 3000.0), random(-100.0, 3000.0)]);
+		assert!(close(transform.world_to_map(transform.map_to_world(map).unwrap()).unwrap().0, map.0));
+
+		let texel = Texel([random(0.0, size[0]), random(0.0, size[1])]);
+		assert!(close(transform.map_to_texel(transform.texel_to_map(texel).unwrap()).unwrap().0, texel.0));
+		assert!(close(transform.world_to_texel(transform.texel_to_world(texel).unwrap()).unwrap().0, texel.0));
+	}
+
+	let zoom = MapZoom { scale: 2.5, offset: [120.0, 40.0] };
+	let map = MapPx([random(-100.0, 3000.0), random(-100.0, 3000.0)]);
+	assert!(close(zoom.to_unzoomed(zoom.to_zoomed(map)).0, map.0));
+	assert_eq!(zoom.minimap_bounds([[120.0, 40.0], [220.0, 140.0]]), [[0.0, 0.0], [250.0, 250.0]]);
+
+	let transform = Transform::new(Viewport::default(), [[0.0, 0.0], [100.0, 100.0]], None);
+	assert_eq!(transform.map_to_minimap(MapPx([50.0, 25.0])), Minimap([0.5, 0.25]));
+	assert_eq!(transform.map_to_texel(MapPx([50.0, 25.0])), None);
+}
@@ -0,0 +1,254 @@
+//! Comparison of two versions of a layer's heightmap, for telling whether a map update actually changed the terrain
+
+use crate::Heightmap;
+
+/// Elevation changes smaller than this (in meters) are treated as noise
+pub const DEFAULT_THRESHOLD: f64 = 0.5;
+
+/// Changed regions smaller than this (in texels) aren't worth reporting
+const MIN_REGION_TEXELS: usize = 16;
+
+/// The elevation change that saturates the difference heatmap's colors, unless the actual changes are larger
+const MIN_HEATMAP_RANGE: f64 = 5.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DiffStats {
+	/// Number of texels that could be compared (i.e. overlap and aren't void in either heightmap)
+	pub compared: usize,
+
+	/// Number of compared texels whose elevation changed by more than the threshold
+	pub changed: usize,
+
+	/// Largest increase and decrease in elevation, in meters
+	pub max_rise: f64,
+	pub max_fall: f64,
+
+	pub mean_abs_change: f64,
+	pub rms_change: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChangedRegion {
+	/// Top left and bottom right (inclusive) of the region, in texels of the new heightmap
+	pub bounds: [[u32; 2]; 2],
+
+	pub texels: usize,
+
+	/// The change with the largest magnitude within the region, in meters
+	pub max_change: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct HeightmapDiff {
+	pub width: u32,
+	pub height: u32,
+
+	/// Elevation change in meters at each texel of the new heightmap, or NaN where it couldn't be compared
+	pub delta: Box<[f32]>,
+
+	pub threshold: f64,
+
+	pub size_changed: bool,
+	pub bounds_changed: bool,
+	pub scale_changed: bool,
+
+	pub stats: DiffStats,
+
+	/// Largest first
+	pub regions: Vec<ChangedRegion>,
+}
+impl HeightmapDiff {
+	/// Whether the terrain is the same in both heightmaps
+	pub fn is_unchanged(&self) -> bool {
+		self.stats.changed == 0 && !self.size_changed && !self.bounds_changed && !self.scale_changed
+	}
+
+	/// Fraction of the compared texels that changed
+	pub fn changed_fraction(&self) -> f64 {
+		if self.stats.compared == 0 {
+			0.0
+		} else {
+			self.stats.changed as f64 / self.stats.compared as f64
+		}
+	}
+
+	/// Renders a difference heatmap: red where the terrain rose, blue where it fell, gray where it didn't change and transparent where it couldn't be compared
+	pub fn to_image(&self) -> image::RgbaImage {
+		let range = self.stats.max_rise.max(-self.stats.max_fall).max(MIN_HEATMAP_RANGE);
+
+		image::RgbaImage::from_fn(self.width, self.height, |x, y| {
+			let delta = self.delta[(y * self.width + x) as usize] as f64;
+			if delta.is_nan() {
+				return image::Rgba([0, 0, 0, 0]);
+			}
+			if delta.abs() <= self.threshold {
+				return image::Rgba([48, 48, 48, 255]);
+			}
+
+			let t = (delta.abs() / range).clamp(0.0, 1.0);
+			let strong = (96.0 + t * 159.0) as u8;
+			let weak = (48.0 * (1.0 - t)) as u8;
+			if delta > 0.0 {
+				image::Rgba([strong, weak, weak, 255])
+			} else {
+				image::Rgba([weak, weak, strong, 255])
+			}
+		})
+	}
+}
+
+/// Groups 8-connected changed texels into regions
+fn changed_regions(width: u32, height: u32, delta: &[f32], threshold: f64) -> Vec<ChangedRegion> {
+	let (w, h) = (width as usize, height as usize);
+	let is_changed = |i: usize| (delta[i] as f64).abs() > threshold;
+
+	let mut visited = vec![false; w * h];
+	let mut regions = Vec::new();
+	let mut stack = Vec::new();
+
+	for start in 0..w * h {
+		if visited[start] || !is_changed(start) {
+			continue;
+		}
+
+		visited[start] = true;
+		stack.push(start);
+
+		let mut region = ChangedRegion {
+			bounds: [[u32::MAX, u32::MAX], [0, 0]],
+			texels: 0,
+			max_change: 0.0,
+		};
+
+		while let Some(i) = stack.pop() {
+			let (x, y) = (i % w, i / w);
+
+			region.texels += 1;
+			region.bounds[0] = [region.bounds[0][0].min(x as u32), region.bounds[0][1].min(y as u32)];
+			region.bounds[1] = [region.bounds[1][0].max(x as u32), region.bounds[1][1].max(y as u32)];
+			if (delta[i] as f64).abs() > region.max_change.abs() {
+				region.max_change = delta[i] as f64;
+			}
+
+			for ny in y.saturating_sub(1)..=(y + 1).min(h - 1) {
+				for nx in x.saturating_sub(1)..=(x + 1).min(w - 1) {
+					let neighbour = ny * w + nx;
+					if !visited[neighbour] && is_changed(neighbour) {
+						visited[neighbour] = true;
+						stack.push(neighbour);
+					}
+				}
+			}
+		}
+
+		if region.texels >= MIN_REGION_TEXELS {
+			regions.push(region);
+		}
+	}
+
+	regions.sort_by_key(|region| core::cmp::Reverse(region.texels));
+	regions
+}
+
+/// Compares an old and a new heightmap of the same layer
+///
+/// The heightmaps are aligned by their bounds, so a heightmap that has grown or shrunk can still be compared where they overlap.
+pub fn diff(old: &Heightmap, new: &Heightmap, threshold: f64) -> HeightmapDiff {
+	let offset = [new.bounds[0][0] as i64 - old.bounds[0][0] as i64, new.bounds[0][1] as i64 - old.bounds[0][1] as i64];
+
+	let old_texel = |x: u32, y: u32| -> Option<(usize, usize)> {
+		let (ox, oy) = (x as i64 + offset[0], y as i64 + offset[1]);
+		if ox < 0 || oy < 0 || ox >= old.width as i64 || oy >= old.height as i64 {
+			None
+		} else {
+			Some((ox as usize, oy as usize))
+		}
+	};
+
+	let mut stats = DiffStats::default();
+	let (mut sum_abs, mut sum_sq) = (0.0, 0.0);
+
+	let mut delta = Vec::with_capacity(new.width as usize * new.height as usize);
+	for y in 0..new.height {
+		for x in 0..new.width {
+			let compared = old_texel(x, y).filter(|&(ox, oy)| old.data[oy * old.width as usize + ox] != 0 && new[(x, y)] != 0);
+			let (ox, oy) = match compared {
+				Some(texel) => texel,
+				None => {
+					delta.push(f32::NAN);
+					continue;
+				}
+			};
+
+			let change = new.height(x as usize, y as usize) - old.height(ox, oy);
+
+			stats.compared += 1;
+			sum_abs += change.abs();
+			sum_sq += change * change;
+			stats.max_rise = stats.max_rise.max(change);
+			stats.max_fall = stats.max_fall.min(change);
+			if change.abs() > threshold {
+				stats.changed += 1;
+			}
+
+			delta.push(change as f32);
+		}
+	}
+
+	if stats.compared > 0 {
+		stats.mean_abs_change = sum_abs / stats.compared as f64;
+		stats.rms_change = (sum_sq / stats.compared as f64).sqrt();
+	}
+
+	let regions = changed_regions(new.width, new.height, &delta, threshold);
+
+	HeightmapDiff {
+		width: new.width,
+		height: new.height,
+		delta: delta.into_boxed_slice(),
+		threshold,
+		size_changed: old.width != new.width || old.height != new.height,
+		bounds_changed: old.bounds != new.bounds,
+		scale_changed: old.scale != new.scale,
+		stats,
+		regions,
+	}
+}
+
+#[test]
+fn test_diff() {
+	use std::sync::Arc;
+
+	let (width, height) = (64, 64);
+	let heightmap = |data: Vec<u16>| Heightmap {
+		width,
+		height,
+		bounds: [[0, 0], [width as i32, height as i32]],
+		scale: [100.0, 100.0, 100.0],
+		data: Arc::from(data),
+		mask: None,
+	};
+
+	let slope = (0..height).flat_map(|y| (0..width).map(move |x| 1000 + (x + y) as u16 * 100)).collect::<Vec<u16>>();
+
+	let diff = diff(&heightmap(slope.clone()), &heightmap(slope.clone()), DEFAULT_THRESHOLD);
+	assert!(diff.is_unchanged());
+	assert_eq!(diff.stats.compared, (width * height) as usize);
+	assert!(diff.regions.is_empty());
+
+	// Raise a hill
+	let mut hill = slope.clone();
+	for y in 10..20 {
+		for x in 30..40 {
+			hill[y * width as usize + x] += 5000;
+		}
+	}
+
+	let diff = self::diff(&heightmap(slope), &heightmap(hill), DEFAULT_THRESHOLD);
+	assert!(!diff.is_unchanged());
+	assert_eq!(diff.stats.changed, 100);
+	assert_eq!(diff.regions.len(), 1);
+	assert_eq!(diff.regions[0].bounds, [[30, 10], [39, 19]]);
+	assert!(diff.regions[0].max_change > 0.0);
+	assert!(diff.stats.max_rise > 0.0 && diff.stats.max_fall == 0.0);
+}
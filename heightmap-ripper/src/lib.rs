@@ -1,6 +1,22 @@
+#[macro_use]
+extern crate lazy_static;
+
 use std::{io::Cursor, path::PathBuf, process::Command, sync::Arc};
 
-use byteorder::{ReadBytesExt, LE};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+
+pub mod bundle;
+pub mod cache;
+pub mod catalogue;
+pub mod contours;
+pub mod coords;
+pub mod diff;
+pub mod registration;
+pub mod render;
+pub mod steam;
+pub mod tiles;
+pub mod validation;
+pub mod viewshed;
 
 pub type LayersList = Box<[Box<str>]>;
 
@@ -10,7 +26,10 @@ pub struct Heightmap {
 	pub height: u32,
 	pub bounds: [[i32; 2]; 2],
 	pub scale: [f32; 3],
-	pub data: Arc<[u16]>
+	pub data: Arc<[u16]>,
+
+	/// Which texels are covered by the landscape, if some aren't, e.g. when stitched together from several landscape tiles
+	pub mask: Option<Arc<[bool]>>,
 }
 impl Heightmap {
 	#[inline]
@@ -18,6 +37,12 @@ impl Heightmap {
 		image::ImageBuffer::from_raw(self.width, self.height, &*self.data).unwrap()
 	}
 
+	/// Whether the landscape covers a texel, in which case its height is meaningful
+	#[inline]
+	pub fn is_valid(&self, x: usize, y: usize) -> bool {
+		self.mask.as_ref().map(|mask| mask[y * self.width as usize + x]).unwrap_or(true)
+	}
+
 	#[inline]
 	pub fn height(&self, x: usize, y: usize) -> f64 {
 		let height = self.data[y * self.width as usize + x];
@@ -40,6 +65,7 @@ impl core::fmt::Debug for Heightmap {
 			.field("bounds", &self.bounds)
 			.field("scale", &self.scale)
 			.field("data", &self.data.len())
+			.field("mask", &self.mask.as_ref().map(|mask| mask.iter().filter(|valid| **valid).count()))
 			.finish()
 	}
 }
@@ -49,13 +75,28 @@ pub enum Error {
 	#[error("I/O error: {0}")]
 	Io(#[from] std::io::Error),
 
+	#[error("Image error: {0}")]
+	Image(#[from] image::ImageError),
+
 	#[error("{0}")]
 	Runtime(Box<str>),
+
+	#[error("This version of SquadHeightmapRipper doesn't support {0}")]
+	Unsupported(&'static str),
 }
 
+/// The AES key that Squad's paks are encrypted with
+pub const DEFAULT_AES_KEY: &str = "0xBC0C07592D6B17BAB88B83A68583A053A6D9A0450CB54ABF5C231DBA59A7466B";
+
 pub const SQUAD_APP_ID: u32 = 393380;
+
+/// The best Squad installation we can find, see [`steam::discover`]
 pub fn find_squad_dir() -> Option<PathBuf> {
-	steamlocate::SteamDir::locate()?.app(&SQUAD_APP_ID).map(|app| app.path.to_path_buf())
+	steam::discover()
+		.into_iter()
+		.find(steam::SquadCandidate::is_valid)
+		.map(|candidate| candidate.path)
+		.or_else(|| steamlocate::SteamDir::locate()?.app(&SQUAD_APP_ID).map(|app| app.path.to_path_buf()))
 }
 
 #[cfg_attr(not(windows), allow(unused_mut))]
@@ -71,6 +112,63 @@ fn invoke() -> Command {
 	cmd
 }
 
+lazy_static! {
+	/// What the ripper prints for `--help`, which lists every option it supports
+	static ref RIPPER_HELP: Box<str> = {
+		let mut cmd = invoke();
+		cmd.arg("--help");
+		match cmd.output() {
+			Ok(output) => String::from_utf8_lossy(&[output.stdout, output.stderr].concat()).into(),
+			Err(err) => {
+				log::warn!("Error asking SquadHeightmapRipper for its options: {err}");
+				Default::default()
+			}
+		}
+	};
+}
+
+fn lists_option(help: &str, option: &str) -> bool {
+	help.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-')).any(|word| word == option)
+}
+
+/// Whether the ripper supports an option, as options get added to the ripper over time and older versions won't know about them
+fn ripper_supports(option: &str) -> bool {
+	lists_option(&RIPPER_HELP, option)
+}
+
+/// Whether the ripper can write each landscape tile of a layer, without which maps split across several landscapes aren't supported
+pub fn supports_tiles() -> bool {
+	ripper_supports("--tiles")
+}
+
+/// Whether the ripper can extract minimap textures, which offline planning needs
+///
+/// The ripper is only asked the first time, after that the answer is cached.
+pub fn supports_minimaps() -> bool {
+	ripper_supports("--minimap")
+}
+
+/// Runs the ripper, returning its stdout
+fn run(mut cmd: Command) -> Result<Vec<u8>, Error> {
+	let output = cmd.output()?;
+	if !output.status.success() {
+		return Err(Error::Runtime(
+			format!(
+				"Status: {:?}\n\n======= STDOUT =======\n{}\n\n======= STDERR =======\n{}",
+				output.status,
+				String::from_utf8_lossy(&output.stdout),
+				String::from_utf8_lossy(&output.stderr)
+			)
+			.into_boxed_str(),
+		));
+	}
+	Ok(output.stdout)
+}
+
+/// Rips the heightmap of a layer
+///
+/// If the layer's landscape is split across several actors, it's only stitched together if the ripper [`supports_tiles`].
+/// Released versions don't, so we only get whichever single tile the ripper finds.
 pub fn get_heightmap(
 	paks_dirs: impl Iterator<Item = impl AsRef<str>>,
 	aes_key: Option<impl AsRef<str>>,
@@ -88,25 +186,36 @@ pub fn get_heightmap(
 	}
 	cmd.arg("-m").arg(map_path.as_ref());
 
+	if supports_tiles() {
+		cmd.arg("--tiles");
+	} else {
+		log::warn!("SquadHeightmapRipper doesn't support --tiles, so only one tile of a split landscape will be ripped");
+	}
+
 	if let Some(aes_key) = aes_key {
 		log::info!("AES key: {:?}", aes_key.as_ref());
 		cmd.arg("-k").arg(aes_key.as_ref());
 	}
 
-	let output = cmd.output()?;
-	if !output.status.success() {
-		return Err(Error::Runtime(
-			format!(
-				"Status: {:?}\n\n======= STDOUT =======\n{}\n\n======= STDERR =======\n{}",
-				output.status,
-				String::from_utf8_lossy(&output.stdout),
-				String::from_utf8_lossy(&output.stderr)
-			)
-			.into_boxed_str(),
-		));
+	let output = run(cmd)?;
+	if tiles::is_tiles(&output) {
+		Ok(tiles::stitch(tiles::read_raw_tiles(&output)?))
+	} else {
+		read_raw(&output)
 	}
+}
 
-	let mut output = Cursor::new(output.stdout);
+/// Parses a heightmap in the format that the ripper writes to stdout
+///
+/// * u32 width
+/// * u32 height
+/// * i32 bounds (x0, y0, x1, y1)
+/// * f32 scale (x, y, z)
+/// * u16 heightmap data
+///
+/// All little endian.
+pub fn read_raw(raw: &[u8]) -> Result<Option<Heightmap>, Error> {
+	let mut output = Cursor::new(raw);
 
 	let width = output.read_u32::<LE>()?;
 	let height = output.read_u32::<LE>()?;
@@ -127,8 +236,7 @@ pub fn get_heightmap(
 
 	let data: Arc<[u16]> = {
 		let pos = output.position() as usize;
-		let output = output.into_inner();
-		let output = &output[pos..];
+		let output = &output.into_inner()[pos..];
 
 		// If the output is completely blank, it means this layer doesn't have a heightmap
 		if !output.iter().copied().any(|byte| byte != 0) {
@@ -136,7 +244,7 @@ pub fn get_heightmap(
 			return Ok(None);
 		}
 
-		let mut data = Vec::with_capacity((output.len() / 2) as usize);
+		let mut data = Vec::with_capacity(output.len() / 2);
 
 		let (prefix, shorts, suffix) = unsafe { output.align_to::<u16>() };
 
@@ -165,10 +273,73 @@ pub fn get_heightmap(
 		height,
 		bounds,
 		scale,
-		data
+		data,
+		mask: None,
 	}))
 }
 
+/// Writes a heightmap in the format that the ripper writes to stdout, see [`read_raw`]
+pub fn write_raw(w: &mut impl std::io::Write, heightmap: &Heightmap) -> Result<(), std::io::Error> {
+	w.write_u32::<LE>(heightmap.width)?;
+	w.write_u32::<LE>(heightmap.height)?;
+
+	for bound in heightmap.bounds.iter().flatten().copied() {
+		w.write_i32::<LE>(bound)?;
+	}
+
+	for xyz in heightmap.scale {
+		w.write_f32::<LE>(xyz)?;
+	}
+
+	for height in heightmap.data.iter().copied() {
+		w.write_u16::<LE>(height)?;
+	}
+
+	w.flush()
+}
+
+/// Extracts the minimap texture of a layer, which the ripper writes to stdout as a PNG when given `--minimap`
+///
+/// Released versions of the ripper can't extract minimaps yet, in which case this returns [`Error::Unsupported`].
+pub fn get_minimap(
+	paks_dirs: impl Iterator<Item = impl AsRef<str>>,
+	aes_key: Option<impl AsRef<str>>,
+	map_path: impl AsRef<str>,
+) -> Result<Option<image::RgbaImage>, Error> {
+	log::info!("Extracting minimap...");
+	log::info!("Map: {}", map_path.as_ref());
+
+	if !supports_minimaps() {
+		return Err(Error::Unsupported("extracting minimaps"));
+	}
+
+	let mut cmd = invoke();
+
+	cmd.arg("-p");
+	for paks_dir in paks_dirs {
+		log::info!("PAKs: {}", paks_dir.as_ref());
+		cmd.arg(paks_dir.as_ref());
+	}
+	cmd.arg("-m").arg(map_path.as_ref());
+	cmd.arg("--minimap");
+
+	if let Some(aes_key) = aes_key {
+		log::info!("AES key: {:?}", aes_key.as_ref());
+		cmd.arg("-k").arg(aes_key.as_ref());
+	}
+
+	let output = run(cmd)?;
+	if output.is_empty() {
+		log::info!("Layer has no minimap");
+		return Ok(None);
+	}
+
+	let minimap = image::load_from_memory_with_format(&output, image::ImageFormat::Png)?.into_rgba8();
+	log::info!("Minimap size {}x{}", minimap.width(), minimap.height());
+
+	Ok(Some(minimap))
+}
+
 pub fn list_maps(paks_dirs: impl Iterator<Item = impl AsRef<str>>, aes_key: Option<impl AsRef<str>>) -> Result<Box<[Box<str>]>, Error> {
 	log::info!("Listing maps...");
 
@@ -186,21 +357,9 @@ pub fn list_maps(paks_dirs: impl Iterator<Item = impl AsRef<str>>, aes_key: Opti
 		cmd.arg("-k").arg(aes_key.as_ref());
 	}
 
-	let output = cmd.output()?;
-	if !output.status.success() {
-		return Err(Error::Runtime(
-			format!(
-				"Status: {:?}\n\n======= STDOUT =======\n{}\n\n======= STDERR =======\n{}",
-				output.status,
-				String::from_utf8_lossy(&output.stdout),
-				String::from_utf8_lossy(&output.stderr)
-			)
-			.into_boxed_str(),
-		));
-	}
+	let output = run(cmd)?;
 
 	let output = output
-		.stdout
 		.split(|&b| b == b'\n')
 		.filter_map(|line| {
 			line.last().copied().and_then(|last| {
@@ -217,39 +376,7 @@ pub fn list_maps(paks_dirs: impl Iterator<Item = impl AsRef<str>>, aes_key: Opti
 		})
 		.filter_map(|line| std::str::from_utf8(line).ok())
 		.filter(|line| line.contains("/Content/Maps/"))
-		.filter(|line| {
-			![
-				"/lighting_layers/",
-				"/lightinglayers/",
-				"/lightlayers/",
-				"/light_layers/",
-				"/lighting_layer/",
-				"/lightinglayer/",
-				"/lightlayer/",
-				"/light_layer/",
-				"/sound_layer/",
-				"/vfx_layers/",
-				"/vfxlayers/",
-				"/vfxlayer/",
-				"/fx_layers/",
-				"/fxlayers/",
-				"/fxlayer/",
-				"/gameplay_layer/",
-				"/gameplay_layers/",
-				"/gameplaylayers/",
-				"/gameplaylayer/",
-				"/gamplaylayer/",
-				"/gamplaylayers/",
-				"/gamplay_layers/",
-				"/gamplay_layer/",
-				"/vfx_sound_layers/",
-				"/vfx_sound_layer/",
-				"/vfxsoundlayer/",
-				"/vfxsoundlayers/",
-			]
-			.into_iter()
-			.any(|filter| line.to_ascii_lowercase().contains(filter))
-		})
+		.filter(|line| !catalogue::is_sublevel(line))
 		.map(Box::from)
 		.collect::<Box<[Box<str>]>>();
 
@@ -287,3 +414,13 @@ fn test_list_maps() {
 		.unwrap()
 	);
 }
+
+#[test]
+fn test_lists_option() {
+	let help = "SquadHeightmapRipper 1.0.0\n\n  -p, --paks       Required. Pak directories\n  -m, --map        Map to rip\n  --tiles          Write each landscape tile\n  --help           Display this help screen.\n";
+	assert!(lists_option(help, "--tiles"));
+	assert!(lists_option(help, "-m"));
+	assert!(!lists_option(help, "--minimap"));
+	assert!(!lists_option(help, "--til"));
+	assert!(!lists_option("", "--tiles"));
+}
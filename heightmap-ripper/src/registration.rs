@@ -0,0 +1,243 @@
+//! Automatic alignment of a heightmap to the in-game minimap
+//!
+//! A hillshade of the heightmap is compared against the captured minimap, searching for the scale and offset that maximise their normalized cross-correlation.
+
+use crate::Heightmap;
+use image::{imageops::FilterType, GrayImage};
+use rayon::prelude::*;
+
+/// Both images are downscaled to roughly this size for the brute force search
+const COARSE_SIZE: u32 = 96;
+
+/// ...and this size for refining the best match
+const REFINE_SIZE: u32 = 384;
+
+/// Less than this fraction of the minimap overlapping with the heightmap isn't a match
+const MIN_OVERLAP: f64 = 0.25;
+
+/// Matches scoring lower than this are probably wrong
+pub const MIN_SCORE: f64 = 0.2;
+
+/// Maps heightmap texels to minimap coordinates, where the minimap coordinates are fractions of the minimap's size
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HeightmapTransform {
+	pub offset: [f64; 2],
+	pub scale: [f64; 2],
+}
+impl HeightmapTransform {
+	/// Stretches the heightmap over the whole minimap
+	pub fn fit(size: [f64; 2]) -> Self {
+		Self {
+			offset: [0.0, 0.0],
+			scale: [1.0 / size[0], 1.0 / size[1]],
+		}
+	}
+
+	/// Anchors the heightmap to the bottom right of the minimap, offsetting it by the start of its bounds
+	pub fn offset_by_bounds(size: [f64; 2], bounds_offset: [f64; 2]) -> Self {
+		let (w, h) = (size[0] + bounds_offset[0], size[1] + bounds_offset[1]);
+		Self {
+			offset: [bounds_offset[0] / w, bounds_offset[1] / h],
+			scale: [1.0 / w, 1.0 / h],
+		}
+	}
+
+	/// The alignment to use when the heightmap hasn't been registered, depending on whether it's fit to the minimap
+	#[inline]
+	pub fn unregistered(size: [f64; 2], bounds_offset: [f64; 2], fit_to_minimap: bool) -> Self {
+		if fit_to_minimap {
+			Self::fit(size)
+		} else {
+			Self::offset_by_bounds(size, bounds_offset)
+		}
+	}
+
+	#[inline]
+	pub fn to_minimap(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+		[self.offset[0] + x * self.scale[0], self.offset[1] + y * self.scale[1]]
+	}
+
+	#[inline]
+	pub fn to_heightmap(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+		[(x - self.offset[0]) / self.scale[0], (y - self.offset[1]) / self.scale[1]]
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Registration {
+	pub transform: HeightmapTransform,
+
+	/// Normalized cross-correlation of the best match, in the range `-1.0..=1.0`
+	pub score: f64,
+}
+
+fn downscale(image: &GrayImage, max_size: u32) -> GrayImage {
+	let (w, h) = image.dimensions();
+	let factor = (max_size as f64 / w.max(h) as f64).min(1.0);
+	image::imageops::resize(image, ((w as f64 * factor).round() as u32).max(1), ((h as f64 * factor).round() as u32).max(1), FilterType::Triangle)
+}
+
+/// Normalized cross-correlation between the minimap and the hillshade projected onto it
+fn score(minimap: &GrayImage, hillshade: &GrayImage, heightmap_size: [f64; 2], transform: &HeightmapTransform) -> f64 {
+	let (mw, mh) = (minimap.width() as f64, minimap.height() as f64);
+	let (hw, hh) = (hillshade.width() as f64 / heightmap_size[0], hillshade.height() as f64 / heightmap_size[1]);
+
+	let (mut n, mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+	for (x, y, a) in minimap.enumerate_pixels() {
+		let [tx, ty] = transform.to_heightmap([(x as f64 + 0.5) / mw, (y as f64 + 0.5) / mh]);
+		let (hx, hy) = (tx * hw, ty * hh);
+		if hx < 0.0 || hy < 0.0 || hx >= hillshade.width() as f64 || hy >= hillshade.height() as f64 {
+			continue;
+		}
+
+		let b = hillshade.get_pixel(hx as u32, hy as u32).0[0] as f64;
+		if b == 0.0 {
+			// Hole in the heightmap
+			continue;
+		}
+
+		let a = a.0[0] as f64;
+		n += 1.0;
+		sum_a += a;
+		sum_b += b;
+		sum_aa += a * a;
+		sum_bb += b * b;
+		sum_ab += a * b;
+	}
+
+	if n < mw * mh * MIN_OVERLAP {
+		return -1.0;
+	}
+
+	let cov = sum_ab - sum_a * sum_b / n;
+	let var_a = sum_aa - sum_a * sum_a / n;
+	let var_b = sum_bb - sum_b * sum_b / n;
+	if var_a <= 0.0 || var_b <= 0.0 {
+		return -1.0;
+	}
+
+	cov / (var_a * var_b).sqrt()
+}
+
+/// Finds the transform that best aligns the heightmap with the minimap image, which should be cropped to the minimap bounds
+pub fn register(heightmap: &Heightmap, minimap: &GrayImage) -> Option<Registration> {
+	if minimap.width() == 0 || minimap.height() == 0 || heightmap.width == 0 || heightmap.height == 0 {
+		return None;
+	}
+
+	let size = [heightmap.width as f64, heightmap.height as f64];
+
+	let hillshade = downscale(&crate::render::hillshade(heightmap, 315.0, 45.0), REFINE_SIZE);
+	let minimap = downscale(minimap, REFINE_SIZE);
+
+	let coarse_hillshade = downscale(&hillshade, COARSE_SIZE);
+	let coarse_minimap = downscale(&minimap, COARSE_SIZE);
+
+	// Brute force search around the two alignments that we already know about
+	let fit = HeightmapTransform::fit(size);
+	let bounds = HeightmapTransform::offset_by_bounds(size, [heightmap.bounds[0][0] as f64, heightmap.bounds[0][1] as f64]);
+
+	let candidates = [fit, bounds]
+		.into_iter()
+		.flat_map(|seed| {
+			(-10..=10).flat_map(move |scale| {
+				let scale = 1.0 + scale as f64 * 0.02;
+				(-12..=12).flat_map(move |ox| {
+					(-12..=12).map(move |oy| HeightmapTransform {
+						offset: [seed.offset[0] + ox as f64 * 0.0125, seed.offset[1] + oy as f64 * 0.0125],
+						scale: [seed.scale[0] * scale, seed.scale[1] * scale],
+					})
+				})
+			})
+		})
+		.collect::<Vec<_>>();
+
+	let (mut best, _) = candidates
+		.into_par_iter()
+		.map(|transform| (transform, score(&coarse_minimap, &coarse_hillshade, size, &transform)))
+		.reduce(|| (fit, f64::MIN), |a, b| if b.1 > a.1 { b } else { a });
+
+	// Refine the best match at a higher resolution, allowing the axes to scale independently
+	let mut best_score = score(&minimap, &hillshade, size, &best);
+	let (mut scale_step, mut offset_step) = (0.01, 0.005);
+	while offset_step > 0.0002 {
+		let neighbours = [
+			([scale_step, 0.0], [0.0, 0.0]),
+			([-scale_step, 0.0], [0.0, 0.0]),
+			([0.0, scale_step], [0.0, 0.0]),
+			([0.0, -scale_step], [0.0, 0.0]),
+			([0.0, 0.0], [offset_step, 0.0]),
+			([0.0, 0.0], [-offset_step, 0.0]),
+			([0.0, 0.0], [0.0, offset_step]),
+			([0.0, 0.0], [0.0, -offset_step]),
+		];
+
+		let improved = neighbours
+			.into_par_iter()
+			.map(|(scale, offset)| {
+				let transform = HeightmapTransform {
+					offset: [best.offset[0] + offset[0], best.offset[1] + offset[1]],
+					scale: [best.scale[0] * (1.0 + scale[0]), best.scale[1] * (1.0 + scale[1])],
+				};
+				(transform, score(&minimap, &hillshade, size, &transform))
+			})
+			.reduce(|| (best, best_score), |a, b| if b.1 > a.1 { b } else { a });
+
+		if improved.1 > best_score {
+			(best, best_score) = improved;
+		} else {
+			scale_step /= 2.0;
+			offset_step /= 2.0;
+		}
+	}
+
+	Some(Registration { transform: best, score: best_score })
+}
+
+#[test]
+fn test_registration() {
+	use std::sync::Arc;
+
+	let (width, height) = (256, 256);
+	let data = (0..height)
+		.flat_map(|y: u32| {
+			(0..width).map(move |x: u32| {
+				let (x, y) = (x as f64 / 16.0, y as f64 / 16.0);
+				(20000.0 + 4000.0 * (x.sin() * (y * 0.7).cos() + (x * 0.3 + y * 0.45).sin())) as u16
+			})
+		})
+		.collect::<Vec<u16>>();
+
+	let heightmap = Heightmap {
+		width,
+		height,
+		bounds: [[0, 0], [width as i32, height as i32]],
+		scale: [100.0, 100.0, 100.0],
+		data: Arc::from(data),
+		mask: None,
+	};
+
+	// Project the hillshade onto a fake minimap with a known transform
+	let expected = HeightmapTransform {
+		offset: [0.04, 0.06],
+		scale: [0.9 / width as f64, 0.9 / height as f64],
+	};
+
+	let hillshade = crate::render::hillshade(&heightmap, 315.0, 45.0);
+	let minimap = GrayImage::from_fn(300, 300, |x, y| {
+		let [tx, ty] = expected.to_heightmap([(x as f64 + 0.5) / 300.0, (y as f64 + 0.5) / 300.0]);
+		if tx >= 0.0 && ty >= 0.0 && tx < width as f64 && ty < height as f64 {
+			*hillshade.get_pixel(tx as u32, ty as u32)
+		} else {
+			image::Luma([128])
+		}
+	});
+
+	let registration = register(&heightmap, &minimap).unwrap();
+	assert!(registration.score > MIN_SCORE, "{registration:?}");
+
+	let [x0, y0] = registration.transform.to_minimap([0.0, 0.0]);
+	let [x1, y1] = registration.transform.to_minimap([width as f64, height as f64]);
+	assert!((x0 - 0.04).abs() < 0.01 && (y0 - 0.06).abs() < 0.01, "{registration:?}");
+	assert!((x1 - 0.94).abs() < 0.01 && (y1 - 0.96).abs() < 0.01, "{registration:?}");
+}
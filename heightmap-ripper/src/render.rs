@@ -0,0 +1,252 @@
+//! Heightmap rendering with color ramps, hillshading and slope shading
+
+use crate::Heightmap;
+use rayon::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ColorRamp {
+	/// The original blue → green → red ramp
+	Classic,
+	Viridis,
+	Magma,
+	Cividis,
+	Terrain,
+	Grayscale,
+}
+impl ColorRamp {
+	pub const ALL: [ColorRamp; 6] = [Self::Classic, Self::Viridis, Self::Magma, Self::Cividis, Self::Terrain, Self::Grayscale];
+
+	#[inline]
+	pub fn from_u8(val: u8) -> Self {
+		Self::ALL.get(val as usize).copied().unwrap_or(Self::Classic)
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Classic => "Classic",
+			Self::Viridis => "Viridis",
+			Self::Magma => "Magma",
+			Self::Cividis => "Cividis",
+			Self::Terrain => "Terrain",
+			Self::Grayscale => "Grayscale",
+		}
+	}
+
+	fn stops(self) -> &'static [(f64, [u8; 3])] {
+		match self {
+			Self::Classic => &[(0.0, [0, 0, 255]), (0.5, [0, 255, 0]), (1.0, [255, 0, 0])],
+
+			Self::Viridis => &[
+				(0.0, [68, 1, 84]),
+				(0.125, [71, 45, 123]),
+				(0.25, [59, 82, 139]),
+				(0.375, [44, 114, 142]),
+				(0.5, [33, 145, 140]),
+				(0.625, [40, 174, 128]),
+				(0.75, [94, 201, 98]),
+				(0.875, [173, 220, 48]),
+				(1.0, [253, 231, 37]),
+			],
+
+			Self::Magma => &[
+				(0.0, [0, 0, 4]),
+				(0.125, [28, 16, 68]),
+				(0.25, [79, 18, 123]),
+				(0.375, [129, 37, 129]),
+				(0.5, [181, 54, 122]),
+				(0.625, [229, 80, 100]),
+				(0.75, [251, 135, 97]),
+				(0.875, [254, 194, 135]),
+				(1.0, [252, 253, 191]),
+			],
+
+			Self::Cividis => &[
+				(0.0, [0, 34, 78]),
+				(0.125, [18, 53, 112]),
+				(0.25, [59, 73, 108]),
+				(0.375, [87, 93, 109]),
+				(0.5, [112, 113, 115]),
+				(0.625, [138, 135, 121]),
+				(0.75, [165, 158, 114]),
+				(0.875, [195, 183, 98]),
+				(1.0, [254, 232, 56]),
+			],
+
+			Self::Terrain => &[
+				(0.0, [40, 90, 50]),
+				(0.25, [95, 140, 70]),
+				(0.5, [190, 175, 110]),
+				(0.75, [140, 100, 70]),
+				(1.0, [245, 245, 245]),
+			],
+
+			Self::Grayscale => &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])],
+		}
+	}
+
+	/// Samples the ramp, where `t` is in the range `0.0..=1.0`
+	pub fn sample(self, t: f64) -> [u8; 3] {
+		let t = t.clamp(0.0, 1.0);
+		let stops = self.stops();
+
+		let i = stops.iter().position(|(stop, _)| *stop >= t).unwrap_or(stops.len() - 1).max(1);
+		let ((t0, a), (t1, b)) = (stops[i - 1], stops[i]);
+
+		let f = if t1 > t0 { (t - t0) / (t1 - t0) } else { 0.0 };
+		[0, 1, 2].map(|c| (a[c] as f64 + (b[c] as f64 - a[c] as f64) * f).round() as u8)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Shading {
+	Flat,
+	Hillshade,
+	Slope,
+}
+impl Shading {
+	pub const ALL: [Shading; 3] = [Self::Flat, Self::Hillshade, Self::Slope];
+
+	#[inline]
+	pub fn from_u8(val: u8) -> Self {
+		Self::ALL.get(val as usize).copied().unwrap_or(Self::Flat)
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Flat => "Flat",
+			Self::Hillshade => "Hillshade",
+			Self::Slope => "Slope",
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderOptions {
+	pub ramp: ColorRamp,
+	pub shading: Shading,
+
+	/// Direction of the sun in degrees, clockwise from north
+	pub azimuth: f64,
+
+	/// Angle of the sun above the horizon in degrees
+	pub altitude: f64,
+}
+impl Default for RenderOptions {
+	fn default() -> Self {
+		Self {
+			ramp: ColorRamp::Classic,
+			shading: Shading::Flat,
+			azimuth: 315.0,
+			altitude: 45.0,
+		}
+	}
+}
+
+/// Returns the lowest and highest raw heights, ignoring holes
+pub fn height_range(heightmap: &Heightmap) -> (u16, u16) {
+	heightmap
+		.data
+		.par_iter()
+		.copied()
+		.filter(|height| *height != 0)
+		.fold(|| (u16::MAX, u16::MIN), |(min, max), height| (min.min(height), max.max(height)))
+		.reduce(|| (u16::MAX, u16::MIN), |(min, max), (min_, max_)| (min.min(min_), max.max(max_)))
+}
+
+/// Slope (radians) and aspect (radians, counter-clockwise from east) of the terrain at a texel
+fn slope_aspect(heightmap: &Heightmap, x: usize, y: usize) -> (f64, f64) {
+	let (w, h) = (heightmap.width as usize, heightmap.height as usize);
+
+	let dzdx = (heightmap.height((x + 1).min(w - 1), y) - heightmap.height(x.saturating_sub(1), y)) / 2.0;
+	let dzdy = (heightmap.height(x, (y + 1).min(h - 1)) - heightmap.height(x, y.saturating_sub(1))) / 2.0;
+
+	((dzdx * dzdx + dzdy * dzdy).sqrt().atan(), dzdy.atan2(-dzdx))
+}
+
+/// Converts a sun azimuth and altitude in degrees to the (zenith, azimuth) radians used by [`illumination`]
+fn sun_angles(azimuth: f64, altitude: f64) -> (f64, f64) {
+	((90.0 - altitude.clamp(0.0, 90.0)).to_radians(), (450.0 - azimuth).rem_euclid(360.0).to_radians())
+}
+
+/// How much light a texel receives from the sun, in the range `0.0..=1.0`
+fn illumination(heightmap: &Heightmap, x: usize, y: usize, (zenith, azimuth): (f64, f64)) -> f64 {
+	let (slope, aspect) = slope_aspect(heightmap, x, y);
+	(zenith.cos() * slope.cos() + zenith.sin() * slope.sin() * (azimuth - aspect).cos()).max(0.0)
+}
+
+/// Renders a plain grayscale hillshade of the heightmap, with holes left black
+pub fn hillshade(heightmap: &Heightmap, azimuth: f64, altitude: f64) -> image::GrayImage {
+	let (width, height) = (heightmap.width as usize, heightmap.height as usize);
+	let sun = sun_angles(azimuth, altitude);
+
+	let mut rendered = vec![0u8; width * height];
+	rendered.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+		for (x, pixel) in row.iter_mut().enumerate() {
+			if heightmap.data[y * width + x] != 0 {
+				*pixel = (illumination(heightmap, x, y, sun) * 255.0) as u8;
+			}
+		}
+	});
+
+	image::GrayImage::from_raw(heightmap.width, heightmap.height, rendered).unwrap()
+}
+
+pub fn render(heightmap: &Heightmap, options: &RenderOptions) -> image::RgbaImage {
+	let (width, height) = (heightmap.width as usize, heightmap.height as usize);
+	let (min, max) = height_range(heightmap);
+	let range = (max as f64 - min as f64).max(1.0);
+
+	let sun = sun_angles(options.azimuth, options.altitude);
+
+	let mut rendered = vec![0u8; width * height * 4];
+	rendered.par_chunks_mut(width * 4).enumerate().for_each(|(y, row)| {
+		for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+			let raw = heightmap.data[y * width + x];
+			if raw == 0 {
+				continue;
+			}
+
+			let [r, g, b] = options.ramp.sample((raw as f64 - min as f64) / range);
+
+			let shade = match options.shading {
+				Shading::Flat => 1.0,
+				Shading::Hillshade => 0.25 + 0.75 * illumination(heightmap, x, y, sun),
+				Shading::Slope => {
+					let (slope, _) = slope_aspect(heightmap, x, y);
+					1.0 - (slope / core::f64::consts::FRAC_PI_2) * 0.8
+				}
+			};
+
+			pixel.copy_from_slice(&[(r as f64 * shade) as u8, (g as f64 * shade) as u8, (b as f64 * shade) as u8, 255]);
+		}
+	});
+
+	image::RgbaImage::from_raw(heightmap.width, heightmap.height, rendered).unwrap()
+}
+
+/// Returns `steps` evenly spaced (meters, color) pairs from the lowest to the highest point of the heightmap
+pub fn legend(heightmap: &Heightmap, ramp: ColorRamp, steps: usize) -> Vec<(f64, [u8; 3])> {
+	let (min, max) = height_range(heightmap);
+	let to_meters = |raw: f64| (raw / u16::MAX as f64) * (heightmap.scale[2] as f64 / 0.1953125);
+
+	(0..steps)
+		.map(|i| {
+			let t = if steps > 1 { i as f64 / (steps - 1) as f64 } else { 0.0 };
+			(to_meters(min as f64 + (max as f64 - min as f64) * t), ramp.sample(t))
+		})
+		.collect()
+}
+
+#[test]
+fn test_color_ramps() {
+	for ramp in ColorRamp::ALL {
+		let stops = ramp.stops();
+		assert_eq!(ramp.sample(0.0), stops[0].1, "{}", ramp.name());
+		assert_eq!(ramp.sample(1.0), stops[stops.len() - 1].1, "{}", ramp.name());
+		assert!(stops.windows(2).all(|stops| stops[0].0 < stops[1].0), "{}", ramp.name());
+	}
+
+	assert_eq!(ColorRamp::Grayscale.sample(0.5), [128, 128, 128]);
+}
@@ -0,0 +1,285 @@
+//! Finds Squad installations across every Steam library we can find
+//!
+//! `steamlocate` only knows about the one Steam install it finds first, which on Linux misses Flatpak and Snap Steam
+//! and any library folders that live on other drives. This enumerates every `libraryfolders.vdf` we can find instead.
+
+use std::{
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use crate::SQUAD_APP_ID;
+
+/// Relative to the Squad directory
+pub const PAKS_DIR: &str = "SquadGame/Content/Paks";
+
+/// How the Steam install that a library was found through was installed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SteamInstall {
+	Native,
+	Flatpak,
+	Snap,
+}
+impl core::fmt::Display for SteamInstall {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.write_str(match self {
+			Self::Native => "Steam",
+			Self::Flatpak => "Flatpak Steam",
+			Self::Snap => "Snap Steam",
+		})
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct SquadCandidate {
+	pub path: PathBuf,
+	pub install: SteamInstall,
+
+	/// Whether `SquadGame/Content/Paks` exists, without which we can't rip anything
+	pub has_paks: bool,
+
+	/// Whether Steam has an app manifest for Squad in this library, rather than the directory being left over from an uninstall
+	pub has_manifest: bool,
+
+	/// Whether Squad has a Proton prefix in this library
+	pub has_compatdata: bool,
+
+	/// When the paks were last modified, so the most recently updated install wins
+	pub modified: Option<SystemTime>,
+}
+impl SquadCandidate {
+	#[inline]
+	pub fn is_valid(&self) -> bool {
+		self.has_paks
+	}
+
+	fn rank(&self) -> impl Ord {
+		(
+			core::cmp::Reverse(self.has_paks),
+			core::cmp::Reverse(self.has_manifest),
+			core::cmp::Reverse(self.modified),
+			core::cmp::Reverse(self.has_compatdata),
+			self.install,
+		)
+	}
+}
+
+/// Steam install roots to look for library folders in, relative to the home directory
+#[cfg(target_os = "linux")]
+const LINUX_STEAM_ROOTS: &[(SteamInstall, &str)] = &[
+	(SteamInstall::Native, ".steam/steam"),
+	(SteamInstall::Native, ".steam/root"),
+	(SteamInstall::Native, ".local/share/Steam"),
+	(SteamInstall::Flatpak, ".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+	(SteamInstall::Flatpak, ".var/app/com.valvesoftware.Steam/data/Steam"),
+	(SteamInstall::Snap, "snap/steam/common/.local/share/Steam"),
+];
+
+fn steam_roots() -> Vec<(SteamInstall, PathBuf)> {
+	let mut roots = Vec::new();
+
+	#[cfg(target_os = "linux")]
+	if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+		roots.extend(LINUX_STEAM_ROOTS.iter().map(|(install, root)| (*install, home.join(root))));
+	}
+
+	// steamlocate still knows best on Windows and macOS
+	// Paranoia again, we don't want discovery to fail because of the registry or a weird config
+	if let Ok(Some(steam_dir)) = std::panic::catch_unwind(steamlocate::SteamDir::locate) {
+		roots.push((SteamInstall::Native, steam_dir.path));
+	}
+
+	roots
+}
+
+/// Extracts the library paths from a `libraryfolders.vdf`
+///
+/// Newer files have a `"path"` key in each numbered library, older ones map the number straight to the path
+pub fn parse_library_folders(vdf: &str) -> Vec<PathBuf> {
+	let mut tokens = Vec::new();
+	let mut chars = vdf.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'{' | '}' => tokens.push(c.to_string()),
+			'"' => {
+				let mut token = String::new();
+				while let Some(c) = chars.next() {
+					match c {
+						'"' => break,
+						'\\' => token.extend(chars.next().map(|c| if c == 'n' { '\n' } else { c })),
+						c => token.push(c),
+					}
+				}
+				tokens.push(token);
+			}
+			'/' if chars.peek() == Some(&'/') => {
+				for c in chars.by_ref() {
+					if c == '\n' {
+						break;
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+
+	let mut paths = Vec::new();
+	let mut depth = 0usize;
+	let mut i = 0;
+	while i < tokens.len() {
+		match tokens[i].as_str() {
+			"{" => depth += 1,
+			"}" => depth = depth.saturating_sub(1),
+			key => {
+				if let Some(value) = tokens.get(i + 1).filter(|value| *value != "{" && *value != "}") {
+					let numbered = key.bytes().all(|b| b.is_ascii_digit());
+					if (depth == 2 && key.eq_ignore_ascii_case("path")) || (depth == 1 && numbered) {
+						paths.push(PathBuf::from(value));
+					}
+					i += 1;
+				}
+			}
+		}
+		i += 1;
+	}
+	paths
+}
+
+fn library_folders(root: &Path) -> Vec<PathBuf> {
+	let mut libraries = vec![root.to_path_buf()];
+	for vdf in [root.join("steamapps/libraryfolders.vdf"), root.join("config/libraryfolders.vdf")] {
+		match std::fs::read_to_string(&vdf) {
+			Ok(vdf) => libraries.extend(parse_library_folders(&vdf)),
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+			Err(err) => log::warn!("Failed to read {}: {err}", vdf.display()),
+		}
+	}
+	libraries
+}
+
+/// Squad's install directory name in a library, from its app manifest if there is one
+fn install_dir(steamapps: &Path) -> (bool, PathBuf) {
+	let manifest = std::fs::read_to_string(steamapps.join(format!("appmanifest_{SQUAD_APP_ID}.acf"))).ok();
+	let install_dir = manifest.as_deref().and_then(|manifest| {
+		manifest.lines().find_map(|line| {
+			let mut parts = line.split('"').filter(|part| !part.trim().is_empty());
+			match (parts.next(), parts.next()) {
+				(Some(key), Some(value)) if key.eq_ignore_ascii_case("installdir") => Some(value.to_owned()),
+				_ => None,
+			}
+		})
+	});
+	(manifest.is_some(), steamapps.join("common").join(install_dir.as_deref().unwrap_or("Squad")))
+}
+
+/// Looks for Squad in a single Steam library
+pub fn candidate_in_library(library: &Path, install: SteamInstall) -> Option<SquadCandidate> {
+	let steamapps = library.join("steamapps");
+	let (has_manifest, path) = install_dir(&steamapps);
+	if !has_manifest && !path.is_dir() {
+		return None;
+	}
+
+	let paks = path.join(PAKS_DIR);
+	Some(SquadCandidate {
+		has_paks: paks.is_dir(),
+		has_compatdata: steamapps.join("compatdata").join(SQUAD_APP_ID.to_string()).is_dir(),
+		modified: std::fs::metadata(&paks).and_then(|metadata| metadata.modified()).ok(),
+		has_manifest,
+		install,
+		path,
+	})
+}
+
+/// Every Squad installation we can find, best first
+pub fn discover() -> Vec<SquadCandidate> {
+	discover_in(steam_roots())
+}
+
+/// Every Squad installation in the libraries of these Steam roots, best first
+pub fn discover_in(roots: impl IntoIterator<Item = (SteamInstall, PathBuf)>) -> Vec<SquadCandidate> {
+	let mut seen = std::collections::HashSet::new();
+	let mut candidates = Vec::new();
+
+	for (install, root) in roots {
+		if !root.is_dir() {
+			continue;
+		}
+		for library in library_folders(&root) {
+			let candidate = match candidate_in_library(&library, install) {
+				Some(candidate) => candidate,
+				None => continue,
+			};
+
+			// ~/.steam/steam and friends are usually symlinks to the same install
+			if seen.insert(std::fs::canonicalize(&candidate.path).unwrap_or_else(|_| candidate.path.clone())) {
+				candidates.push(candidate);
+			}
+		}
+	}
+
+	candidates.sort_by_cached_key(SquadCandidate::rank);
+	candidates
+}
+
+#[test]
+fn test_discover() {
+	let vdf = r#"
+		"libraryfolders"
+		{
+			"0"
+			{
+				"path"		"/home/user/.local/share/Steam"
+				"label"		""
+				"apps"
+				{
+					"393380"		"45312483329"
+				}
+			}
+			"1"
+			{
+				"path"		"/mnt/games/SteamLibrary"
+			}
+		}
+	"#;
+	assert_eq!(parse_library_folders(vdf), [PathBuf::from("/home/user/.local/share/Steam"), PathBuf::from("/mnt/games/SteamLibrary")]);
+
+	let old_vdf = r#"
+		"LibraryFolders"
+		{
+			"TimeNextStatsReport"		"1234567890"
+			"ContentStatsID"		"-123"
+			"1"		"D:\\SteamLibrary"
+		}
+	"#;
+	assert_eq!(parse_library_folders(old_vdf), [PathBuf::from("D:\\SteamLibrary")]);
+
+	let dir = std::env::temp_dir().join(format!("smh-steam-test-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+
+	// A Flatpak Steam root with Squad on a second library, plus a leftover Squad directory without paks in the root library
+	let root = dir.join("flatpak");
+	let library = dir.join("library");
+	std::fs::create_dir_all(root.join("steamapps/common/Squad")).unwrap();
+	std::fs::create_dir_all(library.join("steamapps/common/Squad/SquadGame/Content/Paks")).unwrap();
+	std::fs::create_dir_all(library.join(format!("steamapps/compatdata/{SQUAD_APP_ID}"))).unwrap();
+	std::fs::write(
+		library.join(format!("steamapps/appmanifest_{SQUAD_APP_ID}.acf")),
+		"\"AppState\"\n{\n\t\"appid\"\t\t\"393380\"\n\t\"installdir\"\t\t\"Squad\"\n}\n",
+	)
+	.unwrap();
+	std::fs::write(
+		root.join("steamapps/libraryfolders.vdf"),
+		format!("\"libraryfolders\"\n{{\n\t\"1\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t}}\n}}\n", library.display().to_string().replace('\\', "\\\\")),
+	)
+	.unwrap();
+
+	let candidates = discover_in([(SteamInstall::Flatpak, root.clone()), (SteamInstall::Native, root)]);
+	assert_eq!(candidates.len(), 2);
+	assert!(candidates[0].is_valid() && candidates[0].has_manifest && candidates[0].has_compatdata);
+	assert_eq!(candidates[0].install, SteamInstall::Flatpak);
+	assert!(candidates[0].path.starts_with(&library));
+	assert!(!candidates[1].is_valid());
+
+	std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,301 @@
+//! Landscapes that are split across several actors
+//!
+//! Some maps and mod layers split their terrain across landscape proxies or streaming sublevels, each with its own bounds,
+//! scale and location. This is the format for a ripper that writes every tile it finds when given `--tiles`, so that they can be
+//! stitched back together here.
+//!
+//! No released version of the ripper supports `--tiles` yet (see [`crate::supports_tiles`]), so these maps aren't supported:
+//! they're ripped as whichever single tile the ripper finds.
+//!
+//! * u32 `TILES_MAGIC_NUMBER`
+//! * u32 tile count
+//! * for each tile:
+//!   * u32 width
+//!   * u32 height
+//!   * i32 bounds (x0, y0, x1, y1)
+//!   * f32 scale (x, y, z)
+//!   * f32 location (x, y, z), in centimeters
+//!   * u16 heightmap data
+//!
+//! All little endian. Rippers that don't know about tiles write a single heightmap instead, see [`crate::read_raw`].
+
+use std::{io::Cursor, sync::Arc};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use rayon::prelude::*;
+
+use crate::{Error, Heightmap};
+
+pub const TILES_MAGIC_NUMBER: u32 = u32::from_le_bytes(*b"SMHT");
+
+/// Unreal stores landscape heights as `(height - 32768) / 128` quads of the Z scale
+const LANDSCAPE_ZERO: f64 = 32768.0;
+const LANDSCAPE_ZSCALE: f64 = 1.0 / 128.0;
+
+#[derive(Clone, Debug)]
+pub struct LandscapeTile {
+	pub heightmap: Heightmap,
+
+	/// Location of the landscape actor in the level, in centimeters
+	pub location: [f32; 3],
+}
+impl LandscapeTile {
+	/// Height of a texel in the level, in centimeters
+	#[inline]
+	fn world_z(&self, height: u16) -> f64 {
+		self.location[2] as f64 + (height as f64 - LANDSCAPE_ZERO) * LANDSCAPE_ZSCALE * self.heightmap.scale[2] as f64
+	}
+
+	#[inline]
+	fn area(&self) -> u64 {
+		self.heightmap.width as u64 * self.heightmap.height as u64
+	}
+}
+
+/// Whether the ripper's output is a set of tiles rather than a single heightmap
+#[inline]
+pub fn is_tiles(raw: &[u8]) -> bool {
+	raw.get(..4) == Some(&TILES_MAGIC_NUMBER.to_le_bytes())
+}
+
+/// Parses the tiles that the ripper writes to stdout when given `--tiles`, skipping any without data
+pub fn read_raw_tiles(raw: &[u8]) -> Result<Vec<LandscapeTile>, Error> {
+	let mut r = Cursor::new(raw);
+
+	if r.read_u32::<LE>()? != TILES_MAGIC_NUMBER {
+		return Err(Error::Runtime("Ripper output isn't a set of landscape tiles".into()));
+	}
+
+	let count = r.read_u32::<LE>()?;
+	let mut tiles = Vec::with_capacity(count.min(256) as usize);
+	for _ in 0..count {
+		let width = r.read_u32::<LE>()?;
+		let height = r.read_u32::<LE>()?;
+		let bounds = [[r.read_i32::<LE>()?, r.read_i32::<LE>()?], [r.read_i32::<LE>()?, r.read_i32::<LE>()?]];
+		let scale = [r.read_f32::<LE>()?, r.read_f32::<LE>()?, r.read_f32::<LE>()?];
+		let location = [r.read_f32::<LE>()?, r.read_f32::<LE>()?, r.read_f32::<LE>()?];
+
+		let mut data = vec![0u16; width as usize * height as usize];
+		r.read_u16_into::<LE>(&mut data)?;
+
+		if data.iter().all(|height| *height == 0) {
+			log::info!("Skipping landscape tile at {location:?} with no data");
+			continue;
+		}
+
+		tiles.push(LandscapeTile {
+			heightmap: Heightmap {
+				width,
+				height,
+				bounds,
+				scale,
+				data: Arc::from(data),
+				mask: None,
+			},
+			location,
+		});
+	}
+
+	log::info!("Read {} landscape tiles", tiles.len());
+
+	Ok(tiles)
+}
+
+/// Writes tiles in the format that the ripper writes to stdout, see [`read_raw_tiles`]
+pub fn write_raw_tiles(w: &mut impl std::io::Write, tiles: &[LandscapeTile]) -> Result<(), std::io::Error> {
+	w.write_u32::<LE>(TILES_MAGIC_NUMBER)?;
+	w.write_u32::<LE>(tiles.len() as u32)?;
+
+	for tile in tiles {
+		w.write_u32::<LE>(tile.heightmap.width)?;
+		w.write_u32::<LE>(tile.heightmap.height)?;
+
+		for bound in tile.heightmap.bounds.iter().flatten().copied() {
+			w.write_i32::<LE>(bound)?;
+		}
+
+		for xyz in tile.heightmap.scale.into_iter().chain(tile.location) {
+			w.write_f32::<LE>(xyz)?;
+		}
+
+		for height in tile.heightmap.data.iter().copied() {
+			w.write_u16::<LE>(height)?;
+		}
+	}
+
+	w.flush()
+}
+
+/// Where a tile sits in the stitched heightmap, in quads of the reference tile
+struct Placement<'a> {
+	tile: &'a LandscapeTile,
+
+	/// Position of the tile's first texel
+	origin: [f64; 2],
+
+	/// Reference quads per texel of this tile
+	step: [f64; 2],
+}
+impl Placement<'_> {
+	/// Samples the tile's height in centimeters, if the position is on it
+	fn sample(&self, [x, y]: [f64; 2]) -> Option<f64> {
+		let heightmap = &self.tile.heightmap;
+
+		let x = (x - self.origin[0]) / self.step[0];
+		let y = (y - self.origin[1]) / self.step[1];
+
+		// Allow for a bit of floating point error at the edges
+		const EPSILON: f64 = 1e-6;
+		let max = [(heightmap.width - 1) as f64, (heightmap.height - 1) as f64];
+		if x < -EPSILON || y < -EPSILON || x > max[0] + EPSILON || y > max[1] + EPSILON {
+			return None;
+		}
+		let (x, y) = (x.clamp(0.0, max[0]), y.clamp(0.0, max[1]));
+
+		let x0 = x.floor() as u32;
+		let y0 = y.floor() as u32;
+		let x1 = (x0 + 1).min(heightmap.width - 1);
+		let y1 = (y0 + 1).min(heightmap.height - 1);
+		let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+		let z = |x, y| self.tile.world_z(heightmap[(x, y)]);
+		let top = z(x0, y0) * (1.0 - fx) + z(x1, y0) * fx;
+		let bottom = z(x0, y1) * (1.0 - fx) + z(x1, y1) * fx;
+		Some(top * (1.0 - fy) + bottom * fy)
+	}
+}
+
+/// Merges landscape tiles into one world-aligned heightmap
+///
+/// The largest tile is used as the reference for the stitched heightmap's bounds and horizontal scale, and wins where tiles overlap.
+/// Texels that no tile covers are left out of the heightmap's validity mask. A single tile is returned as is.
+pub fn stitch(mut tiles: Vec<LandscapeTile>) -> Option<Heightmap> {
+	tiles.retain(|tile| tile.heightmap.width > 0 && tile.heightmap.height > 0);
+	tiles.sort_by_key(|tile| core::cmp::Reverse(tile.area()));
+
+	if tiles.len() <= 1 {
+		return tiles.pop().map(|tile| tile.heightmap);
+	}
+
+	let reference = &tiles[0];
+	let ref_scale = [reference.heightmap.scale[0] as f64, reference.heightmap.scale[1] as f64];
+
+	let placements = tiles
+		.iter()
+		.map(|tile| {
+			let step = [tile.heightmap.scale[0] as f64 / ref_scale[0], tile.heightmap.scale[1] as f64 / ref_scale[1]];
+			let origin = [0, 1].map(|axis| {
+				(tile.location[axis] - reference.location[axis]) as f64 / ref_scale[axis] + tile.heightmap.bounds[0][axis] as f64 * step[axis]
+			});
+			Placement { tile, origin, step }
+		})
+		.collect::<Vec<_>>();
+
+	let mut min = [f64::INFINITY; 2];
+	let mut max = [f64::NEG_INFINITY; 2];
+	for placement in &placements {
+		let size = [placement.tile.heightmap.width, placement.tile.heightmap.height];
+		for axis in 0..2 {
+			min[axis] = min[axis].min(placement.origin[axis]);
+			max[axis] = max[axis].max(placement.origin[axis] + (size[axis] - 1) as f64 * placement.step[axis]);
+		}
+	}
+	let min = min.map(|min| min.round() as i32);
+	let max = max.map(|max| max.round() as i32);
+	let width = (max[0] - min[0] + 1) as u32;
+	let height = (max[1] - min[1] + 1) as u32;
+
+	log::info!("Stitching {} landscape tiles into {width}x{height}", tiles.len());
+
+	let mut heights = vec![f64::NAN; width as usize * height as usize];
+	heights.par_chunks_mut(width as usize).enumerate().for_each(|(y, row)| {
+		for (x, z) in row.iter_mut().enumerate() {
+			let quad = [(min[0] + x as i32) as f64, (min[1] + y as i32) as f64];
+			if let Some(sample) = placements.iter().find_map(|placement| placement.sample(quad)) {
+				*z = sample;
+			}
+		}
+	});
+
+	let (min_z, max_z) = heights
+		.iter()
+		.copied()
+		.filter(|z| !z.is_nan())
+		.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), z| (min.min(z), max.max(z)));
+	if min_z > max_z {
+		return None;
+	}
+
+	// Keep the finest vertical scale that can still fit every tile's heights into 16 bits
+	// Zero is left for texels that no tile covers, so that they're picked up as voids
+	const MAX_HEIGHT: f64 = (u16::MAX - 1) as f64;
+	let max_scale_z = tiles.iter().map(|tile| tile.heightmap.scale[2]).fold(0.0f32, f32::max) as f64;
+	let scale_z = max_scale_z.max((max_z - min_z) / (MAX_HEIGHT * LANDSCAPE_ZSCALE));
+
+	let mask = heights.iter().map(|z| !z.is_nan()).collect::<Arc<[bool]>>();
+	let data = heights
+		.iter()
+		.map(|z| if z.is_nan() { 0 } else { 1 + ((z - min_z) / (LANDSCAPE_ZSCALE * scale_z)).round().clamp(0.0, MAX_HEIGHT) as u16 })
+		.collect::<Arc<[u16]>>();
+
+	Some(Heightmap {
+		width,
+		height,
+		bounds: [min, max],
+		scale: [reference.heightmap.scale[0], reference.heightmap.scale[1], scale_z as f32],
+		data,
+		mask: Some(mask),
+	})
+}
+
+#[test]
+fn test_stitch() {
+	let tile = |width: u32, height: u32, bounds_min: [i32; 2], scale: [f32; 3], location: [f32; 3], f: &dyn Fn(u32, u32) -> u16| LandscapeTile {
+		heightmap: Heightmap {
+			width,
+			height,
+			bounds: [bounds_min, [bounds_min[0] + width as i32 - 1, bounds_min[1] + height as i32 - 1]],
+			scale,
+			data: (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| f(x, y)).collect(),
+			mask: None,
+		},
+		location,
+	};
+
+	// A flat tile, with a smaller tile to its right that's 10m higher, sat 2m above the first in the level
+	let left = tile(64, 64, [0, 0], [100.0, 100.0, 100.0], [0.0, 0.0, 0.0], &|_, _| 32768);
+	let right = tile(32, 32, [0, 0], [100.0, 100.0, 100.0], [6400.0, 0.0, 200.0], &|_, _| 32768 + 128 * 8);
+
+	let raw = {
+		let mut raw = Vec::new();
+		write_raw_tiles(&mut raw, &[right.clone(), left.clone()]).unwrap();
+		raw
+	};
+	assert!(is_tiles(&raw));
+	let tiles = read_raw_tiles(&raw).unwrap();
+	assert_eq!(tiles.len(), 2);
+
+	// A single tile is left alone
+	let single = stitch(vec![left.clone()]).unwrap();
+	assert_eq!((single.width, single.height, single.mask.is_some()), (64, 64, false));
+
+	let stitched = stitch(tiles).unwrap();
+	assert_eq!((stitched.width, stitched.height), (96, 64));
+	assert_eq!(stitched.bounds, [[0, 0], [95, 63]]);
+
+	let mask = stitched.mask.as_deref().unwrap();
+	assert!(stitched.is_valid(10, 10) && stitched.is_valid(80, 10));
+	assert!(!stitched.is_valid(80, 40));
+	assert_eq!(mask.iter().filter(|valid| **valid).count(), 64 * 64 + 32 * 32);
+	assert_eq!(stitched.data.iter().filter(|height| **height == 0).count(), 32 * 32);
+
+	let rise = stitched.height(80, 10) - stitched.height(10, 10);
+	assert!((rise - 10.0).abs() < 0.05, "{rise}");
+
+	// Tiles at a different horizontal scale are resampled onto the reference
+	let coarse = tile(16, 16, [0, 0], [200.0, 200.0, 100.0], [6400.0, 0.0, 0.0], &|x, _| 32768 + 128 * x as u16);
+	let stitched = stitch(vec![left, coarse]).unwrap();
+	assert_eq!((stitched.width, stitched.height), (95, 64));
+	let slope = stitched.height(84, 0) - stitched.height(74, 0);
+	assert!((slope - 5.0).abs() < 0.05, "{slope}");
+}
@@ -0,0 +1,258 @@
+//! Sanity checks for ripped heightmaps, so that broken ones are noticed before they're used for fire missions
+
+use crate::Heightmap;
+
+/// More than this fraction of voids makes a heightmap unusable
+const MAX_VOID_FRACTION: f64 = 0.5;
+
+/// ...and more than this is worth a warning
+const WARN_VOID_FRACTION: f64 = 0.05;
+
+/// A single void covering more than this fraction of the heightmap is worth a warning
+const WARN_LARGEST_VOID_FRACTION: f64 = 0.02;
+
+/// Heightmaps with less elevation change than this (in meters) are probably blank
+const MIN_ELEVATION_RANGE: f64 = 1.0;
+
+/// Relative difference between the heightmap's and its bounds' aspect ratios
+const MAX_ASPECT_RATIO_MISMATCH: f64 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	Warning,
+	Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Issue {
+	/// Some of the heightmap is zero-filled
+	Voids { fraction: f64, largest: f64, count: usize },
+
+	/// The heightmap has (almost) no elevation change
+	Flat { range: f64 },
+
+	/// The minimap bounds are empty or inverted
+	InvalidBounds,
+
+	AspectRatioMismatch { heightmap: f64, bounds: f64 },
+
+	/// The scale is zero, negative or not a number
+	InvalidScale,
+
+	/// The horizontal scale differs between the X and Y axes
+	UnevenScale { x: f32, y: f32 },
+
+	/// The scale is far outside of what Squad's landscapes normally use
+	UnusualScale,
+}
+impl Issue {
+	pub fn severity(&self) -> Severity {
+		match self {
+			Self::Voids { fraction, .. } if *fraction > MAX_VOID_FRACTION => Severity::Error,
+			Self::Flat { .. } | Self::InvalidBounds | Self::InvalidScale => Severity::Error,
+			_ => Severity::Warning,
+		}
+	}
+}
+impl core::fmt::Display for Issue {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Self::Voids { fraction, largest, count } => write!(
+				f,
+				"{:.1}% of the heightmap is void ({count} regions, largest covers {:.1}%)",
+				fraction * 100.0,
+				largest * 100.0
+			),
+			Self::Flat { range } => write!(f, "Heightmap is flat ({range:.2}m of elevation change)"),
+			Self::InvalidBounds => write!(f, "Minimap bounds are empty or inverted"),
+			Self::AspectRatioMismatch { heightmap, bounds } => {
+				write!(f, "Aspect ratio ({heightmap:.3}) doesn't match the minimap bounds ({bounds:.3})")
+			}
+			Self::InvalidScale => write!(f, "Scale is zero, negative or not a number"),
+			Self::UnevenScale { x, y } => write!(f, "Horizontal scale differs between axes ({x} x {y})"),
+			Self::UnusualScale => write!(f, "Scale is unusually large or small"),
+		}
+	}
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+	/// Elevation statistics in meters, ignoring voids
+	pub min_elevation: f64,
+	pub max_elevation: f64,
+	pub mean_elevation: f64,
+
+	/// Fraction of texels that are zero-filled
+	pub void_fraction: f64,
+
+	pub issues: Vec<Issue>,
+}
+impl ValidationReport {
+	#[inline]
+	pub fn severity(&self) -> Option<Severity> {
+		self.issues.iter().map(Issue::severity).max()
+	}
+
+	/// Whether the heightmap shouldn't be trusted at all
+	#[inline]
+	pub fn is_broken(&self) -> bool {
+		self.severity() == Some(Severity::Error)
+	}
+}
+
+/// Finds 4-connected regions of zero-filled texels, returning their sizes in texels
+fn void_regions(heightmap: &Heightmap) -> Vec<usize> {
+	let (w, h) = (heightmap.width as usize, heightmap.height as usize);
+
+	let mut visited = vec![false; w * h];
+	let mut regions = Vec::new();
+	let mut stack = Vec::new();
+
+	for start in 0..w * h {
+		if visited[start] || heightmap.data[start] != 0 {
+			continue;
+		}
+
+		visited[start] = true;
+		stack.push(start);
+
+		let mut size = 0;
+		while let Some(i) = stack.pop() {
+			size += 1;
+
+			let (x, y) = (i % w, i / w);
+			let neighbours = [
+				if x > 0 { Some(i - 1) } else { None },
+				if x + 1 < w { Some(i + 1) } else { None },
+				if y > 0 { Some(i - w) } else { None },
+				if y + 1 < h { Some(i + w) } else { None },
+			];
+
+			for neighbour in neighbours.into_iter().flatten() {
+				if !visited[neighbour] && heightmap.data[neighbour] == 0 {
+					visited[neighbour] = true;
+					stack.push(neighbour);
+				}
+			}
+		}
+
+		regions.push(size);
+	}
+
+	regions
+}
+
+pub fn validate(heightmap: &Heightmap) -> ValidationReport {
+	let mut report = ValidationReport::default();
+
+	let area = heightmap.width as usize * heightmap.height as usize;
+	if area == 0 {
+		report.void_fraction = 1.0;
+		report.issues.push(Issue::Voids { fraction: 1.0, largest: 1.0, count: 0 });
+		return report;
+	}
+
+	let (mut min, mut max, mut sum, mut n) = (f64::MAX, f64::MIN, 0.0, 0usize);
+	for y in 0..heightmap.height as usize {
+		for x in 0..heightmap.width as usize {
+			if heightmap.data[y * heightmap.width as usize + x] == 0 {
+				continue;
+			}
+
+			let elevation = heightmap.height(x, y);
+			min = min.min(elevation);
+			max = max.max(elevation);
+			sum += elevation;
+			n += 1;
+		}
+	}
+
+	if n > 0 {
+		report.min_elevation = min;
+		report.max_elevation = max;
+		report.mean_elevation = sum / n as f64;
+	}
+
+	let voids = void_regions(heightmap);
+	report.void_fraction = (area - n) as f64 / area as f64;
+	if !voids.is_empty() {
+		let largest = voids.iter().copied().max().unwrap_or(0) as f64 / area as f64;
+		if report.void_fraction > WARN_VOID_FRACTION || largest > WARN_LARGEST_VOID_FRACTION {
+			report.issues.push(Issue::Voids {
+				fraction: report.void_fraction,
+				largest,
+				count: voids.len(),
+			});
+		}
+	}
+
+	if n > 0 && max - min < MIN_ELEVATION_RANGE {
+		report.issues.push(Issue::Flat { range: max - min });
+	}
+
+	let [[x0, y0], [x1, y1]] = heightmap.bounds;
+	let (bounds_w, bounds_h) = (x1 as f64 - x0 as f64, y1 as f64 - y0 as f64);
+	if bounds_w <= 0.0 || bounds_h <= 0.0 {
+		report.issues.push(Issue::InvalidBounds);
+	} else {
+		let aspect = heightmap.width as f64 / heightmap.height as f64;
+		let bounds_aspect = bounds_w / bounds_h;
+		if (aspect - bounds_aspect).abs() / bounds_aspect > MAX_ASPECT_RATIO_MISMATCH {
+			report.issues.push(Issue::AspectRatioMismatch {
+				heightmap: aspect,
+				bounds: bounds_aspect,
+			});
+		}
+	}
+
+	let [sx, sy, sz] = heightmap.scale;
+	if heightmap.scale.iter().any(|scale| !scale.is_finite() || *scale <= 0.0) {
+		report.issues.push(Issue::InvalidScale);
+	} else {
+		if (sx - sy).abs() / sx.max(sy) > 0.01 {
+			report.issues.push(Issue::UnevenScale { x: sx, y: sy });
+		}
+		if !(10.0..=1000.0).contains(&sx) || !(10.0..=1000.0).contains(&sy) || !(1.0..=10000.0).contains(&sz) {
+			report.issues.push(Issue::UnusualScale);
+		}
+	}
+
+	report
+}
+
+#[test]
+fn test_validation() {
+	use std::sync::Arc;
+
+	let (width, height) = (64, 64);
+	let heightmap = |data: Vec<u16>, scale: [f32; 3]| Heightmap {
+		width,
+		height,
+		bounds: [[0, 0], [width as i32, height as i32]],
+		scale,
+		data: Arc::from(data),
+		mask: None,
+	};
+
+	let slope = (0..height).flat_map(|y| (0..width).map(move |x| 1000 + (x + y) as u16 * 100)).collect::<Vec<u16>>();
+
+	let report = validate(&heightmap(slope.clone(), [100.0, 100.0, 100.0]));
+	assert!(report.issues.is_empty(), "{:?}", report.issues);
+	assert!(report.min_elevation < report.mean_elevation && report.mean_elevation < report.max_elevation);
+
+	// Punch a hole in the middle
+	let mut holey = slope.clone();
+	for y in 16..48 {
+		for x in 16..48 {
+			holey[y * width as usize + x] = 0;
+		}
+	}
+	let report = validate(&heightmap(holey, [100.0, 100.0, 100.0]));
+	assert!(matches!(report.issues[..], [Issue::Voids { count: 1, .. }]), "{:?}", report.issues);
+	assert!(!report.is_broken());
+
+	let report = validate(&heightmap(vec![1000; (width * height) as usize], [100.0, 0.0, 100.0]));
+	assert!(report.issues.contains(&Issue::Flat { range: 0.0 }));
+	assert!(report.issues.contains(&Issue::InvalidScale));
+	assert!(report.is_broken());
+}
@@ -0,0 +1,274 @@
+//! Line of sight and viewshed analysis over heightmap data
+//!
+//! Positions are in heightmap texels, which are 1m apart. Eye and target heights are in meters above the terrain.
+
+use crate::Heightmap;
+use rayon::prelude::*;
+use std::{
+	collections::VecDeque,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+};
+
+/// Eye height of a standing soldier
+pub const DEFAULT_EYE_HEIGHT: f64 = 1.7;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineOfSight {
+	pub visible: bool,
+
+	/// The first point along the line where the terrain gets in the way
+	pub obstruction: Option<[f64; 2]>,
+}
+
+/// Elevation of the nearest texel, or `None` if it's outside of the heightmap or void
+#[inline]
+fn elevation(heightmap: &Heightmap, [x, y]: [f64; 2]) -> Option<f64> {
+	let (x, y) = (x.round(), y.round());
+	if x < 0.0 || y < 0.0 || x >= heightmap.width as f64 || y >= heightmap.height as f64 {
+		return None;
+	}
+
+	let (x, y) = (x as usize, y as usize);
+	if heightmap.data[y * heightmap.width as usize + x] == 0 {
+		return None;
+	}
+
+	Some(heightmap.height(x, y))
+}
+
+/// Whether a target `target_height` meters above the ground at `to` can be seen from `eye_height` meters above the ground at `from`
+pub fn line_of_sight(heightmap: &Heightmap, from: [f64; 2], to: [f64; 2], eye_height: f64, target_height: f64) -> LineOfSight {
+	let eye = elevation(heightmap, from).unwrap_or(0.0) + eye_height;
+	let target = elevation(heightmap, to).unwrap_or(0.0) + target_height;
+
+	let distance = ((to[0] - from[0]).powi(2) + (to[1] - from[1]).powi(2)).sqrt();
+	let steps = distance.ceil() as usize;
+
+	for step in 1..steps {
+		let t = step as f64 / steps as f64;
+		let point = [from[0] + (to[0] - from[0]) * t, from[1] + (to[1] - from[1]) * t];
+
+		if let Some(ground) = elevation(heightmap, point) {
+			if ground > eye + (target - eye) * t {
+				return LineOfSight {
+					visible: false,
+					obstruction: Some(point),
+				};
+			}
+		}
+	}
+
+	LineOfSight { visible: true, obstruction: None }
+}
+
+#[derive(Clone, Debug)]
+pub struct Viewshed {
+	pub observer: [u32; 2],
+	pub eye_height: f64,
+	pub radius: u32,
+
+	/// Top left of the area covered by the viewshed, in heightmap texels
+	pub origin: [u32; 2],
+	pub width: u32,
+	pub height: u32,
+
+	/// Whether the ground at each texel of the area can be seen by the observer
+	pub visible: Box<[bool]>,
+}
+impl Viewshed {
+	/// Whether the ground at a heightmap texel can be seen by the observer
+	pub fn is_visible(&self, x: u32, y: u32) -> bool {
+		let (x, y) = match (x.checked_sub(self.origin[0]), y.checked_sub(self.origin[1])) {
+			(Some(x), Some(y)) if x < self.width && y < self.height => (x, y),
+			_ => return false,
+		};
+		self.visible[(y * self.width + x) as usize]
+	}
+
+	/// Fraction of the ground within the radius that can be seen
+	pub fn visible_fraction(&self) -> f64 {
+		let radius_sq = self.radius as i64 * self.radius as i64;
+		let (mut visible, mut total) = (0usize, 0usize);
+		for y in 0..self.height {
+			for x in 0..self.width {
+				let dx = (self.origin[0] + x) as i64 - self.observer[0] as i64;
+				let dy = (self.origin[1] + y) as i64 - self.observer[1] as i64;
+				if dx * dx + dy * dy <= radius_sq {
+					total += 1;
+					if self.visible[(y * self.width + x) as usize] {
+						visible += 1;
+					}
+				}
+			}
+		}
+		if total == 0 {
+			0.0
+		} else {
+			visible as f64 / total as f64
+		}
+	}
+
+	/// Renders the viewshed as an overlay, with hidden ground shaded and anything outside of the radius left transparent
+	pub fn to_image(&self, visible_color: [u8; 4], hidden_color: [u8; 4]) -> image::RgbaImage {
+		let radius_sq = self.radius as i64 * self.radius as i64;
+		image::RgbaImage::from_fn(self.width, self.height, |x, y| {
+			let dx = (self.origin[0] + x) as i64 - self.observer[0] as i64;
+			let dy = (self.origin[1] + y) as i64 - self.observer[1] as i64;
+			if dx * dx + dy * dy > radius_sq {
+				image::Rgba([0, 0, 0, 0])
+			} else if self.visible[(y * self.width + x) as usize] {
+				image::Rgba(visible_color)
+			} else {
+				image::Rgba(hidden_color)
+			}
+		})
+	}
+}
+
+/// Computes which texels within `radius` meters of the observer can be seen from `eye_height` meters above the ground
+///
+/// This is a radial sweep: a ray is cast from the observer to every texel on the edge of the area, and each texel along
+/// the ray is visible if the angle to it is at least as steep as the steepest angle seen so far along that ray.
+pub fn viewshed(heightmap: &Heightmap, observer: [u32; 2], eye_height: f64, radius: u32) -> Viewshed {
+	let observer = [observer[0].min(heightmap.width.saturating_sub(1)), observer[1].min(heightmap.height.saturating_sub(1))];
+
+	let origin = [observer[0].saturating_sub(radius), observer[1].saturating_sub(radius)];
+	let end = [
+		(observer[0] + radius).min(heightmap.width.saturating_sub(1)),
+		(observer[1] + radius).min(heightmap.height.saturating_sub(1)),
+	];
+	let (width, height) = (end[0] + 1 - origin[0], end[1] + 1 - origin[1]);
+
+	let visible = (0..width as usize * height as usize).map(|_| AtomicBool::new(false)).collect::<Box<[_]>>();
+	visible[((observer[1] - origin[1]) * width + (observer[0] - origin[0])) as usize].store(true, Ordering::Relaxed);
+
+	let eye = elevation(heightmap, [observer[0] as f64, observer[1] as f64]).unwrap_or(0.0) + eye_height;
+	let radius_sq = radius as f64 * radius as f64;
+
+	let perimeter = (origin[0]..=end[0])
+		.flat_map(|x| [[x, origin[1]], [x, end[1]]])
+		.chain((origin[1]..=end[1]).flat_map(|y| [[origin[0], y], [end[0], y]]))
+		.collect::<Vec<_>>();
+
+	perimeter.into_par_iter().for_each(|[tx, ty]| {
+		let (dx, dy) = (tx as f64 - observer[0] as f64, ty as f64 - observer[1] as f64);
+		let steps = dx.abs().max(dy.abs()) as usize;
+		if steps == 0 {
+			return;
+		}
+
+		let mut max_slope = f64::NEG_INFINITY;
+		for step in 1..=steps {
+			let t = step as f64 / steps as f64;
+			let (x, y) = ((observer[0] as f64 + dx * t).round(), (observer[1] as f64 + dy * t).round());
+
+			let distance_sq = (x - observer[0] as f64).powi(2) + (y - observer[1] as f64).powi(2);
+			if distance_sq > radius_sq {
+				break;
+			}
+
+			let ground = match elevation(heightmap, [x, y]) {
+				Some(ground) => ground,
+				None => continue,
+			};
+
+			let slope = (ground - eye) / distance_sq.sqrt();
+			if slope >= max_slope {
+				visible[((y as u32 - origin[1]) * width + (x as u32 - origin[0])) as usize].store(true, Ordering::Relaxed);
+				max_slope = slope;
+			}
+		}
+	});
+
+	Viewshed {
+		observer,
+		eye_height,
+		radius,
+		origin,
+		width,
+		height,
+		visible: visible.iter().map(|visible| visible.load(Ordering::Relaxed)).collect(),
+	}
+}
+
+/// Remembers the most recently computed viewsheds, so that moving back to a previous observer position is instant
+pub struct ViewshedCache {
+	capacity: usize,
+	viewsheds: VecDeque<Arc<Viewshed>>,
+}
+impl ViewshedCache {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity,
+			viewsheds: VecDeque::with_capacity(capacity),
+		}
+	}
+
+	pub fn get(&mut self, observer: [u32; 2], eye_height: f64, radius: u32) -> Option<Arc<Viewshed>> {
+		let i = self
+			.viewsheds
+			.iter()
+			.position(|viewshed| viewshed.observer == observer && viewshed.eye_height == eye_height && viewshed.radius == radius)?;
+
+		// Most recently used goes to the front
+		let viewshed = self.viewsheds.remove(i)?;
+		self.viewsheds.push_front(viewshed.clone());
+		Some(viewshed)
+	}
+
+	pub fn insert(&mut self, viewshed: Arc<Viewshed>) {
+		self.viewsheds
+			.retain(|cached| !(cached.observer == viewshed.observer && cached.eye_height == viewshed.eye_height && cached.radius == viewshed.radius));
+
+		self.viewsheds.push_front(viewshed);
+		self.viewsheds.truncate(self.capacity);
+	}
+
+	pub fn clear(&mut self) {
+		self.viewsheds.clear();
+	}
+}
+
+#[test]
+fn test_viewshed() {
+	let (width, height) = (64, 64);
+
+	// Flat ground with a tall wall running north to south at x = 32
+	let data = (0..height)
+		.flat_map(|_| (0..width).map(|x| if x == 32 { 30000 } else { 1000 }))
+		.collect::<Vec<u16>>();
+
+	let heightmap = Heightmap {
+		width,
+		height,
+		bounds: [[0, 0], [width as i32, height as i32]],
+		scale: [100.0, 100.0, 100.0],
+		data: Arc::from(data),
+		mask: None,
+	};
+
+	let los = line_of_sight(&heightmap, [10.0, 10.0], [20.0, 50.0], DEFAULT_EYE_HEIGHT, 0.0);
+	assert!(los.visible);
+
+	let los = line_of_sight(&heightmap, [10.0, 10.0], [50.0, 10.0], DEFAULT_EYE_HEIGHT, 0.0);
+	assert!(!los.visible);
+	assert_eq!(los.obstruction.map(|[x, _]| x.round()), Some(32.0));
+
+	let viewshed = viewshed(&heightmap, [10, 10], DEFAULT_EYE_HEIGHT, 100);
+	assert_eq!(viewshed.origin, [0, 0]);
+	assert_eq!([viewshed.width, viewshed.height], [width, height]);
+	assert!(viewshed.is_visible(10, 10));
+	assert!(viewshed.is_visible(20, 50));
+	assert!(viewshed.is_visible(32, 10));
+	assert!(!viewshed.is_visible(50, 10));
+	assert!(!viewshed.is_visible(63, 63));
+
+	let viewshed = Arc::new(viewshed);
+	let mut cache = ViewshedCache::new(2);
+	cache.insert(viewshed.clone());
+	assert!(cache.get([10, 10], DEFAULT_EYE_HEIGHT, 100).is_some());
+	assert!(cache.get([10, 10], DEFAULT_EYE_HEIGHT, 50).is_none());
+}
@@ -132,11 +132,23 @@ settings! {
 		hardware_acceleration: AtomicBool = true,
 		github_star_modal: AtomicU8 = 0,
 		detect_markers: AtomicBool = true,
-		grayscale_map: AtomicBool = true
+		hough_lines: AtomicBool = false,
+		grayscale_map: AtomicBool = true,
+		contour_interval: AtomicU16 = 10,
+		contour_index_interval: AtomicU16 = 50,
+		heightmap_color_ramp: AtomicU8 = 0,
+		heightmap_shading: AtomicU8 = 0,
+		hillshade_azimuth: AtomicU16 = 315,
+		hillshade_altitude: AtomicU8 = 45,
+		heightmap_registration: AtomicBool = true,
+		heightmap_layer_grouping: AtomicU8 = 3,
+		heightmap_layer_sort: AtomicU8 = 0,
+		map_icons: AtomicU8 = 0
 	},
 
 	spinners => {
 		squad_dir: Option<Box<str>> = None,
-		squad_pak_aes: Option<Box<str>> = None
+		squad_pak_aes: Option<Box<str>> = None,
+		workshop_mods: std::collections::BTreeMap<u64, crate::squadex::mods::ModSettings> = Default::default()
 	}
 }
\ No newline at end of file
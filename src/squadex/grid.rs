@@ -0,0 +1,306 @@
+//! Squad's map grid, for turning map pixels into the grid references that squad leads call out (e.g. "C4 keypad 7-3")
+//!
+//! The grid starts at the top left of the minimap. Columns are lettered from A and rows numbered from 1, and each 300m
+//! grid square is divided into 3x3 keypads, numbered like a phone's numpad, which can be divided into 3x3 keypads again.
+
+use crate::*;
+use smh_heightmap_ripper::registration::HeightmapTransform;
+
+pub const GRID_SIZE: f64 = 300.0;
+
+/// Keypad, sub-keypad and so on; a fourth level is already under 4m across, which is more precise than anyone calls out
+pub const MAX_KEYPAD_DEPTH: usize = 4;
+
+/// Keypad and sub-keypad, which is how precise SLs usually are
+pub const DEFAULT_KEYPAD_DEPTH: usize = 2;
+
+/// Size of a grid square (depth 0) or keypad at the given depth, in meters
+#[inline]
+pub fn cell_size(depth: usize) -> f64 {
+	GRID_SIZE / 3.0_f64.powi(depth as i32)
+}
+
+/// Keypad number of the cell in the given column and row (from the top left) of a 3x3 subdivision
+#[inline]
+fn keypad([x, y]: [u8; 2]) -> u8 {
+	(2 - y) * 3 + x + 1
+}
+
+/// Column and row (from the top left) of a keypad number
+#[inline]
+fn keypad_cell(keypad: u8) -> [u8; 2] {
+	let i = keypad - 1;
+	[i % 3, 2 - i / 3]
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum GridRefError {
+	#[error("Enter a grid reference, e.g. C4-7-3")]
+	Empty,
+
+	#[error("Grid references start with the column's letter, e.g. the C in C4-7-3")]
+	MissingColumn,
+
+	#[error("Expected a row number after {0}, e.g. the 4 in C4-7-3")]
+	MissingRow(Box<str>),
+
+	#[error("Rows are numbered from 1")]
+	RowZero,
+
+	#[error("{0:?} is too far off the map to be a grid reference")]
+	OutOfRange(Box<str>),
+
+	#[error("{0:?} isn't a keypad, keypads are numbered 1 to 9 like a numpad")]
+	InvalidKeypad(Box<str>),
+
+	#[error("Too many keypads, at most {} levels are supported", MAX_KEYPAD_DEPTH)]
+	TooPrecise,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GridRef {
+	/// Zero based, so 0 is column A
+	pub column: u32,
+
+	/// Zero based, so 0 is row 1
+	pub row: u32,
+
+	keypads: [u8; MAX_KEYPAD_DEPTH],
+	depth: u8,
+}
+impl GridRef {
+	pub fn new(column: u32, row: u32, keypads: &[u8]) -> Option<Self> {
+		if keypads.len() > MAX_KEYPAD_DEPTH || keypads.iter().any(|keypad| !(1..=9).contains(keypad)) {
+			return None;
+		}
+
+		let mut grid_ref = Self {
+			column,
+			row,
+			keypads: [0; MAX_KEYPAD_DEPTH],
+			depth: keypads.len() as u8,
+		};
+		grid_ref.keypads[..keypads.len()].copy_from_slice(keypads);
+		Some(grid_ref)
+	}
+
+	/// Keypad, sub-keypad, ...
+	#[inline]
+	pub fn keypads(&self) -> &[u8] {
+		&self.keypads[..self.depth as usize]
+	}
+
+	/// Offset from the top left of the map to the center of the grid reference's smallest keypad, in meters
+	pub fn to_meters(&self) -> [f64; 2] {
+		let mut meters = [self.column as f64 * GRID_SIZE, self.row as f64 * GRID_SIZE];
+		for (depth, keypad) in self.keypads().iter().enumerate() {
+			let [x, y] = keypad_cell(*keypad);
+			let size = cell_size(depth + 1);
+			meters[0] += x as f64 * size;
+			meters[1] += y as f64 * size;
+		}
+
+		let half = cell_size(self.depth as usize) / 2.0;
+		[meters[0] + half, meters[1] + half]
+	}
+
+	/// The grid reference of an offset from the top left of the map, in meters, down to `depth` keypads
+	pub fn from_meters([x, y]: [f64; 2], depth: usize) -> Option<Self> {
+		if !(x >= 0.0 && y >= 0.0 && x.is_finite() && y.is_finite()) {
+			return None;
+		}
+
+		let depth = depth.min(MAX_KEYPAD_DEPTH);
+		let mut grid_ref = Self {
+			column: (x / GRID_SIZE) as u32,
+			row: (y / GRID_SIZE) as u32,
+			keypads: [0; MAX_KEYPAD_DEPTH],
+			depth: depth as u8,
+		};
+
+		let (mut x, mut y) = (x % GRID_SIZE, y % GRID_SIZE);
+		for level in 1..=depth {
+			let size = cell_size(level);
+			let (cx, cy) = (((x / size) as u8).min(2), ((y / size) as u8).min(2));
+			grid_ref.keypads[level - 1] = keypad([cx, cy]);
+			x -= cx as f64 * size;
+			y -= cy as f64 * size;
+		}
+
+		Some(grid_ref)
+	}
+}
+impl std::fmt::Display for GridRef {
+	/// Formats as e.g. `C4-7-3`; columns past Z continue as AA, AB, ...
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		fn write_column(f: &mut std::fmt::Formatter<'_>, column: u32) -> std::fmt::Result {
+			if column >= 26 {
+				write_column(f, column / 26 - 1)?;
+			}
+			write!(f, "{}", (b'A' + (column % 26) as u8) as char)
+		}
+
+		write_column(f, self.column)?;
+		write!(f, "{}", self.row + 1)?;
+		for keypad in self.keypads() {
+			write!(f, "-{keypad}")?;
+		}
+		Ok(())
+	}
+}
+impl core::str::FromStr for GridRef {
+	type Err = GridRefError;
+
+	/// Parses grid references the way they're written or called out, e.g. `C4-7-3`, `c4 kp 7 3`, `C4 keypad 73` or `D11-1-9-5`
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		if s.is_empty() {
+			return Err(GridRefError::Empty);
+		}
+
+		let letters_end = s.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(s.len());
+		let (letters, rest) = s.split_at(letters_end);
+		if letters.is_empty() {
+			return Err(GridRefError::MissingColumn);
+		}
+
+		let mut column = 0u32;
+		for letter in letters.bytes() {
+			column = column
+				.checked_mul(26)
+				.and_then(|column| column.checked_add((letter.to_ascii_uppercase() - b'A') as u32 + 1))
+				.ok_or_else(|| GridRefError::OutOfRange(s.into()))?;
+		}
+		let column = column - 1;
+
+		let rest = rest.trim_start();
+		let digits_end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+		let (digits, rest) = rest.split_at(digits_end);
+		if digits.is_empty() {
+			return Err(GridRefError::MissingRow(letters.to_ascii_uppercase().into()));
+		}
+
+		let row = digits.parse::<u32>().map_err(|_| GridRefError::OutOfRange(s.into()))?;
+		let row = row.checked_sub(1).ok_or(GridRefError::RowZero)?;
+
+		let mut keypads = SmallVec::<u8, MAX_KEYPAD_DEPTH>::new();
+		for token in rest.split(|c: char| c.is_whitespace() || matches!(c, '-' | ',' | '.' | '/')) {
+			if token.is_empty() || token.eq_ignore_ascii_case("kp") || token.eq_ignore_ascii_case("keypad") {
+				continue;
+			}
+
+			// "73" is keypad 7, sub-keypad 3
+			for c in token.chars() {
+				let keypad = match c.to_digit(10) {
+					Some(keypad @ 1..=9) => keypad as u8,
+					_ => return Err(GridRefError::InvalidKeypad(token.into())),
+				};
+				if keypads.is_full() {
+					return Err(GridRefError::TooPrecise);
+				}
+				keypads.push(keypad);
+			}
+		}
+
+		Ok(Self::new(column, row, &keypads).sus_unwrap())
+	}
+}
+
+/// Maps map pixels onto the grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Grid {
+	/// The map pixel at the top left of the grid
+	pub origin: [f64; 2],
+
+	/// Meters per map pixel along each axis
+	pub meters_per_px: [f64; 2],
+}
+impl Grid {
+	/// The heightmap is 1 texel per meter, so its registration on the minimap is the most accurate scale we have
+	///
+	/// `minimap_bounds` are the top left and bottom right of the minimap in map pixels.
+	pub fn from_heightmap(transform: &HeightmapTransform, [[left, top], [right, bottom]]: [[f64; 2]; 2]) -> Self {
+		let [x0, y0] = transform.to_heightmap([0.0, 0.0]);
+		let [x1, y1] = transform.to_heightmap([1.0, 1.0]);
+		Self {
+			origin: [left, top],
+			meters_per_px: [(x1 - x0) / (right - left), (y1 - y0) / (bottom - top)],
+		}
+	}
+
+	/// Falls back on the scale read off of the map's scale bars
+	pub fn from_minimap([origin, _]: [[f64; 2]; 2], meters_to_px_ratio: f64) -> Self {
+		Self {
+			origin,
+			meters_per_px: [meters_to_px_ratio, meters_to_px_ratio],
+		}
+	}
+
+	#[inline]
+	pub fn px_to_meters(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+		[(x - self.origin[0]) * self.meters_per_px[0], (y - self.origin[1]) * self.meters_per_px[1]]
+	}
+
+	#[inline]
+	pub fn meters_to_px(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+		[self.origin[0] + x / self.meters_per_px[0], self.origin[1] + y / self.meters_per_px[1]]
+	}
+
+	#[inline]
+	pub fn grid_ref(&self, px: [f64; 2], depth: usize) -> Option<GridRef> {
+		GridRef::from_meters(self.px_to_meters(px), depth)
+	}
+
+	/// The map pixel at the center of a grid reference's smallest keypad
+	#[inline]
+	pub fn grid_ref_to_px(&self, grid_ref: &GridRef) -> [f64; 2] {
+		self.meters_to_px(grid_ref.to_meters())
+	}
+}
+
+#[test]
+fn test_grid_ref() {
+	let grid_ref = GridRef::from_meters([2.0 * GRID_SIZE + 10.0, 3.0 * GRID_SIZE + 290.0], DEFAULT_KEYPAD_DEPTH).unwrap();
+	assert_eq!(grid_ref, GridRef::new(2, 3, &[1, 1]).unwrap());
+	assert_eq!(grid_ref.to_string(), "C4-1-1");
+
+	let grid_ref = GridRef::from_meters([150.0, 10.0], DEFAULT_KEYPAD_DEPTH).unwrap();
+	assert_eq!(grid_ref.to_string(), "A1-8-8");
+	assert_eq!(GridRef::from_meters(grid_ref.to_meters(), DEFAULT_KEYPAD_DEPTH), Some(grid_ref));
+
+	assert_eq!(GridRef::new(26, 0, &[5, 5]).unwrap().to_string(), "AA1-5-5");
+	assert_eq!(GridRef::from_meters([-1.0, 0.0], DEFAULT_KEYPAD_DEPTH), None);
+	assert_eq!(GridRef::from_meters([450.0, 150.0], 0).unwrap().to_meters(), [450.0, 150.0]);
+
+	let grid = Grid::from_minimap([[100.0, 50.0], [1100.0, 1050.0]], 4.0);
+	let grid_ref = grid.grid_ref([100.0 + 350.0 / 4.0, 50.0 + 950.0 / 4.0], DEFAULT_KEYPAD_DEPTH).unwrap();
+	assert_eq!(grid_ref.to_string(), "B4-7-5");
+
+	let [x, y] = grid.grid_ref_to_px(&grid_ref);
+	assert_eq!(grid.grid_ref([x, y], DEFAULT_KEYPAD_DEPTH), Some(grid_ref));
+}
+
+#[test]
+fn test_parse_grid_ref() {
+	for (s, expected) in [
+		("C4-7-3", "C4-7-3"),
+		("c4 kp 7 3", "C4-7-3"),
+		("C4 keypad 73", "C4-7-3"),
+		(" D11-1-9-5 ", "D11-1-9-5"),
+		("AA2", "AA2"),
+		("b 12 - 5", "B12-5"),
+	] {
+		assert_eq!(s.parse::<GridRef>().map(|grid_ref| grid_ref.to_string()).as_deref(), Ok(expected), "{s}");
+	}
+
+	assert_eq!("".parse::<GridRef>(), Err(GridRefError::Empty));
+	assert_eq!("4-7-3".parse::<GridRef>(), Err(GridRefError::MissingColumn));
+	assert_eq!("C-7-3".parse::<GridRef>(), Err(GridRefError::MissingRow("C".into())));
+	assert_eq!("C0-7".parse::<GridRef>(), Err(GridRefError::RowZero));
+	assert_eq!("C4-0".parse::<GridRef>(), Err(GridRefError::InvalidKeypad("0".into())));
+	assert_eq!("C4-7x".parse::<GridRef>(), Err(GridRefError::InvalidKeypad("7x".into())));
+	assert_eq!("C4-1-2-3-4-5".parse::<GridRef>(), Err(GridRefError::TooPrecise));
+
+	let grid_ref = "B2-5-5".parse::<GridRef>().unwrap();
+	assert_eq!(grid_ref.to_meters(), [450.0, 450.0]);
+}
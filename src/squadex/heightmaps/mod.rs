@@ -2,8 +2,12 @@ use crate::*;
 use smh_heightmap_ripper::Heightmap;
 use atomic_refcell::AtomicRef;
 
-mod serde;
-pub use self::serde::{deserialize, serialize};
+pub use smh_heightmap_ripper::{
+	bundle,
+	cache::{cache_path, cached_layers, cached_size, deserialize, read_size, serialize},
+};
+
+pub mod registration;
 
 static ACTIVE_HEIGHTMAP: SpinCell<Option<Heightmap>> = SpinCell::new(None);
 
@@ -0,0 +1,33 @@
+//! Cache of heightmap to minimap registrations, keyed by layer and minimap resolution
+
+use crate::*;
+use smh_heightmap_ripper::registration::Registration;
+use std::{collections::BTreeMap, fs::File};
+
+const REGISTRATION_CACHE_PATH: &str = "heightmaps/registration.json";
+
+fn cache_key(layer: &str, [width, height]: [u32; 2]) -> String {
+	format!("{layer}@{width}x{height}")
+}
+
+fn read_cache() -> BTreeMap<String, Registration> {
+	File::open(REGISTRATION_CACHE_PATH)
+		.ok()
+		.and_then(|f| serde_json::from_reader(f).ok())
+		.unwrap_or_default()
+}
+
+pub fn load(layer: &str, resolution: [u32; 2]) -> Option<Registration> {
+	read_cache().remove(&cache_key(layer, resolution))
+}
+
+pub fn store(layer: &str, resolution: [u32; 2], registration: Registration) {
+	let mut cache = read_cache();
+	cache.insert(cache_key(layer, resolution), registration);
+
+	if let Err(err) = std::fs::create_dir_all("heightmaps").and_then(|_| {
+		std::fs::write(REGISTRATION_CACHE_PATH, serde_json::to_string_pretty(&cache).map_err(std::io::Error::from)?)
+	}) {
+		log::warn!("Error writing heightmap registration cache: {err}");
+	}
+}
@@ -1,4 +1,7 @@
 pub mod window;
 pub mod milliradians;
+pub mod grid;
 pub mod heightmaps;
+pub mod mods;
+pub mod offline;
 pub mod capture;
\ No newline at end of file
@@ -0,0 +1,234 @@
+//! Discovery of Steam Workshop mods installed for Squad
+
+use crate::*;
+use std::ffi::OsStr;
+
+#[derive(Clone, Debug)]
+pub struct WorkshopMod {
+	/// Workshop item ID
+	pub id: u64,
+
+	/// Friendly name from the mod's `.uplugin`, or its ID if it doesn't have one
+	pub name: Box<str>,
+
+	/// Names of the plugins (i.e. `SquadGame/Plugins/Mods/<plugin>`) that the mod contains
+	pub plugins: Box<[Box<str>]>,
+
+	/// `WindowsNoEditor` directories containing the mod's paks
+	pub paks: Box<[Box<str>]>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ModSettings {
+	#[serde(default)]
+	pub disabled: bool,
+
+	/// Overrides the AES key used for this mod's paks
+	#[serde(default)]
+	pub aes_key: Option<Box<str>>,
+}
+
+/// A set of paks that can be read with the same AES key, as the ripper only accepts one key per invocation
+#[derive(Clone, Debug)]
+pub struct PakGroup {
+	pub aes_key: Box<str>,
+	pub paks: Box<[Box<str>]>,
+	pub workshop_ids: Box<[u64]>,
+}
+
+fn workshop_dir(squad_dir: &str) -> Option<PathBuf> {
+	Path::new(squad_dir)
+		.parent()
+		.and_then(|p| p.parent())
+		.map(|p| p.join(format!("workshop/content/{}", smh_heightmap_ripper::SQUAD_APP_ID)))
+		.filter(|p| p.is_dir())
+}
+
+fn read_friendly_name(uplugin: &Path) -> Option<Box<str>> {
+	let uplugin = std::fs::read_to_string(uplugin).ok()?;
+	let uplugin: serde_json::Value = serde_json::from_str(uplugin.trim_start_matches('\u{feff}')).ok()?;
+	uplugin
+		.get("FriendlyName")
+		.and_then(|name| name.as_str())
+		.map(str::trim)
+		.filter(|name| !name.is_empty())
+		.map(Box::from)
+}
+
+fn read_workshop_mod(id: u64, dir: &Path) -> Option<WorkshopMod> {
+	let mut paks = BTreeSet::new();
+	let mut plugins = BTreeSet::new();
+	let mut name = None;
+
+	for entry in walkdir::WalkDir::new(dir) {
+		let entry = match entry {
+			Ok(entry) => entry,
+			Err(err) => {
+				log::warn!("Error reading workshop directory: {err}");
+				continue;
+			}
+		};
+
+		if !entry.file_type().is_file() {
+			continue;
+		}
+
+		match entry.path().extension().and_then(OsStr::to_str) {
+			Some("pak") => {
+				if let Some(parent) = entry.path().parent().filter(|parent| parent.file_name() == Some(OsStr::new("WindowsNoEditor"))) {
+					paks.insert(Box::from(parent.to_string_lossy().as_ref()));
+				}
+			}
+
+			Some("uplugin") => {
+				if let Some(plugin) = entry.path().file_stem() {
+					plugins.insert(Box::from(plugin.to_string_lossy().as_ref()));
+				}
+				if name.is_none() {
+					name = read_friendly_name(entry.path());
+				}
+			}
+
+			_ => {}
+		}
+	}
+
+	if paks.is_empty() {
+		return None;
+	}
+
+	Some(WorkshopMod {
+		id,
+		name: name.unwrap_or_else(|| id.to_string().into_boxed_str()),
+		plugins: Vec::from_iter(plugins).into_boxed_slice(),
+		paks: Vec::from_iter(paks).into_boxed_slice(),
+	})
+}
+
+/// Finds every workshop mod with paks in the Steam library that Squad is installed in
+pub fn discover(squad_dir: &str) -> Box<[WorkshopMod]> {
+	let workshop_dir = match workshop_dir(squad_dir) {
+		Some(workshop_dir) => workshop_dir,
+		None => return Default::default(),
+	};
+
+	let entries = match std::fs::read_dir(&workshop_dir) {
+		Ok(entries) => entries,
+		Err(err) => {
+			log::warn!("Error reading workshop directory: {err}");
+			return Default::default();
+		}
+	};
+
+	let mut mods = entries
+		.filter_map(Result::ok)
+		.filter(|entry| entry.file_type().map(|ty| ty.is_dir()).unwrap_or(false))
+		.filter_map(|entry| {
+			let id = entry.file_name().to_str()?.parse::<u64>().ok()?;
+			read_workshop_mod(id, &entry.path())
+		})
+		.collect::<Vec<_>>();
+
+	mods.sort_by(|a, b| a.name.to_ascii_lowercase().cmp(&b.name.to_ascii_lowercase()).then(a.id.cmp(&b.id)));
+
+	log::info!("Discovered {} workshop mods", mods.len());
+
+	mods.into_boxed_slice()
+}
+
+/// Groups the paks of the base game and every enabled mod by AES key
+///
+/// The first group always uses the default AES key and contains the base game's paks. The base game's paks can't be read with a mod's
+/// custom key, so they are left out of every other group.
+pub fn pak_groups(mods: &[WorkshopMod], settings: &BTreeMap<u64, ModSettings>, main_paks: &str, default_aes_key: &str) -> Box<[PakGroup]> {
+	// (AES key, paks, workshop IDs)
+	type Group = (Box<str>, Vec<Box<str>>, Vec<u64>);
+	let mut groups: Vec<Group> = vec![(default_aes_key.into(), Vec::new(), Vec::new())];
+
+	for workshop_mod in mods {
+		let mod_settings = settings.get(&workshop_mod.id);
+		if mod_settings.map(|mod_settings| mod_settings.disabled).unwrap_or(false) {
+			continue;
+		}
+
+		let aes_key = mod_settings
+			.and_then(|mod_settings| mod_settings.aes_key.as_deref())
+			.map(str::trim)
+			.filter(|aes_key| !aes_key.is_empty())
+			.unwrap_or(default_aes_key);
+
+		let group = match groups.iter().position(|(group_aes_key, ..)| &**group_aes_key == aes_key) {
+			Some(group) => group,
+			None => {
+				groups.push((aes_key.into(), Vec::new(), Vec::new()));
+				groups.len() - 1
+			}
+		};
+
+		groups[group].1.extend(workshop_mod.paks.iter().cloned());
+		groups[group].2.push(workshop_mod.id);
+	}
+
+	groups
+		.into_iter()
+		.enumerate()
+		.map(|(i, (aes_key, mut paks, workshop_ids))| {
+			// Mods will probably reference assets in the base game
+			if i == 0 {
+				paks.push(main_paks.into());
+			}
+			PakGroup {
+				aes_key,
+				paks: paks.into_boxed_slice(),
+				workshop_ids: workshop_ids.into_boxed_slice(),
+			}
+		})
+		.collect()
+}
+
+/// Finds the workshop mod that contains a plugin
+pub fn find_by_plugin<'a>(mods: &'a [WorkshopMod], plugin: &str) -> Option<&'a WorkshopMod> {
+	mods.iter().find(|workshop_mod| workshop_mod.plugins.iter().any(|candidate| candidate.eq_ignore_ascii_case(plugin)))
+}
+
+#[test]
+fn test_pak_groups() {
+	let workshop_mod = |id: u64| WorkshopMod {
+		id,
+		name: id.to_string().into_boxed_str(),
+		plugins: Box::new([]),
+		paks: Box::new([format!("workshop/{id}/WindowsNoEditor").into_boxed_str()]),
+	};
+	let mods = [workshop_mod(1), workshop_mod(2), workshop_mod(3), workshop_mod(4), workshop_mod(5)];
+
+	let settings = BTreeMap::from_iter([
+		// Disabled, even though it has a key of its own
+		(2, ModSettings { disabled: true, aes_key: Some("0xCUSTOM".into()) }),
+		// Sharing a custom key, once with whitespace around it
+		(3, ModSettings { disabled: false, aes_key: Some("0xCUSTOM".into()) }),
+		(4, ModSettings { disabled: false, aes_key: Some(" 0xCUSTOM ".into()) }),
+		// A blank key is the default key
+		(5, ModSettings { disabled: false, aes_key: Some("  ".into()) }),
+	]);
+
+	let groups = pak_groups(&mods, &settings, "SquadGame/Content/Paks", "0xDEFAULT");
+	assert_eq!(groups.len(), 2, "{groups:?}");
+
+	assert_eq!(&*groups[0].aes_key, "0xDEFAULT");
+	assert_eq!(&*groups[0].workshop_ids, [1, 5]);
+	assert_eq!(
+		&*groups[0].paks,
+		["workshop/1/WindowsNoEditor".into(), "workshop/5/WindowsNoEditor".into(), "SquadGame/Content/Paks".into()] as [Box<str>; 3]
+	);
+
+	// The base game's paks can't be read with a custom key
+	assert_eq!(&*groups[1].aes_key, "0xCUSTOM");
+	assert_eq!(&*groups[1].workshop_ids, [3, 4]);
+	assert_eq!(&*groups[1].paks, ["workshop/3/WindowsNoEditor".into(), "workshop/4/WindowsNoEditor".into()] as [Box<str>; 2]);
+
+	// Without any mods there's still a group for the base game
+	let groups = pak_groups(&[], &BTreeMap::new(), "SquadGame/Content/Paks", "0xDEFAULT");
+	assert_eq!(groups.len(), 1);
+	assert_eq!(&*groups[0].paks, ["SquadGame/Content/Paks".into()] as [Box<str>; 1]);
+	assert!(groups[0].workshop_ids.is_empty());
+}
@@ -0,0 +1,62 @@
+//! Offline planning, where the map comes from the layer's ripped minimap texture instead of the screen
+
+use crate::*;
+
+#[derive(Debug)]
+pub struct OfflineMap {
+	pub layer: Box<str>,
+	pub map: Arc<image::RgbaImage>,
+
+	/// Assumes the minimap texture covers the same area as the heightmap
+	pub meters_to_px_ratio: Option<f64>,
+}
+
+static OFFLINE_MAP: SpinCell<Option<Arc<OfflineMap>>> = SpinCell::new(None);
+
+#[inline]
+pub fn is_set() -> bool {
+	OFFLINE_MAP.read().is_some()
+}
+
+#[inline]
+pub fn get() -> Option<Arc<OfflineMap>> {
+	OFFLINE_MAP.read().clone()
+}
+
+/// Switches the vision thread between offline planning and screen capture
+pub fn set(offline_map: Option<OfflineMap>) {
+	*OFFLINE_MAP.write() = offline_map.map(Arc::new);
+}
+
+/// Where the minimap texture for a layer is cached
+pub fn cache_path(layer: &str) -> PathBuf {
+	let mut cache_path = Path::new("heightmaps").join(layer);
+	cache_path.set_extension("minimap.png");
+	cache_path
+}
+
+/// Loads the minimap texture for a layer from the cache, ripping it if it hasn't been cached yet
+pub fn load_minimap(paks: &[Box<str>], aes_key: &str, layer: &str) -> Result<Option<image::RgbaImage>, smh_heightmap_ripper::Error> {
+	let cache_path = cache_path(layer);
+
+	if cache_path.is_file() {
+		match image::open(&cache_path) {
+			Ok(minimap) => return Ok(Some(minimap.into_rgba8())),
+			Err(err) => log::warn!("Error opening cached minimap: {err}"),
+		}
+	}
+
+	let minimap = smh_heightmap_ripper::get_minimap(paks.iter().map(|pak| &**pak), Some(aes_key), layer);
+	match minimap {
+		Ok(Some(ref minimap)) => {
+			if let Err(err) = std::fs::create_dir_all(cache_path.parent().unwrap()).map_err(image::ImageError::from).and_then(|_| minimap.save(&cache_path)) {
+				std::fs::remove_file(&cache_path).ok();
+				log::warn!("Error writing minimap to disk: {err}");
+			}
+		}
+		Err(ref err) => log::warn!("Error extracting minimap for {layer}: {err}"),
+		Ok(None) => {}
+	}
+
+	minimap
+}
@@ -0,0 +1,231 @@
+use super::*;
+use squadex::heightmaps::bundle::{self, BundleError, ImportReport, BUNDLE_EXTENSION};
+
+const BUNDLES_DIR: &str = "heightmaps/bundles";
+
+type ExportBundleResult = Result<(PathBuf, usize), BundleError>;
+struct ExportBundleOp {
+	layers: Vec<Box<str>>,
+}
+impl ExportBundleOp {
+	fn export_bundle(self) -> ExportBundleResult {
+		std::fs::create_dir_all(BUNDLES_DIR)?;
+
+		let mut path = Path::new(BUNDLES_DIR).join(format!("heightmaps-{}", chrono::Local::now().format("%Y-%m-%d-%H%M%S")));
+		path.set_extension(BUNDLE_EXTENSION);
+
+		let manifest = bundle::export(&mut std::io::BufWriter::new(File::create(&path)?), &self.layers);
+		if manifest.is_err() {
+			std::fs::remove_file(&path).ok();
+		}
+
+		Ok((path, manifest?.heightmaps.len()))
+	}
+}
+
+type ImportBundleResult = Result<ImportReport, BundleError>;
+struct ImportBundleOp {
+	path: Box<str>,
+}
+impl ImportBundleOp {
+	fn import_bundle(self) -> ImportBundleResult {
+		let result = File::open(&*self.path).map_err(Into::into).and_then(|f| bundle::import(&mut std::io::BufReader::new(f)));
+		match result {
+			Ok(ref report) => log::info!(
+				"Imported heightmap bundle {}: {} imported, {} unchanged, {} failed",
+				self.path,
+				report.imported.len(),
+				report.unchanged.len(),
+				report.failed.len()
+			),
+			Err(ref err) => log::warn!("Error importing heightmap bundle {}: {err}", self.path),
+		}
+		result
+	}
+}
+
+fn find_bundles() -> Vec<Box<str>> {
+	let mut bundles = std::fs::read_dir(BUNDLES_DIR)
+		.into_iter()
+		.flatten()
+		.filter_map(Result::ok)
+		.map(|entry| entry.path())
+		.filter(|path| path.extension().map(|ext| ext == BUNDLE_EXTENSION).unwrap_or(false))
+		.map(|path| path.to_string_lossy().into_owned().into_boxed_str())
+		.collect::<Vec<_>>();
+
+	bundles.sort_unstable();
+	bundles
+}
+
+pub struct BundlesState {
+	pub window_open: bool,
+
+	cached_layers: Option<Vec<(Box<str>, bool)>>,
+	bundles: Option<Vec<Box<str>>>,
+	import_path: String,
+
+	export: ImCell<ExportBundleOp, ExportBundleResult>,
+	import: ImCell<ImportBundleOp, ImportBundleResult>,
+	imported: bool,
+}
+impl Default for BundlesState {
+	fn default() -> Self {
+		Self {
+			window_open: false,
+
+			cached_layers: None,
+			bundles: None,
+			import_path: String::new(),
+
+			export: ImCell::new(ExportBundleOp::export_bundle, Some(ui::redraw)),
+			import: ImCell::new(ImportBundleOp::import_bundle, Some(ui::redraw)),
+			imported: false,
+		}
+	}
+}
+
+fn render_import_report(ui: &Ui, report: &ImportReport) {
+	ui.text_wrapped(format!(
+		"Imported {} heightmaps ({} unchanged, {} failed)",
+		report.imported.len(),
+		report.unchanged.len(),
+		report.failed.len()
+	));
+
+	if !report.failed.is_empty() {
+		let color = ui.push_style_color(imgui::StyleColor::Text, [1.0, 0.0, 0.0, 1.0]);
+		for (layer, err) in &report.failed {
+			ui.text_wrapped(format!("{layer}: {err}"));
+		}
+		color.end();
+	}
+}
+
+pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
+	if !state.bundles.window_open {
+		state.bundles.cached_layers = None;
+		state.bundles.bundles = None;
+		return;
+	}
+
+	let window = match imgui::Window::new("Heightmap Bundles")
+		.collapsible(false)
+		.size([325.0, 450.0], imgui::Condition::FirstUseEver)
+		.opened(&mut state.bundles.window_open)
+		.begin(ui)
+	{
+		Some(window) => window,
+		None => {
+			state.bundles.window_open = false;
+			return;
+		}
+	};
+
+	// Once an import has finished, pick up the new heightmaps
+	if state.bundles.imported && !matches!(state.bundles.import.get(), ImCellStateRef::Loading) {
+		state.bundles.imported = false;
+		state.bundles.cached_layers = None;
+		state.heightmaps.reload_layers();
+	}
+
+	let cached_layers = state
+		.bundles
+		.cached_layers
+		.get_or_insert_with(|| squadex::heightmaps::cached_layers().into_iter().map(|layer| (layer, false)).collect());
+
+	if imgui::CollapsingHeader::new("Export").default_open(true).build(ui) {
+		ui.text_wrapped("Bundle heightmaps you've already generated so that they can be shared with people who can't run the ripper.");
+		ui.spacing();
+
+		if cached_layers.is_empty() {
+			ui.text_wrapped("No heightmaps have been generated yet!");
+		} else {
+			if ui.small_button("Select All") {
+				cached_layers.iter_mut().for_each(|(_, selected)| *selected = true);
+			}
+			ui.same_line();
+			if ui.small_button("Select None") {
+				cached_layers.iter_mut().for_each(|(_, selected)| *selected = false);
+			}
+
+			if let Some(list) = imgui::ChildWindow::new("CachedLayers").size([0.0, 150.0]).border(true).begin(ui) {
+				for (layer, selected) in cached_layers.iter_mut() {
+					let label = layer.strip_prefix("SquadGame/Content/Maps/").unwrap_or(layer);
+					let label = label.strip_prefix("SquadGame/Plugins/").unwrap_or(label);
+					ui.checkbox(label, selected);
+				}
+				list.end();
+			}
+
+			let selected = cached_layers.iter().filter(|(_, selected)| *selected).count();
+			let exporting = matches!(state.bundles.export.get(), ImCellStateRef::Loading);
+
+			if ui.button_with_size(format!("Export {selected} Heightmaps"), [-1.0, 0.0]) && selected > 0 && !exporting {
+				state.bundles.export.load(ExportBundleOp {
+					layers: cached_layers.iter().filter(|(_, selected)| *selected).map(|(layer, _)| layer.clone()).collect(),
+				});
+			}
+
+			match state.bundles.export.get() {
+				ImCellStateRef::Loading => ui.text_wrapped("Exporting..."),
+				ImCellStateRef::Initialized(result) => match &*result {
+					Ok((path, count)) => {
+						ui.text_wrapped(format!("Exported {count} heightmaps to {}", path.display()));
+						if ui.small_button("Open Folder") {
+							if let Ok(path) = Path::new(BUNDLES_DIR).canonicalize() {
+								open::that(path).ok();
+							}
+						}
+					}
+					Err(err) => {
+						let color = ui.push_style_color(imgui::StyleColor::Text, [1.0, 0.0, 0.0, 1.0]);
+						ui.text_wrapped(format!("Error: {err}"));
+						color.end();
+					}
+				},
+				ImCellStateRef::None => {}
+			}
+		}
+	}
+
+	if imgui::CollapsingHeader::new("Import").default_open(true).build(ui) {
+		ui.text_wrapped(format!("Checksums are verified before heightmaps are merged into your cache. Bundles placed in {BUNDLES_DIR} are listed below."));
+		ui.spacing();
+
+		let bundles = state.bundles.bundles.get_or_insert_with(find_bundles);
+		for bundle in bundles.iter() {
+			if imgui::Selectable::new(Path::new(&**bundle).file_name().map(|name| name.to_string_lossy()).unwrap_or_default()).build(ui) {
+				state.bundles.import_path = bundle.to_string();
+			}
+		}
+
+		ui.set_next_item_width(-1.0);
+		ui.input_text("##BundlePath", &mut state.bundles.import_path)
+			.hint(format!("Path to .{BUNDLE_EXTENSION} file"))
+			.build();
+
+		let importing = matches!(state.bundles.import.get(), ImCellStateRef::Loading);
+		if ui.button_with_size("Import", [-1.0, 0.0]) && !importing && !state.bundles.import_path.trim().is_empty() {
+			state.bundles.imported = true;
+			state.bundles.import.load(ImportBundleOp {
+				path: Box::from(state.bundles.import_path.trim()),
+			});
+		}
+
+		match state.bundles.import.get() {
+			ImCellStateRef::Loading => ui.text_wrapped("Importing..."),
+			ImCellStateRef::Initialized(result) => match &*result {
+				Ok(report) => render_import_report(ui, report),
+				Err(err) => {
+					let color = ui.push_style_color(imgui::StyleColor::Text, [1.0, 0.0, 0.0, 1.0]);
+					ui.text_wrapped(format!("Error: {err}"));
+					color.end();
+				}
+			},
+			ImCellStateRef::None => {}
+		}
+	}
+
+	window.end();
+}
@@ -7,11 +7,13 @@ pub static SYNCED_DEBUG_STATE: SyncedDebugState = SyncedDebugState {
 	debug_view: AtomicU8::new(DebugView::None as u8),
 	ocr_overlay: AtomicBool::new(false),
 	scales_overlay: AtomicBool::new(false),
+	circles_overlay: AtomicBool::new(false),
 };
 pub struct SyncedDebugState {
 	pub debug_view: AtomicU8,
 	pub ocr_overlay: AtomicBool,
 	pub scales_overlay: AtomicBool,
+	pub circles_overlay: AtomicBool,
 }
 impl SyncedDebugState {
 	pub fn debug_view(&self) -> DebugView {
@@ -37,6 +39,14 @@ impl SyncedDebugState {
 	pub fn set_scales_overlay(&self, scales_overlay: bool) {
 		self.scales_overlay.store(scales_overlay, std::sync::atomic::Ordering::Release);
 	}
+
+	pub fn circles_overlay(&self) -> bool {
+		self.circles_overlay.load(std::sync::atomic::Ordering::Relaxed)
+	}
+
+	pub fn set_circles_overlay(&self, circles_overlay: bool) {
+		self.circles_overlay.store(circles_overlay, std::sync::atomic::Ordering::Release);
+	}
 }
 
 lazy_static! {
@@ -98,6 +108,7 @@ pub struct DebugBox {
 	pub timeshares: Timeshares,
 	pub ocr: Vec<smh_vision_ocr::OCRText>,
 	pub scales: SmallVec<(u32, Line<u32>), 3>,
+	pub circles: Vec<lsd::Circle>,
 	pub debug_view: Option<Arc<image::RgbaImage>>,
 }
 
@@ -125,6 +136,14 @@ pub(super) fn menu_bar(ui: &Ui, state: &mut UiState) {
 		SYNCED_DEBUG_STATE.set_scales_overlay(!scales_overlay);
 	}
 
+	let circles_overlay = SYNCED_DEBUG_STATE.circles_overlay();
+	if imgui::MenuItem::new("Show Detected Circles").selected(circles_overlay).build(ui) {
+		SYNCED_DEBUG_STATE.set_circles_overlay(!circles_overlay);
+	}
+	if ui.is_item_hovered() {
+		ui.tooltip_text("Range rings and other circles that were found on the map and ignored when looking for markers");
+	}
+
 	if imgui::MenuItem::new("Show Minimap Bounds")
 		.selected(state.debug.minimap_bounds_overlay)
 		.build(ui)
@@ -316,11 +335,23 @@ pub(super) fn render(state: &mut UiState, ui: &Ui) {
 		}
 	}
 
+	if SYNCED_DEBUG_STATE.circles_overlay() {
+		let draw_list = ui.get_foreground_draw_list();
+
+		for circle in state.vision.debug.circles.iter() {
+			let centre = state.map.viewport.translate_xy(circle.centre.into());
+			let radius = circle.radius * state.map.viewport.scale_factor_w;
+
+			draw_list.add_circle(centre, radius, [0.0, 1.0, 1.0]).thickness(2.0).build();
+			draw_list.add_text(centre, [0.0, 1.0, 1.0], ui_format!(state, "r={:.0}px", circle.radius));
+		}
+	}
+
 	if state.debug.minimap_bounds_overlay {
-		if let Some(minimap_viewport) = state.vision.minimap_bounds {
+		if let Some([top_left, bottom_right]) = state.vision.minimap_px() {
 			let [mut p0, mut p1] = [
-				state.map.viewport.translate_xy(minimap_viewport.top_left().map(f32::lossy_from)),
-				state.map.viewport.translate_xy(minimap_viewport.bottom_right().map(f32::lossy_from)),
+				state.map.viewport.translate_xy(top_left.map(|xy| xy as f32)),
+				state.map.viewport.translate_xy(bottom_right.map(|xy| xy as f32)),
 			];
 
 			// seems to be drawn 1 pixel off
@@ -8,7 +8,23 @@ pub struct DrawState {
 	pub custom_markers: Vec<[[f32; 2]; 2]>,
 	measure_start: Option<[f32; 2]>,
 	drag_start: Option<[f32; 2]>,
-	drag_debounce: bool
+	drag_debounce: bool,
+
+	grid_gun: String,
+	grid_target: String,
+	grid_error: Option<String>,
+
+	/// Markers start from the player's own icon, so only the target needs to be clicked or entered
+	gun_at_player: bool
+}
+
+/// Where the gun is if it's at the player's position, as detected on the map
+fn player_gun(state: &UiState) -> Option<[f32; 2]> {
+	if !state.draw.gun_at_player {
+		return None;
+	}
+
+	state.vision.player.map(|player| [player.position.x, player.position.y])
 }
 
 pub(super) fn delete_marker(state: &mut UiState, i: usize) {
@@ -19,7 +35,7 @@ pub(super) fn delete_marker(state: &mut UiState, i: usize) {
 	state.draw.custom_markers.remove(i);
 
 	if let Some(web) = &state.web.server {
-		web.send(smh_web::Event::Markers { markers: Box::from(&*state.draw.custom_markers), custom: true });
+		web.send(smh_web::Event::Markers { markers: Box::from(&*state.draw.custom_markers), fireteams: Box::default(), commands: Box::default(), ids: Box::default(), custom: true });
 	}
 }
 
@@ -27,7 +43,88 @@ pub(super) fn add_marker(state: &mut UiState, p0: [f32; 2], p1: [f32; 2]) {
 	state.draw.custom_markers.push([p0, p1]);
 
 	if let Some(web) = &state.web.server {
-		web.send(smh_web::Event::Markers { markers: Box::from(&*state.draw.custom_markers), custom: true });
+		web.send(smh_web::Event::Markers { markers: Box::from(&*state.draw.custom_markers), fireteams: Box::default(), commands: Box::default(), ids: Box::default(), custom: true });
+	}
+}
+
+/// Places a marker from the gun to the target, e.g. when the SL calls out a grid on comms without marking it
+pub(super) fn add_grid_marker(state: &mut UiState, gun: &str, target: &str) -> Result<(), AnyError> {
+	let grid = markers::grid(state).ok_or_else(|| anyhow::anyhow!("The map's scale isn't known yet, so grid references can't be placed"))?;
+	let [[left, top], [right, bottom]] = state.vision.minimap_px().sus_unwrap();
+
+	let place = |name: &str, grid_ref: &str| -> Result<[f32; 2], AnyError> {
+		let grid_ref = grid_ref.parse::<squadex::grid::GridRef>().map_err(|err| anyhow::anyhow!("{name}: {err}"))?;
+
+		let [x, y] = grid.grid_ref_to_px(&grid_ref);
+		if x < left || y < top || x > right || y > bottom {
+			anyhow::bail!("{name}: {grid_ref} is off the map");
+		}
+
+		Ok([x as f32, y as f32])
+	};
+
+	let gun = match player_gun(state) {
+		Some(player) if gun.trim().is_empty() => player,
+		_ => place("Gun", gun)?,
+	};
+	let target = place("Target", target)?;
+	add_marker(state, gun, target);
+
+	Ok(())
+}
+
+pub(super) fn menu_bar(state: &mut UiState, ui: &Ui) {
+	if let Some(menu) = ui.begin_menu("Markers") {
+		ui.text_disabled("Place by grid reference");
+		if ui.is_item_hovered() {
+			ui.tooltip_text("e.g. C4-7-3, C4 kp 7 3 or D11-1-9-5");
+		}
+
+		let mut place = false;
+
+		ui.set_next_item_width(150.0);
+		let gun_hint = if player_gun(state).is_some() { "My position" } else { "C4-7-3" };
+		place |= ui.input_text("Gun", &mut state.draw.grid_gun).hint(gun_hint).enter_returns_true(true).build();
+
+		ui.set_next_item_width(150.0);
+		place |= ui.input_text("Target", &mut state.draw.grid_target).hint("D6-1-9").enter_returns_true(true).build();
+
+		place |= ui.button_with_size("Place", [-1.0, 0.0]);
+
+		if place {
+			let (gun, target) = (state.draw.grid_gun.clone(), state.draw.grid_target.clone());
+			state.draw.grid_error = add_grid_marker(state, &gun, &target).err().map(|err| err.to_string());
+			if state.draw.grid_error.is_none() {
+				state.draw.grid_target.clear();
+			}
+		}
+
+		if let Some(ref err) = state.draw.grid_error {
+			ui.text_colored([1.0, 0.0, 0.0, 1.0], err);
+		}
+
+		ui.separator();
+
+		if imgui::MenuItem::new("Use My Position as Gun").selected(state.draw.gun_at_player).build(ui) {
+			state.draw.gun_at_player = !state.draw.gun_at_player;
+		}
+		if ui.is_item_hovered() {
+			ui.tooltip_text(if state.vision.player.is_some() {
+				"Click on the map to place a marker from your icon to the target"
+			} else {
+				"Your icon isn't visible on the map"
+			});
+		}
+
+		if imgui::MenuItem::new("Clear Markers").enabled(!state.draw.custom_markers.is_empty()).build(ui) {
+			state.draw.custom_markers.clear();
+
+			if let Some(web) = &state.web.server {
+				web.send(smh_web::Event::Markers { markers: Box::from(&*state.draw.custom_markers), fireteams: Box::default(), commands: Box::default(), ids: Box::default(), custom: true });
+			}
+		}
+
+		menu.end();
 	}
 }
 
@@ -102,6 +199,9 @@ fn mouse_ctl(state: &mut UiState, ui: &Ui) {
 
 			if dist >= ui.io().mouse_drag_threshold.powi(2) {
 				add_marker(state, drag_start, mouse_pos);
+			} else if let Some(gun) = player_gun(state) {
+				// Just a click, which targets from the player's position
+				add_marker(state, gun, mouse_pos);
 			}
 		}
 	}
@@ -1,18 +1,19 @@
 use super::*;
 use atomic_refcell::AtomicRef;
-use smh_heightmap_ripper::Heightmap;
-
-fn set_fit_to_minimap(state: &mut UiState, value: bool) {
-	state.heightmaps.fit_to_minimap = value;
-
-	if let Some(ref web) = state.web.server {
-		web.send(smh_web::Event::HeightmapFitToMinimap {
-			fit_to_minimap: value
-		});
-	}
-}
-
-const AES_KEY: &str = "0xBC0C07592D6B17BAB88B83A68583A053A6D9A0450CB54ABF5C231DBA59A7466B";
+use squadex::mods::{ModSettings, PakGroup, WorkshopMod};
+use smh_heightmap_ripper::{
+	catalogue::CatalogueLayer,
+	contours::ContourLine,
+	coords,
+	diff::HeightmapDiff,
+	registration::{HeightmapTransform, Registration},
+	render::{ColorRamp, RenderOptions, Shading},
+	steam::SquadCandidate,
+	validation::{Severity, ValidationReport},
+	Heightmap,
+};
+
+const AES_KEY: &str = smh_heightmap_ripper::DEFAULT_AES_KEY;
 
 enum LoadedHeightmap {
 	Generated(Heightmap),
@@ -23,51 +24,11 @@ fn fmt_system_time(time: SystemTime) -> String {
 	chrono::DateTime::<chrono::Local>::from(time).format("%d %b %Y %R").to_string()
 }
 
-fn find_workshop_paks(squad_dir: &str) -> Box<[Box<str>]> {
-	use std::ffi::OsStr;
-
-	let workshop_dir = Path::new(squad_dir).parent().and_then(|p| p.parent()).map(|p| {
-		p.join(format!("workshop/content/{}", smh_heightmap_ripper::SQUAD_APP_ID))
-	}).filter(|p| p.is_dir());
-
-	let workshop_dir = match workshop_dir {
-		Some(workshop_dir) => workshop_dir.into_boxed_path(),
-		None => return Default::default(),
-	};
-
-	let mut paks = BTreeSet::new();
-
-	for entry in walkdir::WalkDir::new(workshop_dir) {
-		let entry = match entry {
-			Ok(entry) => entry,
-			Err(err) => {
-				log::warn!("Error reading workshop directory: {err}");
-				continue
-			}
-		};
-
-		if entry.file_type().is_file() && entry.path().extension() == Some(OsStr::new("pak")) {
-			let parent = match entry.path().parent() {
-				Some(parent) => parent,
-				None => continue
-			};
-
-			if parent.file_name() != Some(OsStr::new("WindowsNoEditor")) {
-				continue;
-			}
-
-			paks.insert(Box::from(parent.to_string_lossy().as_ref()));
-		}
-	}
-
-	Vec::from_iter(paks).into_boxed_slice()
-}
-
-fn find_squad_dir() -> Option<Box<str>> {
+fn discover_squad_dirs() -> Box<[SquadCandidate]> {
 	// I don't want the program to crash because of something as mundane as this
 	// so I'm going to wrap it in a `catch_unwind` purely for paranoia reasons
-	match std::panic::catch_unwind(smh_heightmap_ripper::find_squad_dir) {
-		Ok(squad_dir) => squad_dir.map(|squad_dir| Box::from(squad_dir.to_string_lossy().as_ref())),
+	match std::panic::catch_unwind(smh_heightmap_ripper::steam::discover) {
+		Ok(candidates) => candidates.into_boxed_slice(),
 		Err(err) => {
 			if let Some(err) = err.downcast_ref::<Box<dyn std::error::Error>>() {
 				log::error!("Error finding Squad paks dir: {err}");
@@ -80,53 +41,187 @@ fn find_squad_dir() -> Option<Box<str>> {
 			} else {
 				log::error!("Error finding Squad paks dir: {err:?}");
 			}
-			None
+			Box::default()
+		}
+	}
+}
+
+/// The best installation that we found, if it's usable
+fn default_squad_dir(candidates: &[SquadCandidate]) -> Option<Box<str>> {
+	candidates.iter().find(|candidate| candidate.is_valid()).map(|candidate| Box::from(candidate.path.to_string_lossy().as_ref()))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LayerGrouping {
+	None,
+	Map,
+	GameMode,
+	Mod,
+}
+impl LayerGrouping {
+	const ALL: [LayerGrouping; 4] = [Self::None, Self::Map, Self::GameMode, Self::Mod];
+
+	#[inline]
+	fn from_u8(val: u8) -> Self {
+		Self::ALL.get(val as usize).copied().unwrap_or(Self::None)
+	}
+
+	fn name(self) -> &'static str {
+		match self {
+			Self::None => "None",
+			Self::Map => "Map",
+			Self::GameMode => "Game Mode",
+			Self::Mod => "Mod",
+		}
+	}
+
+	fn group(self, layer: &CatalogueLayer) -> Option<&str> {
+		match self {
+			Self::None => None,
+			Self::Map => Some(&layer.map),
+			Self::GameMode => Some(layer.game_mode.unwrap_or("Other")),
+			Self::Mod => Some(layer.mod_name.as_deref().unwrap_or("Squad")),
 		}
 	}
 }
 
-type WorkshopPaks = Box<[Box<str>]>;
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LayerSort {
+	Name,
+	Map,
+	GameMode,
+	Size,
+}
+impl LayerSort {
+	const ALL: [LayerSort; 4] = [Self::Name, Self::Map, Self::GameMode, Self::Size];
+
+	#[inline]
+	fn from_u8(val: u8) -> Self {
+		Self::ALL.get(val as usize).copied().unwrap_or(Self::Name)
+	}
+
+	fn name(self) -> &'static str {
+		match self {
+			Self::Name => "Name",
+			Self::Map => "Map",
+			Self::GameMode => "Game Mode",
+			Self::Size => "Size",
+		}
+	}
+
+	fn compare(self, a: &CatalogueLayer, b: &CatalogueLayer) -> core::cmp::Ordering {
+		let by_name = || a.name.cmp(&b.name).then_with(|| a.path.cmp(&b.path));
+		match self {
+			Self::Name => by_name(),
+			Self::Map => a.map.cmp(&b.map).then_with(by_name),
+			Self::GameMode => a.game_mode.is_none().cmp(&b.game_mode.is_none()).then(a.game_mode.cmp(&b.game_mode)).then_with(by_name),
+			// Largest first, unknown sizes last
+			Self::Size => {
+				let area = |layer: &CatalogueLayer| layer.size.map(|[w, h]| w as u64 * h as u64);
+				area(a).is_none().cmp(&area(b).is_none()).then(area(b).cmp(&area(a))).then_with(by_name)
+			}
+		}
+	}
+}
+
+fn fmt_map_size([width, height]: [u32; 2]) -> String {
+	format!("{:.1} x {:.1} km", width as f64 / 1000.0, height as f64 / 1000.0)
+}
+
+struct DiscoveredLayers {
+	catalogue: Box<[CatalogueLayer]>,
+	mods: Box<[WorkshopMod]>,
+	pak_groups: Box<[PakGroup]>,
+}
+impl DiscoveredLayers {
+	/// The paks and AES key needed to rip a layer
+	fn pak_group(&self, layer: &CatalogueLayer) -> &PakGroup {
+		layer
+			.workshop_id
+			.and_then(|workshop_id| self.pak_groups.iter().find(|group| group.workshop_ids.contains(&workshop_id)))
+			.unwrap_or(&self.pak_groups[0])
+	}
+}
+
 struct LoadLayersResult {
 	squad_dir: Box<str>,
 	aes_key: Box<str>,
-	result: Result<(smh_heightmap_ripper::LayersList, WorkshopPaks), smh_heightmap_ripper::Error>,
+	result: Result<DiscoveredLayers, smh_heightmap_ripper::Error>,
 }
 struct LoadLayersOp {
 	squad_dir: Box<str>,
 	aes_key: Box<str>,
+	mod_settings: BTreeMap<u64, ModSettings>,
 }
 impl LoadLayersOp {
 	fn load_layers(self) -> LoadLayersResult {
 		let main_paks = self.squad_dir.to_string() + "/SquadGame/Content/Paks";
-		let workshop_paks = find_workshop_paks(&self.squad_dir);
-
-		let mut result = smh_heightmap_ripper::list_maps(workshop_paks.iter().map(|pak| &**pak).chain([&*main_paks]), Some(&*self.aes_key));
+		let mods = squadex::mods::discover(&self.squad_dir);
+		let pak_groups = squadex::mods::pak_groups(&mods, &self.mod_settings, &main_paks, &self.aes_key);
+
+		// The ripper only takes one AES key, so mods with their own key are listed separately
+		let mut result = pak_groups.iter().enumerate().try_fold(BTreeSet::new(), |mut layers, (i, group)| {
+			match smh_heightmap_ripper::list_maps(group.paks.iter().map(|pak| &**pak), Some(&*group.aes_key)) {
+				Ok(group_layers) => {
+					layers.extend(group_layers.into_vec());
+					Ok(layers)
+				}
+				Err(err) if i != 0 => {
+					log::warn!("Error loading layers for mods using AES key {}: {}", group.aes_key, err);
+					Ok(layers)
+				}
+				Err(err) => Err(err),
+			}
+		});
 		if let Err(ref err) = result {
 			log::warn!("Error loading layers: {}", err);
 		}
-		if let Ok(layers) = result.as_deref_mut() {
-			layers.sort();
+
+		// Heightmaps imported from bundles can be used without the ripper
+		let cached_layers = squadex::heightmaps::cached_layers();
+		match result {
+			Ok(ref mut layers) => layers.extend(cached_layers),
+			Err(_) if !cached_layers.is_empty() => result = Ok(cached_layers.into_iter().collect()),
+			_ => {}
 		}
+
+		let result = result.map(|layers| DiscoveredLayers {
+			catalogue: layers
+				.iter()
+				.map(|path| {
+					let mut layer = CatalogueLayer::parse(path);
+					layer.size = squadex::heightmaps::cached_size(path);
+
+					if let Some(workshop_mod) = layer.mod_name.as_deref().and_then(|plugin| squadex::mods::find_by_plugin(&mods, plugin)) {
+						layer.workshop_id = Some(workshop_mod.id);
+						layer.mod_name = Some(workshop_mod.name.clone());
+					}
+
+					layer
+				})
+				.collect(),
+			mods,
+			pak_groups,
+		});
+
 		LoadLayersResult {
 			squad_dir: self.squad_dir,
 			aes_key: self.aes_key,
-			result: result.map(|result| (result, workshop_paks))
+			result
 		}
 	}
 }
 
-type LoadHeightmapResult = Result<Option<LoadedHeightmap>, smh_heightmap_ripper::Error>;
+type LoadHeightmapResult = Result<Option<(LoadedHeightmap, ValidationReport)>, smh_heightmap_ripper::Error>;
 struct LoadHeightmapOp {
 	aes_key: Box<str>,
-	paks_dir: Box<str>,
-	workshop_paks: WorkshopPaks,
+	paks: Box<[Box<str>]>,
 	layer_path: Box<str>,
 	skip_cache: bool,
 }
 impl LoadHeightmapOp {
 	fn load_heightmap(self) -> LoadHeightmapResult {
-		let mut cache_path = Path::new("heightmaps").join(&*self.layer_path);
-		cache_path.set_extension("smhhm");
+		let cache_path = squadex::heightmaps::cache_path(&self.layer_path);
 
 		if !self.skip_cache && cache_path.is_file() {
 			match File::open(&cache_path)
@@ -136,17 +231,21 @@ impl LoadHeightmapOp {
 				Ok(None) => {}
 
 				Ok(Some(cached)) => {
-					return Ok(Some(LoadedHeightmap::Loaded {
-						created: cache_path.metadata().and_then(|metadata| metadata.modified()).ok().map(fmt_system_time),
-						heightmap: cached,
-					}))
+					let report = smh_heightmap_ripper::validation::validate(&cached);
+					return Ok(Some((
+						LoadedHeightmap::Loaded {
+							created: cache_path.metadata().and_then(|metadata| metadata.modified()).ok().map(fmt_system_time),
+							heightmap: cached,
+						},
+						report,
+					)));
 				}
 
 				Err(err) => log::warn!("Error opening cached heightmap: {}", err),
 			}
 		}
 
-		let result = smh_heightmap_ripper::get_heightmap(self.workshop_paks.iter().map(|pak| &**pak).chain([&*self.paks_dir]), Some(&*self.aes_key), &*self.layer_path);
+		let result = smh_heightmap_ripper::get_heightmap(self.paks.iter().map(|pak| &**pak), Some(&*self.aes_key), &*self.layer_path);
 		if let Err(ref err) = result {
 			log::warn!("Error generating heightmap for {}: {}", self.layer_path, err);
 		}
@@ -162,48 +261,212 @@ impl LoadHeightmapOp {
 			}
 		}
 
-		result.map(|heightmap| heightmap.map(LoadedHeightmap::Generated))
+		result.map(|heightmap| {
+			heightmap.map(|heightmap| {
+				let report = smh_heightmap_ripper::validation::validate(&heightmap);
+				(LoadedHeightmap::Generated(heightmap), report)
+			})
+		})
 	}
 }
 
-fn color_map_heightmap(heightmap: &Heightmap) -> image::RgbaImage {
-	let (width, height, heightmap) = (heightmap.width, heightmap.height, &*heightmap.data);
+struct LoadMinimapResult {
+	layer: Box<str>,
+	minimap: Result<Option<Arc<image::RgbaImage>>, smh_heightmap_ripper::Error>,
+}
+struct LoadMinimapOp {
+	aes_key: Box<str>,
+	paks: Box<[Box<str>]>,
+	layer_path: Box<str>,
+}
+impl LoadMinimapOp {
+	fn load_minimap(self) -> LoadMinimapResult {
+		LoadMinimapResult {
+			minimap: squadex::offline::load_minimap(&self.paks, &self.aes_key, &self.layer_path).map(|minimap| minimap.map(Arc::new)),
+			layer: self.layer_path,
+		}
+	}
+}
+
+struct DiffHeightmapResult {
+	layer: Box<str>,
+	result: Result<Option<(Heightmap, Arc<HeightmapDiff>)>, smh_heightmap_ripper::Error>,
+}
+struct DiffHeightmapOp {
+	cached: Heightmap,
+	aes_key: Box<str>,
+	paks: Box<[Box<str>]>,
+	layer_path: Box<str>,
+}
+impl DiffHeightmapOp {
+	fn diff_heightmap(self) -> DiffHeightmapResult {
+		let result = smh_heightmap_ripper::get_heightmap(self.paks.iter().map(|pak| &**pak), Some(&*self.aes_key), &*self.layer_path).map(|fresh| {
+			fresh.map(|fresh| {
+				let diff = smh_heightmap_ripper::diff::diff(&self.cached, &fresh, smh_heightmap_ripper::diff::DEFAULT_THRESHOLD);
+				log::info!(
+					"Compared {} with a fresh rip: {:.2}% changed in {} regions",
+					self.layer_path,
+					diff.changed_fraction() * 100.0,
+					diff.regions.len()
+				);
+				(fresh, Arc::new(diff))
+			})
+		});
+		if let Err(ref err) = result {
+			log::warn!("Error ripping {} for comparison: {}", self.layer_path, err);
+		}
+
+		DiffHeightmapResult {
+			layer: self.layer_path,
+			result,
+		}
+	}
+}
 
-	let (max, min) = heightmap
-		.par_iter()
-		.copied()
-		.fold(|| (u16::MIN, u16::MAX), |(max, min), val| (val.max(max), val.min(min)))
-		.reduce(|| (u16::MIN, u16::MAX), |(max, min), (max_, min_)| (max.max(max_), min.min(min_)));
+struct TerrainDiffState {
+	diff: ImCell<DiffHeightmapOp, DiffHeightmapResult>,
+	texture: Option<(usize, imgui::TextureId)>,
+}
 
-	let mut colored = image::RgbaImage::new(width, height);
-	let par_colored = UnsafeSendPtr::new_mut(&mut colored);
-	heightmap.par_iter().copied().enumerate().for_each(|(i, height)| {
-		let i = i as u32;
-		let x = i % width;
-		let y = i / width;
+type GenerateContoursResult = Arc<[ContourLine]>;
+struct GenerateContoursOp {
+	heightmap: Heightmap,
+	interval: u16,
+	index_interval: u16,
+}
+impl GenerateContoursOp {
+	fn generate_contours(self) -> GenerateContoursResult {
+		let contours = smh_heightmap_ripper::contours::generate(&self.heightmap, self.interval as f64, self.index_interval as f64);
+		log::info!("Generated {} contour lines ({}m interval, {}m index interval)", contours.len(), self.interval, self.index_interval);
+		Arc::from(contours)
+	}
+}
 
-		let color = if height == 0 && min != 0 {
-			image::Rgba([0, 0, 0, 0])
+struct RegisterHeightmapResult {
+	layer: Box<str>,
+	resolution: [u32; 2],
+	registration: Option<Registration>,
+}
+struct RegisterHeightmapOp {
+	layer: Box<str>,
+	resolution: [u32; 2],
+	heightmap: Heightmap,
+	minimap: image::GrayImage,
+	skip_cache: bool,
+}
+impl RegisterHeightmapOp {
+	fn register_heightmap(self) -> RegisterHeightmapResult {
+		let cached = if !self.skip_cache {
+			squadex::heightmaps::registration::load(&self.layer, self.resolution)
 		} else {
-			// Normalize height
-			let height = (height as f64 - min as f64) / (max - min) as f64;
+			None
+		};
 
-			let r = (height - 0.5).max(0.0) / 0.5;
-			let b = ((1.0 - height) - 0.5).max(0.0) / 0.5;
-			let g = 1.0 - if height > 0.5 { r } else { b };
+		let registration = cached.or_else(|| {
+			let registration = smh_heightmap_ripper::registration::register(&self.heightmap, &self.minimap);
+			match registration {
+				Some(registration) if registration.score >= smh_heightmap_ripper::registration::MIN_SCORE => {
+					log::info!("Aligned heightmap to minimap: {:?}", registration);
+					squadex::heightmaps::registration::store(&self.layer, self.resolution, registration);
+				}
+				Some(registration) => log::warn!("Couldn't confidently align heightmap to minimap (score {:.2})", registration.score),
+				None => log::warn!("Couldn't align heightmap to minimap"),
+			}
+			registration
+		});
 
-			let r = (r * 255.0) as u8;
-			let g = (g * 255.0) as u8;
-			let b = (b * 255.0) as u8;
+		RegisterHeightmapResult {
+			layer: self.layer,
+			resolution: self.resolution,
+			registration,
+		}
+	}
+}
 
-			image::Rgba([r, g, b, 255])
-		};
+const CONTOUR_COLOR: [f32; 4] = [0.85, 0.55, 0.2, 0.55];
+const CONTOUR_INDEX_COLOR: [f32; 4] = [0.85, 0.55, 0.2, 0.85];
+
+const LEGEND_STEPS: usize = 32;
+const LEGEND_HEIGHT: f32 = 10.0;
+
+fn load_render_options() -> RenderOptions {
+	RenderOptions {
+		ramp: ColorRamp::from_u8(SETTINGS.heightmap_color_ramp()),
+		shading: Shading::from_u8(SETTINGS.heightmap_shading()),
+		azimuth: SETTINGS.hillshade_azimuth() as f64,
+		altitude: SETTINGS.hillshade_altitude() as f64,
+	}
+}
+
+fn render_legend(ui: &Ui, legend: &[(f64, [u8; 3])]) {
+	let (min, max) = match (legend.first(), legend.last()) {
+		(Some((min, _)), Some((max, _))) if legend.len() > 1 => (*min, *max),
+		_ => return,
+	};
+
+	let [x, y] = ui.cursor_screen_pos();
+	let width = ui.content_region_avail()[0];
+	let step = width / legend.len() as f32;
+
+	let dl = ui.get_window_draw_list();
+	for (i, (_, [r, g, b])) in legend.iter().enumerate() {
+		dl.add_rect(
+			[x + i as f32 * step, y],
+			[x + (i + 1) as f32 * step, y + LEGEND_HEIGHT],
+			[*r as f32 / 255.0, *g as f32 / 255.0, *b as f32 / 255.0],
+		)
+		.filled(true)
+		.build();
+	}
+	ui.dummy([width, LEGEND_HEIGHT]);
+
+	let mid = format!("{:.0}m", (min + max) / 2.0);
+	let max = format!("{:.0}m", max);
+
+	ui.text(format!("{:.0}m", min));
+	ui.same_line_with_pos((width - ui.calc_text_size(&mid)[0]) / 2.0);
+	ui.text(mid);
+	ui.same_line_with_pos(width - ui.calc_text_size(&max)[0]);
+	ui.text(max);
+}
+
+fn validation_color(severity: Severity) -> [f32; 4] {
+	match severity {
+		Severity::Warning => [1.0, 0.75, 0.0, 1.0],
+		Severity::Error => [1.0, 0.0, 0.0, 1.0],
+	}
+}
+
+/// Warns about a heightmap that failed validation, with the issues listed in a tooltip
+fn render_validation_badge(ui: &Ui, report: &ValidationReport) {
+	let severity = match report.severity() {
+		Some(severity) => severity,
+		None => return,
+	};
 
-		let colored = unsafe { par_colored.clone().as_mut() };
-		colored.put_pixel_fast(x, y, color);
+	let color = ui.push_style_color(imgui::StyleColor::Text, validation_color(severity));
+	ui.text_wrapped(match severity {
+		Severity::Warning => "(!) This heightmap might have problems",
+		Severity::Error => "(!) This heightmap looks broken, don't trust it for fire missions",
 	});
+	color.end();
 
-	colored
+	if ui.is_item_hovered() {
+		ui.tooltip(|| {
+			for issue in &report.issues {
+				ui.text_colored(validation_color(issue.severity()), issue.to_string());
+			}
+		});
+	}
+}
+
+fn open_heightmaps_folder(opened_heightmaps_folder: &mut bool) {
+	if !*opened_heightmaps_folder {
+		*opened_heightmaps_folder = true;
+		if let Ok(path) = Path::new("heightmaps").canonicalize() {
+			open::that(path).ok();
+		}
+	}
 }
 
 fn create_heightmap_texture(
@@ -211,6 +474,7 @@ fn create_heightmap_texture(
 	textures: &mut Textures<Texture>,
 	heightmap_texture: &mut Option<TextureId>,
 	heightmap: &Heightmap,
+	options: &RenderOptions,
 ) -> Result<TextureId, glium::texture::TextureCreationError> {
 	let texture = Texture {
 		texture: Rc::new(Texture2d::with_format(
@@ -218,7 +482,7 @@ fn create_heightmap_texture(
 			RawImage2d {
 				width: heightmap.width,
 				height: heightmap.height,
-				data: Cow::Owned(color_map_heightmap(heightmap).into_raw()),
+				data: Cow::Owned(smh_heightmap_ripper::render::render(heightmap, options).into_raw()),
 				format: glium::texture::ClientFormat::U8U8U8U8,
 			},
 			glium::texture::UncompressedFloatFormat::U8U8U8U8,
@@ -247,17 +511,51 @@ pub struct HeightmapsUiState {
 	heightmap_info_fake_input: String,
 
 	default_squad_dir: Option<Box<str>>,
+	squad_dir_candidates: Box<[SquadCandidate]>,
 	squad_dir: String,
 	aes_key: String,
 	filter: String,
+	layer_grouping: LayerGrouping,
+	layer_sort: LayerSort,
+	mod_aes_keys: BTreeMap<u64, String>,
 
 	layers: ImCell<LoadLayersOp, LoadLayersResult>,
 	heightmap: ImCell<LoadHeightmapOp, LoadHeightmapResult>,
 	heightmap_texture: Option<imgui::TextureId>,
+	heightmap_legend: Box<[(f64, [u8; 3])]>,
+
+	render_options: RenderOptions,
+	hillshade_azimuth: i32,
+	hillshade_altitude: i32,
 
 	pub selected_heightmap: Option<(imgui::TextureId, [f32; 2], [f32; 2])>,
+	selected_report: Option<ValidationReport>,
 	draw_heightmap: bool,
 
+	preview_layer_path: Option<Box<str>>,
+	selected_layer_path: Option<Box<str>>,
+
+	/// AES key and paks that the layer was ripped from
+	preview_pak_group: Option<(Box<str>, Box<[Box<str>]>)>,
+	selected_pak_group: Option<(Box<str>, Box<[Box<str>]>)>,
+
+	offline: bool,
+	offline_minimap: ImCell<LoadMinimapOp, LoadMinimapResult>,
+	offline_error: Option<String>,
+
+	terrain_diff: TerrainDiffState,
+
+	registration: ImCell<RegisterHeightmapOp, RegisterHeightmapResult>,
+	realign: bool,
+	sent_transform: Option<HeightmapTransform>,
+	sent_grid: Option<squadex::grid::Grid>,
+
+	contours: ImCell<GenerateContoursOp, GenerateContoursResult>,
+	contour_interval: i32,
+	contour_index_interval: i32,
+	draw_contours: bool,
+	contours_sent: bool,
+
 	/// When TRUE, the heightmap will be extended to the bounds of the minimap
 	///
 	/// When FALSE, the heightmap will be offset to its starting point on the minimap
@@ -269,7 +567,6 @@ pub struct HeightmapsUiState {
 	fit_modal_hover: bool,
 
 	window_open: bool,
-	selected_layer: i32,
 }
 impl HeightmapsUiState {
 	#[inline]
@@ -281,11 +578,80 @@ impl HeightmapsUiState {
 	pub fn fit_to_minimap(&self) -> bool {
 		self.fit_modal_hover || ((self.fit_to_minimap ^ self.fit_to_minimap_hover) && !self.fit_modal)
 	}
+
+	/// The automatically registered alignment of the selected heightmap for this minimap, if any
+	fn registration(&self, minimap_bounds: Rect<u32>) -> Option<Registration> {
+		if !SETTINGS.heightmap_registration() {
+			return None;
+		}
+		match self.registration.get() {
+			ImCellStateRef::Initialized(result) if result.resolution == [minimap_bounds.width(), minimap_bounds.height()] => result
+				.registration
+				.filter(|registration| registration.score >= smh_heightmap_ripper::registration::MIN_SCORE),
+			_ => None,
+		}
+	}
+
+	/// Returns how heightmap texels map onto the minimap
+	pub fn transform(&self, size: [f32; 2], bounds_offset: [f32; 2], minimap_bounds: Rect<u32>) -> HeightmapTransform {
+		let previewing = self.fit_modal || self.fit_modal_hover || self.fit_to_minimap_hover;
+		if !previewing {
+			if let Some(registration) = self.registration(minimap_bounds) {
+				return registration.transform;
+			}
+		}
+
+		HeightmapTransform::unregistered(
+			[size[0] as f64, size[1] as f64],
+			[bounds_offset[0] as f64, bounds_offset[1] as f64],
+			self.fit_to_minimap(),
+		)
+	}
+
+	/// Switches between planning on the layer's ripped minimap texture and the game's map screen
+	fn set_offline(&mut self, offline: bool) {
+		self.offline = offline;
+		self.offline_error = None;
+		self.offline_minimap.reset();
+
+		if !offline {
+			squadex::offline::set(None);
+		}
+	}
+
+	/// Rediscovers the available layers, e.g. after heightmaps have been imported
+	pub(super) fn reload_layers(&mut self) {
+		self.layers.reset();
+	}
+
+	/// The alignment of the current heightmap that web clients should be using, if any
+	#[inline]
+	pub fn web_transform(&self) -> Option<HeightmapTransform> {
+		self.sent_transform
+	}
+
+	/// The grid that web clients should be labelling markers with, if any
+	#[inline]
+	pub fn web_grid(&self) -> Option<[[f64; 2]; 2]> {
+		self.sent_grid.map(|grid| [grid.origin, grid.meters_per_px])
+	}
+
+	/// The contour lines that web clients should be drawing, if any
+	pub fn web_contours(&self) -> Option<GenerateContoursResult> {
+		if !self.draw_contours {
+			return None;
+		}
+		match self.contours.get() {
+			ImCellStateRef::Initialized(contours) => Some(contours.clone()),
+			_ => None,
+		}
+	}
 }
 impl Default for HeightmapsUiState {
 	fn default() -> Self {
 		// we can get away with resolving the paks dir here
-		let default_squad_dir = find_squad_dir();
+		let squad_dir_candidates = discover_squad_dirs();
+		let default_squad_dir = default_squad_dir(&squad_dir_candidates);
 		Self {
 			opened_heightmaps_folder: false,
 
@@ -298,21 +664,58 @@ impl Default for HeightmapsUiState {
 				.or_else(|| default_squad_dir.as_deref().map(Into::into))
 				.unwrap_or_default(),
 			default_squad_dir,
+			squad_dir_candidates,
 
 			aes_key: SETTINGS.squad_pak_aes().to_owned().map(Into::into).unwrap_or_else(|| AES_KEY.to_string()),
 
 			filter: String::new(),
+			layer_grouping: LayerGrouping::from_u8(SETTINGS.heightmap_layer_grouping()),
+			layer_sort: LayerSort::from_u8(SETTINGS.heightmap_layer_sort()),
+			mod_aes_keys: Default::default(),
 
 			layers: ImCell::new(LoadLayersOp::load_layers, Some(ui::redraw)),
 			heightmap: ImCell::new(LoadHeightmapOp::load_heightmap, Some(ui::redraw)),
 			heightmap_texture: None,
+			heightmap_legend: Default::default(),
+
+			render_options: load_render_options(),
+			hillshade_azimuth: SETTINGS.hillshade_azimuth() as i32,
+			hillshade_altitude: SETTINGS.hillshade_altitude() as i32,
+
 			selected_heightmap: None,
+			selected_report: None,
+
+			preview_layer_path: None,
+			selected_layer_path: None,
+
+			preview_pak_group: None,
+			selected_pak_group: None,
+
+			offline: false,
+			offline_minimap: ImCell::new(LoadMinimapOp::load_minimap, Some(ui::redraw)),
+			offline_error: None,
+
+			terrain_diff: TerrainDiffState {
+				diff: ImCell::new(DiffHeightmapOp::diff_heightmap, Some(ui::redraw)),
+				texture: None,
+			},
+
+			registration: ImCell::new(RegisterHeightmapOp::register_heightmap, Some(ui::redraw)),
+			realign: false,
+			sent_transform: None,
+			sent_grid: None,
+
+			contours: ImCell::new(GenerateContoursOp::generate_contours, Some(ui::redraw)),
+			contour_interval: SETTINGS.contour_interval() as i32,
+			contour_index_interval: SETTINGS.contour_index_interval() as i32,
+			draw_contours: false,
+			contours_sent: false,
+
 			fit_to_minimap: true,
 			fit_to_minimap_hover: false,
 
 			draw_heightmap: Default::default(),
 			window_open: Default::default(),
-			selected_layer: -1,
 
 			fit_modal: false,
 			fit_modal_opened: false,
@@ -334,6 +737,10 @@ pub(super) fn menu_bar(state: &mut UiState, ui: &Ui) {
 		state.heightmaps.window_open = true;
 	}
 
+	if imgui::MenuItem::new("Share...").build(ui) {
+		state.bundles.window_open = true;
+	}
+
 	let is_set = squadex::heightmaps::is_set();
 
 	if is_set && imgui::MenuItem::new("Clear Selection").build(ui) {
@@ -341,12 +748,51 @@ pub(super) fn menu_bar(state: &mut UiState, ui: &Ui) {
 
 		state.heightmaps.draw_heightmap = false;
 		state.heightmaps.selected_heightmap = None;
+		state.heightmaps.selected_layer_path = None;
+		state.heightmaps.selected_pak_group = None;
+		state.heightmaps.selected_report = None;
+		state.heightmaps.contours.reset();
+		state.heightmaps.registration.reset();
+		state.heightmaps.set_offline(false);
 
 		if let Some(ref server) = state.web.server {
 			server.send(smh_web::Event::Heightmap { heightmap: None });
 		}
 	}
 
+	if let Some(severity) = state.heightmaps.selected_report.as_ref().and_then(|report| report.severity()).filter(|_| is_set) {
+		let color = ui.push_style_color(imgui::StyleColor::Text, validation_color(severity));
+		imgui::MenuItem::new("(!) Heightmap Problems").build(ui);
+		color.end();
+
+		if ui.is_item_hovered() {
+			if let Some(ref report) = state.heightmaps.selected_report {
+				ui.tooltip(|| {
+					for issue in &report.issues {
+						ui.text_colored(validation_color(issue.severity()), issue.to_string());
+					}
+				});
+			}
+		}
+	}
+
+	// Released versions of the ripper can't extract minimaps, so there's nothing to plan on
+	if smh_heightmap_ripper::supports_minimaps() {
+		if imgui::MenuItem::new("Offline Planning")
+			.enabled(is_set && state.heightmaps.selected_pak_group.is_some())
+			.selected(state.heightmaps.offline)
+			.build(ui)
+		{
+			state.heightmaps.set_offline(!state.heightmaps.offline);
+		}
+		if ui.is_item_hovered() {
+			ui.tooltip_text("Plan using the layer's minimap texture instead of the game's map screen");
+		}
+		if let Some(ref err) = state.heightmaps.offline_error {
+			ui.text_colored([1.0, 0.0, 0.0, 1.0], err);
+		}
+	}
+
 	if imgui::MenuItem::new("Show Heightmap")
 		.enabled(is_set)
 		.selected(state.heightmaps.draw_heightmap)
@@ -355,17 +801,64 @@ pub(super) fn menu_bar(state: &mut UiState, ui: &Ui) {
 		state.heightmaps.draw_heightmap = !state.heightmaps.draw_heightmap;
 	}
 
+	if imgui::MenuItem::new("Show Contours")
+		.enabled(is_set)
+		.selected(state.heightmaps.draw_contours)
+		.build(ui)
+	{
+		state.heightmaps.draw_contours = !state.heightmaps.draw_contours;
+	}
+
+	if let Some(contours_menu) = ui.begin_menu_with_enabled("Contour Intervals", is_set) {
+		ui.set_next_item_width(100.0);
+		imgui::Slider::new("Interval (m)", 1, 100).build(ui, &mut state.heightmaps.contour_interval);
+		if ui.is_item_deactivated_after_edit() {
+			SETTINGS.set_contour_interval(state.heightmaps.contour_interval as u16);
+			state.heightmaps.contours.reset();
+		}
+
+		ui.set_next_item_width(100.0);
+		imgui::Slider::new("Index Interval (m)", 0, 500).build(ui, &mut state.heightmaps.contour_index_interval);
+		if ui.is_item_deactivated_after_edit() {
+			SETTINGS.set_contour_index_interval(state.heightmaps.contour_index_interval as u16);
+			state.heightmaps.contours.reset();
+		}
+
+		contours_menu.end();
+	}
+
+	if imgui::MenuItem::new("Auto-Align")
+		.enabled(is_set)
+		.selected(SETTINGS.heightmap_registration())
+		.build(ui)
+	{
+		SETTINGS.set_heightmap_registration(!SETTINGS.heightmap_registration());
+	}
+	if ui.is_item_hovered() {
+		ui.tooltip_text("Automatically align the heightmap to the minimap by matching its terrain");
+	}
+
+	if imgui::MenuItem::new("Re-Align")
+		.enabled(is_set && SETTINGS.heightmap_registration() && !matches!(state.heightmaps.registration.get(), ImCellStateRef::Loading))
+		.build(ui)
+	{
+		state.heightmaps.realign = true;
+	}
+
 	if imgui::MenuItem::new("Fit to Minimap")
 		.enabled(is_set)
 		.selected(state.heightmaps.fit_to_minimap)
 		.build(ui)
 	{
 		state.heightmaps.fit_to_minimap_hover = false;
-		set_fit_to_minimap(state, !state.heightmaps.fit_to_minimap);
+		state.heightmaps.fit_to_minimap = !state.heightmaps.fit_to_minimap;
 	} else if is_set && !state.heightmaps.fit_modal {
 		state.heightmaps.fit_to_minimap_hover = ui.is_item_hovered();
 	}
 
+	ui.separator();
+	viewshed::menu(state, ui, is_set);
+
 	menu.end();
 }
 
@@ -387,7 +880,7 @@ pub(super) fn render_fit_modal(state: &mut UiState, ui: &Ui) {
 
 		let yes = ui.button("Yes");
 		if yes {
-			set_fit_to_minimap(state, false);
+			state.heightmaps.fit_to_minimap = false;
 			state.heightmaps.fit_modal = false;
 			state.heightmaps.fit_modal_hover = false;
 			ui.close_current_popup();
@@ -396,7 +889,7 @@ pub(super) fn render_fit_modal(state: &mut UiState, ui: &Ui) {
 		ui.same_line();
 
 		if ui.button("No") {
-			set_fit_to_minimap(state, true);
+			state.heightmaps.fit_to_minimap = true;
 			state.heightmaps.fit_modal = false;
 			state.heightmaps.fit_modal_hover = false;
 			ui.close_current_popup();
@@ -443,12 +936,13 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 		state.heightmaps.layers.reset();
 		state.heightmaps.heightmap.reset();
 		state.heightmaps.heightmap_texture = None;
-		state.heightmaps.selected_layer = -1;
+		state.heightmaps.preview_layer_path = None;
 
 		if state.heightmaps.squad_dir.is_empty() {
 			SETTINGS.set_squad_dir(None);
 
-			let squad_dir = find_squad_dir();
+			state.heightmaps.squad_dir_candidates = discover_squad_dirs();
+			let squad_dir = default_squad_dir(&state.heightmaps.squad_dir_candidates);
 			state.heightmaps.default_squad_dir = squad_dir.as_deref().map(Into::into);
 			state.heightmaps.squad_dir = squad_dir.map(Into::into).unwrap_or_default();
 		} else {
@@ -456,6 +950,8 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 		}
 	}
 
+	render_squad_dir_candidates(state, ui);
+
 	ui.spacing();
 
 	ui.text("AES Key");
@@ -470,7 +966,7 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 		state.heightmaps.layers.reset();
 		state.heightmaps.heightmap.reset();
 		state.heightmaps.heightmap_texture = None;
-		state.heightmaps.selected_layer = -1;
+		state.heightmaps.preview_layer_path = None;
 
 		if state.heightmaps.aes_key.is_empty() {
 			SETTINGS.set_squad_pak_aes(None);
@@ -488,12 +984,15 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 
 	if state.heightmaps.squad_dir.is_empty() {
 		ui.text_centered("Squad directory not set. Please input the path to your Squad installation.");
-		return;
+		ui.text_centered("Imported heightmap bundles can still be used without it.");
 	}
 
 	// When set to true the heightmap will be regenerated, skipping the cache.
 	let mut regenerate = false;
 
+	// When set to true the heightmap will be reloaded, e.g. because its cache was updated.
+	let mut reload = false;
+
 	{
 		match state.heightmaps.heightmap.get_mut() {
 			ImCellStateRefMut::None => {
@@ -519,9 +1018,9 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 					ui.spacing();
 				}
 				Ok(opt) => {
-					let (heightmap, created) = match &*opt {
-						Some(LoadedHeightmap::Generated(heightmap)) => (heightmap, Some(Cow::Owned(fmt_system_time(SystemTime::now())))),
-						Some(LoadedHeightmap::Loaded { heightmap, created }) => (heightmap, created.as_deref().map(Cow::Borrowed)),
+					let (heightmap, created, report) = match &*opt {
+						Some((LoadedHeightmap::Generated(heightmap), report)) => (heightmap, Some(Cow::Owned(fmt_system_time(SystemTime::now()))), report),
+						Some((LoadedHeightmap::Loaded { heightmap, created }, report)) => (heightmap, created.as_deref().map(Cow::Borrowed), report),
 						None => unsafe { core::hint::unreachable_unchecked() },
 					};
 
@@ -538,8 +1037,17 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 
 					let texture = match state.heightmaps.heightmap_texture {
 						Some(texture) => Some(texture),
-						None => match create_heightmap_texture(state.display.get_context(), state.renderer.textures(), &mut state.heightmaps.heightmap_texture, &*heightmap) {
-							Ok(texture) => Some(texture),
+						None => match create_heightmap_texture(
+							state.display.get_context(),
+							state.renderer.textures(),
+							&mut state.heightmaps.heightmap_texture,
+							&*heightmap,
+							&state.heightmaps.render_options,
+						) {
+							Ok(texture) => {
+								state.heightmaps.heightmap_legend = smh_heightmap_ripper::render::legend(heightmap, state.heightmaps.render_options.ramp, LEGEND_STEPS).into_boxed_slice();
+								Some(texture)
+							}
 							Err(err) => {
 								log::error!("Error creating heightmap texture: {}", err);
 								None
@@ -575,7 +1083,13 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 
 					if texture.is_some() {
 						ui.spacing();
-						if ui.button_with_size("SELECT", [-1.0, 0.0]) {
+						render_legend(ui, &state.heightmaps.heightmap_legend);
+
+						ui.spacing();
+						render_validation_badge(ui, report);
+
+						ui.spacing();
+						if ui.button_with_size(if report.is_broken() { "SELECT ANYWAY" } else { "SELECT" }, [-1.0, 0.0]) {
 							state.heightmaps.window_open = false;
 							state.heightmaps.selected_heightmap = Some((
 								state.heightmaps.heightmap_texture.expect("Expected heightmap texture"),
@@ -587,11 +1101,20 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 								server.send(smh_web::Event::Heightmap { heightmap: Some(heightmap.clone()) });
 							}
 
-							squadex::heightmaps::set_current(Some(match opt.take().sus_unwrap() {
+							let (loaded, report) = opt.take().sus_unwrap();
+							state.heightmaps.selected_report = Some(report);
+
+							squadex::heightmaps::set_current(Some(match loaded {
 								LoadedHeightmap::Generated(heightmap) => heightmap,
 								LoadedHeightmap::Loaded { heightmap, .. } => heightmap,
 							}));
 
+							state.heightmaps.contours.reset();
+
+							state.heightmaps.selected_layer_path = state.heightmaps.preview_layer_path.clone();
+							state.heightmaps.selected_pak_group = state.heightmaps.preview_pak_group.clone();
+							state.heightmaps.registration.reset();
+
 							state.heightmaps.fit_modal = true;
 							state.heightmaps.fit_modal_opened = false;
 
@@ -607,6 +1130,76 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 					ui.spacing();
 					ui.spacing();
 
+					if ui.collapsing_header("Rendering", imgui::TreeNodeFlags::NO_TREE_PUSH_ON_OPEN) {
+						let mut rerender = false;
+
+						let mut ramp = state.heightmaps.render_options.ramp as usize;
+						let ramps = ColorRamp::ALL.map(ColorRamp::name);
+						ui.set_next_item_width(-1.0);
+						if ui.combo_simple_string("##ColorRamp", &mut ramp, &ramps) {
+							state.heightmaps.render_options.ramp = ColorRamp::ALL[ramp];
+							SETTINGS.set_heightmap_color_ramp(ramp as u8);
+							rerender = true;
+						}
+
+						let mut shading = state.heightmaps.render_options.shading as usize;
+						let shadings = Shading::ALL.map(Shading::name);
+						ui.set_next_item_width(-1.0);
+						if ui.combo_simple_string("##Shading", &mut shading, &shadings) {
+							state.heightmaps.render_options.shading = Shading::ALL[shading];
+							SETTINGS.set_heightmap_shading(shading as u8);
+							rerender = true;
+						}
+
+						if state.heightmaps.render_options.shading == Shading::Hillshade {
+							imgui::Slider::new("Sun Azimuth", 0, 359).build(ui, &mut state.heightmaps.hillshade_azimuth);
+							if ui.is_item_deactivated_after_edit() {
+								state.heightmaps.render_options.azimuth = state.heightmaps.hillshade_azimuth as f64;
+								SETTINGS.set_hillshade_azimuth(state.heightmaps.hillshade_azimuth as u16);
+								rerender = true;
+							}
+
+							imgui::Slider::new("Sun Altitude", 0, 90).build(ui, &mut state.heightmaps.hillshade_altitude);
+							if ui.is_item_deactivated_after_edit() {
+								state.heightmaps.render_options.altitude = state.heightmaps.hillshade_altitude as f64;
+								SETTINGS.set_hillshade_altitude(state.heightmaps.hillshade_altitude as u8);
+								rerender = true;
+							}
+						}
+
+						if rerender {
+							// Re-render into the preview's existing texture, rather than dropping it and leaking the old one
+							if state.heightmaps.heightmap_texture.is_some() {
+								match create_heightmap_texture(
+									state.display.get_context(),
+									state.renderer.textures(),
+									&mut state.heightmaps.heightmap_texture,
+									&*heightmap,
+									&state.heightmaps.render_options,
+								) {
+									Ok(_) => {
+										state.heightmaps.heightmap_legend =
+											smh_heightmap_ripper::render::legend(heightmap, state.heightmaps.render_options.ramp, LEGEND_STEPS).into_boxed_slice();
+									}
+									Err(err) => log::error!("Error creating heightmap texture: {}", err),
+								}
+							}
+
+							// Re-render the heightmap that is currently overlayed on the map too
+							if let (Some((texture_id, ..)), Some(selected)) = (state.heightmaps.selected_heightmap, squadex::heightmaps::get_current()) {
+								if let Err(err) = create_heightmap_texture(
+									state.display.get_context(),
+									state.renderer.textures(),
+									&mut Some(texture_id),
+									&selected,
+									&state.heightmaps.render_options,
+								) {
+									log::error!("Error creating heightmap texture: {}", err);
+								}
+							}
+						}
+					}
+
 					if ui.collapsing_header("Heightmap Info", imgui::TreeNodeFlags::NO_TREE_PUSH_ON_OPEN) {
 						use std::fmt::Write;
 
@@ -616,16 +1209,28 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 
 						write!(
 							&mut state.heightmaps.heightmap_info_fake_input,
-							"Generated: {}\nSize: {}x{} ({:.2} MB)\nScale: {:?}\nMinimap Bounds: {:?}",
+							"Generated: {}\nSize: {}x{} ({:.2} MB)\nScale: {:?}\nMinimap Bounds: {:?}\nElevation: {:.1}m - {:.1}m (mean {:.1}m)\nVoids: {:.2}%",
 							created.unwrap_or(Cow::Borrowed("Unknown")),
 							heightmap.width,
 							heightmap.height,
 							(heightmap.width as usize * heightmap.height as usize * 2) as f32 / 1000000.0,
 							heightmap.scale,
-							heightmap.bounds
+							heightmap.bounds,
+							report.min_elevation,
+							report.max_elevation,
+							report.mean_elevation,
+							report.void_fraction * 100.0
 						)
 						.ok();
 
+						for issue in &report.issues {
+							let severity = match issue.severity() {
+								Severity::Warning => "Warning",
+								Severity::Error => "Error",
+							};
+							write!(&mut state.heightmaps.heightmap_info_fake_input, "\n{severity}: {issue}").ok();
+						}
+
 						let info_size = ui.calc_text_size(&state.heightmaps.heightmap_info_fake_input);
 
 						ui.input_text_multiline(
@@ -658,22 +1263,45 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 							}) {
 								Ok(_) => {
 									log::info!("Exported PNG heightmap!");
-
-									if !state.heightmaps.opened_heightmaps_folder {
-										state.heightmaps.opened_heightmaps_folder = true;
-										if let Ok(path) = Path::new("heightmaps").canonicalize() {
-											open::that(path).ok();
-										}
-									}
+									open_heightmaps_folder(&mut state.heightmaps.opened_heightmaps_folder);
 								},
 
 								Err(err) => log::error!("Error exporting PNG heightmap: {err}"),
 							}
 						}
 
+						if ui.button_with_size("Export Rendered PNG", [-1.0, 0.0]) {
+							let rendered = smh_heightmap_ripper::render::render(heightmap, &state.heightmaps.render_options);
+							match std::fs::create_dir_all("heightmaps")
+								.map_err(Into::into)
+								.and_then(|_| rendered.save_with_format("heightmaps/exported_rendered.png", image::ImageFormat::Png))
+							{
+								Ok(_) => {
+									log::info!("Exported rendered PNG heightmap!");
+									open_heightmaps_folder(&mut state.heightmaps.opened_heightmaps_folder);
+								},
+
+								Err(err) => log::error!("Error exporting rendered PNG heightmap: {err}"),
+							}
+						}
+
 						font.end();
 					}
 
+					if matches!(&*opt, Some((LoadedHeightmap::Loaded { .. }, _)))
+						&& ui.collapsing_header("Terrain Changes", imgui::TreeNodeFlags::NO_TREE_PUSH_ON_OPEN)
+					{
+						reload |= render_terrain_diff(
+							ui,
+							state.display.get_context(),
+							state.renderer.textures(),
+							&mut state.heightmaps.terrain_diff,
+							state.heightmaps.preview_layer_path.as_deref(),
+							state.heightmaps.preview_pak_group.as_ref(),
+							heightmap,
+						);
+					}
+
 					ui.spacing();
 				}
 				Err(err) => {
@@ -686,7 +1314,7 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 	}
 
 	{
-		let layers = match state.heightmaps.layers.get() {
+		let mut layers = match state.heightmaps.layers.get() {
 			ImCellStateRef::Initialized(layers) => {
 				if (!state.heightmaps.squad_dir.is_empty() && layers.squad_dir.as_ref() != state.heightmaps.squad_dir.as_str())
 					|| (!state.heightmaps.aes_key.is_empty() && layers.aes_key.as_ref() != state.heightmaps.aes_key.as_str())
@@ -696,7 +1324,7 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 					state.heightmaps.layers.reset();
 					state.heightmaps.heightmap.reset();
 					state.heightmaps.heightmap_texture = None;
-					state.heightmaps.selected_layer = -1;
+					state.heightmaps.preview_layer_path = None;
 
 					None
 				} else {
@@ -708,11 +1336,26 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 				state.heightmaps.layers.load(LoadLayersOp {
 					squad_dir: Box::from(state.heightmaps.squad_dir.trim()),
 					aes_key: Box::from(state.heightmaps.aes_key.trim()),
+					mod_settings: SETTINGS.workshop_mods().clone(),
 				});
 				None
 			}
 		};
 
+		if let Some(Ok(discovered)) = layers.as_deref() {
+			if !discovered.mods.is_empty()
+				&& imgui::CollapsingHeader::new(format!("Workshop Mods ({})###WorkshopMods", discovered.mods.len())).build(ui)
+				&& render_workshop_mods(ui, &discovered.mods, &mut state.heightmaps.mod_aes_keys)
+			{
+				layers = None;
+
+				state.heightmaps.layers.reset();
+				state.heightmaps.heightmap.reset();
+				state.heightmaps.heightmap_texture = None;
+				state.heightmaps.preview_layer_path = None;
+			}
+		}
+
 		ui.spacing();
 
 		ui.align_text_to_frame_padding();
@@ -720,6 +1363,27 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 		ui.same_line();
 		ui.set_next_item_width(-1.0);
 		ui.input_text("##Filter", &mut state.heightmaps.filter).build();
+
+		ui.align_text_to_frame_padding();
+		ui.text("Group");
+		ui.same_line();
+		ui.set_next_item_width(-1.0);
+		let mut grouping = state.heightmaps.layer_grouping as usize;
+		if ui.combo_simple_string("##LayerGrouping", &mut grouping, &LayerGrouping::ALL.map(LayerGrouping::name)) {
+			state.heightmaps.layer_grouping = LayerGrouping::ALL[grouping];
+			SETTINGS.set_heightmap_layer_grouping(grouping as u8);
+		}
+
+		ui.align_text_to_frame_padding();
+		ui.text("Sort");
+		ui.same_line();
+		ui.set_next_item_width(-1.0);
+		let mut sort = state.heightmaps.layer_sort as usize;
+		if ui.combo_simple_string("##LayerSort", &mut sort, &LayerSort::ALL.map(LayerSort::name)) {
+			state.heightmaps.layer_sort = LayerSort::ALL[sort];
+			SETTINGS.set_heightmap_layer_sort(sort as u8);
+		}
+
 		ui.spacing();
 
 		let window = match imgui::ChildWindow::new("LayersList")
@@ -734,45 +1398,60 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 		};
 
 		match layers.as_deref() {
-			Some(Ok((layers, workshop_paks))) => {
-				if layers.is_empty() {
+			Some(Ok(discovered)) => {
+				if discovered.catalogue.is_empty() {
 					ui.text_centered("No layers found! Maybe your Squad directory or AES key are incorrect?");
 				} else {
-					let layer_labels = layers
-						.iter()
-						.enumerate()
-						.map(|(i, label)| {
-							let label = label.strip_prefix("SquadGame/Content/Maps/").unwrap_or_else(|| label.as_ref());
-							let label = label.strip_prefix("SquadGame/Plugins/").unwrap_or(label);
-							(i, label)
-						});
+					let (grouping, sort) = (state.heightmaps.layer_grouping, state.heightmaps.layer_sort);
 
 					let filter = state.heightmaps.filter.trim();
-					let (layer_refs, layer_labels): (Vec<usize>, Vec<&str>) = if !filter.is_empty() {
-						layer_labels.filter(|(_, label)| label.contains_ignore_ascii_case(filter)).unzip()
-					} else {
-						layer_labels.unzip()
-					};
+					let mut layers = discovered.catalogue.iter().filter(|layer| filter.is_empty() || layer.matches(filter)).collect::<Vec<_>>();
+					layers.sort_by(|a, b| grouping.group(a).cmp(&grouping.group(b)).then_with(|| sort.compare(a, b)));
 
-					if layer_labels.is_empty() {
+					let mut clicked = None;
+					if layers.is_empty() {
 						ui.text_wrapped("No results found!");
 					} else {
-						ui.set_next_item_width(-1.0);
-						if regenerate || ui.list_box("", &mut state.heightmaps.selected_layer, &*layer_labels, layer_labels.len() as i32) {
-							state.heightmaps.heightmap.reset();
-							state.heightmaps.heightmap_texture = None;
-
-							if (0..layer_labels.len() as i32).contains(&state.heightmaps.selected_layer) {
-								state.heightmaps.heightmap.load(LoadHeightmapOp {
-									aes_key: Box::from(state.heightmaps.aes_key.trim()),
-									paks_dir: (state.heightmaps.squad_dir.trim().to_owned() + "/SquadGame/Content/Paks").into_boxed_str(),
-									workshop_paks: workshop_paks.clone(),
-									layer_path: layers[layer_refs[state.heightmaps.selected_layer as usize]].clone(),
-									skip_cache: regenerate,
-								});
+						let selected = state.heightmaps.preview_layer_path.as_deref();
+
+						let mut layers = &layers[..];
+						while !layers.is_empty() {
+							let group = grouping.group(layers[0]);
+							let (group_layers, rest) = layers.split_at(layers.iter().take_while(|layer| grouping.group(layer) == group).count());
+							layers = rest;
+
+							match group {
+								None => render_layer_list(ui, group_layers, selected, &mut clicked),
+								Some(group) => {
+									if imgui::CollapsingHeader::new(format!("{group} ({})###{group}", group_layers.len()))
+										.default_open(grouping == LayerGrouping::Mod)
+										.build(ui)
+									{
+										render_layer_list(ui, group_layers, selected, &mut clicked);
+									}
+								}
 							}
 						}
 					}
+
+					if regenerate || reload {
+						clicked = state.heightmaps.preview_layer_path.clone();
+					}
+
+					if let Some(layer) = clicked.and_then(|clicked| discovered.catalogue.iter().find(|layer| layer.path == clicked)) {
+						state.heightmaps.heightmap.reset();
+						state.heightmaps.heightmap_texture = None;
+						state.heightmaps.preview_layer_path = Some(layer.path.clone());
+
+						let pak_group = discovered.pak_group(layer);
+						state.heightmaps.preview_pak_group = Some((pak_group.aes_key.clone(), pak_group.paks.clone()));
+						state.heightmaps.heightmap.load(LoadHeightmapOp {
+							aes_key: pak_group.aes_key.clone(),
+							paks: pak_group.paks.clone(),
+							layer_path: layer.path.clone(),
+							skip_cache: regenerate,
+						});
+					}
 				}
 			}
 
@@ -791,36 +1470,493 @@ pub(super) fn render_window(state: &mut UiState, ui: &Ui) {
 	window.end();
 }
 
-pub(super) fn render_overlay(state: &mut UiState, ui: &Ui) {
-	if state.heightmaps.draw_heightmap() {
-		if let Some((texture_id, offset, [width, height])) = state.heightmaps.selected_heightmap {
-			if let Some(minimap_viewport) = state.vision.minimap_bounds {
-				// To position the heightmap:
-				// 1. Add offset to the heightmap position, anchoring the heightmap to the bottom right corner
-				// 2. Scale the heightmap to the minimap size
-
-				let offset = if !state.heightmaps.fit_to_minimap() {
-					let hm_scale_factor_w = (minimap_viewport.right - minimap_viewport.left) as f32 / (width + offset[0]);
-					let hm_scale_factor_h = (minimap_viewport.bottom - minimap_viewport.top) as f32 / (height + offset[1]);
-					[offset[0] * hm_scale_factor_w * state.map.viewport.scale_factor_w, offset[1] * hm_scale_factor_h * state.map.viewport.scale_factor_h]
-				} else {
-					[0.0, 0.0]
-				};
+/// Returns true if any mod's settings were changed
+fn render_workshop_mods(ui: &Ui, mods: &[WorkshopMod], aes_keys: &mut BTreeMap<u64, String>) -> bool {
+	let mut changed = None;
+
+	for workshop_mod in mods {
+		let mut mod_settings = SETTINGS.workshop_mods().get(&workshop_mod.id).cloned().unwrap_or_default();
+
+		let mut enabled = !mod_settings.disabled;
+		if ui.checkbox(format!("{}##Mod{}", workshop_mod.name, workshop_mod.id), &mut enabled) {
+			mod_settings.disabled = !enabled;
+			changed = Some((workshop_mod.id, mod_settings.clone()));
+		}
+		if ui.is_item_hovered() {
+			ui.tooltip(|| {
+				ui.text(format!("Workshop ID: {}", workshop_mod.id));
+				for plugin in workshop_mod.plugins.iter() {
+					ui.text(format!("Plugin: {plugin}"));
+				}
+				for paks in workshop_mod.paks.iter() {
+					ui.text(&**paks);
+				}
+			});
+		}
+
+		let aes_key = aes_keys
+			.entry(workshop_mod.id)
+			.or_insert_with(|| mod_settings.aes_key.as_deref().unwrap_or_default().to_string());
+
+		ui.indent();
+		ui.set_next_item_width(-1.0);
+		if ui
+			.input_text(format!("##ModAESKey{}", workshop_mod.id), aes_key)
+			.hint("Default AES key")
+			.always_overwrite(false)
+			.enter_returns_true(true)
+			.build()
+		{
+			let aes_key = aes_key.trim();
+			mod_settings.aes_key = if aes_key.is_empty() { None } else { Some(Box::from(aes_key)) };
+			changed = Some((workshop_mod.id, mod_settings));
+		}
+		ui.unindent();
+	}
+
+	match changed {
+		Some((id, mod_settings)) => {
+			let mut settings = SETTINGS.workshop_mods().clone();
+			if mod_settings == ModSettings::default() {
+				settings.remove(&id);
+			} else {
+				settings.insert(id, mod_settings);
+			}
+			SETTINGS.set_workshop_mods(settings);
+			true
+		}
+		None => false,
+	}
+}
+
+/// Lists every Squad installation we found, best first, so that users with several Steam libraries can pick one
+fn render_squad_dir_candidates(state: &mut UiState, ui: &Ui) {
+	// Nothing to choose between if the only installation we found is already in use
+	let candidates = &state.heightmaps.squad_dir_candidates;
+	if candidates.len() < 2 && candidates.iter().all(|candidate| candidate.path.to_string_lossy() == state.heightmaps.squad_dir.trim()) {
+		return;
+	}
+
+	let mut clicked = None;
+	if imgui::CollapsingHeader::new(format!("Detected Installations ({})###SquadDirCandidates", state.heightmaps.squad_dir_candidates.len())).build(ui) {
+		for (i, candidate) in state.heightmaps.squad_dir_candidates.iter().enumerate() {
+			let path = candidate.path.to_string_lossy();
+			if imgui::Selectable::new(format!("{path}##SquadDirCandidate{i}"))
+				.selected(path == state.heightmaps.squad_dir.trim())
+				.disabled(!candidate.is_valid())
+				.build(ui)
+			{
+				clicked = Some(path.to_string());
+			}
+
+			if ui.is_item_hovered_with_flags(imgui::ItemHoveredFlags::ALLOW_WHEN_DISABLED) {
+				ui.tooltip(|| {
+					ui.text(&*path);
+					ui.text(format!("Found through {}", candidate.install));
+					if candidate.has_compatdata {
+						ui.text("Has a Proton prefix");
+					}
+					if !candidate.has_manifest {
+						ui.text("Not in Steam's app manifests, this might be left over from an uninstall");
+					}
+					if let Some(modified) = candidate.modified {
+						ui.text(format!("Paks modified: {}", fmt_system_time(modified)));
+					}
+					if !candidate.is_valid() {
+						ui.text_colored([1.0, 0.0, 0.0, 1.0], format!("{} is missing", smh_heightmap_ripper::steam::PAKS_DIR));
+					}
+				});
+			}
+		}
+	}
+
+	if let Some(squad_dir) = clicked {
+		state.heightmaps.layers.reset();
+		state.heightmaps.heightmap.reset();
+		state.heightmaps.heightmap_texture = None;
+		state.heightmaps.preview_layer_path = None;
+
+		SETTINGS.set_squad_dir(Some(Box::from(squad_dir.as_str())));
+		state.heightmaps.squad_dir = squad_dir;
+	}
+}
+
+fn render_layer_list(ui: &Ui, layers: &[&CatalogueLayer], selected: Option<&str>, clicked: &mut Option<Box<str>>) {
+	for layer in layers {
+		if imgui::Selectable::new(format!("{}##{}", layer.name, layer.path))
+			.selected(selected == Some(&*layer.path))
+			.build(ui)
+		{
+			*clicked = Some(layer.path.clone());
+		}
 
-				let minimap_viewport = Rect {
-					left: state.map.viewport.translate_x(minimap_viewport.left as f32) + offset[0],
-					top: state.map.viewport.translate_y(minimap_viewport.top as f32) + offset[1],
-					right: state.map.viewport.translate_x(minimap_viewport.right as f32),
-					bottom: state.map.viewport.translate_y(minimap_viewport.bottom as f32),
+		if ui.is_item_hovered() {
+			ui.tooltip(|| {
+				ui.text(&*layer.path);
+				ui.text(format!("Map: {}", layer.map));
+				if let Some(game_mode) = layer.game_mode {
+					ui.text(format!("Game Mode: {game_mode}"));
+				}
+				if let Some(ref mod_name) = layer.mod_name {
+					ui.text(format!("Mod: {mod_name}"));
+				}
+				if let Some(size) = layer.size {
+					ui.text(format!("Size: {}", fmt_map_size(size)));
+				}
+			});
+		}
+	}
+}
+
+/// Rips the previewed layer again and shows how its terrain differs from the cached heightmap
+///
+/// Returns true if the cached heightmap was replaced by the fresh one.
+fn render_terrain_diff(
+	ui: &Ui,
+	facade: &Rc<glium::backend::Context>,
+	textures: &mut Textures<Texture>,
+	terrain_diff: &mut TerrainDiffState,
+	layer: Option<&str>,
+	pak_group: Option<&(Box<str>, Box<[Box<str>]>)>,
+	cached: &Heightmap,
+) -> bool {
+	let (layer, (aes_key, paks)) = match (layer, pak_group) {
+		(Some(layer), Some(pak_group)) => (layer, pak_group),
+		_ => {
+			ui.text_disabled("The ripper isn't available for this layer");
+			return false;
+		}
+	};
+
+	let result = match terrain_diff.diff.get() {
+		ImCellStateRef::Loading => {
+			ui.text("Ripping and comparing... this might take a while!");
+			return false;
+		}
+		ImCellStateRef::Initialized(result) if &*result.layer == layer => Some(result),
+		_ => None,
+	};
+	let result = match result {
+		Some(result) => result,
+		None => {
+			if ui.button_with_size("Check for Changes", [-1.0, 0.0]) {
+				terrain_diff.diff.reset();
+				terrain_diff.diff.load(DiffHeightmapOp {
+					cached: cached.clone(),
+					aes_key: aes_key.clone(),
+					paks: paks.clone(),
+					layer_path: layer.into(),
+				});
+			}
+			if ui.is_item_hovered() {
+				ui.tooltip_text("Rips this layer again and compares it with the cached heightmap, e.g. after a game update");
+			}
+			return false;
+		}
+	};
+
+	let (fresh, diff) = match &result.result {
+		Ok(Some((fresh, diff))) => (fresh, diff),
+		Ok(None) => {
+			ui.text_wrapped("The game files no longer have a heightmap for this layer");
+			return false;
+		}
+		Err(err) => {
+			ui.text_colored([1.0, 0.0, 0.0, 1.0], format!("Error: {err}"));
+			return false;
+		}
+	};
+
+	if diff.is_unchanged() {
+		ui.text_colored([0.0, 1.0, 0.0, 1.0], "The terrain hasn't changed");
+	} else {
+		ui.text_colored([1.0, 1.0, 0.0, 1.0], "The terrain has changed");
+	}
+
+	ui.text(format!(
+		"Changed: {:.2}% in {} regions\nLargest rise: {:.1}m\nLargest fall: {:.1}m\nMean change: {:.2}m (RMS {:.2}m)",
+		diff.changed_fraction() * 100.0,
+		diff.regions.len(),
+		diff.stats.max_rise,
+		diff.stats.max_fall.abs(),
+		diff.stats.mean_abs_change,
+		diff.stats.rms_change
+	));
+	if diff.size_changed {
+		ui.text(format!("Size changed: {}x{} -> {}x{}", cached.width, cached.height, fresh.width, fresh.height));
+	}
+	if diff.bounds_changed {
+		ui.text(format!("Minimap bounds changed: {:?} -> {:?}", cached.bounds, fresh.bounds));
+	}
+	if diff.scale_changed {
+		ui.text(format!("Scale changed: {:?} -> {:?}", cached.scale, fresh.scale));
+	}
+
+	for region in diff.regions.iter().take(5) {
+		let [[x0, y0], [x1, y1]] = region.bounds;
+		ui.text_disabled(format!("{}x{}m at ({x0}, {y0}): {:+.1}m", x1 - x0 + 1, y1 - y0 + 1, region.max_change));
+	}
+
+	let diff_id = Arc::as_ptr(diff) as usize;
+	if terrain_diff.texture.map(|(id, _)| id) != Some(diff_id) {
+		let texture = Texture2d::with_format(
+			facade,
+			RawImage2d {
+				width: diff.width,
+				height: diff.height,
+				data: Cow::Owned(diff.to_image().into_raw()),
+				format: glium::texture::ClientFormat::U8U8U8U8,
+			},
+			glium::texture::UncompressedFloatFormat::U8U8U8U8,
+			glium::texture::MipmapsOption::NoMipmap,
+		)
+		.map(|texture| Texture {
+			texture: Rc::new(texture),
+			sampler: SamplerBehavior {
+				magnify_filter: glium::uniforms::MagnifySamplerFilter::Nearest,
+				minify_filter: glium::uniforms::MinifySamplerFilter::Linear,
+				..Default::default()
+			},
+		});
+
+		match texture {
+			Ok(texture) => {
+				let texture_id = match terrain_diff.texture {
+					Some((_, texture_id)) => {
+						textures.replace(texture_id, texture);
+						texture_id
+					}
+					None => textures.insert(texture),
 				};
+				terrain_diff.texture = Some((diff_id, texture_id));
+			}
+			Err(err) => log::error!("Error creating terrain difference texture: {err}"),
+		}
+	}
+
+	if let Some((_, texture_id)) = terrain_diff.texture {
+		let width = ui.content_region_avail()[0];
+		imgui::Image::new(texture_id, [width, width * (diff.height as f32 / diff.width.max(1) as f32)]).build(ui);
+	}
+
+	let mut updated = false;
+	if !diff.is_unchanged() && ui.button_with_size("Update Cached Heightmap", [-1.0, 0.0]) {
+		let cache_path = squadex::heightmaps::cache_path(layer);
+		match File::create(&cache_path).and_then(|mut w| squadex::heightmaps::serialize(&mut w, fresh)) {
+			Ok(_) => {
+				log::info!("Updated cached heightmap for {layer}");
+				updated = true;
+			}
+			Err(err) => log::error!("Error updating cached heightmap: {err}"),
+		}
+	}
+
+	drop(result);
+	if updated {
+		terrain_diff.diff.reset();
+	}
+
+	updated
+}
 
+fn sync_contours(state: &mut UiState) {
+	if state.heightmaps.draw_contours && matches!(state.heightmaps.contours.get(), ImCellStateRef::None) {
+		if let Some(heightmap) = squadex::heightmaps::get_current() {
+			state.heightmaps.contours.load(GenerateContoursOp {
+				heightmap: heightmap.clone(),
+				interval: state.heightmaps.contour_interval.max(1) as u16,
+				index_interval: state.heightmaps.contour_index_interval.max(0) as u16,
+			});
+		}
+	}
+
+	// Keep web clients in sync with whatever contours we're drawing
+	let contours = state.heightmaps.web_contours();
+	if contours.is_some() != state.heightmaps.contours_sent {
+		state.heightmaps.contours_sent = contours.is_some();
+
+		if let Some(ref server) = state.web.server {
+			server.send(smh_web::Event::Contours { contours });
+		}
+	}
+}
+
+fn draw_contours(ui: &Ui, contours: &[ContourLine], viewport: Rect<f32>, [width, height]: [f32; 2]) {
+	let dl = ui.get_background_draw_list();
+
+	let scale_x = viewport.width() / width;
+	let scale_y = viewport.height() / height;
+
+	for contour in contours {
+		let (color, thickness) = if contour.index { (CONTOUR_INDEX_COLOR, 2.0) } else { (CONTOUR_COLOR, 1.0) };
+
+		let points = contour
+			.points
+			.iter()
+			.map(|[x, y]| [viewport.left + x * scale_x, viewport.top + y * scale_y])
+			.collect::<Vec<_>>();
+
+		dl.add_polyline(points, color).thickness(thickness).build();
+	}
+}
+
+fn sync_registration(state: &mut UiState) {
+	let minimap_bounds = match state.vision.minimap_bounds {
+		// Zoomed in maps don't have the whole minimap to register against
+		Some(minimap_bounds) if state.vision.zoom.is_none() => minimap_bounds,
+		_ => return,
+	};
+
+	if SETTINGS.heightmap_registration() {
+		let resolution = [minimap_bounds.width(), minimap_bounds.height()];
+
+		let up_to_date = match state.heightmaps.registration.get() {
+			ImCellStateRef::Loading => true,
+			ImCellStateRef::Initialized(result) => {
+				result.resolution == resolution && Some(&result.layer) == state.heightmaps.selected_layer_path.as_ref() && !state.heightmaps.realign
+			}
+			ImCellStateRef::None => false,
+		};
+
+		let map = &state.vision.map;
+		if !up_to_date && minimap_bounds.right <= map.width() && minimap_bounds.bottom <= map.height() {
+			if let (Some(layer), Some(heightmap)) = (state.heightmaps.selected_layer_path.clone(), squadex::heightmaps::get_current()) {
+				let minimap = image::imageops::crop_imm(&**map, minimap_bounds.left, minimap_bounds.top, resolution[0], resolution[1]).to_image();
+
+				state.heightmaps.registration.load(RegisterHeightmapOp {
+					layer,
+					resolution,
+					heightmap: heightmap.clone(),
+					minimap: image::imageops::grayscale(&minimap),
+					skip_cache: core::mem::take(&mut state.heightmaps.realign),
+				});
+			}
+		}
+
+		// No need to ask the user how to fit the heightmap if we've worked it out ourselves
+		if state.heightmaps.fit_modal && state.heightmaps.registration(minimap_bounds).is_some() {
+			state.heightmaps.fit_modal = false;
+			state.heightmaps.fit_modal_hover = false;
+		}
+	}
+}
+
+/// Keeps web clients in sync with the alignment and grid we're using, so they don't have to work them out themselves
+fn sync_web(state: &mut UiState) {
+	let transform = squadex::heightmaps::get_current().map(|heightmap| {
+		match state.vision.minimap_bounds.and_then(|minimap_bounds| state.heightmaps.registration(minimap_bounds)) {
+			Some(registration) => registration.transform,
+			None => HeightmapTransform::unregistered(
+				[heightmap.width as f64, heightmap.height as f64],
+				[heightmap.bounds[0][0] as f64, heightmap.bounds[0][1] as f64],
+				state.heightmaps.fit_to_minimap,
+			),
+		}
+	});
+	if transform != state.heightmaps.sent_transform {
+		state.heightmaps.sent_transform = transform;
+
+		if let Some(ref server) = state.web.server {
+			server.send(smh_web::Event::HeightmapTransform { transform });
+		}
+	}
+
+	let grid = state.vision.minimap_px().and_then(|minimap_px| match transform {
+		Some(transform) => Some(squadex::grid::Grid::from_heightmap(&transform, minimap_px)),
+		None => state.vision.meters_to_px_ratio.map(|meters_to_px_ratio| squadex::grid::Grid::from_minimap(minimap_px, meters_to_px_ratio)),
+	});
+	if grid != state.heightmaps.sent_grid {
+		state.heightmaps.sent_grid = grid;
+
+		if let Some(ref server) = state.web.server {
+			server.send(smh_web::Event::Grid { grid: state.heightmaps.web_grid() });
+		}
+	}
+}
+
+fn sync_offline(state: &mut UiState) {
+	if !state.heightmaps.offline {
+		return;
+	}
+
+	let (layer, (aes_key, paks)) = match (&state.heightmaps.selected_layer_path, &state.heightmaps.selected_pak_group) {
+		(Some(layer), Some(pak_group)) => (layer.clone(), pak_group.clone()),
+		_ => {
+			state.heightmaps.set_offline(false);
+			return;
+		}
+	};
+
+	let minimap = match state.heightmaps.offline_minimap.get() {
+		ImCellStateRef::Loading => return,
+		ImCellStateRef::Initialized(result) if result.layer == layer => Some(match result.minimap {
+			Ok(Some(ref minimap)) => Ok(minimap.clone()),
+			Ok(None) => Err("This layer doesn't have a minimap".to_string()),
+			Err(ref err) => Err(format!("Error extracting minimap: {err}")),
+		}),
+		_ => None,
+	};
+
+	match minimap {
+		// Nothing loaded yet, or a different layer was selected
+		None => {
+			state.heightmaps.offline_minimap.reset();
+			state.heightmaps.offline_minimap.load(LoadMinimapOp { aes_key, paks, layer_path: layer });
+		}
+
+		Some(Ok(minimap)) => {
+			if squadex::offline::get().map(|offline_map| offline_map.layer != layer).unwrap_or(true) {
+				squadex::offline::set(Some(squadex::offline::OfflineMap {
+					meters_to_px_ratio: squadex::heightmaps::get_current().map(|heightmap| heightmap.width as f64 / minimap.width() as f64),
+					map: minimap,
+					layer,
+				}));
+			}
+		}
+
+		Some(Err(err)) => {
+			state.heightmaps.set_offline(false);
+			state.heightmaps.offline_error = Some(err);
+		}
+	}
+}
+
+pub(super) fn render_overlay(state: &mut UiState, ui: &Ui) {
+	sync_offline(state);
+	sync_contours(state);
+	sync_registration(state);
+	sync_web(state);
+
+	let draw_heightmap = state.heightmaps.draw_heightmap();
+	if !draw_heightmap && !state.heightmaps.draw_contours {
+		return;
+	}
+
+	if let Some((texture_id, offset, size)) = state.heightmaps.selected_heightmap {
+		if let (Some(minimap_bounds), Some(minimap_px)) = (state.vision.minimap_bounds, state.vision.minimap_px()) {
+			let transform = state.heightmaps.transform(size, offset, minimap_bounds);
+			let coords = coords::Transform::new(state.map.viewport.coords(), minimap_px, None);
+
+			let to_screen = |texel: [f64; 2]| coords.minimap_to_screen(coords::Minimap(transform.to_minimap(texel))).to_f32();
+
+			let [left, top] = to_screen([0.0, 0.0]);
+			let [right, bottom] = to_screen([size[0] as f64, size[1] as f64]);
+			let heightmap_viewport = Rect { left, top, right, bottom };
+
+			if state.heightmaps.draw_contours {
+				if let ImCellStateRef::Initialized(contours) = state.heightmaps.contours.get() {
+					draw_contours(ui, &contours, heightmap_viewport, size);
+				}
+			}
+
+			if draw_heightmap {
 				// cheating really
-				ui.set_cursor_pos(minimap_viewport.top_left());
+				ui.set_cursor_pos(heightmap_viewport.top_left());
 
-				imgui::Image::new(texture_id, [minimap_viewport.width(), minimap_viewport.height()])
+				imgui::Image::new(texture_id, [heightmap_viewport.width(), heightmap_viewport.height()])
 					.tint_col([1.0, 1.0, 1.0, 0.25])
 					.build(ui);
 			}
 		}
 	}
-}
\ No newline at end of file
+}
@@ -1,4 +1,5 @@
 use super::*;
+use smh_heightmap_ripper::coords;
 
 #[derive(Default)]
 pub(super) struct MapState {
@@ -76,6 +77,14 @@ impl MapViewport {
 		)
 	}
 
+	#[inline]
+	pub fn coords(&self) -> coords::Viewport {
+		coords::Viewport {
+			scale: [self.scale_factor_w as f64, self.scale_factor_h as f64],
+			top_left: [self.top_left[0] as f64, self.top_left[1] as f64],
+		}
+	}
+
 	#[inline]
 	pub fn translate_x<T: Copy>(&self, x: T) -> f32
 	where
@@ -97,7 +106,7 @@ impl MapViewport {
 	where
 		f32: LossyFrom<T>,
 	{
-		[self.translate_x(xy[0]), self.translate_y(xy[1])]
+		self.coords().to_screen(coords::MapPx::from([f32::lossy_from(xy[0]), f32::lossy_from(xy[1])])).to_f32()
 	}
 
 	#[inline]
@@ -121,14 +130,37 @@ impl MapViewport {
 	where
 		f32: LossyFrom<T>,
 	{
-		[self.inverse_x(xy[0]), self.inverse_y(xy[1])]
+		self.coords().to_map(coords::Screen::from([f32::lossy_from(xy[0]), f32::lossy_from(xy[1])])).to_f32()
 	}
 }
 
+/// Converts between the screen, the map, the minimap and the current heightmap, if the minimap has been found
+pub(super) fn coords(state: &UiState) -> Option<coords::Transform> {
+	let minimap_bounds = state.vision.minimap_bounds?;
+	let minimap_px = state.vision.minimap_px()?;
+
+	let heightmap = squadex::heightmaps::get_current().map(|heightmap| {
+		let transform = state.heightmaps.transform(
+			[heightmap.width as f32, heightmap.height as f32],
+			[heightmap.bounds[0][0] as f32, heightmap.bounds[0][1] as f32],
+			minimap_bounds,
+		);
+		coords::HeightmapFrame::new(&heightmap, transform)
+	});
+
+	Some(coords::Transform::new(state.map.viewport.coords(), minimap_px, heightmap))
+}
+
 const MAX_ZOOM: f32 = 4.0;
 const ZOOM_LEVELS: u8 = 10;
 const PAN_ACCELERATION: f32 = 2.0;
 
+/// Squad's own colour for the player icon
+const PLAYER_COLOR: [f32; 3] = [0.98, 0.82, 0.14];
+
+/// Friendly blue, like the icons themselves
+const ICON_COLOR: [f32; 3] = [0.3, 0.6, 1.0];
+
 pub(super) fn zoom_ctl(state: &mut UiState, ui: &Ui) {
 	if ui.is_any_item_focused() || ui.is_any_item_hovered() {
 		return;
@@ -229,6 +261,9 @@ pub(super) fn render(state: &mut UiState, ui: &Ui) {
 			web.send(smh_web::Event::Markers {
 				custom: false,
 				markers: state.vision.markers.iter().map(|marker| [marker.p0, marker.p1]).collect::<Box<_>>(),
+				fireteams: state.vision.markers.iter().map(Marker::fireteam_id).collect::<Box<_>>(),
+				commands: state.vision.markers.iter().map(Marker::command_id).collect::<Box<_>>(),
+				ids: state.vision.markers.iter().filter_map(|marker| marker.id).collect::<Box<_>>(),
 			});
 		}
 	}
@@ -254,6 +289,7 @@ pub(super) fn render(state: &mut UiState, ui: &Ui) {
 	}
 
 	heightmaps::render_overlay(state, ui);
+	viewshed::render_overlay(state, ui);
 
 	draw::render(state, ui);
 
@@ -262,12 +298,19 @@ pub(super) fn render(state: &mut UiState, ui: &Ui) {
 		.iter()
 		.enumerate()
 		.map(|(i, marker)| {
-			let f = (i + 1) as f32 / markers_n as f32;
-			(marker, [1. - f, f, 0.0])
+			// Detected markers keep their colour for as long as they're tracked
+			let f = match marker.id {
+				Some(id) => (id as f32 * 0.618_034).fract(),
+				None => (i + 1) as f32 / markers_n as f32,
+			};
+			(marker, marker.fireteam.map(Fireteam::rgb).unwrap_or([1. - f, f, 0.0]))
 		})
 		.for_each(|(marker, color)| {
 			markers::draw(state, ui, marker, color, DrawList::Background);
 		});
 
+	markers::draw_icons(state, ui, ICON_COLOR, DrawList::Background);
+	markers::draw_player(state, ui, PLAYER_COLOR, DrawList::Background);
+
 	settings::render_paused_overlay(state, ui);
 }
@@ -1,4 +1,5 @@
 use super::*;
+use smh_heightmap_ripper::coords;
 
 #[inline]
 fn draw_right_info_text(ui: &Ui, text: &str, x: f32, mut y: f32, color: [f32; 3], dl: &imgui::DrawListMut) {
@@ -20,6 +21,42 @@ fn draw_centered_info_text(ui: &Ui, text: &str, x: f32, mut y: f32, color: [f32;
 	}
 }
 
+/// The map grid, preferring the heightmap's registration over the scale read off of the map
+pub(super) fn grid(state: &UiState) -> Option<squadex::grid::Grid> {
+	let minimap_px = state.vision.minimap_px()?;
+
+	if let Some(frame) = map::coords(state).and_then(|coords| coords.heightmap) {
+		return Some(squadex::grid::Grid::from_heightmap(&frame.transform, minimap_px));
+	}
+
+	state.vision.meters_to_px_ratio.map(|meters_to_px_ratio| squadex::grid::Grid::from_minimap(minimap_px, meters_to_px_ratio))
+}
+
+/// Labels each end of the marker with its grid reference, just past the end of the line
+fn draw_grid_refs(state: &UiState, ui: &Ui, marker: &Marker, [p0, p1]: [[f32; 2]; 2], color: [f32; 3], dl: &imgui::DrawListMut) {
+	let grid = match grid(state) {
+		Some(grid) => grid,
+		None => return,
+	};
+
+	for (point, screen, other) in [(marker.p0, p0, p1), (marker.p1, p1, p0)] {
+		let grid_ref = match grid.grid_ref([point[0] as f64, point[1] as f64], squadex::grid::DEFAULT_KEYPAD_DEPTH) {
+			Some(grid_ref) => grid_ref,
+			None => continue,
+		};
+
+		let text = bumpalo::format!(in &state.ui_fmt_alloc, "{grid_ref}");
+		let text_size = ui.calc_text_size(&text);
+
+		let (dx, dy) = (screen[0] - other[0], screen[1] - other[1]);
+		let length = (dx * dx + dy * dy).sqrt().max(1.0);
+		let distance = 6.0 + (text_size[0] / 2.0).max(text_size[1] / 2.0);
+		let center = [screen[0] + dx / length * distance, screen[1] + dy / length * distance];
+
+		dl.add_text([center[0] - text_size[0] / 2.0, center[1] - text_size[1] / 2.0], color, &text);
+	}
+}
+
 pub(super) fn draw(state: &UiState, ui: &Ui, marker: &Marker, color: [f32; 3], draw_list: DrawList) {
 	use std::fmt::Write;
 
@@ -27,6 +64,15 @@ pub(super) fn draw(state: &UiState, ui: &Ui, marker: &Marker, color: [f32; 3], d
 
 	let [p0, p1] = [state.map.viewport.translate_xy(marker.p0), state.map.viewport.translate_xy(marker.p1)];
 
+	// Move, build etc. markers aren't something to fire on, so they're just labelled
+	if let Some(command) = marker.command.filter(|_| !marker.is_fire_target()) {
+		dl.add_line(p0, p1, color).thickness(1.0).build();
+
+		let text_size = ui.calc_text_size(command.name());
+		dl.add_text([p1[0] - text_size[0] / 2.0, p1[1] + 8.0], color, command.name());
+		return;
+	}
+
 	dl.add_line(p0, p1, color).thickness(2.0).build();
 
 	let mut meters = None;
@@ -34,56 +80,18 @@ pub(super) fn draw(state: &UiState, ui: &Ui, marker: &Marker, color: [f32; 3], d
 	let midpoint = Point::new((p0[0] + p1[0]) / 2., (p0[1] + p1[1]) / 2.);
 
 	let mut alt_delta = None;
-	if let Some(minimap_viewport) = state.vision.minimap_bounds {
-		if let Some(heightmap) = squadex::heightmaps::get_current() {
-			let offset = if !state.heightmaps.fit_to_minimap() {
-				let offset = [heightmap.bounds[0][0] as f32, heightmap.bounds[0][1] as f32];
-
-				let hm_scale_factor_w = minimap_viewport.width() as f32 / (heightmap.width as f32 + offset[0]);
-				let hm_scale_factor_h = minimap_viewport.height() as f32 / (heightmap.height as f32 + offset[1]);
-
-				[
-					offset[0] * hm_scale_factor_w * state.map.viewport.scale_factor_w,
-					offset[1] * hm_scale_factor_h * state.map.viewport.scale_factor_h,
-				]
-			} else {
-				[0.0, 0.0]
-			};
-
-			let minimap_viewport = Rect {
-				left: state.map.viewport.translate_x(minimap_viewport.left as f32) + offset[0],
-				top: state.map.viewport.translate_y(minimap_viewport.top as f32) + offset[1],
-				right: state.map.viewport.translate_x(minimap_viewport.right as f32),
-				bottom: state.map.viewport.translate_y(minimap_viewport.bottom as f32),
-			};
-
-			let p0_xf = (p0[0] as f64 - minimap_viewport.left as f64) / minimap_viewport.width() as f64;
-			let p0_yf = (p0[1] as f64 - minimap_viewport.top as f64) / minimap_viewport.height() as f64;
-
-			let p1_xf = (p1[0] as f64 - minimap_viewport.left as f64) / minimap_viewport.width() as f64;
-			let p1_yf = (p1[1] as f64 - minimap_viewport.top as f64) / minimap_viewport.height() as f64;
-
-			let p0_x = p0_xf * heightmap.width as f64;
-			let p0_y = p0_yf * heightmap.height as f64;
-			let p1_x = p1_xf * heightmap.width as f64;
-			let p1_y = p1_yf * heightmap.height as f64;
+	if let (Some(coords), Some(heightmap)) = (map::coords(state), squadex::heightmaps::get_current()) {
+		if let Some(frame) = coords.heightmap {
+			let t0 = frame.to_texel(coords.map_to_minimap(coords::MapPx::from(marker.p0)));
+			let t1 = frame.to_texel(coords.map_to_minimap(coords::MapPx::from(marker.p1)));
 
 			// The heightmap can be used to calculate a more accurate length than eyeballing the map scales
-			meters = Some(((p0_x - p1_x).powi(2) + (p0_y - p1_y).powi(2)).sqrt());
-
-			let p0_x = p0_x.round() as i32;
-			let p0_y = p0_y.round() as i32;
-			let p1_x = p1_x.round() as i32;
-			let p1_y = p1_y.round() as i32;
-
-			if p0_x >= 0
-				&& p0_y >= 0 && p1_x >= 0
-				&& p1_y >= 0 && p0_x < heightmap.width as i32
-				&& p0_y < heightmap.height as i32
-				&& p1_x < heightmap.width as i32
-				&& p1_y < heightmap.height as i32
-			{
-				alt_delta = Some(heightmap.height(p1_x as _, p1_y as _) - heightmap.height(p0_x as _, p0_y as _));
+			meters = Some(((t0.x() - t1.x()).powi(2) + (t0.y() - t1.y()).powi(2)).sqrt());
+
+			let [t0, t1] = [t0, t1].map(|texel| coords::Texel([texel.x().round(), texel.y().round()]));
+			let valid = |texel: coords::Texel| frame.contains(texel) && heightmap.is_valid(texel.x() as _, texel.y() as _);
+			if valid(t0) && valid(t1) {
+				alt_delta = Some(heightmap.height(t1.x() as _, t1.y() as _) - heightmap.height(t0.x() as _, t0.y() as _));
 			} else {
 				meters = None;
 			}
@@ -95,6 +103,8 @@ pub(super) fn draw(state: &UiState, ui: &Ui, marker: &Marker, color: [f32; 3], d
 		None => return,
 	};
 
+	draw_grid_refs(state, ui, marker, [p0, p1], color, &dl);
+
 	let angle = f32::atan2(p0[1] - p1[1], p0[0] - p1[0]);
 
 	let mut bearing_fwd = angle.to_degrees();
@@ -213,3 +223,35 @@ pub(super) fn draw(state: &UiState, ui: &Ui, marker: &Marker, color: [f32; 3], d
 	font.pop();
 	rotate.end();
 }
+
+/// Circles the player's own icon
+pub(super) fn draw_player(state: &UiState, ui: &Ui, color: [f32; 3], draw_list: DrawList) {
+	let player = match state.vision.player {
+		Some(player) => player,
+		None => return,
+	};
+
+	let dl = draw_list.get(ui);
+
+	let center = state.map.viewport.translate_xy([player.position.x, player.position.y]);
+	dl.add_circle(center, 10.0, color).thickness(2.0).build();
+}
+
+/// Boxes and labels the icons found on the map
+pub(super) fn draw_icons(state: &UiState, ui: &Ui, color: [f32; 3], draw_list: DrawList) {
+	if state.vision.icons.is_empty() {
+		return;
+	}
+
+	let dl = draw_list.get(ui);
+
+	for icon in state.vision.icons.iter() {
+		let half_size = icon.size as f32 / 2.0;
+		let top_left = state.map.viewport.translate_xy([icon.position.x - half_size, icon.position.y - half_size]);
+		let bottom_right = state.map.viewport.translate_xy([icon.position.x + half_size, icon.position.y + half_size]);
+		dl.add_rect(top_left, bottom_right, color).thickness(1.5).build();
+
+		let text_size = ui.calc_text_size(icon.kind.name());
+		dl.add_text([(top_left[0] + bottom_right[0] - text_size[0]) / 2.0, top_left[1] - text_size[1]], color, icon.kind.name());
+	}
+}
@@ -65,6 +65,7 @@ pub mod logs;
 pub mod debug;
 
 mod about;
+mod bundles;
 mod clipboard;
 mod draw;
 mod fonts;
@@ -77,6 +78,7 @@ mod window;
 mod state;
 mod update;
 mod settings;
+mod viewshed;
 
 #[path = "imgui.rs"]
 mod imgui_ex;
@@ -84,6 +86,7 @@ mod imgui_ex;
 use fonts::Fonts;
 use imgui_ex::ImguiEx;
 use map::MapViewport;
+use smh_vision_common::markers::{CommandMarker, Fireteam};
 use rotate::ImRotate;
 use state::UiState;
 pub use window::start;
@@ -125,6 +128,15 @@ pub struct Marker {
 	pub p0: [f32; 2],
 	pub p1: [f32; 2],
 	pub meters: Option<f64>,
+
+	/// Whose colour the marker was drawn in, only known for markers that were detected on the map
+	pub fireteam: Option<Fireteam>,
+
+	/// Stays the same for as long as a detected marker is on the map
+	pub id: Option<u32>,
+
+	/// The squad leader's command marker at the `p1` end, if there is one
+	pub command: Option<CommandMarker>,
 }
 impl Marker {
 	#[inline]
@@ -132,12 +144,33 @@ impl Marker {
 		Self {
 			p0,
 			p1,
+			fireteam: None,
+			id: None,
+			command: None,
 			meters: meters_to_px_ratio.map(|meters_to_px_ratio| {
 				let length = ((p0[0] as f64 - p1[0] as f64).powi(2) + (p0[1] as f64 - p1[1] as f64).powi(2)).sqrt();
 				length * meters_to_px_ratio
 			}),
 		}
 	}
+
+	/// How the fireteam is sent to web clients
+	#[inline]
+	pub fn fireteam_id(&self) -> u8 {
+		self.fireteam.map(|fireteam| fireteam as u8).unwrap_or(0)
+	}
+
+	/// How the command marker is sent to web clients
+	#[inline]
+	pub fn command_id(&self) -> u8 {
+		self.command.map(|command| command as u8).unwrap_or(0)
+	}
+
+	/// Whether this marker should be fired on, which is any marker without a command marker telling us otherwise
+	#[inline]
+	pub fn is_fire_target(&self) -> bool {
+		self.command.map(CommandMarker::is_fire_target).unwrap_or(true)
+	}
 }
 
 #[derive(Default, Debug)]
@@ -146,6 +179,23 @@ pub struct UiData {
 	pub markers: Box<[Marker]>,
 	pub map: Arc<image::RgbaImage>,
 	pub minimap_bounds: Option<Rect<u32>>,
+	pub zoom: Option<smh_heightmap_ripper::coords::MapZoom>,
+	pub player: Option<smh_vision_common::player::PlayerIcon>,
+	pub icons: Box<[smh_vision_common::icons::DetectedIcon<smh_vision_common::icons::MapIconKind>]>,
 	pub meters_to_px_ratio: Option<f64>,
 	pub debug: DebugBox,
+}
+impl UiData {
+	/// Where the minimap is in the captured map, which is partly off of it if the in-game map is zoomed in
+	pub fn minimap_px(&self) -> Option<[[f64; 2]; 2]> {
+		let minimap_bounds = self.minimap_bounds?;
+		let bounds = [
+			[minimap_bounds.left as f64, minimap_bounds.top as f64],
+			[minimap_bounds.right as f64, minimap_bounds.bottom as f64],
+		];
+		Some(match self.zoom {
+			Some(zoom) => zoom.minimap_bounds(bounds),
+			None => bounds,
+		})
+	}
 }
\ No newline at end of file
@@ -1,8 +1,9 @@
 use super::*;
+use smh_vision_common::icons::MapIconKind;
 
 pub(super) fn menu_bar(state: &UiState, ui: &Ui) {
 	let paused = SETTINGS.paused();
-	let mut toggle_pause = ui.is_key_pressed(imgui::Key::Space);
+	let mut toggle_pause = ui.is_key_pressed(imgui::Key::Space) && !ui.io().want_text_input;
 
 	if let Some(settings) = ui.begin_menu("Settings") {
 		if imgui::MenuItem::new("PAUSE").selected(paused).shortcut("Space").build(ui) {
@@ -25,11 +26,36 @@ pub(super) fn menu_bar(state: &UiState, ui: &Ui) {
 			SETTINGS.set_detect_markers(!detect_markers);
 		}
 
+		let hough_lines = SETTINGS.hough_lines();
+		if imgui::MenuItem::new("Faster Marker Detection (Hough)")
+			.selected(hough_lines)
+			.enabled(detect_markers)
+			.build(ui)
+		{
+			SETTINGS.set_hough_lines(!hough_lines);
+		}
+		if ui.is_item_hovered() {
+			ui.tooltip_text("Finds marker lines with a Hough transform, which is much faster without hardware acceleration");
+		}
+
 		let grayscale_map = SETTINGS.grayscale_map();
 		if imgui::MenuItem::new("Grayscale Map").selected(grayscale_map).build(ui) {
 			SETTINGS.set_grayscale_map(!grayscale_map);
 		}
 
+		if let Some(icons) = ui.begin_menu("Map Icons") {
+			ui.text_disabled("Templates are loaded from icons/<kind>/");
+
+			let map_icons = SETTINGS.map_icons();
+			for kind in MapIconKind::ALL {
+				if imgui::MenuItem::new(kind.name()).selected(map_icons & kind.bit() != 0).build(ui) {
+					SETTINGS.set_map_icons(map_icons ^ kind.bit());
+				}
+			}
+
+			icons.end();
+		}
+
 		settings.end();
 	}
 
@@ -13,6 +13,8 @@ pub(super) struct UiState {
 	pub map: map::MapState,
 	pub web: web::WebState,
 	pub heightmaps: heightmaps::HeightmapsUiState,
+	pub bundles: bundles::BundlesState,
+	pub viewshed: viewshed::ViewshedState,
 
 	pub new_data: bool,
 	pub frame: u64,
@@ -37,6 +39,8 @@ impl UiState {
 			draw: Default::default(),
 			web: Default::default(),
 			heightmaps: Default::default(),
+			bundles: Default::default(),
+			viewshed: Default::default(),
 			map: Default::default(),
 			update_check: Default::default(),
 			logs,
@@ -82,7 +86,7 @@ impl UiState {
 			if let Some(ref server) = self.web.server {
 				server.send(smh_web::Event::UpdateState {
 					meters_to_px_ratio: self.vision.meters_to_px_ratio,
-					minimap_bounds: self.vision.minimap_bounds
+					minimap_bounds: self.vision.minimap_px()
 				});
 			}
 		}
@@ -91,6 +95,7 @@ impl UiState {
 
 		if let Some(menu) = ui.begin_menu_bar() {
 			heightmaps::menu_bar(self, ui);
+			draw::menu_bar(self, ui);
 			web::menu_bar(self, ui);
 			settings::menu_bar(self, ui);
 			debug::menu_bar(ui, self);
@@ -105,6 +110,7 @@ impl UiState {
 
 		heightmaps::render_window(self, ui);
 		heightmaps::render_fit_modal(self, ui);
+		bundles::render_window(self, ui);
 		logs::render_window(self, ui);
 		web::render_popup(self, ui);
 		about::render_star_pls(self, ui);
@@ -0,0 +1,270 @@
+use super::*;
+use smh_heightmap_ripper::{
+	coords,
+	viewshed::{Viewshed, ViewshedCache, DEFAULT_EYE_HEIGHT},
+	Heightmap,
+};
+
+const VISIBLE_COLOR: [u8; 4] = [0, 255, 0, 30];
+const HIDDEN_COLOR: [u8; 4] = [0, 0, 0, 150];
+const OBSERVER_COLOR: [f32; 3] = [0.0, 1.0, 1.0];
+const LOS_CLEAR_COLOR: [f32; 3] = [0.0, 1.0, 0.0];
+const LOS_BLOCKED_COLOR: [f32; 3] = [1.0, 0.0, 0.0];
+
+const CACHE_CAPACITY: usize = 16;
+
+type ComputeViewshedResult = Arc<Viewshed>;
+struct ComputeViewshedOp {
+	heightmap: Heightmap,
+	observer: [u32; 2],
+	eye_height: f64,
+	radius: u32,
+}
+impl ComputeViewshedOp {
+	fn compute_viewshed(self) -> ComputeViewshedResult {
+		let viewshed = smh_heightmap_ripper::viewshed::viewshed(&self.heightmap, self.observer, self.eye_height, self.radius);
+		log::info!(
+			"Computed viewshed from {:?} ({}m eye height, {}m radius)",
+			viewshed.observer,
+			viewshed.eye_height,
+			viewshed.radius
+		);
+		Arc::new(viewshed)
+	}
+}
+
+pub struct ViewshedState {
+	placing: bool,
+	observer: Option<[u32; 2]>,
+	eye_height: f32,
+	radius: i32,
+
+	/// Identifies the heightmap that the observer and cache belong to
+	heightmap: Option<usize>,
+	cache: ViewshedCache,
+	compute: ImCell<ComputeViewshedOp, ComputeViewshedResult>,
+	current: Option<Arc<Viewshed>>,
+	texture: Option<(usize, TextureId)>,
+}
+impl Default for ViewshedState {
+	fn default() -> Self {
+		Self {
+			placing: false,
+			observer: None,
+			eye_height: DEFAULT_EYE_HEIGHT as f32,
+			radius: 1500,
+
+			heightmap: None,
+			cache: ViewshedCache::new(CACHE_CAPACITY),
+			compute: ImCell::new(ComputeViewshedOp::compute_viewshed, Some(ui::redraw)),
+			current: None,
+			texture: None,
+		}
+	}
+}
+impl ViewshedState {
+	fn clear(&mut self) {
+		self.placing = false;
+		self.observer = None;
+		self.current = None;
+		self.compute.reset();
+	}
+
+	#[inline]
+	fn is_current(&self, viewshed: &Viewshed, observer: [u32; 2]) -> bool {
+		viewshed.observer == observer && viewshed.eye_height == self.eye_height as f64 && viewshed.radius == self.radius as u32
+	}
+}
+
+pub(super) fn menu(state: &mut UiState, ui: &Ui, is_set: bool) {
+	let menu = match ui.begin_menu_with_enabled("Viewshed", is_set) {
+		Some(menu) => menu,
+		None => return,
+	};
+
+	if imgui::MenuItem::new("Place Observer").selected(state.viewshed.placing).build(ui) {
+		state.viewshed.placing = !state.viewshed.placing;
+	}
+	if ui.is_item_hovered() {
+		ui.tooltip_text("Click on the map to see what can be seen from there\n\nHold SHIFT to check line of sight from the observer to the cursor");
+	}
+
+	ui.set_next_item_width(100.0);
+	imgui::Slider::new("Eye Height (m)", 0.0, 50.0).display_format("%.1f").build(ui, &mut state.viewshed.eye_height);
+
+	ui.set_next_item_width(100.0);
+	imgui::Slider::new("Radius (m)", 100, 5000).build(ui, &mut state.viewshed.radius);
+
+	if imgui::MenuItem::new("Clear").enabled(state.viewshed.observer.is_some()).build(ui) {
+		state.viewshed.clear();
+	}
+
+	if let Some(ref viewshed) = state.viewshed.current {
+		ui.separator();
+		ui.text_disabled(format!("{:.0}% visible", viewshed.visible_fraction() * 100.0));
+	}
+
+	menu.end();
+}
+
+fn create_viewshed_texture(state: &mut UiState, viewshed: &Viewshed) -> Result<TextureId, glium::texture::TextureCreationError> {
+	let texture = Texture {
+		texture: Rc::new(Texture2d::with_format(
+			state.display.get_context(),
+			RawImage2d {
+				width: viewshed.width,
+				height: viewshed.height,
+				data: Cow::Owned(viewshed.to_image(VISIBLE_COLOR, HIDDEN_COLOR).into_raw()),
+				format: glium::texture::ClientFormat::U8U8U8U8,
+			},
+			glium::texture::UncompressedFloatFormat::U8U8U8U8,
+			glium::texture::MipmapsOption::NoMipmap,
+		)?),
+		sampler: SamplerBehavior {
+			magnify_filter: glium::uniforms::MagnifySamplerFilter::Nearest,
+			minify_filter: glium::uniforms::MinifySamplerFilter::Linear,
+			..Default::default()
+		},
+	};
+
+	Ok(if let Some((_, texture_id)) = state.viewshed.texture {
+		state.renderer.textures().replace(texture_id, texture);
+		texture_id
+	} else {
+		state.renderer.textures().insert(texture)
+	})
+}
+
+/// Finds or computes the viewshed for the current observer and settings
+fn sync_viewshed(state: &mut UiState, heightmap: &Heightmap) {
+	let observer = match state.viewshed.observer {
+		Some(observer) => observer,
+		None => return,
+	};
+
+	if state.viewshed.current.as_ref().map(|viewshed| state.viewshed.is_current(viewshed, observer)).unwrap_or(false) {
+		return;
+	}
+
+	let (eye_height, radius) = (state.viewshed.eye_height as f64, state.viewshed.radius.max(1) as u32);
+	if let Some(viewshed) = state.viewshed.cache.get(observer, eye_height, radius) {
+		state.viewshed.current = Some(viewshed);
+		return;
+	}
+
+	let computed = match state.viewshed.compute.get() {
+		ImCellStateRef::Loading => return,
+		ImCellStateRef::Initialized(viewshed) if state.viewshed.is_current(&viewshed, observer) => Some(viewshed.clone()),
+		_ => None,
+	};
+
+	match computed {
+		Some(viewshed) => {
+			state.viewshed.cache.insert(viewshed.clone());
+			state.viewshed.current = Some(viewshed);
+		}
+		None => {
+			state.viewshed.compute.reset();
+			state.viewshed.compute.load(ComputeViewshedOp {
+				heightmap: heightmap.clone(),
+				observer,
+				eye_height,
+				radius,
+			});
+		}
+	}
+}
+
+pub(super) fn render_overlay(state: &mut UiState, ui: &Ui) {
+	let heightmap = match squadex::heightmaps::get_current() {
+		Some(heightmap) => heightmap,
+		None => {
+			if state.viewshed.heightmap.take().is_some() {
+				state.viewshed.clear();
+				state.viewshed.cache.clear();
+			}
+			return;
+		}
+	};
+
+	// Observers and cached viewsheds are meaningless on a different heightmap
+	let heightmap_id = heightmap.data.as_ptr() as usize;
+	if state.viewshed.heightmap != Some(heightmap_id) {
+		state.viewshed.heightmap = Some(heightmap_id);
+		state.viewshed.clear();
+		state.viewshed.cache.clear();
+	}
+
+	let coords = match map::coords(state) {
+		Some(coords) if coords.heightmap.is_some() => coords,
+		_ => return,
+	};
+
+	let mouse_pos = Some(ui.io().mouse_pos).filter(|mouse_pos| *mouse_pos != [f32::MAX, f32::MAX] && !ui.is_any_item_hovered());
+	let mouse_texel = mouse_pos.and_then(|mouse_pos| coords.screen_to_texel(coords::Screen::from(mouse_pos))).map(|texel| texel.0);
+
+	if state.viewshed.placing {
+		ui.set_mouse_cursor(Some(imgui::MouseCursor::Hand));
+
+		if ui.is_mouse_clicked(imgui::MouseButton::Left) {
+			if let Some([x, y]) = mouse_texel.filter(|[x, y]| *x >= 0.0 && *y >= 0.0 && *x < heightmap.width as f64 && *y < heightmap.height as f64) {
+				state.viewshed.observer = Some([x.round() as u32, y.round() as u32]);
+				state.viewshed.placing = false;
+			}
+		}
+	}
+
+	sync_viewshed(state, &heightmap);
+
+	let observer = match state.viewshed.observer {
+		Some(observer) => observer,
+		None => return,
+	};
+
+	if let Some(ref viewshed) = state.viewshed.current.clone() {
+		let viewshed_id = Arc::as_ptr(viewshed) as usize;
+		if state.viewshed.texture.map(|(id, _)| id) != Some(viewshed_id) {
+			match create_viewshed_texture(state, viewshed) {
+				Ok(texture_id) => state.viewshed.texture = Some((viewshed_id, texture_id)),
+				Err(err) => log::warn!("Failed to create viewshed texture: {err}"),
+			}
+		}
+	}
+
+	let to_screen = |texel: [f64; 2]| coords.texel_to_screen(coords::Texel(texel)).unwrap_or_default().to_f32();
+
+	if let (Some(viewshed), Some((_, texture_id))) = (&state.viewshed.current, state.viewshed.texture) {
+			let p_min = to_screen([viewshed.origin[0] as f64, viewshed.origin[1] as f64]);
+			let p_max = to_screen([(viewshed.origin[0] + viewshed.width) as f64, (viewshed.origin[1] + viewshed.height) as f64]);
+			ui.get_background_draw_list().add_image(texture_id, p_min, p_max).build();
+	}
+
+	let dl = ui.get_background_draw_list();
+
+	let observer = [observer[0] as f64, observer[1] as f64];
+	let observer_pos = to_screen(observer);
+	dl.add_circle(observer_pos, 6.0, OBSERVER_COLOR).thickness(2.0).build();
+
+	// Point to point line of sight, e.g. for checking where a spotter can see
+	if ui.io().key_shift && !state.viewshed.placing {
+		if let (Some(mouse_pos), Some(target)) = (mouse_pos, mouse_texel) {
+			let eye_height = state.viewshed.eye_height as f64;
+			let los = smh_heightmap_ripper::viewshed::line_of_sight(&heightmap, observer, target, eye_height, DEFAULT_EYE_HEIGHT);
+
+			match los.obstruction {
+				Some(obstruction) => {
+					let obstruction_pos = to_screen(obstruction);
+					dl.add_line(observer_pos, obstruction_pos, LOS_CLEAR_COLOR).thickness(2.0).build();
+					dl.add_line(obstruction_pos, mouse_pos, LOS_BLOCKED_COLOR).thickness(2.0).build();
+
+					let distance = ((obstruction[0] - observer[0]).powi(2) + (obstruction[1] - observer[1]).powi(2)).sqrt();
+					ui.tooltip_text(format!("Line of sight blocked {distance:.0}m from the observer"));
+				}
+				None => {
+					dl.add_line(observer_pos, mouse_pos, LOS_CLEAR_COLOR).thickness(2.0).build();
+					ui.tooltip_text("Line of sight is clear");
+				}
+			}
+		}
+	}
+}
@@ -98,11 +98,16 @@ pub(super) fn render_popup(state: &mut UiState, ui: &Ui) {
 					let event_data = smh_web::EventData {
 						map: state.vision.map.clone(),
 						computer_vision_markers: state.vision.markers.iter().map(|marker| [marker.p0, marker.p1]).collect::<Box<_>>(),
+						computer_vision_fireteams: state.vision.markers.iter().map(Marker::fireteam_id).collect::<Box<_>>(),
+						computer_vision_commands: state.vision.markers.iter().map(Marker::command_id).collect::<Box<_>>(),
+						computer_vision_ids: state.vision.markers.iter().filter_map(|marker| marker.id).collect::<Box<_>>(),
 						custom_markers: Box::from(&*state.draw.custom_markers),
 						meters_to_px_ratio: state.vision.meters_to_px_ratio,
-						minimap_bounds: state.vision.minimap_bounds,
+						minimap_bounds: state.vision.minimap_px(),
 						heightmap: squadex::heightmaps::get_current().as_deref().map(ToOwned::to_owned),
-						heightmap_fit_to_minimap: state.heightmaps.fit_to_minimap
+						contours: state.heightmaps.web_contours(),
+						heightmap_transform: state.heightmaps.web_transform(),
+						grid: state.heightmaps.web_grid()
 					};
 
 					let port = (*port).max(0).min(u16::MAX as i32) as u16;
@@ -138,7 +143,11 @@ pub(super) fn handle_interactions(state: &mut UiState) {
 	while let Some(interaction) = state.web.server.as_mut().sus_unwrap().recv() {
 		match interaction {
 			Interaction::AddCustomMarker([p0, p1]) => draw::add_marker(state, p0, p1),
-			Interaction::DeleteCustomMarker(id) => draw::delete_marker(state, id as usize)
+			Interaction::DeleteCustomMarker(id) => draw::delete_marker(state, id as usize),
+			Interaction::AddGridMarker { gun, target } => {
+				let error = draw::add_grid_marker(state, &gun, &target).err().map(|err| err.to_string().into_boxed_str());
+				state.web.server.as_ref().sus_unwrap().send(Event::GridMarkerPlaced { error });
+			}
 		}
 	}
 }
\ No newline at end of file
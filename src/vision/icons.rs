@@ -0,0 +1,87 @@
+//! Finds FOBs, HABs, rallies, emplacements and vehicles on the map
+//!
+//! SMH only ships a template for one kind of truck, cut from a screenshot,
+//! so the rest are loaded from `icons/<kind>/` (e.g. `icons/fob/`), as PNG or TGA.
+//! Icons rarely move, so the (relatively slow) search only runs every so often and its results are reused in between.
+
+use super::*;
+use smh_vision_common::icons::{builtin_templates, DetectedIcon, IconLibrary, MapIconKind};
+
+const ICONS_DIR: &str = "icons";
+
+/// Sizes to try each template at, in map pixels
+///
+/// The map sidebar's icon scale setting isn't something we can read, so this covers everything from its smallest to its largest
+const ICON_SIZES: core::ops::RangeInclusive<u32> = 14..=34;
+const ICON_SIZE_STEP: usize = 2;
+
+const SEARCH_INTERVAL: Duration = Duration::from_secs(1);
+
+fn load_library() -> IconLibrary<MapIconKind> {
+	let mut templates = builtin_templates().collect::<Vec<_>>();
+	let builtin = templates.len();
+
+	for kind in MapIconKind::ALL {
+		let dir = match std::fs::read_dir(std::path::Path::new(ICONS_DIR).join(kind.dir_name())) {
+			Ok(dir) => dir,
+			Err(_) => continue,
+		};
+
+		for path in dir.filter_map(|entry| Some(entry.ok()?.path())) {
+			let is_image = path
+				.extension()
+				.and_then(|extension| extension.to_str())
+				.map(|extension| extension.eq_ignore_ascii_case("png") || extension.eq_ignore_ascii_case("tga"))
+				.unwrap_or(false);
+			if !is_image {
+				continue;
+			}
+
+			match image::open(&path) {
+				Ok(template) => templates.push((kind, template.into_rgba8())),
+				Err(err) => log::warn!("Failed to load icon template {}: {err}", path.display()),
+			}
+		}
+	}
+
+	if templates.len() == builtin {
+		log::info!("No map icon templates found in {ICONS_DIR}/, so only the built in ones will be highlighted");
+	} else {
+		log::info!("Loaded {} map icon templates from {ICONS_DIR}/", templates.len() - builtin);
+	}
+
+	IconLibrary::new(templates, ICON_SIZES.step_by(ICON_SIZE_STEP))
+}
+
+#[derive(Default)]
+pub struct IconState {
+	library: Option<IconLibrary<MapIconKind>>,
+	last_search: Option<Instant>,
+	icons: Vec<DetectedIcon<MapIconKind>>,
+}
+impl IconState {
+	/// Finds the icons of the kinds in `kinds` (a set of `MapIconKind::bit`s), reusing the last search if it was recent enough
+	pub fn find<I>(&mut self, map: &I, kinds: u8) -> Vec<DetectedIcon<MapIconKind>>
+	where
+		I: image::GenericImageView + Sync,
+		I::Pixel: image::Pixel<Subpixel = u8>,
+	{
+		if kinds == 0 {
+			self.last_search = None;
+			self.icons.clear();
+			return Vec::new();
+		}
+
+		let library = self.library.get_or_insert_with(load_library);
+		if library.is_empty() {
+			return Vec::new();
+		}
+
+		if self.last_search.map(|last_search| last_search.elapsed() >= SEARCH_INTERVAL).unwrap_or(true) {
+			self.icons = library.find(map);
+			self.last_search = Some(Instant::now());
+		}
+
+		self.icons.iter().filter(|icon| kinds & icon.kind.bit() != 0).copied().collect()
+	}
+}
@@ -1,4 +1,4 @@
-use smh_vision_common::Vision;
+use smh_vision_common::{icons::{DetectedIcon, MapIconKind}, markers::{classify_marker_line, CommandMarker, CommandMarkerClassifier, Fireteam}, player::{find_player_icon, PlayerIcon}, Vision};
 use crate::{prelude::*, capture::Frame};
 
 pub const FPS: u32 = 15;
@@ -11,6 +11,13 @@ use mpx_ratio::calc_meters_to_px_ratio;
 mod find_minimap;
 use find_minimap::find_minimap;
 
+mod zoom;
+
+mod tracker;
+use tracker::{MarkerTracker, TrackedMarker};
+
+mod icons;
+
 struct DebugWaterfall(*mut Option<Duration>, Instant);
 impl Drop for DebugWaterfall {
 	#[inline(always)]
@@ -21,16 +28,22 @@ impl Drop for DebugWaterfall {
 
 #[derive(Default, Debug)]
 pub struct VisionResults {
-	pub markers: SmallVec<Line<f32>, 32>,
+	pub markers: SmallVec<(Line<f32>, Option<Fireteam>, Option<CommandMarker>), 32>,
 	pub meters_to_px_ratio: Option<f64>,
 	pub minimap_bounds: Option<Rect<u32>>,
+	pub zoom: Option<smh_heightmap_ripper::coords::MapZoom>,
+	pub player: Option<PlayerIcon>,
+	pub icons: Vec<DetectedIcon<MapIconKind>>,
 	pub map: image::RgbaImage,
 	pub debug_view: Option<Arc<image::RgbaImage>>
 }
 pub struct VisionState {
 	threads: rayon::ThreadPool,
 	find_scales_threads: rayon::ThreadPool,
-	find_minimap_threads: rayon::ThreadPool
+	find_minimap_threads: rayon::ThreadPool,
+	zoom: zoom::ZoomState,
+	icons: icons::IconState,
+	command_markers: CommandMarkerClassifier
 }
 impl VisionState {
 	fn process<V: Vision>(&mut self, vision: &mut V, frame: Frame, debug: &mut DebugBox) -> Result<Option<VisionResults>, AnyError>
@@ -38,9 +51,13 @@ impl VisionState {
 		AnyError: std::convert::From<V::Error>
 	{
 		// split the borrow, allowing for the rayon tasks to mutate the DebugBox in parallel!
-		let DebugBox { timeshares, ocr: ocr_debug, scales: scales_debug, .. } = debug;
+		let DebugBox { timeshares, ocr: ocr_debug, scales: scales_debug, circles: circles_debug, .. } = debug;
 
-		let (ocr_overlay, scales_overlay) = (SYNCED_DEBUG_STATE.ocr_overlay(), SYNCED_DEBUG_STATE.scales_overlay());
+		let (ocr_overlay, scales_overlay, circles_overlay) = (
+			SYNCED_DEBUG_STATE.ocr_overlay(),
+			SYNCED_DEBUG_STATE.scales_overlay(),
+			SYNCED_DEBUG_STATE.circles_overlay(),
+		);
 
 		/*
 		DISABLED: see filter_map_marker_icons in this file
@@ -109,10 +126,15 @@ impl VisionState {
 					// Perform line segment detection on the map to find the map marker lines (i.e. what the player/squad leader is ordering mortar fire on)
 					debug_waterfall!(mask_marker_lines => vision.mask_marker_lines())?;
 
-					debug_waterfall!(find_marker_lines => vision.find_marker_lines(
-						/* DISABLED ((map_marker_size as f32) * MAP_MARKER_POI_LOCATION) as u32 */
-						15
-					))?
+					debug_waterfall!(find_marker_lines => if SETTINGS.hough_lines() {
+						vision.find_marker_lines_hough(15, circles_overlay.then_some(&mut *circles_debug))
+					} else {
+						vision.find_marker_lines(
+							/* DISABLED ((map_marker_size as f32) * MAP_MARKER_POI_LOCATION) as u32 */
+							15,
+							circles_overlay.then_some(&mut *circles_debug)
+						)
+					})?
 				} else {
 					Default::default()
 				})
@@ -223,9 +245,46 @@ impl VisionState {
 			};
 
 			result.minimap_bounds = minimap_bounds;
-			result.markers = markers?;
+
+			// Line segment detection only sees every marker colour merged together, so go back to the map to see whose markers they are
+			let frame = vision.get_cpu_frame();
+			let map = frame.view(x, y, w, h);
+			for line in markers? {
+				// The end with the squad leader's command marker on it is the target, which we keep as p1
+				let (line, command) = match self.command_markers.classify(&map, line.p1) {
+					Some(command) => (line, Some(command)),
+					None => match self.command_markers.classify(&map, line.p0) {
+						Some(command) => (Line { p0: line.p1, p1: line.p0 }, Some(command)),
+						None => (line, None)
+					}
+				};
+				result.markers.push((line, classify_marker_line(&map, line), command));
+			}
+			result.player = debug_waterfall!(find_player => self.threads.install(|| find_player_icon(&map)));
+
+			let map_icons = SETTINGS.map_icons();
+			if map_icons != 0 {
+				let (threads, icons) = (&self.threads, &mut self.icons);
+				result.icons = debug_waterfall!(find_icons => threads.install(|| icons.find(&map, map_icons)));
+			}
 			result.meters_to_px_ratio = meters_to_px_ratio?;
 
+			// The minimap's edges are off screen once the map is zoomed in, so work out where we are on the last zoomed-out map instead
+			if let Some(minimap_bounds) = minimap_bounds {
+				if self.zoom.wants_reference([w, h], minimap_bounds) {
+					let map = image::imageops::grayscale(&vision.get_cpu_frame().view(x, y, w, h));
+					self.zoom.set_reference(&map, minimap_bounds, result.meters_to_px_ratio);
+				}
+			} else {
+				let map = image::imageops::grayscale(&vision.get_cpu_frame().view(x, y, w, h));
+				let (threads, zoom) = (&self.threads, &mut self.zoom);
+				if let Some(zoomed) = debug_waterfall!(estimate_zoom => threads.install(|| zoom.estimate(&map, result.meters_to_px_ratio))) {
+					result.zoom = Some(zoomed.zoom);
+					result.minimap_bounds = Some(zoomed.minimap_bounds);
+					result.meters_to_px_ratio = zoomed.meters_to_px_ratio;
+				}
+			}
+
 			Ok(Some(result))
 		})();
 
@@ -246,10 +305,15 @@ pub fn start() {
 	let mut state = VisionState {
 		threads: rayon::ThreadPoolBuilder::new().num_threads(4).build().expect("Failed to create rayon thread pool"),
 		find_scales_threads: rayon::ThreadPoolBuilder::new().num_threads(3).build().expect("Failed to create rayon thread pool"),
-		find_minimap_threads: rayon::ThreadPoolBuilder::new().num_threads(4).build().expect("Failed to create rayon thread pool")
+		find_minimap_threads: rayon::ThreadPoolBuilder::new().num_threads(4).build().expect("Failed to create rayon thread pool"),
+		zoom: Default::default(),
+		icons: Default::default(),
+		command_markers: CommandMarkerClassifier::new()
 	};
 
 	let fps_interval = Duration::from_secs_f32(1.0 / FPS as f32);
+	let mut last_offline_map = None;
+	let mut tracker = MarkerTracker::default();
 	loop {
 		if crate::is_shutdown() {
 			break;
@@ -263,6 +327,34 @@ pub fn start() {
 			}
 		}
 
+		// Offline planning bypasses the vision pipeline entirely
+		if let Some(offline_map) = squadex::offline::get() {
+			tracker.clear();
+			if !last_offline_map.as_ref().map(|last| Arc::ptr_eq(last, &offline_map)).unwrap_or(false) {
+				ui::update(|ui_data| {
+					ui_data.debug = DebugBox::default();
+					ui_data.sleeping = false;
+					ui_data.map = offline_map.map.clone();
+					ui_data.meters_to_px_ratio = offline_map.meters_to_px_ratio;
+					ui_data.minimap_bounds = Some(Rect {
+						left: 0,
+						top: 0,
+						right: offline_map.map.width(),
+						bottom: offline_map.map.height(),
+					});
+					ui_data.zoom = None;
+					ui_data.player = None;
+					ui_data.icons = Default::default();
+					ui_data.markers = Default::default();
+				});
+				last_offline_map = Some(offline_map);
+			}
+
+			std::thread::sleep(fps_interval);
+			continue;
+		}
+		last_offline_map = None;
+
 		if let Some(frame) = ui::debug::FakeInputs::selected().map(|image| Frame { dpi: None, image }).or_else(capture::fresh_frame) {
 			let last_frame = Instant::now();
 
@@ -276,6 +368,14 @@ pub fn start() {
 				vision.ok().flatten()
 			};
 
+			let markers = match &vision {
+				Some(vision) => tracker.update(&vision.markers, Instant::now()),
+				None => {
+					tracker.clear();
+					Vec::new()
+				}
+			};
+
 			ui::update(|ui_data| {
 				ui_data.debug = debug_box;
 
@@ -295,10 +395,21 @@ pub fn start() {
 
 				ui_data.minimap_bounds = vision.minimap_bounds;
 
+				ui_data.zoom = vision.zoom;
+
+				ui_data.player = vision.player;
+
+				ui_data.icons = vision.icons.into_boxed_slice();
+
 				ui_data.debug.debug_view = vision.debug_view;
 
-				ui_data.markers = vision.markers.into_iter().map(|Line { p0, p1 }| {
-					ui::Marker::new(p0.into(), p1.into(), vision.meters_to_px_ratio)
+				ui_data.markers = markers.into_iter().map(|TrackedMarker { id, line: Line { p0, p1 }, fireteam, command }| {
+					ui::Marker {
+						id: Some(id),
+						fireteam,
+						command,
+						..ui::Marker::new(p0.into(), p1.into(), vision.meters_to_px_ratio)
+					}
 				}).collect::<Box<_>>();
 			});
 
@@ -0,0 +1,187 @@
+//! Follows detected markers from frame to frame
+//!
+//! Line segment detection starts from scratch every frame, so on its own markers flicker in and out, come back in a different order and
+//! jitter by a pixel or two. This matches each frame's lines to the ones we already know about, gives them IDs that stay the same for as
+//! long as the marker is on the map, smooths their endpoints and keeps them around for a moment if they drop out.
+
+use super::*;
+use smh_vision_common::markers::{CommandMarker, Fireteam};
+
+/// Endpoints further than this from a marker's (in map pixels) make it a different marker
+const MAX_MATCH_DISTANCE: f32 = 12.0;
+
+/// Endpoints that move further than this have actually moved, rather than jittered, so aren't smoothed
+const SNAP_DISTANCE: f32 = 4.0;
+
+/// How much of each new detection is blended into a marker's endpoints
+const SMOOTHING: f32 = 0.35;
+
+/// How long a marker sticks around after it was last detected
+const GRACE_PERIOD: Duration = Duration::from_millis(750);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrackedMarker {
+	pub id: u32,
+	pub line: Line<f32>,
+	pub fireteam: Option<Fireteam>,
+	pub command: Option<CommandMarker>,
+}
+
+struct Track {
+	marker: TrackedMarker,
+	last_seen: Instant,
+}
+
+#[derive(Default)]
+pub struct MarkerTracker {
+	tracks: Vec<Track>,
+	next_id: u32,
+}
+impl MarkerTracker {
+	/// Forgets every marker, e.g. when the map is closed
+	pub fn clear(&mut self) {
+		self.tracks.clear();
+	}
+
+	/// Matches this frame's detections to the markers we're tracking, returning every marker that's still alive
+	pub fn update(&mut self, detections: &[(Line<f32>, Option<Fireteam>, Option<CommandMarker>)], now: Instant) -> Vec<TrackedMarker> {
+		// Every pairing that's close enough, closest first
+		let mut pairs = self
+			.tracks
+			.iter()
+			.enumerate()
+			.flat_map(|(track, Track { marker, .. })| {
+				detections.iter().enumerate().filter_map(move |(detection, (line, ..))| {
+					let (distance, flipped) = distance(&marker.line, line);
+					(distance <= MAX_MATCH_DISTANCE).then_some((distance, track, detection, flipped))
+				})
+			})
+			.collect::<Vec<_>>();
+		pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+		let mut matched_tracks = vec![false; self.tracks.len()];
+		let mut matched_detections = vec![false; detections.len()];
+		for (_, track, detection, flipped) in pairs {
+			if matched_tracks[track] || matched_detections[detection] {
+				continue;
+			}
+			matched_tracks[track] = true;
+			matched_detections[detection] = true;
+
+			let (line, fireteam, command) = detections[detection];
+			let Track { marker, last_seen } = &mut self.tracks[track];
+
+			// The end with the command marker on it is the target, so the detection's direction wins if it knows which end that is
+			let flip = |line: Line<f32>| Line { p0: line.p1, p1: line.p0 };
+			let line = match (flipped, command) {
+				(false, _) => line,
+				(true, None) => flip(line),
+				(true, Some(_)) => {
+					marker.line = flip(marker.line);
+					line
+				}
+			};
+
+			marker.line = Line {
+				p0: smooth(marker.line.p0, line.p0),
+				p1: smooth(marker.line.p1, line.p1),
+			};
+			marker.fireteam = fireteam.or(marker.fireteam);
+			marker.command = command.or(marker.command);
+			*last_seen = now;
+		}
+
+		for (detection, (line, fireteam, command)) in detections.iter().enumerate() {
+			if matched_detections[detection] {
+				continue;
+			}
+
+			self.tracks.push(Track {
+				marker: TrackedMarker {
+					id: self.next_id,
+					line: *line,
+					fireteam: *fireteam,
+					command: *command,
+				},
+				last_seen: now,
+			});
+			self.next_id = self.next_id.wrapping_add(1);
+		}
+
+		self.tracks.retain(|track| now.saturating_duration_since(track.last_seen) <= GRACE_PERIOD);
+
+		self.tracks.iter().map(|track| track.marker).collect()
+	}
+}
+
+#[inline]
+fn point_distance(a: Point<f32>, b: Point<f32>) -> f32 {
+	((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+/// The furthest apart that the two lines' endpoints are, and whether the second line runs the other way
+#[inline]
+fn distance(a: &Line<f32>, b: &Line<f32>) -> (f32, bool) {
+	let same = point_distance(a.p0, b.p0).max(point_distance(a.p1, b.p1));
+	let flipped = point_distance(a.p0, b.p1).max(point_distance(a.p1, b.p0));
+	if flipped < same {
+		(flipped, true)
+	} else {
+		(same, false)
+	}
+}
+
+#[inline]
+fn smooth(old: Point<f32>, new: Point<f32>) -> Point<f32> {
+	if point_distance(old, new) > SNAP_DISTANCE {
+		new
+	} else {
+		Point {
+			x: old.x + (new.x - old.x) * SMOOTHING,
+			y: old.y + (new.y - old.y) * SMOOTHING,
+		}
+	}
+}
+
+#[test]
+fn test_marker_tracker() {
+	let line = |x0: f32, y0: f32, x1: f32, y1: f32| Line {
+		p0: Point { x: x0, y: y0 },
+		p1: Point { x: x1, y: y1 },
+	};
+
+	let mut tracker = MarkerTracker::default();
+	let start = Instant::now();
+	let frame = |n: u64| start + Duration::from_millis(n * 66);
+
+	let markers = tracker.update(&[(line(0.0, 0.0, 100.0, 0.0), None, None), (line(50.0, 50.0, 50.0, 150.0), Some(Fireteam::Bravo), None)], frame(0));
+	assert_eq!(markers.iter().map(|marker| marker.id).collect::<Vec<_>>(), [0, 1]);
+
+	// Reordered, reversed and jittered: same IDs, smoothed endpoints
+	let markers = tracker.update(&[(line(50.0, 151.0, 50.0, 51.0), None, None), (line(1.0, 0.0, 101.0, 1.0), None, None)], frame(1));
+	assert_eq!(markers.len(), 2);
+	assert_eq!(markers[0].id, 0);
+	assert!((markers[0].line.p0.x - 0.35).abs() < 1e-4 && (markers[0].line.p1.y - 0.35).abs() < 1e-4);
+	assert_eq!(markers[1].id, 1);
+	assert!((markers[1].line.p0.y - 50.35).abs() < 1e-4, "{:?}", markers[1]);
+	assert_eq!(markers[1].fireteam, Some(Fireteam::Bravo));
+
+	// A real move snaps straight to the new position
+	let markers = tracker.update(&[(line(8.0, 0.0, 101.0, 1.0), None, None), (line(50.0, 51.0, 50.0, 151.0), None, None)], frame(2));
+	assert_eq!(markers[0].line.p0.x, 8.0);
+
+	// Dropping out briefly keeps the marker, for too long forgets it
+	let markers = tracker.update(&[(line(8.0, 0.0, 101.0, 1.0), None, None)], frame(3));
+	assert_eq!(markers.len(), 2);
+	let markers = tracker.update(&[(line(8.0, 0.0, 101.0, 1.0), None, None)], frame(20));
+	assert_eq!(markers.iter().map(|marker| marker.id).collect::<Vec<_>>(), [0]);
+
+	// Somewhere else entirely is a new marker
+	let markers = tracker.update(&[(line(8.0, 0.0, 101.0, 1.0), None, None), (line(300.0, 300.0, 400.0, 400.0), None, None)], frame(21));
+	assert_eq!(markers.iter().map(|marker| marker.id).collect::<Vec<_>>(), [0, 2]);
+
+	// Finding a command marker on one end makes that end the target, whichever way round the marker was
+	let markers = tracker.update(&[(line(101.0, 1.0, 8.0, 0.0), None, Some(CommandMarker::Attack))], frame(22));
+	assert_eq!((markers[0].id, markers[0].command), (0, Some(CommandMarker::Attack)));
+	assert_eq!(markers[0].line.p1, Point { x: 8.0, y: 0.0 });
+}
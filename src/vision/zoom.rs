@@ -0,0 +1,369 @@
+//! Works out how far the in-game map is zoomed in and where it's panned to
+//!
+//! `find_minimap` only works on the fully zoomed-out map, so we hold on to the last zoomed-out map and, once the minimap can't be found,
+//! search for the scale and offset at which the current map best matches it. The map's scale bars narrow the search down when they can be read.
+
+use super::*;
+use image::{imageops::FilterType, GrayImage};
+use smh_heightmap_ripper::coords::{MapPx, MapZoom};
+
+/// Size of the zoomed-out map that every zoom level and offset is tried on
+const COARSE_SIZE: u32 = 128;
+
+/// Size of the images that the best coarse match is refined at
+const REFINE_SIZE: u32 = 384;
+
+/// Only every this many pixels of the current map are compared while refining, which is still plenty at the refinement size
+const SCORE_STRIDE: u32 = 2;
+
+/// The in-game map doesn't zoom in any further than this
+const MAX_SCALE: f64 = 8.0;
+
+/// Ratio between neighbouring zoom levels when searching all of them
+const SCALE_STEP: f64 = 1.1;
+
+/// How far the map can pan between frames, as a fraction of its size
+const MAX_PAN: f64 = 0.05;
+
+/// How much of the current map has to land on the zoomed-out map
+const MIN_OVERLAP: f64 = 0.75;
+
+/// Normalized cross-correlation below this isn't a match
+const MIN_SCORE: f64 = 0.5;
+
+/// How often the zoomed-out map is refreshed while it's on screen, as markers and icons come and go
+const REFERENCE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The last fully zoomed-out map
+struct Reference {
+	/// Size of the captured map
+	size: [u32; 2],
+
+	coarse: GrayImage,
+	refine: GrayImage,
+
+	/// Summed-area tables of the coarse image and its square, for the mean and variance under every template position
+	sum: Vec<f64>,
+	sum_sq: Vec<f64>,
+
+	minimap_bounds: Rect<u32>,
+	meters_to_px_ratio: Option<f64>,
+
+	updated: Instant,
+}
+impl Reference {
+	fn new(map: &GrayImage, minimap_bounds: Rect<u32>, meters_to_px_ratio: Option<f64>) -> Self {
+		let refine = downscale(map, REFINE_SIZE);
+		let coarse = downscale(&refine, COARSE_SIZE);
+
+		let (w, h) = (coarse.width() as usize + 1, coarse.height() as usize + 1);
+		let (mut sum, mut sum_sq) = (vec![0.0; w * h], vec![0.0; w * h]);
+		for (x, y, pixel) in coarse.enumerate_pixels() {
+			let (i, v) = ((y as usize + 1) * w + x as usize + 1, pixel.0[0] as f64);
+			sum[i] = v + sum[i - 1] + sum[i - w] - sum[i - w - 1];
+			sum_sq[i] = v * v + sum_sq[i - 1] + sum_sq[i - w] - sum_sq[i - w - 1];
+		}
+
+		Self {
+			size: [map.width(), map.height()],
+			coarse,
+			refine,
+			sum,
+			sum_sq,
+			minimap_bounds,
+			meters_to_px_ratio,
+			updated: Instant::now(),
+		}
+	}
+
+	#[inline]
+	fn window(table: &[f64], stride: usize, [x, y]: [usize; 2], [w, h]: [usize; 2]) -> f64 {
+		table[(y + h) * stride + x + w] - table[y * stride + x + w] - table[(y + h) * stride + x] + table[y * stride + x]
+	}
+
+	/// Template matches the current map, shrunk to the size it'd be on the zoomed-out map, returning the best offset at this zoom level
+	///
+	/// If we know roughly where the map was panned to, only offsets near it are tried.
+	fn match_scale(&self, map: &GrayImage, scale: f64, near: Option<[f64; 2]>) -> (MapZoom, f64) {
+		let (cw, ch) = (self.coarse.width(), self.coarse.height());
+		let (tw, th) = ((cw as f64 / scale).round() as u32, (ch as f64 / scale).round() as u32);
+		let worst = (MapZoom { scale, offset: [0.0, 0.0] }, f64::MIN);
+		if tw < 2 || th < 2 || tw > cw || th > ch {
+			return worst;
+		}
+
+		let template = image::imageops::resize(map, tw, th, FilterType::Triangle);
+		let n = (tw * th) as f64;
+		let (sum_a, sum_aa) = template.pixels().fold((0.0, 0.0), |(sum, sum_sq), pixel| {
+			let v = pixel.0[0] as f64;
+			(sum + v, sum_sq + v * v)
+		});
+		let var_a = sum_aa - sum_a * sum_a / n;
+		if var_a <= 0.0 {
+			return worst;
+		}
+
+		// Map pixels per coarse pixel
+		let (px, py) = (self.size[0] as f64 / cw as f64, self.size[1] as f64 / ch as f64);
+
+		let (mut x_range, mut y_range) = (0..=cw - tw, 0..=ch - th);
+		if let Some([x, y]) = near {
+			let (rx, ry) = ((cw as f64 * MAX_PAN).ceil() as i64, (ch as f64 * MAX_PAN).ceil() as i64);
+			let (x, y) = ((x / px).round() as i64, (y / py).round() as i64);
+			x_range = (x - rx).clamp(0, (cw - tw) as i64) as u32..=(x + rx).clamp(0, (cw - tw) as i64) as u32;
+			y_range = (y - ry).clamp(0, (ch - th) as i64) as u32..=(y + ry).clamp(0, (ch - th) as i64) as u32;
+		}
+
+		let stride = cw as usize + 1;
+		let mut best = worst;
+		for y in y_range {
+			for x in x_range.clone() {
+				let position = [x as usize, y as usize];
+				let size = [tw as usize, th as usize];
+				let sum_b = Self::window(&self.sum, stride, position, size);
+				let var_b = Self::window(&self.sum_sq, stride, position, size) - sum_b * sum_b / n;
+				if var_b <= 0.0 {
+					continue;
+				}
+
+				let sum_ab = template
+					.as_raw()
+					.chunks_exact(tw as usize)
+					.zip(self.coarse.as_raw()[y as usize * cw as usize..].chunks(cw as usize))
+					.map(|(a, b)| a.iter().zip(&b[x as usize..]).map(|(a, b)| *a as u32 * *b as u32).sum::<u32>() as f64)
+					.sum::<f64>();
+
+				let score = (sum_ab - sum_a * sum_b / n) / (var_a * var_b).sqrt();
+				if score > best.1 {
+					best = (
+						MapZoom {
+							scale: cw as f64 / tw as f64,
+							offset: [x as f64 * px, y as f64 * py],
+						},
+						score,
+					);
+				}
+			}
+		}
+		best
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Zoomed {
+	pub zoom: MapZoom,
+
+	/// The minimap bounds found on the zoomed-out map
+	pub minimap_bounds: Rect<u32>,
+
+	/// Meters per captured map pixel, scaled from the zoomed-out map's if the scale bars couldn't be read
+	pub meters_to_px_ratio: Option<f64>,
+}
+
+#[derive(Default)]
+pub struct ZoomState {
+	reference: Option<Reference>,
+	last: Option<MapZoom>,
+}
+impl ZoomState {
+	/// Whether the zoomed-out map on screen is worth remembering, as grayscaling and downscaling it every frame would be a waste
+	pub fn wants_reference(&self, size: [u32; 2], minimap_bounds: Rect<u32>) -> bool {
+		match &self.reference {
+			Some(reference) => reference.size != size || reference.minimap_bounds != minimap_bounds || reference.updated.elapsed() >= REFERENCE_INTERVAL,
+			None => true,
+		}
+	}
+
+	/// Remembers the fully zoomed-out map, which zoomed-in maps are matched against
+	pub fn set_reference(&mut self, map: &GrayImage, minimap_bounds: Rect<u32>, meters_to_px_ratio: Option<f64>) {
+		self.reference = Some(Reference::new(map, minimap_bounds, meters_to_px_ratio));
+		self.last = None;
+	}
+
+	/// Finds the zoom and pan of a map whose minimap bounds couldn't be found
+	pub fn estimate(&mut self, map: &GrayImage, meters_to_px_ratio: Option<f64>) -> Option<Zoomed> {
+		let reference = self.reference.as_ref().filter(|reference| reference.size == [map.width(), map.height()])?;
+
+		let refine = downscale(map, REFINE_SIZE);
+
+		// Zoom level from the scale bars, if we can read them on both maps
+		let ocr_scale = reference
+			.meters_to_px_ratio
+			.zip(meters_to_px_ratio)
+			.map(|(reference, current)| reference / current)
+			.filter(|scale| (SCALE_STEP..=MAX_SCALE * SCALE_STEP).contains(scale));
+
+		let around = |scale: f64| (-2..=2).map(|step| scale * (1.0 + step as f64 * 0.02)).collect::<Vec<_>>();
+
+		// Cheapest first: near where we were last frame, then the zoom level from the scale bars, then every zoom level
+		let searches = [
+			self.last.map(|last| (around(last.scale), Some(last.offset))),
+			ocr_scale.map(|scale| (around(scale), None)),
+			Some((
+				core::iter::successors(Some(SCALE_STEP), |scale| Some(scale * SCALE_STEP)).take_while(|scale| *scale <= MAX_SCALE).collect(),
+				None,
+			)),
+		];
+
+		let zoom = searches.into_iter().flatten().find_map(|(scales, near)| {
+			let (best, _) = scales
+				.into_par_iter()
+				.map(|scale| reference.match_scale(&refine, scale, near))
+				.reduce(|| (MapZoom::default(), f64::MIN), |a, b| if b.1 > a.1 { b } else { a });
+
+			let (zoom, score) = refine_zoom(&refine, &reference.refine, reference.size, best);
+			if score >= MIN_SCORE {
+				Some(zoom)
+			} else {
+				None
+			}
+		});
+
+		self.last = zoom;
+
+		zoom.map(|zoom| Zoomed {
+			zoom,
+			minimap_bounds: reference.minimap_bounds,
+			meters_to_px_ratio: meters_to_px_ratio.or_else(|| reference.meters_to_px_ratio.map(|ratio| ratio / zoom.scale)),
+		})
+	}
+}
+
+fn downscale(image: &GrayImage, max_size: u32) -> GrayImage {
+	let (w, h) = image.dimensions();
+	let factor = (max_size as f64 / w.max(h) as f64).min(1.0);
+
+	// Box filtering, which is plenty for this and much faster than resize() on full size maps
+	image::imageops::thumbnail(image, ((w as f64 * factor).round() as u32).max(1), ((h as f64 * factor).round() as u32).max(1))
+}
+
+#[inline]
+fn sample(image: &GrayImage, x: f64, y: f64) -> Option<f64> {
+	if x < 0.0 || y < 0.0 || x > (image.width() - 1) as f64 || y > (image.height() - 1) as f64 {
+		return None;
+	}
+
+	let (x0, y0) = (x as u32, y as u32);
+	let (x1, y1) = ((x0 + 1).min(image.width() - 1), (y0 + 1).min(image.height() - 1));
+	let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+	let px = |x, y| image.get_pixel(x, y).0[0] as f64;
+	let top = px(x0, y0) * (1.0 - fx) + px(x1, y0) * fx;
+	let bottom = px(x0, y1) * (1.0 - fx) + px(x1, y1) * fx;
+	Some(top * (1.0 - fy) + bottom * fy)
+}
+
+/// Normalized cross-correlation between the current map and the zoomed-out map under it
+fn score(map: &GrayImage, reference: &GrayImage, size: [u32; 2], zoom: &MapZoom) -> f64 {
+	let (mx, my) = (size[0] as f64 / map.width() as f64, size[1] as f64 / map.height() as f64);
+	let (rx, ry) = (reference.width() as f64 / size[0] as f64, reference.height() as f64 / size[1] as f64);
+
+	let (mut n, mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+	for (x, y, a) in map.enumerate_pixels().filter(|(x, y, _)| x % SCORE_STRIDE == 0 && y % SCORE_STRIDE == 0) {
+		let MapPx([ux, uy]) = zoom.to_unzoomed(MapPx([(x as f64 + 0.5) * mx, (y as f64 + 0.5) * my]));
+		let b = match sample(reference, ux * rx - 0.5, uy * ry - 0.5) {
+			Some(b) => b,
+			None => continue,
+		};
+
+		let a = a.0[0] as f64;
+		n += 1.0;
+		sum_a += a;
+		sum_b += b;
+		sum_aa += a * a;
+		sum_bb += b * b;
+		sum_ab += a * b;
+	}
+
+	if n < ((map.width() / SCORE_STRIDE) * (map.height() / SCORE_STRIDE)) as f64 * MIN_OVERLAP {
+		return -1.0;
+	}
+
+	let cov = sum_ab - sum_a * sum_b / n;
+	let var_a = sum_aa - sum_a * sum_a / n;
+	let var_b = sum_bb - sum_b * sum_b / n;
+	if var_a <= 0.0 || var_b <= 0.0 {
+		return -1.0;
+	}
+
+	cov / (var_a * var_b).sqrt()
+}
+
+/// Hill climbs from the best coarse match at a higher resolution
+fn refine_zoom(map: &GrayImage, reference: &GrayImage, size: [u32; 2], mut best: MapZoom) -> (MapZoom, f64) {
+	let mut best_score = score(map, reference, size, &best);
+	let (mut scale_step, mut offset_step) = (0.01, size[0] as f64 / COARSE_SIZE as f64 / 2.0);
+	while offset_step > 0.5 {
+		let neighbours = [
+			(scale_step, [0.0, 0.0]),
+			(-scale_step, [0.0, 0.0]),
+			(0.0, [offset_step, 0.0]),
+			(0.0, [-offset_step, 0.0]),
+			(0.0, [0.0, offset_step]),
+			(0.0, [0.0, -offset_step]),
+		];
+
+		let improved = neighbours
+			.into_par_iter()
+			.map(|(scale, offset)| {
+				let zoom = MapZoom {
+					scale: best.scale * (1.0 + scale),
+					offset: [best.offset[0] + offset[0], best.offset[1] + offset[1]],
+				};
+				(zoom, score(map, reference, size, &zoom))
+			})
+			.reduce(|| (best, best_score), |a, b| if b.1 > a.1 { b } else { a });
+
+		if improved.1 > best_score {
+			(best, best_score) = improved;
+		} else {
+			scale_step /= 2.0;
+			offset_step /= 2.0;
+		}
+	}
+
+	(best, best_score)
+}
+
+#[test]
+fn test_zoom() {
+	// Smooth "terrain" with features at a few different scales, so that it only matches itself in one place
+	let (w, h) = (640, 520);
+	let map = GrayImage::from_fn(w, h, |x, y| {
+		let (x, y) = (x as f64, y as f64);
+		let v = (x / 37.0).sin() * (y / 53.0).cos() + 0.6 * ((x + 2.0 * y) / 19.0).sin() + 0.4 * ((x * y).sqrt() / 11.0).cos() + 0.3 * ((3.0 * x - y) / 7.0).sin();
+		image::Luma([(128.0 + v * 50.0) as u8])
+	});
+
+	let zoom_in = |zoom: MapZoom| {
+		GrayImage::from_fn(w, h, |x, y| {
+			let MapPx([ux, uy]) = zoom.to_unzoomed(MapPx([x as f64 + 0.5, y as f64 + 0.5]));
+			image::Luma([sample(&map, ux - 0.5, uy - 0.5).unwrap_or(0.0).round() as u8])
+		})
+	};
+
+	let minimap_bounds = Rect { left: 20, top: 10, right: 620, bottom: 510 };
+	let mut state = ZoomState::default();
+	assert!(state.estimate(&map, None).is_none());
+	state.set_reference(&map, minimap_bounds, Some(4.0));
+
+	let expected = MapZoom { scale: 2.5, offset: [173.0, 118.0] };
+	let zoomed = zoom_in(expected);
+
+	for meters_to_px_ratio in [Some(4.0 / 2.5), None] {
+		state.last = None;
+
+		let estimate = state.estimate(&zoomed, meters_to_px_ratio).unwrap();
+		assert_eq!(estimate.minimap_bounds, minimap_bounds);
+		assert!((estimate.zoom.scale - expected.scale).abs() < 0.02, "{estimate:?}");
+		assert!((estimate.zoom.offset[0] - expected.offset[0]).abs() < 2.0, "{estimate:?}");
+		assert!((estimate.zoom.offset[1] - expected.offset[1]).abs() < 2.0, "{estimate:?}");
+		assert!((estimate.meters_to_px_ratio.unwrap() - 1.6).abs() < 0.05);
+	}
+
+	// Panning a little further should be picked up from last frame's zoom
+	let panned = MapZoom { offset: [190.0, 110.0], ..expected };
+	let estimate = state.estimate(&zoom_in(panned), None).unwrap();
+	assert!((estimate.zoom.offset[0] - panned.offset[0]).abs() < 2.0, "{estimate:?}");
+	assert!((estimate.zoom.offset[1] - panned.offset[1]).abs() < 2.0, "{estimate:?}");
+}
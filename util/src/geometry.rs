@@ -1,7 +1,7 @@
 use super::*;
 use core::ops::*;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Rect<T> {
 	pub left: T,
 	pub top: T,
@@ -200,6 +200,12 @@ impl HSV for image::Rgba<u8> {
 		hsv(self.0[0], self.0[1], self.0[2])
 	}
 }
+impl HSV for image::Bgra<u8> {
+	#[inline]
+	fn to_hsv(self) -> (u16, u8, u8) {
+		hsv(self.0[2], self.0[1], self.0[0])
+	}
+}
 
 pub trait AsRefImage<P: image::Pixel> {
 	fn as_ref_image(&self) -> image::ImageBuffer<P, &[P::Subpixel]>;
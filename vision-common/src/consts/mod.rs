@@ -16,4 +16,20 @@ pub const CLOSE_DEPLOYMENT_BUTTON_BOUNDS: RelativeBounds2D = RelativeBounds2D {
 	y: Bottom(ScreenH(0.0078125)),
 	w: ScreenH(0.236132813),
 	h: ScreenH(0.038205295)
+};
+
+/// The quick map is the same map panel as the deployment screen, but centered on its own without the rest of the deployment UI
+pub const QUICK_MAP_BOUNDS: RelativeBounds2D = RelativeBounds2D {
+	x: Center,
+	y: Top(ScreenH(0.139583333)),
+	w: ScreenH(0.913194444),
+	h: ScreenH(0.761078559)
+};
+
+/// The yellow rule along the top of the quick map, which is found instead of the "Close Deployment" button
+pub const QUICK_MAP_HEADER_BOUNDS: RelativeBounds2D = RelativeBounds2D {
+	x: Center,
+	y: Top(ScreenH(0.134027778)),
+	w: ScreenH(0.913194444),
+	h: ScreenH(0.005555556)
 };
\ No newline at end of file
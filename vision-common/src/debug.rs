@@ -18,6 +18,9 @@ timeshares! {
 	load_frame => [0.0, 1.0, 1.0],
 	crop_to_map => [1.0, 0.4, 0.0],
 	find_minimap => [0.0, 0.0, 1.0],
+	find_player => [1.0, 0.85, 0.2],
+	find_icons => [0.3, 0.6, 1.0],
+	estimate_zoom => [0.5, 0.5, 1.0],
 	ocr_preprocess => [0.0, 0.35, 1.0],
 	ocr => [0.35, 0.0, 1.0],
 	find_scales_preprocess => [1.0, 0.0, 1.0],
@@ -142,7 +142,8 @@ vision_dylib_wrapper! {
 		fn isolate_map_markers(&self) -> Result<(), E>;
 		// fn filter_map_marker_icons(&self) -> Result<(), E>;
 		fn mask_marker_lines(&self) -> Result<(), E>;
-		fn find_marker_lines(&self, max_gap: u32) -> Result<SmallVec<Line<f32>, 32>, E>;
+		fn find_marker_lines(&self, max_gap: u32, circles: Option<&mut Vec<lsd::Circle>>) -> Result<SmallVec<Line<f32>, 32>, E>;
+		fn find_marker_lines_hough(&self, max_gap: u32, circles: Option<&mut Vec<lsd::Circle>>) -> Result<SmallVec<Line<f32>, 32>, E>;
 		fn find_longest_line(&self, image: &LSDImage, pt: Point<f32>, max_gap: f32) -> Result<(Line<f32>, f32), E>;
 
 		fn get_debug_view(&self, choice: debug::DebugView) -> Option<Arc<image::RgbaImage>>;
@@ -0,0 +1,305 @@
+//! Finds marker lines with a progressive probabilistic Hough transform
+//!
+//! `lsd::find_lines` casts thousands of rays from every mask pixel that isn't already on a line, which the GPU can keep up with but the CPU
+//! struggles to. Here pixels vote for the lines they could be on one at a time, in a random order, and as soon as enough of them agree on a
+//! line it's followed through the mask to find its ends. Its pixels are then taken out of the running, so most pixels never vote at all.
+//!
+//! Candidates go through the same circle rejection as `lsd::find_lines`.
+
+use crate::*;
+use lsd::Circle;
+
+/// How many angles lines are voted for at, over 180 degrees
+const THETA_BINS: usize = 360;
+
+/// How many pixels have to vote for a line before it's followed through the mask
+const MIN_VOTES: u16 = 25;
+
+/// Lines shorter than this (in pixels) aren't marker lines
+///
+/// The same as the minimum length `lsd::find_lines` accepts
+const MIN_LENGTH: f32 = 50.0;
+
+/// How far (in pixels) to either side of where we expect the line to be it's looked for while following it
+const FOLLOW_TOLERANCE: i32 = 2;
+
+/// How far (in pixels) the line is allowed to drift to the side per pixel followed
+///
+/// This is enough to make up for the angle only being known to the nearest bin, but not enough to follow a circle round.
+const FOLLOW_MAX_DRIFT: f32 = 0.1;
+
+/// How far (in pixels) around a followed line its pixels are taken out of the running
+const CONSUME_RADIUS: i32 = 3;
+
+/// xorshift32, so the order pixels vote in (and so the results) are the same every time
+#[inline]
+fn next_random(state: &mut u32) -> u32 {
+	*state ^= *state << 13;
+	*state ^= *state >> 17;
+	*state ^= *state << 5;
+	*state
+}
+
+struct Mask {
+	w: u32,
+	h: u32,
+	pixels: Vec<bool>,
+}
+impl Mask {
+	#[inline]
+	fn get(&self, pt: Point<f32>) -> bool {
+		let (x, y) = (pt.x.round(), pt.y.round());
+		x >= 0.0 && y >= 0.0 && (x as u32) < self.w && (y as u32) < self.h && self.pixels[y as usize * self.w as usize + x as usize]
+	}
+}
+
+/// Follows a line through the mask from `start` in both directions, returning the middle of it every pixel along the way
+fn follow(mask: &Mask, start: Point<f32>, direction: (f32, f32), max_gap: f32) -> Vec<Point<f32>> {
+	let normal = (-direction.1, direction.0);
+	let max_steps = ((mask.w as f32).powi(2) + (mask.h as f32).powi(2)).sqrt();
+
+	let at = |t: f32, offset: f32| {
+		Point::new(
+			start.x + direction.0 * t + normal.0 * offset,
+			start.y + direction.1 * t + normal.1 * offset,
+		)
+	};
+
+	let walk = |sign: f32| {
+		let mut points = Vec::new();
+		let (mut offset, mut gap, mut step) = (0.0, 0.0, if sign > 0.0 { 0.0 } else { 1.0 });
+		while gap <= max_gap && step <= max_steps {
+			let t = step * sign;
+			let centre = at(t, offset);
+
+			let (mut sum, mut n) = (0, 0);
+			for side in -FOLLOW_TOLERANCE..=FOLLOW_TOLERANCE {
+				if mask.get(Point::new(centre.x + normal.0 * side as f32, centre.y + normal.1 * side as f32)) {
+					sum += side;
+					n += 1;
+				}
+			}
+
+			if n == 0 {
+				gap += 1.0;
+			} else {
+				offset += (sum as f32 / n as f32).clamp(-FOLLOW_MAX_DRIFT, FOLLOW_MAX_DRIFT);
+				points.push(at(t, offset));
+				gap = 0.0;
+			}
+
+			step += 1.0;
+		}
+		points
+	};
+
+	let mut points = walk(-1.0);
+	points.reverse();
+	points.extend(walk(1.0));
+	points
+}
+
+/// The segment of the best fitting (total least squares) straight line through the points that spans all of them
+fn fit_segment(points: &[Point<f32>]) -> Option<Line<f32>> {
+	if points.len() < 2 {
+		return None;
+	}
+
+	let n = points.len() as f32;
+	let (mean_x, mean_y) = points.iter().fold((0.0, 0.0), |(x, y), pt| (x + pt.x / n, y + pt.y / n));
+	let (sxx, syy, sxy) = points.iter().fold((0.0, 0.0, 0.0), |(sxx, syy, sxy), pt| {
+		let (x, y) = (pt.x - mean_x, pt.y - mean_y);
+		(sxx + x * x, syy + y * y, sxy + x * y)
+	});
+
+	let angle = 0.5 * f32::atan2(2.0 * sxy, sxx - syy);
+	let (dx, dy) = (angle.cos(), angle.sin());
+
+	let (t_min, t_max) = points.iter().fold((f32::MAX, f32::MIN), |(min, max), pt| {
+		let t = (pt.x - mean_x) * dx + (pt.y - mean_y) * dy;
+		(min.min(t), max.max(t))
+	});
+
+	Some(Line::new(
+		Point::new(mean_x + dx * t_min, mean_y + dy * t_min),
+		Point::new(mean_x + dx * t_max, mean_y + dy * t_max),
+	))
+}
+
+/// Finds up to `N` marker lines in the marker mask
+///
+/// A drop-in alternative to `lsd::find_lines`, with the same gap tolerance and circle rejection, that doesn't need a `find_longest_line`.
+pub fn find_lines<const N: usize, I>(image: &I, max_gap: u32, circles: Option<&mut Vec<Circle>>) -> SmallVec<Line<f32>, N>
+where
+	I: image::GenericImageView<Pixel = image::Luma<u8>>,
+{
+	let (w, h) = image.dimensions();
+	if N == 0 || w == 0 || h == 0 {
+		return Default::default();
+	}
+
+	let max_gap = max_gap as f32;
+
+	let mut mask = Mask {
+		w,
+		h,
+		pixels: (0..h)
+			.flat_map(|y| (0..w).map(move |x| (x, y)))
+			.map(|(x, y)| image.get_pixel_fast(x, y).0[0] == 255)
+			.collect(),
+	};
+
+	let mut order = mask
+		.pixels
+		.iter()
+		.enumerate()
+		.filter(|(_, white)| **white)
+		.map(|(i, _)| i)
+		.collect::<Vec<usize>>();
+	let mut random = 0x9e3779b9;
+	for i in (1..order.len()).rev() {
+		order.swap(i, next_random(&mut random) as usize % (i + 1));
+	}
+
+	let (sin, cos): (Vec<f32>, Vec<f32>) = (0..THETA_BINS)
+		.map(|theta| (theta as f32 * core::f32::consts::PI / THETA_BINS as f32).sin_cos())
+		.unzip();
+
+	let max_rho = ((w as f32).powi(2) + (h as f32).powi(2)).sqrt().ceil() as usize;
+	let rho_bins = max_rho * 2 + 1;
+	let rho = |i: usize, theta: usize| {
+		let (x, y) = ((i % w as usize) as f32, (i / w as usize) as f32);
+		((x * cos[theta] + y * sin[theta]).round() as isize + max_rho as isize) as usize
+	};
+
+	let mut accumulator = vec![0u16; THETA_BINS * rho_bins];
+	let mut voted = vec![false; mask.pixels.len()];
+
+	// Takes a pixel out of the running, taking back its votes if it's voted
+	let consume = |mask: &mut Mask, accumulator: &mut [u16], voted: &mut [bool], x: i64, y: i64| {
+		if x < 0 || y < 0 || x >= w as i64 || y >= h as i64 {
+			return;
+		}
+
+		let i = y as usize * w as usize + x as usize;
+		mask.pixels[i] = false;
+		if core::mem::take(&mut voted[i]) {
+			for theta in 0..THETA_BINS {
+				accumulator[theta * rho_bins + rho(i, theta)] -= 1;
+			}
+		}
+	};
+
+	let mut candidates: Vec<Line<f32>> = Vec::new();
+	let mut found_circles: Vec<Circle> = Vec::new();
+
+	for i in order {
+		// Already taken by a line we've found
+		if !mask.pixels[i] {
+			continue;
+		}
+
+		let mut best = (0, 0);
+		for theta in 0..THETA_BINS {
+			let votes = &mut accumulator[theta * rho_bins + rho(i, theta)];
+			*votes += 1;
+			if *votes > best.0 {
+				best = (*votes, theta);
+			}
+		}
+		voted[i] = true;
+
+		if best.0 < MIN_VOTES {
+			continue;
+		}
+
+		// The line's normal is at theta, so it runs perpendicular to that
+		let start = Point::new((i % w as usize) as f32, (i / w as usize) as f32);
+		let points = follow(&mask, start, (-sin[best.1], cos[best.1]), max_gap);
+
+		// Whatever it turns out to be, these pixels have been dealt with
+		for pt in &points {
+			let (x, y) = (pt.x.round() as i64, pt.y.round() as i64);
+			for dy in -CONSUME_RADIUS..=CONSUME_RADIUS {
+				for dx in -CONSUME_RADIUS..=CONSUME_RADIUS {
+					consume(&mut mask, &mut accumulator, &mut voted, x + dx as i64, y + dy as i64);
+				}
+			}
+		}
+
+		let line = match fit_segment(&points) {
+			Some(line) if line.p0.distance_sqr(&line.p1) >= MIN_LENGTH * MIN_LENGTH => line,
+			_ => continue,
+		};
+
+		if let Some(circle) = lsd::find_arc(image, &line, max_gap) {
+			let circle = lsd::refine_circle(image, circle);
+
+			// Take the rest of the circle out of the running too
+			let extent = (circle.radius + lsd::CIRCLE_SKIP_DISTANCE).ceil() as i64;
+			let (cx, cy) = (circle.centre.x.round() as i64, circle.centre.y.round() as i64);
+			for y in (cy - extent).max(0)..=(cy + extent).min(h as i64 - 1) {
+				for x in (cx - extent).max(0)..=(cx + extent).min(w as i64 - 1) {
+					if circle.distance(Point::new(x as f32, y as f32)) < lsd::CIRCLE_SKIP_DISTANCE {
+						consume(&mut mask, &mut accumulator, &mut voted, x, y);
+					}
+				}
+			}
+
+			if !found_circles.iter().any(|found| found.is_same(&circle)) {
+				found_circles.push(circle);
+			}
+			continue;
+		}
+
+		candidates.push(line);
+	}
+
+	// Pixels vote in a random order, so bits of a circle can be found before the circle itself is
+	let mut lines: SmallVec<Line<f32>, N> = SmallVec::new();
+	for line in candidates.into_iter().filter(|line| !found_circles.iter().any(|circle| circle.covers(line))) {
+		lines.push(line);
+		if lines.is_full() {
+			break;
+		}
+	}
+
+	if let Some(circles) = circles {
+		circles.extend(found_circles);
+	}
+
+	lines
+}
+
+#[test]
+fn test_hough_find_lines() {
+	let (centre, radius) = (Point::new(300.0, 220.0), 120.0);
+	let (line_start, line_end) = (Point::new(20.0, 30.0), Point::new(560.0, 70.0));
+
+	let image = image::GrayImage::from_fn(600, 400, |x, y| {
+		let pt = Point::new(x as f32, y as f32);
+
+		// A range ring, and a marker line with a gap in it that's just short of max_gap
+		let on_ring = Circle { centre, radius }.distance(pt) <= 1.5;
+		let t = (pt.x - line_start.x) / (line_end.x - line_start.x);
+		let on_line =
+			(0.0..=1.0).contains(&t) && (line_start.y + (line_end.y - line_start.y) * t - pt.y).abs() <= 1.5 && !(200.0..214.0).contains(&pt.x);
+
+		image::Luma([if on_ring || on_line { 255 } else { 0 }])
+	});
+
+	let mut circles = Vec::new();
+	let lines: SmallVec<Line<f32>, 32> = find_lines(&image, 15, Some(&mut circles));
+
+	assert_eq!(lines.len(), 1, "{lines:?}");
+	let line = lines[0];
+	for end in [line_start, line_end] {
+		assert!(line.p0.distance_sqr(&end).min(line.p1.distance_sqr(&end)) < 25.0, "{line:?}");
+	}
+
+	assert_eq!(circles.len(), 1, "{circles:?}");
+	assert!(
+		circles[0].centre.distance_sqr(&centre) < 4.0 && (circles[0].radius - radius).abs() < 2.0,
+		"{circles:?}"
+	);
+}
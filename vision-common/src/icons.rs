@@ -0,0 +1,362 @@
+//! Finds icons on the map by template matching
+//!
+//! Icons are drawn at whatever size the user has picked in the map's sidebar, so every template is tried at a range of sizes.
+//! Most of the map looks nothing like any icon, so templates are only tried where the map has their most distinctive colour (their "key"),
+//! and each comparison is abandoned as soon as it's clearly not going to be close enough.
+
+use crate::prelude::*;
+
+/// Average difference per colour channel (`0.0..=1.0`) over an icon's opaque pixels for it to count as a match
+const MAX_MEAN_DIFFERENCE: f32 = 0.12;
+
+/// Template pixels with less alpha than this are background
+const MIN_TEMPLATE_ALPHA: u8 = 128;
+
+/// How many of a template's most distinctive pixels are checked before the rest, which rules out almost every position early
+const ANCHOR_PIXELS: usize = 8;
+
+/// How far (summed over the colour channels) a map pixel can be from a template's key colour for the template to be tried there
+const KEY_TOLERANCE: u32 = 96;
+
+/// Templates that ship with SMH, cut from the sample screenshots at the size they were drawn
+const BUILTIN_TEMPLATES: [(MapIconKind, &[u8]); 1] = [
+	// A truck, from in_mortar.png
+	(MapIconKind::Vehicle, include_bytes!("icons/vehicle_truck.png")),
+];
+
+/// Decodes the templates that ship with SMH
+pub fn builtin_templates() -> impl Iterator<Item = (MapIconKind, image::RgbaImage)> {
+	BUILTIN_TEMPLATES.into_iter().map(|(kind, png)| {
+		let template = image::load_from_memory_with_format(png, image::ImageFormat::Png)
+			.expect("Failed to load embedded map icon template! This should never happen...");
+		(kind, template.into_rgba8())
+	})
+}
+
+/// Things shown on the map by their own icon
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MapIconKind {
+	Fob = 1,
+	Hab = 2,
+	Rally = 3,
+	Emplacement = 4,
+	Vehicle = 5,
+}
+impl MapIconKind {
+	pub const ALL: [Self; 5] = [Self::Fob, Self::Hab, Self::Rally, Self::Emplacement, Self::Vehicle];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Fob => "FOB",
+			Self::Hab => "HAB",
+			Self::Rally => "Rally Point",
+			Self::Emplacement => "Emplacement",
+			Self::Vehicle => "Vehicle",
+		}
+	}
+
+	/// Where this kind's templates are kept, relative to the templates directory
+	pub fn dir_name(self) -> &'static str {
+		match self {
+			Self::Fob => "fob",
+			Self::Hab => "hab",
+			Self::Rally => "rally",
+			Self::Emplacement => "emplacement",
+			Self::Vehicle => "vehicle",
+		}
+	}
+
+	/// This kind's bit in a set of kinds packed into a `u8`
+	#[inline]
+	pub fn bit(self) -> u8 {
+		1 << self as u8
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DetectedIcon<K> {
+	pub kind: K,
+
+	/// The centre of the icon, in map pixels
+	pub position: Point<f32>,
+
+	/// The size it was found at, in map pixels
+	pub size: u32,
+
+	/// Mean difference from the template, lower is better
+	pub score: f32,
+}
+
+struct ScaledTemplate<K> {
+	kind: K,
+	width: u32,
+	height: u32,
+
+	/// Index of the key colour in `IconLibrary::keys`, and the template pixel closest to it
+	key: usize,
+	key_offset: (u32, u32),
+
+	/// Opaque pixels as `(x, y, rgb)`, most distinctive first
+	pixels: Box<[(u32, u32, [u8; 3])]>,
+}
+
+/// Every template, at every size it could be drawn at
+pub struct IconLibrary<K> {
+	keys: Vec<[u8; 3]>,
+	templates: Vec<ScaledTemplate<K>>,
+}
+impl<K: Copy + Send + Sync> IconLibrary<K> {
+	/// Scales each template so its longest side is each of `sizes`
+	pub fn new<T, S>(templates: T, sizes: S) -> Self
+	where
+		T: IntoIterator<Item = (K, image::RgbaImage)>,
+		S: IntoIterator<Item = u32> + Clone,
+	{
+		let mut keys = Vec::new();
+		let mut scaled = Vec::new();
+		for (kind, template) in templates {
+			let (w, h) = template.dimensions();
+			let opaque = || template.pixels().filter(|pixel| pixel.0[3] >= MIN_TEMPLATE_ALPHA).map(|pixel| [pixel.0[0], pixel.0[1], pixel.0[2]]);
+			let key = match mean_color(opaque()).and_then(|mean| opaque().max_by_key(|rgb| channel_difference(*rgb, mean))) {
+				Some(key) => key,
+				None => continue,
+			};
+
+			let key_index = keys.iter().position(|other| *other == key).unwrap_or_else(|| {
+				keys.push(key);
+				keys.len() - 1
+			});
+
+			for size in sizes.clone() {
+				let scale = size as f32 / w.max(h) as f32;
+				let (width, height) = (((w as f32 * scale).round() as u32).max(1), ((h as f32 * scale).round() as u32).max(1));
+				let resized = image::imageops::resize(&template, width, height, image::imageops::FilterType::Triangle);
+
+				let mut pixels = resized
+					.enumerate_pixels()
+					.filter(|(_, _, pixel)| pixel.0[3] >= MIN_TEMPLATE_ALPHA)
+					.map(|(x, y, pixel)| (x, y, [pixel.0[0], pixel.0[1], pixel.0[2]]))
+					.collect::<Vec<_>>();
+				let mean = match mean_color(pixels.iter().map(|(_, _, rgb)| *rgb)) {
+					Some(mean) => mean,
+					None => continue,
+				};
+
+				// Pixels that are furthest from the template's average colour rule out the most positions
+				pixels.sort_by_key(|(_, _, rgb)| core::cmp::Reverse(channel_difference(*rgb, mean)));
+
+				let key_offset = pixels.iter().min_by_key(|(_, _, rgb)| channel_difference(*rgb, key)).map(|(x, y, _)| (*x, *y)).unwrap();
+
+				scaled.push(ScaledTemplate {
+					kind,
+					width,
+					height,
+					key: key_index,
+					key_offset,
+					pixels: pixels.into_boxed_slice(),
+				});
+			}
+		}
+
+		Self { keys, templates: scaled }
+	}
+
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.templates.is_empty()
+	}
+
+	/// Finds every icon on the map, keeping only the best match where several overlap
+	pub fn find<I>(&self, map: &I) -> Vec<DetectedIcon<K>>
+	where
+		I: image::GenericImageView + Sync,
+		I::Pixel: image::Pixel<Subpixel = u8>,
+	{
+		use image::Pixel;
+
+		let (w, h) = map.dimensions();
+		if self.templates.is_empty() || w == 0 || h == 0 {
+			return Vec::new();
+		}
+
+		// Copying the map out once is much faster than going through GenericImageView for every comparison
+		let rgb = (0..h)
+			.into_par_iter()
+			.flat_map_iter(|y| (0..w).map(move |x| map.get_pixel(x, y).to_rgb().0))
+			.collect::<Vec<[u8; 3]>>();
+
+		// Where each key colour is on the map
+		let key_pixels = self
+			.keys
+			.par_iter()
+			.map(|key| {
+				rgb.iter()
+					.enumerate()
+					.filter(|(_, pixel)| channel_difference(**pixel, *key) <= KEY_TOLERANCE)
+					.map(|(i, _)| i as u32)
+					.collect::<Vec<u32>>()
+			})
+			.collect::<Vec<_>>();
+
+		let mut found = self
+			.templates
+			.par_iter()
+			.flat_map_iter(|template| {
+				let rgb = &rgb;
+				key_pixels[template.key].iter().filter_map(move |&i| {
+					let x = (i % w).checked_sub(template.key_offset.0)?;
+					let y = (i / w).checked_sub(template.key_offset.1)?;
+					if x + template.width > w || y + template.height > h {
+						return None;
+					}
+
+					let score = score(rgb, w, template, x, y)?;
+					Some(DetectedIcon {
+						kind: template.kind,
+						position: Point {
+							x: x as f32 + template.width as f32 / 2.0,
+							y: y as f32 + template.height as f32 / 2.0,
+						},
+						size: template.width.max(template.height),
+						score,
+					})
+				})
+			})
+			.collect::<Vec<_>>();
+
+		// Non-maximum suppression: the best match wins, and anything overlapping it is the same icon
+		found.sort_by(|a, b| a.score.total_cmp(&b.score));
+		let mut icons: Vec<DetectedIcon<K>> = Vec::new();
+		for icon in found {
+			let overlaps = icons.iter().any(|kept| {
+				let distance = ((kept.position.x - icon.position.x).powi(2) + (kept.position.y - icon.position.y).powi(2)).sqrt();
+				distance < kept.size.max(icon.size) as f32 / 2.0
+			});
+			if !overlaps {
+				icons.push(icon);
+			}
+		}
+
+		icons
+	}
+}
+
+fn mean_color(pixels: impl Iterator<Item = [u8; 3]>) -> Option<[u8; 3]> {
+	let (sum, n) = pixels.fold(([0u32; 3], 0u32), |(sum, n), rgb| ([sum[0] + rgb[0] as u32, sum[1] + rgb[1] as u32, sum[2] + rgb[2] as u32], n + 1));
+	if n == 0 {
+		None
+	} else {
+		Some(sum.map(|c| (c / n) as u8))
+	}
+}
+
+#[inline]
+fn channel_difference(a: [u8; 3], b: [u8; 3]) -> u32 {
+	a.into_iter().zip(b).map(|(a, b)| a.abs_diff(b) as u32).sum()
+}
+
+/// Mean difference between the template and the map at this position, or `None` if it's not a match
+#[inline]
+fn score<K>(rgb: &[[u8; 3]], w: u32, template: &ScaledTemplate<K>, x: u32, y: u32) -> Option<f32> {
+	let max_difference = template.pixels.len() as f32 * 3.0 * 255.0;
+	let budget = (max_difference * MAX_MEAN_DIFFERENCE) as u32;
+
+	// The anchors get twice the average budget each, as they're the pixels that antialiasing smudges the most
+	let anchors = template.pixels.len().min(ANCHOR_PIXELS);
+	let anchor_budget = (anchors as f32 * 3.0 * 255.0 * MAX_MEAN_DIFFERENCE * 2.0) as u32;
+
+	let mut difference = 0;
+	for (i, &(dx, dy, pixel)) in template.pixels.iter().enumerate() {
+		difference += channel_difference(rgb[((y + dy) * w + x + dx) as usize], pixel);
+		if difference > budget || (i + 1 == anchors && difference > anchor_budget) {
+			return None;
+		}
+	}
+
+	Some(difference as f32 / max_difference)
+}
+
+#[test]
+fn test_icon_library() {
+	// A blue square with a white cross, and a red diamond
+	let cross = image::RgbaImage::from_fn(32, 32, |x, y| {
+		if (12..20).contains(&x) || (12..20).contains(&y) {
+			image::Rgba([255, 255, 255, 255])
+		} else {
+			image::Rgba([30, 90, 220, 255])
+		}
+	});
+	let diamond = image::RgbaImage::from_fn(32, 32, |x, y| {
+		if (x as i32 - 16).abs() + (y as i32 - 16).abs() <= 14 {
+			image::Rgba([220, 40, 40, 255])
+		} else {
+			image::Rgba([0, 0, 0, 0])
+		}
+	});
+
+	let library = IconLibrary::new([(MapIconKind::Fob, cross.clone()), (MapIconKind::Rally, diamond.clone())], (16..=28).step_by(4));
+
+	let mut map = image::RgbaImage::from_fn(300, 200, |x, y| {
+		let noise = ((x * 7 + y * 13) % 17) as u8;
+		image::Rgba([90 + noise, 95 + noise, 80 + noise, 255])
+	});
+	let place = |map: &mut image::RgbaImage, icon: &image::RgbaImage, size: u32, x: u32, y: u32| {
+		let icon = image::imageops::resize(icon, size, size, image::imageops::FilterType::Triangle);
+		for (dx, dy, pixel) in icon.enumerate_pixels() {
+			if pixel.0[3] >= MIN_TEMPLATE_ALPHA {
+				map.put_pixel(x + dx, y + dy, *pixel);
+			}
+		}
+	};
+	place(&mut map, &cross, 24, 40, 50);
+	place(&mut map, &cross, 24, 200, 120);
+	place(&mut map, &diamond, 20, 120, 30);
+
+	let mut icons = library.find(&map);
+	icons.sort_by(|a, b| a.position.x.total_cmp(&b.position.x));
+	assert_eq!(icons.len(), 3, "{icons:?}");
+
+	let expect = [(MapIconKind::Fob, 52.0, 62.0), (MapIconKind::Rally, 130.0, 40.0), (MapIconKind::Fob, 212.0, 132.0)];
+	for (icon, (kind, x, y)) in icons.iter().zip(expect) {
+		assert_eq!(icon.kind, kind);
+		assert!((icon.position.x - x).abs() <= 1.0 && (icon.position.y - y).abs() <= 1.0, "{icon:?}");
+	}
+
+	let blank = image::RgbaImage::from_pixel(300, 200, image::Rgba([90, 95, 80, 255]));
+	assert!(library.find(&blank).is_empty());
+}
+
+#[test]
+fn test_icon_library_samples() {
+	let library = IconLibrary::new(builtin_templates(), (14..=34).step_by(2));
+
+	// Searching the whole map takes minutes in debug builds, so only the area around each labelled icon is searched
+	// (sample, area of the map to search, the vehicle in it)
+	let samples = [
+		// The truck the template was cut from, next to the main base's spawns
+		("in_mortar.png", &include_bytes!("../samples/in_mortar.png")[..], [700, 200, 300, 300], Some((857.0, 313.0))),
+		// The same truck, with the map zoomed in a bit further
+		("point.png", include_bytes!("../samples/point.png"), [700, 200, 300, 300], Some((856.0, 313.0))),
+		// The main base's spawns and buildings
+		("in_mortar.png", include_bytes!("../samples/in_mortar.png"), [560, 420, 300, 300], None),
+		// A town, which loose templates have mistaken for trucks
+		("albasrah.png", include_bytes!("../samples/albasrah.png"), [650, 650, 300, 300], None),
+	];
+
+	for (name, bytes, [area_x, area_y, area_w, area_h], expected) in samples {
+		let frame = image::load_from_memory(bytes).unwrap().into_bgra8();
+		let frame: VisionFrame = image::ImageBuffer::from_raw(frame.width(), frame.height(), frame.into_raw().into_boxed_slice()).unwrap();
+
+		let [x, y, ..] = crate::screen_state::classify(&frame).unwrap().map_bounds([frame.width(), frame.height()]);
+		let area = frame.view(x + area_x, y + area_y, area_w, area_h);
+
+		let icons = library.find(&area);
+		assert_eq!(icons.len(), expected.iter().count(), "{name}: {icons:?}");
+		if let (Some(icon), Some((x, y))) = (icons.first(), expected) {
+			assert_eq!(icon.kind, MapIconKind::Vehicle, "{name}: {icon:?}");
+			let (x, y) = (x - area_x as f32, y - area_y as f32);
+			assert!((icon.position.x - x).abs() <= 2.0 && (icon.position.y - y).abs() <= 2.0, "{name}: {icon:?}");
+		}
+	}
+}
@@ -22,10 +22,14 @@ use prelude::*;
 
 pub mod consts;
 pub mod debug;
+pub mod icons;
 pub mod dylib;
 pub mod screen;
+pub mod screen_state;
 pub mod lsd;
+pub mod hough;
 pub mod markers;
+pub mod player;
 
 pub trait Vision: Sized + Send + Sync {
 	type LSDImage;
@@ -55,7 +59,14 @@ pub trait Vision: Sized + Send + Sync {
 	// fn filter_map_marker_icons(&self) -> Result<(), Self::Error>;
 	fn mask_marker_lines(&self) -> Result<(), Self::Error>;
 	fn find_longest_line(&self, image: &Self::LSDImage, pt: Point<f32>, max_gap: f32) -> Result<(Line<f32>, f32), Self::Error>;
-	fn find_marker_lines(&self, max_gap: u32) -> Result<SmallVec<Line<f32>, 32>, Self::Error>;
+	/// Finds the marker lines in the mask made by `mask_marker_lines`
+	///
+	/// Circles in the mask (range rings and the like) aren't marker lines, and are added to `circles` instead if it's given
+	fn find_marker_lines(&self, max_gap: u32, circles: Option<&mut Vec<lsd::Circle>>) -> Result<SmallVec<Line<f32>, 32>, Self::Error>;
+	/// Like `find_marker_lines`, but with a probabilistic Hough transform instead of casting rays from every pixel
+	///
+	/// See `hough::find_lines`
+	fn find_marker_lines_hough(&self, max_gap: u32, circles: Option<&mut Vec<lsd::Circle>>) -> Result<SmallVec<Line<f32>, 32>, Self::Error>;
 
 	fn get_debug_view(&self, choice: debug::DebugView) -> Option<Arc<image::RgbaImage>>;
 }
\ No newline at end of file
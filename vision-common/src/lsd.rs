@@ -1,5 +1,69 @@
 use crate::*;
 
+/// How far (in pixels, on average) a candidate's centreline can stray from a circle for it to be lying on that circle
+const ARC_MAX_RESIDUAL: f32 = 1.5;
+
+/// How far (in pixels) a candidate's centreline has to bow away from a straight line for it to be an arc rather than a slightly wobbly line
+const ARC_MIN_SAGITTA: f32 = 2.0;
+
+/// How many times better a circle has to fit a candidate's centreline than a straight line does
+const ARC_MIN_IMPROVEMENT: f32 = 1.5;
+
+/// Candidates with fewer centreline points than this are too short to tell an arc from a line
+const ARC_MIN_POINTS: usize = 16;
+
+/// How many of the last points traced are used to predict where the mask goes next
+const ARC_TRACE_HISTORY: usize = 16;
+
+/// How far (in pixels) from where we predicted the mask to be it's looked for while tracing
+const ARC_TRACE_TOLERANCE: f32 = 3.0;
+
+/// How many times wider than a marker line's gaps the gaps in a traced arc can be
+///
+/// At lower resolutions range rings are only drawn as a ring of dots, which are further apart than a marker line is ever broken up.
+const ARC_TRACE_GAP_SCALE: f32 = 3.0;
+
+/// How many times a circle is refitted to the mask pixels near it
+const CIRCLE_REFINE_ITERATIONS: usize = 3;
+
+/// How much (as a fraction of its radius) a circle's radius can change while being refitted
+const CIRCLE_REFINE_MAX_CHANGE: f32 = 0.25;
+
+/// Pixels this close (in pixels) to a circle we've already found aren't tried as the start of a line again
+pub(crate) const CIRCLE_SKIP_DISTANCE: f32 = 6.0;
+
+/// A circle in the marker mask, such as a FOB's radius, a HAB's circle or the arc in front of the player
+///
+/// A short enough arc of one is straight enough for the line segment detection to mistake for a marker line.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Circle {
+	pub centre: Point<f32>,
+	pub radius: f32,
+}
+impl Circle {
+	/// How far a point is from the circle's edge
+	#[inline]
+	pub(crate) fn distance(&self, pt: Point<f32>) -> f32 {
+		(((pt.x - self.centre.x).powi(2) + (pt.y - self.centre.y).powi(2)).sqrt() - self.radius).abs()
+	}
+
+	/// Whether the whole of a line lies along the circle's edge
+	pub(crate) fn covers(&self, line: &Line<f32>) -> bool {
+		let steps = (line.p0.distance_sqr(&line.p1).sqrt() / 2.0).ceil().max(1.0) as u32;
+		(0..=steps).all(|i| {
+			let t = i as f32 / steps as f32;
+			let pt = Point::new(line.p0.x + (line.p1.x - line.p0.x) * t, line.p0.y + (line.p1.y - line.p0.y) * t);
+			self.distance(pt) < CIRCLE_SKIP_DISTANCE
+		})
+	}
+
+	#[inline]
+	pub(crate) fn is_same(&self, other: &Circle) -> bool {
+		let tolerance = self.radius.max(other.radius) * 0.1 + CIRCLE_SKIP_DISTANCE;
+		(self.radius - other.radius).abs() < tolerance && self.centre.distance_sqr(&other.centre) < tolerance * tolerance
+	}
+}
+
 // checks pixels left, right, up, and down from the given pixel to determine which pixel is the center based on if they're white or black
 #[inline]
 fn get_centre<I>(image: &I, pt: Point<f32>) -> Point<f32>
@@ -57,7 +121,193 @@ fn nearest_point_on_line(pt: Point<f32>, r0: Point<f32>, r1: Point<f32>) -> Poin
 	Point::new(r0.x + u * dx, r0.y + u * dy)
 }
 
-pub fn find_lines<const N: usize, I, IRef: Borrow<I>, FLL, E>(image_ref: &IRef, max_gap: u32, find_longest_line: FLL) -> Result<SmallVec<Line<f32>, N>, E>
+/// Traces the mask along a candidate line, half its length again past both of its ends, and returns the middle of it every pixel along the way
+///
+/// The trace follows the mask if it curves away from the line, and carries on over gaps of up to `max_gap` pixels (scaled by `ARC_TRACE_GAP_SCALE`).
+fn centreline<I>(image: &I, line: &Line<f32>, max_gap: f32) -> Vec<Point<f32>>
+where
+	I: image::GenericImageView<Pixel = image::Luma<u8>>
+{
+	let (w, h) = image.dimensions();
+	let (dx, dy) = (line.p1.x - line.p0.x, line.p1.y - line.p0.y);
+	let length = (dx * dx + dy * dy).sqrt();
+	if length < 1.0 {
+		return Vec::new();
+	}
+
+	// Work in the line's own coordinates: distance along it, and distance to its side
+	let (ux, uy) = (dx / length, dy / length);
+	let to_image = |t: f32, offset: f32| Point::new(line.p0.x + t * ux - offset * uy, line.p0.y + t * uy + offset * ux);
+
+	let max_gap = max_gap * ARC_TRACE_GAP_SCALE;
+	let trace = |direction: f32, end: f32| {
+		let mut points: Vec<(f32, f32)> = Vec::new();
+		let mut gap = 0.0;
+		let mut t = if direction > 0.0 { 0.0 } else { -1.0 };
+		while t * direction <= end && gap <= max_gap {
+			// Extrapolate from the last few points to follow curves
+			let predicted = match points.len() {
+				0 => 0.0,
+				1 => points[0].1,
+				len => {
+					let (first, last) = (points[len.saturating_sub(ARC_TRACE_HISTORY)], points[len - 1]);
+					last.1 + (last.1 - first.1) / (last.0 - first.0) * (t - last.0)
+				}
+			};
+
+			let (mut sum, mut n) = (0.0, 0);
+			let mut offset = (predicted - ARC_TRACE_TOLERANCE).round();
+			while offset <= predicted + ARC_TRACE_TOLERANCE {
+				let pt = to_image(t, offset);
+				let (x, y) = (pt.x.round(), pt.y.round());
+				if x >= 0.0 && y >= 0.0 && (x as u32) < w && (y as u32) < h && image.get_pixel(x as u32, y as u32)[0] == 255 {
+					sum += offset;
+					n += 1;
+				}
+				offset += 1.0;
+			}
+
+			if n == 0 {
+				gap += 1.0;
+			} else {
+				points.push((t, sum / n as f32));
+				gap = 0.0;
+			}
+
+			t += direction;
+		}
+		points
+	};
+
+	let mut points = trace(-1.0, length / 2.0);
+	points.reverse();
+	points.extend(trace(1.0, length * 1.5));
+
+	points.into_iter().map(|(t, offset)| to_image(t, offset)).collect()
+}
+
+/// Least squares (Kåsa) circle fit, returning the circle and the root mean square distance of the points from it
+fn fit_circle(points: &[Point<f32>]) -> Option<(Circle, f32)> {
+	let n = points.len() as f64;
+	if points.len() < 3 {
+		return None;
+	}
+
+	let (mean_x, mean_y) = points.iter().fold((0.0, 0.0), |(x, y), pt| (x + pt.x as f64 / n, y + pt.y as f64 / n));
+
+	let (mut suu, mut svv, mut suv, mut suuu, mut svvv, mut suvv, mut svuu) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+	for pt in points {
+		let (u, v) = (pt.x as f64 - mean_x, pt.y as f64 - mean_y);
+		suu += u * u;
+		svv += v * v;
+		suv += u * v;
+		suuu += u * u * u;
+		svvv += v * v * v;
+		suvv += u * v * v;
+		svuu += v * u * u;
+	}
+
+	let det = suu * svv - suv * suv;
+	if det.abs() < f64::EPSILON * suu.max(svv).powi(2) {
+		// Collinear
+		return None;
+	}
+
+	let (b0, b1) = ((suuu + suvv) / 2.0, (svvv + svuu) / 2.0);
+	let (uc, vc) = ((b0 * svv - b1 * suv) / det, (suu * b1 - suv * b0) / det);
+
+	let circle = Circle {
+		centre: Point::new((uc + mean_x) as f32, (vc + mean_y) as f32),
+		radius: (uc * uc + vc * vc + (suu + svv) / n).sqrt() as f32,
+	};
+
+	let residual = (points.iter().map(|pt| circle.distance(*pt).powi(2)).sum::<f32>() / points.len() as f32).sqrt();
+
+	Some((circle, residual))
+}
+
+/// Root mean square distance of the points from their best fitting (total least squares) straight line
+fn line_residual(points: &[Point<f32>]) -> f32 {
+	let n = points.len() as f32;
+	let (mean_x, mean_y) = points.iter().fold((0.0, 0.0), |(x, y), pt| (x + pt.x / n, y + pt.y / n));
+	let (sxx, syy, sxy) = points.iter().fold((0.0, 0.0, 0.0), |(sxx, syy, sxy), pt| {
+		let (x, y) = (pt.x - mean_x, pt.y - mean_y);
+		(sxx + x * x / n, syy + y * y / n, sxy + x * y / n)
+	});
+
+	// The smallest eigenvalue of the covariance matrix is the variance across the line
+	let across = (sxx + syy) / 2.0 - (((sxx - syy) / 2.0).powi(2) + sxy * sxy).sqrt();
+	across.max(0.0).sqrt()
+}
+
+/// Refits a circle to every mask pixel near it, so that the rest of it can be skipped rather than just the arc it was found from
+pub(crate) fn refine_circle<I>(image: &I, mut circle: Circle) -> Circle
+where
+	I: image::GenericImageView<Pixel = image::Luma<u8>>
+{
+	let (w, h) = image.dimensions();
+	for _ in 0..CIRCLE_REFINE_ITERATIONS {
+		let extent = circle.radius + CIRCLE_SKIP_DISTANCE;
+		let (x0, x1) = ((circle.centre.x - extent).max(0.0) as u32, ((circle.centre.x + extent).max(0.0) as u32).min(w - 1));
+		let (y0, y1) = ((circle.centre.y - extent).max(0.0) as u32, ((circle.centre.y + extent).max(0.0) as u32).min(h - 1));
+
+		let points = (y0..=y1)
+			.flat_map(|y| (x0..=x1).map(move |x| (x, y)))
+			.filter(|&(x, y)| image.get_pixel(x, y)[0] == 255)
+			.map(|(x, y)| Point::new(x as f32, y as f32))
+			.filter(|pt| circle.distance(*pt) < CIRCLE_SKIP_DISTANCE)
+			.collect::<Vec<_>>();
+
+		// Too little of the circle to go on, or the fit has wandered off onto something else
+		match fit_circle(&points) {
+			Some((refined, _))
+				if points.len() >= ARC_MIN_POINTS && (refined.radius - circle.radius).abs() < circle.radius * CIRCLE_REFINE_MAX_CHANGE =>
+			{
+				circle = refined
+			}
+			_ => break,
+		}
+	}
+	circle
+}
+
+/// Returns the circle a candidate line is actually an arc of, if it's one
+pub(crate) fn find_arc<I>(image: &I, line: &Line<f32>, max_gap: f32) -> Option<Circle>
+where
+	I: image::GenericImageView<Pixel = image::Luma<u8>>
+{
+	let points = centreline(image, line, max_gap);
+	if points.len() < ARC_MIN_POINTS {
+		return None;
+	}
+
+	let (circle, residual) = fit_circle(&points)?;
+	if residual > ARC_MAX_RESIDUAL || residual * ARC_MIN_IMPROVEMENT > line_residual(&points) {
+		return None;
+	}
+
+	// How far the arc bows away from the chord between its ends, which has to cover at least the candidate itself
+	let half_chord = points[0].distance_sqr(&points[points.len() - 1]).sqrt() / 2.0;
+	if half_chord * half_chord * 4.0 < line.p0.distance_sqr(&line.p1) {
+		return None;
+	}
+	let sagitta = circle.radius - (circle.radius.powi(2) - half_chord.powi(2)).max(0.0).sqrt();
+	if sagitta < ARC_MIN_SAGITTA {
+		return None;
+	}
+
+	Some(circle)
+}
+
+/// Finds up to `N` marker lines in the marker mask
+///
+/// Candidates that are really arcs of circles are rejected, and the circles they're on are added to `circles` if it's given.
+pub fn find_lines<const N: usize, I, IRef: Borrow<I>, FLL, E>(
+	image_ref: &IRef,
+	max_gap: u32,
+	circles: Option<&mut Vec<Circle>>,
+	find_longest_line: FLL
+) -> Result<SmallVec<Line<f32>, N>, E>
 where
 	I: image::GenericImageView<Pixel = image::Luma<u8>>,
 	FLL: Fn(&IRef, Point<f32>, f32) -> Result<(Line<f32>, f32), E>
@@ -70,6 +320,7 @@ where
 	let max_gap = max_gap as f32;
 
 	let mut lines: SmallVec<Line<f32>, N> = SmallVec::new();
+	let mut found_circles: Vec<Circle> = Vec::new();
 
 	'row: for y in 0..image.height() {
 		'column: for x in 0..image.width() {
@@ -87,6 +338,10 @@ where
 					continue 'column;
 				}
 			}
+			if found_circles.iter().any(|circle| circle.distance(pt) < CIRCLE_SKIP_DISTANCE) {
+				continue 'column;
+			}
+
 			pt = get_centre(image, pt);
 
 			let (mut longest, max_length) = find_longest_line(image_ref, pt, max_gap)?;
@@ -94,6 +349,14 @@ where
 			if max_length > 2500.0 {
 				longest.p1 = get_centre(image, longest.p1);
 
+				if let Some(circle) = find_arc(image, &longest, max_gap) {
+					let circle = refine_circle(image, circle);
+					if !found_circles.iter().any(|found| found.is_same(&circle)) {
+						found_circles.push(circle);
+					}
+					continue 'column;
+				}
+
 				lines.push(longest);
 
 				if lines.len() == N {
@@ -103,7 +366,18 @@ where
 		}
 	}
 
+	// Arcs of a circle that's only found further down the mask can't be told apart from lines until it has been
+	if lines.iter().any(|line| found_circles.iter().any(|circle| circle.covers(line))) {
+		let mut kept = SmallVec::new();
+		for line in lines.into_iter().filter(|line| !found_circles.iter().any(|circle| circle.covers(line))) {
+			kept.push(line);
+		}
+		lines = kept;
+	}
+
+	if let Some(circles) = circles {
+		circles.extend(found_circles);
+	}
+
 	Ok(lines)
 }
-
-// TODO somehow filter out circles
\ No newline at end of file
@@ -8,6 +8,9 @@ const CHARLIE_MARKER_COLOR_HSV_TUP: (u16, u8, u8) = (
 	CHARLIE_MARKER_COLOR_HSV[2] as _,
 );
 
+/// Fewer matching pixels than this along a line isn't enough to say whose marker it is
+const MIN_FIRETEAM_VOTES: u32 = 3;
+
 /// Saturation is a special case.
 ///
 /// The markers can be brightened by the lightness arc that the player icon emits on the map.
@@ -18,17 +21,59 @@ fn saturation_ok(s: u8, ms: u8) -> bool {
 	ms.abs_diff(s) <= FIND_MARKER_HSV_SAT_TOLERANCE || (s as i16 - (ms as i16 - FIND_MARKER_PLAYER_DIR_ARC_SAT)).abs() as u8 <= FIND_MARKER_HSV_SAT_TOLERANCE
 }
 
+/// Whose colour a map marker is drawn in
+///
+/// The discriminants are what's sent to web clients, where `0` means the colour wasn't recognised.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Fireteam {
-	Alpha,
-	Bravo,
-	Charlie
+	Alpha = 1,
+	Bravo = 2,
+	Charlie = 3
 }
+impl Fireteam {
+	pub const ALL: [Self; 3] = [Self::Alpha, Self::Bravo, Self::Charlie];
+
+	#[inline]
+	fn hsv(self) -> (u16, u8, u8) {
+		match self {
+			Self::Alpha => ALPHA_MARKER_COLOR_HSV_TUP,
+			Self::Bravo => BRAVO_MARKER_COLOR_HSV_TUP,
+			Self::Charlie => CHARLIE_MARKER_COLOR_HSV_TUP
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Alpha => "Alpha",
+			Self::Bravo => "Bravo",
+			Self::Charlie => "Charlie"
+		}
+	}
+
+	/// The marker colour as RGB in `0.0..=1.0`, for drawing markers in the same colour as they are in game
+	pub fn rgb(self) -> [f32; 3] {
+		let (h, s, v) = self.hsv();
+		let (s, v) = (s as f32 / 100.0, v as f32 / 100.0);
+
+		let c = v * s;
+		let x = c * (1.0 - ((h as f32 / 60.0) % 2.0 - 1.0).abs());
+		let (r, g, b) = match h / 60 {
+			0 => (c, x, 0.0),
+			1 => (x, c, 0.0),
+			2 => (0.0, c, x),
+			3 => (0.0, x, c),
+			4 => (x, 0.0, c),
+			_ => (c, 0.0, x)
+		};
+
+		let m = v - c;
+		[r + m, g + m, b + m]
+	}
+}
+
 pub fn debug_is_map_marker_color(h: u16, s: u8, v: u8, fireteam: Fireteam) -> [bool; 3] {
-	let (mh, ms, mv) = match fireteam {
-		Fireteam::Alpha => ALPHA_MARKER_COLOR_HSV_TUP,
-		Fireteam::Bravo => BRAVO_MARKER_COLOR_HSV_TUP,
-		Fireteam::Charlie => CHARLIE_MARKER_COLOR_HSV_TUP
-	};
+	let (mh, ms, mv) = fireteam.hsv();
 
 	[
 		mh.abs_diff(h) <= FIND_MARKER_HSV_HUE_TOLERANCE,
@@ -37,20 +82,293 @@ pub fn debug_is_map_marker_color(h: u16, s: u8, v: u8, fireteam: Fireteam) -> [b
 	]
 }
 
+#[inline]
+fn hsv_fireteam((h, s, v): (u16, u8, u8)) -> Option<Fireteam> {
+	if s < FIND_MARKER_HSV_MIN_SAT {
+		return None;
+	}
+
+	Fireteam::ALL.into_iter().find(|fireteam| {
+		let (mh, ms, mv) = fireteam.hsv();
+		mh.abs_diff(h) <= FIND_MARKER_HSV_HUE_TOLERANCE
+			&& saturation_ok(s, ms)
+			&& mv.abs_diff(v) <= FIND_MARKER_HSV_VIB_TOLERANCE
+	})
+}
+
+/// Which fireteam's marker colour this pixel is, if any
+#[inline]
+pub fn map_marker_fireteam<P: HSV>(pixel: P) -> Option<Fireteam> {
+	hsv_fireteam(pixel.to_hsv())
+}
+
+#[inline]
 pub fn is_any_map_marker_color<P: HSV>(pixel: P) -> bool {
+	map_marker_fireteam(pixel).is_some()
+}
+
+/// Works out which fireteam a detected marker line belongs to, by voting on the colour of the map along it
+///
+/// The line segment detection only sees a mask of every marker colour merged together, so this has to go back to the map itself.
+pub fn classify_marker_line<I>(map: &I, line: Line<f32>) -> Option<Fireteam>
+where
+	I: image::GenericImageView,
+	I::Pixel: HSV
+{
+	let (w, h) = map.dimensions();
+	if w == 0 || h == 0 {
+		return None;
+	}
+
+	let length = ((line.p1.x - line.p0.x).powi(2) + (line.p1.y - line.p0.y).powi(2)).sqrt();
+	let samples = (length / 2.0).ceil().clamp(1.0, 128.0) as u32;
+
+	let mut votes = [0u32; Fireteam::ALL.len()];
+	for i in 0..=samples {
+		let t = i as f32 / samples as f32;
+		let (x, y) = (line.p0.x + (line.p1.x - line.p0.x) * t, line.p0.y + (line.p1.y - line.p0.y) * t);
+
+		// The line's position is only as good as the mask it was found in, so look around it a little
+		for (dx, dy) in (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (dx, dy))) {
+			let (x, y) = ((x.round() as i64 + dx).clamp(0, w as i64 - 1) as u32, (y.round() as i64 + dy).clamp(0, h as i64 - 1) as u32);
+			if let Some(fireteam) = map_marker_fireteam(map.get_pixel(x, y)) {
+				votes[fireteam as usize - 1] += 1;
+			}
+		}
+	}
+
+	let (fireteam, votes) = Fireteam::ALL.into_iter().zip(votes).max_by_key(|(_, votes)| *votes)?;
+	if votes >= MIN_FIRETEAM_VOTES {
+		Some(fireteam)
+	} else {
+		None
+	}
+}
+
+#[test]
+fn test_classify_marker_line() {
+	let mut map = image::RgbaImage::from_pixel(200, 200, image::Rgba([90, 90, 80, 255]));
+
+	let to_rgba = |[r, g, b]: [f32; 3]| image::Rgba([(r * 255.0).round() as u8, (g * 255.0).round() as u8, (b * 255.0).round() as u8, 255]);
+	for (i, fireteam) in Fireteam::ALL.into_iter().enumerate() {
+		assert_eq!(map_marker_fireteam(to_rgba(fireteam.rgb())), Some(fireteam));
+
+		let y = 40 + i as u32 * 60;
+		for x in 20..180 {
+			for y in y - 1..=y + 1 {
+				map.put_pixel(x, y, to_rgba(fireteam.rgb()));
+			}
+		}
+	}
+
+	let line = |y: f32| Line { p0: Point { x: 20.0, y }, p1: Point { x: 179.0, y } };
+	assert_eq!(classify_marker_line(&map, line(40.0)), Some(Fireteam::Alpha));
+	assert_eq!(classify_marker_line(&map, line(101.0)), Some(Fireteam::Bravo));
+	assert_eq!(classify_marker_line(&map, line(159.0)), Some(Fireteam::Charlie));
+	assert_eq!(classify_marker_line(&map, line(10.0)), None);
+}
+
+/// The icon a squad leader can place on the map, which the marker lines are usually drawn to
+///
+/// The discriminants are what's sent to web clients, where `0` means there was no icon or it wasn't recognised.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CommandMarker {
+	Attack = 1,
+	Build = 2,
+	Defend = 3,
+	Move = 4,
+	Observe = 5
+}
+impl CommandMarker {
+	pub const ALL: [Self; 5] = [Self::Attack, Self::Build, Self::Defend, Self::Move, Self::Observe];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Attack => "Attack",
+			Self::Build => "Build",
+			Self::Defend => "Defend",
+			Self::Move => "Move",
+			Self::Observe => "Observe"
+		}
+	}
+
+	/// Whether this is something to fire on, rather than e.g. somewhere to go
+	#[inline]
+	pub fn is_fire_target(self) -> bool {
+		matches!(self, Self::Attack)
+	}
+
+	fn template(self) -> &'static [u8] {
+		match self {
+			Self::Attack => include_bytes!("resources/map_commandmarker_squad_attack.TGA"),
+			Self::Build => include_bytes!("resources/map_commandmarker_squad_build.TGA"),
+			Self::Defend => include_bytes!("resources/map_commandmarker_squad_defend.TGA"),
+			Self::Move => include_bytes!("resources/map_commandmarker_squad_move.TGA"),
+			Self::Observe => include_bytes!("resources/map_commandmarker_squad_observe.TGA")
+		}
+	}
+}
+
+/// Sizes to try the command marker icons at, in pixels
+///
+/// They're drawn at the size the user picks in the map's sidebar, which is 22px by default
+const COMMAND_MARKER_SIZES: core::ops::RangeInclusive<u32> = 16..=30;
+const COMMAND_MARKER_SIZE_STEP: usize = 2;
+
+/// How far (in pixels) the end of a marker line can be from where its icon points
+const COMMAND_MARKER_SEARCH_RADIUS: i64 = 3;
+
+const COMMAND_MARKER_HUE_TOLERANCE: u16 = FIND_MARKER_HSV_HUE_TOLERANCE * 2;
+const COMMAND_MARKER_MIN_VIB: u8 = 60;
+
+/// How much of an icon and the marker colours around the end of a line must overlap (intersection over union) for it to be that icon
+const MIN_COMMAND_MARKER_IOU: f32 = 0.45;
+
+/// The icons are blended with the map and have a dark outline, so they're a lot less vivid than the marker lines
+///
+/// This only goes by hue, and excludes the outline by brightness.
+#[inline]
+fn is_command_marker_color<P: HSV>(pixel: P) -> bool {
 	let (h, s, v) = pixel.to_hsv();
+	s >= FIND_MARKER_HSV_MIN_SAT
+		&& v >= COMMAND_MARKER_MIN_VIB
+		&& Fireteam::ALL.into_iter().any(|fireteam| fireteam.hsv().0.abs_diff(h) <= COMMAND_MARKER_HUE_TOLERANCE)
+}
 
-	if s < FIND_MARKER_HSV_MIN_SAT {
-		return false;
+struct CommandMarkerTemplate {
+	marker: CommandMarker,
+	size: u32,
+
+	/// Which pixels are marker coloured, row by row
+	mask: Box<[bool]>
+}
+
+/// Works out which command marker icon, if any, is at the end of a marker line
+pub struct CommandMarkerClassifier {
+	templates: Vec<CommandMarkerTemplate>
+}
+impl Default for CommandMarkerClassifier {
+	fn default() -> Self {
+		Self::new()
 	}
+}
+impl CommandMarkerClassifier {
+	pub fn new() -> Self {
+		let mut templates = Vec::new();
+		for marker in CommandMarker::ALL {
+			let template = image::load_from_memory_with_format(marker.template(), image::ImageFormat::Tga)
+				.expect("Failed to load embedded command marker TGA! This should never happen...");
+
+			for size in COMMAND_MARKER_SIZES.step_by(COMMAND_MARKER_SIZE_STEP) {
+				let resized = template.resize_exact(size, size, image::imageops::FilterType::Triangle).into_rgba8();
+				templates.push(CommandMarkerTemplate {
+					marker,
+					size,
+					mask: resized.pixels().map(|pixel| pixel.0[3] >= 128 && is_command_marker_color(*pixel)).collect()
+				});
+			}
+		}
 
-	[ALPHA_MARKER_COLOR_HSV_TUP, BRAVO_MARKER_COLOR_HSV_TUP, CHARLIE_MARKER_COLOR_HSV_TUP]
-		.into_iter()
-		.any(|(mh, ms, mv)| {
-			mh.abs_diff(h) <= FIND_MARKER_HSV_HUE_TOLERANCE
-				&& saturation_ok(s, ms)
-				&& mv.abs_diff(v) <= FIND_MARKER_HSV_VIB_TOLERANCE
-		})
+		Self { templates }
+	}
+
+	/// Classifies the icon at `poi`, the end of a marker line
+	pub fn classify<I>(&self, map: &I, poi: Point<f32>) -> Option<CommandMarker>
+	where
+		I: image::GenericImageView,
+		I::Pixel: HSV
+	{
+		let (w, h) = map.dimensions();
+		let (px, py) = (poi.x.round() as i64, poi.y.round() as i64);
+
+		// Mask the marker colours in a square that fits every size and offset we'll try
+		let margin = *COMMAND_MARKER_SIZES.end() as i64 + COMMAND_MARKER_SEARCH_RADIUS;
+		let side = (margin * 2 + 1) as usize;
+		let (x0, y0) = (px - margin, py - margin);
+		let mask = (0..side * side)
+			.map(|i| {
+				let (x, y) = (x0 + (i % side) as i64, y0 + (i / side) as i64);
+				x >= 0 && y >= 0 && x < w as i64 && y < h as i64 && is_command_marker_color(map.get_pixel(x as u32, y as u32))
+			})
+			.collect::<Vec<bool>>();
+
+		let mut best: Option<(CommandMarker, f32)> = None;
+		for template in self.templates.iter() {
+			let size = template.size as usize;
+
+			// The icon points at the middle of its bottom edge, a little way up
+			let left = px - size as i64 / 2 - x0;
+			let top = py - (template.size as f32 * MAP_MARKER_POI_LOCATION).round() as i64 - y0;
+
+			for dy in -COMMAND_MARKER_SEARCH_RADIUS..=COMMAND_MARKER_SEARCH_RADIUS {
+				for dx in -COMMAND_MARKER_SEARCH_RADIUS..=COMMAND_MARKER_SEARCH_RADIUS {
+					let (ox, oy) = ((left + dx) as usize, (top + dy) as usize);
+
+					let (mut intersection, mut union) = (0u32, 0u32);
+					for ty in 0..size {
+						let row = &mask[(oy + ty) * side + ox..][..size];
+						for (t, m) in template.mask[ty * size..][..size].iter().zip(row) {
+							intersection += (*t && *m) as u32;
+							union += (*t || *m) as u32;
+						}
+					}
+
+					let iou = if union == 0 { 0.0 } else { intersection as f32 / union as f32 };
+					if best.map(|(_, best)| iou > best).unwrap_or(true) {
+						best = Some((template.marker, iou));
+					}
+				}
+			}
+		}
+
+		best.filter(|(_, iou)| *iou >= MIN_COMMAND_MARKER_IOU).map(|(marker, _)| marker)
+	}
+}
+
+#[test]
+fn test_command_marker_classifier() {
+	let classifier = CommandMarkerClassifier::new();
+
+	// Every icon, drawn onto a plain map at the default size with the end of a line pointing at it
+	let mut map = image::RgbaImage::from_pixel(400, 100, image::Rgba([90, 90, 80, 255]));
+	let size = 22;
+	let icon = |marker: CommandMarker| {
+		let icon = image::load_from_memory_with_format(marker.template(), image::ImageFormat::Tga).unwrap();
+		icon.resize_exact(size, size, image::imageops::FilterType::Triangle).into_rgba8()
+	};
+	for (i, marker) in CommandMarker::ALL.into_iter().enumerate() {
+		let (x, y) = (20 + i as u32 * 70, 30);
+		image::imageops::overlay(&mut map, &icon(marker), x, y);
+
+		let poi = Point { x: (x + size / 2) as f32, y: y as f32 + (size as f32 * MAP_MARKER_POI_LOCATION).round() };
+		assert_eq!(classifier.classify(&map, poi), Some(marker));
+	}
+	assert_eq!(classifier.classify(&map, Point { x: 380.0, y: 80.0 }), None);
+
+	fn load(bytes: &[u8]) -> image::RgbaImage {
+		image::load_from_memory(bytes).unwrap().into_rgba8()
+	}
+
+	// Labelled by hand: attack markers at the end of a line...
+	let point = load(include_bytes!("../../samples/point.png"));
+	assert_eq!(classifier.classify(&point, Point { x: 2310.0, y: 1114.0 }), Some(CommandMarker::Attack));
+	let small = load(include_bytes!("../../samples/full_1600x1024.png"));
+	assert_eq!(classifier.classify(&small, Point { x: 1382.0, y: 519.0 }), Some(CommandMarker::Attack));
+
+	// ...and the ends of lines with no icon at all
+	let points = load(include_bytes!("../../samples/points.png"));
+	for (x, y) in [(1495.0, 451.0), (1659.0, 589.0), (2161.0, 777.0), (2141.0, 983.0), (1789.0, 1078.0), (2054.0, 1193.0)] {
+		assert_eq!(classifier.classify(&points, Point { x, y }), None, "{x}, {y}");
+	}
+
+	// None of the samples have the other icons in them, so put them at the ends of those same lines, over the real map
+	for ((x, y), marker) in [((1495, 451), CommandMarker::Move), ((1659, 589), CommandMarker::Build), ((2161, 777), CommandMarker::Defend)] {
+		let mut points = points.clone();
+		let top = y - (size as f32 * MAP_MARKER_POI_LOCATION).round() as u32;
+		image::imageops::overlay(&mut points, &icon(marker), x - size / 2, top);
+		assert_eq!(classifier.classify(&points, Point { x: x as f32, y: y as f32 }), Some(marker), "{x}, {y}");
+	}
 }
 
 /*
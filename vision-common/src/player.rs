@@ -0,0 +1,161 @@
+//! Finds the local player's own icon on the map
+//!
+//! Squad draws the local player as a yellow-green hexagon outline, a different shade to the orange-yellow of the main base and spawn icons.
+//! Which way the player is facing isn't read, as none of the sample screenshots show it clearly enough to check against.
+
+use crate::{consts::*, prelude::*};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayerIcon {
+	/// The centre of the icon, in map pixels
+	pub position: Point<f32>,
+}
+
+#[inline]
+fn is_player_icon_color<P: HSV>(pixel: P) -> bool {
+	let (h, s, v) = pixel.to_hsv();
+	PLAYER_ICON_COLOR_HSV[0].abs_diff(h) <= PLAYER_ICON_HSV_HUE_TOLERANCE && s >= PLAYER_ICON_HSV_MIN_SAT && v >= PLAYER_ICON_HSV_MIN_VIB
+}
+
+/// Finds the player's icon, returning `None` if there's nothing on the map that looks like it
+pub fn find_player_icon<I>(map: &I) -> Option<PlayerIcon>
+where
+	I: image::GenericImageView + Sync,
+	I::Pixel: HSV
+{
+	let (w, h) = map.dimensions();
+	if w == 0 || h == 0 {
+		return None;
+	}
+
+	let mut mask = (0..h)
+		.into_par_iter()
+		.flat_map_iter(|y| (0..w).map(move |x| is_player_icon_color(map.get_pixel(x, y))))
+		.collect::<Vec<bool>>();
+
+	// Flood fill every blob of the icon's colour, keeping the biggest one that's shaped like an icon.
+	// The icon is a thin outline that the map blends into in places, so pixels up to PLAYER_ICON_GAP apart are joined up.
+	let mut best: Option<Vec<(u32, u32)>> = None;
+	let mut stack = Vec::new();
+	let mut blob = Vec::new();
+	for start in 0..mask.len() {
+		if !mask[start] {
+			continue;
+		}
+
+		mask[start] = false;
+		stack.push(start);
+		blob.clear();
+
+		while let Some(i) = stack.pop() {
+			let (x, y) = ((i % w as usize) as u32, (i / w as usize) as u32);
+			blob.push((x, y));
+
+			for ny in y.saturating_sub(PLAYER_ICON_GAP)..=(y + PLAYER_ICON_GAP).min(h - 1) {
+				for nx in x.saturating_sub(PLAYER_ICON_GAP)..=(x + PLAYER_ICON_GAP).min(w - 1) {
+					let neighbour = ny as usize * w as usize + nx as usize;
+					if mask[neighbour] {
+						mask[neighbour] = false;
+						stack.push(neighbour);
+					}
+				}
+			}
+		}
+
+		let area = blob.len() as u32;
+		if !(PLAYER_ICON_MIN_AREA..=PLAYER_ICON_MAX_AREA).contains(&area) || best.as_ref().map(|best| best.len() >= blob.len()).unwrap_or(false) {
+			continue;
+		}
+
+		let (min_x, max_x) = blob.iter().fold((u32::MAX, 0), |(min, max), &(x, _)| (min.min(x), max.max(x)));
+		let (min_y, max_y) = blob.iter().fold((u32::MAX, 0), |(min, max), &(_, y)| (min.min(y), max.max(y)));
+		let (blob_w, blob_h) = (max_x - min_x + 1, max_y - min_y + 1);
+
+		// Lines, text and specks aren't icons, and the icon is about as wide as it is tall
+		let size = PLAYER_ICON_MIN_SIZE..=PLAYER_ICON_MAX_SIZE;
+		if !size.contains(&blob_w) || !size.contains(&blob_h) || blob_w.max(blob_h) * 5 > blob_w.min(blob_h) * 8 {
+			continue;
+		}
+
+		best = Some(core::mem::take(&mut blob));
+	}
+
+	let blob = best?;
+
+	let n = blob.len() as f32;
+	let (sum_x, sum_y) = blob.iter().fold((0.0, 0.0), |(sum_x, sum_y), &(x, y)| (sum_x + x as f32, sum_y + y as f32));
+	let position = Point { x: sum_x / n, y: sum_y / n };
+
+	Some(PlayerIcon { position })
+}
+
+#[cfg(test)]
+fn test_draw_hexagon(map: &mut image::RgbaImage, centre: Point<f32>, radius: f32, color: image::Rgba<u8>) {
+	let corners = (0..6)
+		.map(|i| (i as f32 * 60.0).to_radians())
+		.map(|angle| Point { x: centre.x + radius * angle.cos(), y: centre.y + radius * angle.sin() })
+		.collect::<Vec<_>>();
+	for i in 0..6 {
+		let (a, b) = (corners[i], corners[(i + 1) % 6]);
+		for step in 0..=20 {
+			let t = step as f32 / 20.0;
+			map.put_pixel((a.x + (b.x - a.x) * t).round() as u32, (a.y + (b.y - a.y) * t).round() as u32, color);
+		}
+	}
+}
+
+#[test]
+fn test_find_player_icon() {
+	let mut map = image::RgbaImage::from_pixel(300, 200, image::Rgba([90, 90, 80, 255]));
+	let yellow_green = image::Rgba([215, 225, 30, 255]);
+	assert!(is_player_icon_color(yellow_green));
+
+	test_draw_hexagon(&mut map, Point { x: 120.0, y: 80.0 }, 8.0, yellow_green);
+
+	// A long line of the same colour somewhere else isn't the player
+	for x in 10..290 {
+		map.put_pixel(x, 180, yellow_green);
+	}
+
+	// Neither is the main base's orange-yellow icon
+	for y in 30..45 {
+		for x in 200..215 {
+			map.put_pixel(x, y, image::Rgba([250, 205, 35, 255]));
+		}
+	}
+
+	let player = find_player_icon(&map).unwrap();
+	assert!((player.position.x - 120.0).abs() < 1.0, "{player:?}");
+	assert!((player.position.y - 80.0).abs() < 1.0, "{player:?}");
+
+	let blank = image::RgbaImage::from_pixel(300, 200, image::Rgba([90, 90, 80, 255]));
+	assert_eq!(find_player_icon(&blank), None);
+}
+
+#[test]
+fn test_find_player_icon_samples() {
+	// The keypad in each sample's "Player Position" header, measured off of the map's grid, in map pixels
+	let samples: [(&str, &[u8], [f32; 4]); 5] = [
+		// B1-3-4, next to the main base
+		("quickmap.jpg", include_bytes!("../samples/quickmap.jpg"), [274.1, 91.4, 282.9, 100.2]),
+		// B1-3-4
+		("full.jpg", include_bytes!("../samples/full.jpg"), [273.9, 91.4, 282.7, 100.2]),
+		// B1-2-3
+		("fullmapsnow.jpg", include_bytes!("../samples/fullmapsnow.jpg"), [265.1, 100.2, 273.9, 109.0]),
+		// G6-1-8
+		("point.png", include_bytes!("../samples/point.png"), [628.6, 481.7, 637.5, 490.5]),
+		// J8-5-7
+		("vlcsnap-2022-05-11-06h03m39s483.png", include_bytes!("../samples/vlcsnap-2022-05-11-06h03m39s483.png"), [1116.8, 796.3, 1128.4, 807.9]),
+	];
+
+	for (name, bytes, [min_x, min_y, max_x, max_y]) in samples {
+		let frame = image::load_from_memory(bytes).unwrap().into_bgra8();
+		let frame: VisionFrame = image::ImageBuffer::from_raw(frame.width(), frame.height(), frame.into_raw().into_boxed_slice()).unwrap();
+
+		let [x, y, w, h] = crate::screen_state::classify(&frame).unwrap().map_bounds([frame.width(), frame.height()]);
+		let map = frame.view(x, y, w, h);
+
+		let player = find_player_icon(&map).unwrap_or_else(|| panic!("{name}: player not found"));
+		assert!((min_x..=max_x).contains(&player.position.x) && (min_y..=max_y).contains(&player.position.y), "{name}: {player:?}");
+	}
+}
@@ -3,14 +3,16 @@ use smh_util::image;
 #[derive(Clone, Copy, Debug)]
 pub enum CornerBoundX {
 	Left(RelativeBound),
-	Right(RelativeBound)
+	Right(RelativeBound),
+	Center
 }
 impl CornerBoundX {
 	#[inline]
 	pub fn into_absolute(self, screen_size: [u32; 2], w: u32) -> u32 {
 		match self {
 			Self::Left(left) => left.into_absolute(screen_size),
-			Self::Right(right) => screen_size[0] - right.into_absolute(screen_size) - w
+			Self::Right(right) => screen_size[0] - right.into_absolute(screen_size) - w,
+			Self::Center => screen_size[0].saturating_sub(w) / 2
 		}
 	}
 }
@@ -0,0 +1,126 @@
+//! Works out which of Squad's map screens, if any, is open
+
+use crate::{consts::*, prelude::*};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenState {
+	/// The full deployment screen, with the red "Close Deployment" button
+	Deployment,
+
+	/// The in-game quick map overlay, which has no red button
+	QuickMap,
+}
+impl ScreenState {
+	/// Where the map is on the screen in this state, as `[x, y, w, h]`
+	pub fn map_bounds(self, screen_size: [u32; 2]) -> [u32; 4] {
+		match self {
+			Self::Deployment => {
+				let [x, y, w, h] = MAP_BOUNDS.into_absolute(screen_size);
+
+				// Map fills remaining space
+				let w = screen_size[0] - w;
+				let x = screen_size[0] - x - w;
+
+				[x, y, w, h]
+			}
+
+			Self::QuickMap => QUICK_MAP_BOUNDS.into_absolute(screen_size),
+		}
+	}
+}
+
+#[inline]
+fn matches_color(pixel: image::Bgra<u8>, color: [i16; 3], tolerance: u16) -> bool {
+	pixel.to_rgb().0.into_iter().zip(color).all(|(pixel, color)| (color - pixel as i16).unsigned_abs() <= tolerance)
+}
+
+/// Finds the red "Close Deployment" button
+///
+/// Gives up as soon as too many pixels aren't red for the button to be there, so frames without it are cheap to reject.
+fn is_deployment(frame: &VisionFrame) -> bool {
+	let [x, y, w, h] = CLOSE_DEPLOYMENT_BUTTON_BOUNDS.into_absolute([frame.width(), frame.height()]);
+	if w == 0 || h == 0 {
+		return false;
+	}
+
+	let max_misses = ((w * h) as f32 * (1.0 - CLOSE_DEPLOYMENT_BUTTON_RED_PIXEL_THRESHOLD)) as u32;
+	let mut misses = 0;
+	for row in y..y + h {
+		for col in x..x + w {
+			if !matches_color(*frame.get_pixel(col, row), CLOSE_DEPLOYMENT_BUTTON_COLOR, CLOSE_DEPLOYMENT_BUTTON_TOLERANCE) {
+				misses += 1;
+				if misses > max_misses {
+					return false;
+				}
+			}
+		}
+	}
+	true
+}
+
+/// Finds the yellow rule along the top of the quick map
+///
+/// It's only a few pixels tall and its exact position shifts with rounding, so any row that's almost all yellow will do
+fn is_quick_map(frame: &VisionFrame) -> bool {
+	let [x, y, w, h] = QUICK_MAP_HEADER_BOUNDS.into_absolute([frame.width(), frame.height()]);
+	if w == 0 || x + w > frame.width() || y + h > frame.height() {
+		return false;
+	}
+
+	(y..y + h).any(|row| {
+		let yellow_pixels = (x..x + w)
+			.filter(|&col| matches_color(*frame.get_pixel(col, row), QUICK_MAP_HEADER_COLOR, QUICK_MAP_HEADER_TOLERANCE))
+			.count();
+
+		yellow_pixels as f32 / w as f32 >= QUICK_MAP_HEADER_YELLOW_PIXEL_THRESHOLD
+	})
+}
+
+/// Classifies the frame, returning `None` if no map is open
+///
+/// This runs on the CPU for every frame, whichever vision backend is in use, but it only looks at the pixels around the button
+/// and the quick map's header, which takes around a tenth of a millisecond at 1440p.
+pub fn classify(frame: &VisionFrame) -> Option<ScreenState> {
+	if is_deployment(frame) {
+		Some(ScreenState::Deployment)
+	} else if is_quick_map(frame) {
+		Some(ScreenState::QuickMap)
+	} else {
+		None
+	}
+}
+
+#[test]
+fn test_classify() {
+	fn load(bytes: &[u8]) -> VisionFrame {
+		let image = image::load_from_memory(bytes).unwrap().into_bgra8();
+		image::ImageBuffer::from_raw(image.width(), image.height(), image.into_raw().into_boxed_slice()).unwrap()
+	}
+
+	for (name, sample) in [
+		("full.jpg", &include_bytes!("../samples/full.jpg")[..]),
+		("full_1024x768.png", include_bytes!("../samples/full_1024x768.png")),
+		("full_1280x1024.png", include_bytes!("../samples/full_1280x1024.png")),
+		("full_1600x1024.png", include_bytes!("../samples/full_1600x1024.png")),
+		("in_mortar.png", include_bytes!("../samples/in_mortar.png")),
+	] {
+		assert_eq!(classify(&load(sample)), Some(ScreenState::Deployment), "{name}");
+	}
+
+	let quick_map = load(include_bytes!("../samples/quickmap.jpg"));
+	assert_eq!(classify(&quick_map), Some(ScreenState::QuickMap));
+
+	// The map starts just below the yellow rule
+	let [x, y, w, h] = ScreenState::QuickMap.map_bounds([quick_map.width(), quick_map.height()]);
+	assert!(x + w <= quick_map.width() && y + h <= quick_map.height());
+	let yellow = |row: u32| {
+		(x..x + w)
+			.filter(|&col| matches_color(*quick_map.get_pixel(col, row), QUICK_MAP_HEADER_COLOR, QUICK_MAP_HEADER_TOLERANCE))
+			.count() as u32
+	};
+	assert!(yellow(y - 3) > w * 9 / 10);
+	assert!(yellow(y + 2) < w / 10);
+
+	let blank: VisionFrame = image::ImageBuffer::from_raw(1920, 1080, vec![0; 1920 * 1080 * 4].into_boxed_slice()).unwrap();
+	assert_eq!(classify(&blank), None);
+}
@@ -5,12 +5,17 @@ use smh_vision_common::{
 	consts::*,
 	markers,
 	prelude::{image::Pixel, *},
+	screen_state::{self, ScreenState},
 	Vision,
 };
 
 #[derive(Default)]
 pub struct CPUFallback {
 	frame: Arc<VisionFrame>,
+	screen_state: Option<ScreenState>,
+
+	/// The frame size and screen state that the buffers below are sized for
+	buffers: Option<((u32, u32), ScreenState)>,
 
 	cropped_map: SusRefCell<image::RgbImage>,
 	cropped_brq: SusRefCell<image::RgbImage>,
@@ -67,11 +72,13 @@ impl Vision for CPUFallback {
 	}
 
 	fn load_frame(&mut self, frame: VisionFrame) -> Result<(), Self::Error> {
-		if self.frame.dimensions() != frame.dimensions() {
-			let [_, _, w, h] = MAP_BOUNDS.into_absolute([frame.width(), frame.height()]);
+		self.screen_state = screen_state::classify(&frame);
+
+		let buffers = self.screen_state.map(|screen_state| (frame.dimensions(), screen_state));
+		if let Some((_, screen_state)) = buffers.filter(|_| self.buffers != buffers) {
+			self.buffers = buffers;
 
-			// Map fills remaining space
-			let w = frame.width() - w;
+			let [_, _, w, h] = screen_state.map_bounds([frame.width(), frame.height()]);
 
 			// brq = bottom right quadrant
 			let brq_w = w / 2;
@@ -112,33 +119,13 @@ impl Vision for CPUFallback {
 		let mut cropped_map = memory!(&mut self.cropped_map);
 		let mut cropped_brq = memory!(&mut self.cropped_brq);
 
-		{
-			let [x, y, w, h] = CLOSE_DEPLOYMENT_BUTTON_BOUNDS.into_absolute([frame.width(), frame.height()]);
-
-			// Find the red "Close Deployment" button
-			// TODO: support the quick map as well
-			let red_pixels = par_iter_pixels!(frame[x, y, w, h])
-				.filter(|(_, _, pixel)| {
-					for (i, pixel) in pixel.to_rgb().0.into_iter().enumerate() {
-						if (CLOSE_DEPLOYMENT_BUTTON_COLOR[i] - pixel as i16).abs() as u16 > CLOSE_DEPLOYMENT_BUTTON_TOLERANCE {
-							return false;
-						}
-					}
-					true
-				})
-				.count();
-
-			let red_pixels = red_pixels as f32 / (w * h) as f32;
-			if red_pixels < CLOSE_DEPLOYMENT_BUTTON_RED_PIXEL_THRESHOLD {
-				return Ok(None);
-			}
-		}
-
-		let [x, y, w, h] = MAP_BOUNDS.into_absolute([frame.width(), frame.height()]);
+		// The screen state is classified when the frame is loaded, so that the buffers can be sized for it
+		let screen_state = match self.screen_state {
+			Some(screen_state) => screen_state,
+			None => return Ok(None),
+		};
 
-		// Map fills remaining space
-		let w = frame.width() - w;
-		let x = frame.width() - x - w;
+		let [x, y, w, h] = screen_state.map_bounds([frame.width(), frame.height()]);
 
 		// brq = bottom right quadrant
 		let brq_w = w / 2;
@@ -374,16 +361,22 @@ impl Vision for CPUFallback {
 		Ok(())
 	}
 
-	fn find_marker_lines(&self, max_gap: u32) -> Result<SmallVec<Line<f32>, 32>, Self::Error> {
+	fn find_marker_lines(&self, max_gap: u32, circles: Option<&mut Vec<lsd::Circle>>) -> Result<SmallVec<Line<f32>, 32>, Self::Error> {
 		let lsd_image = memory!(&self.lsd_image);
 		lsd::find_lines(
 			&*lsd_image,
 			max_gap,
+			circles,
 			#[inline]
 			|image, pt, max_gap| self.find_longest_line(image, pt, max_gap),
 		)
 	}
 
+	fn find_marker_lines_hough(&self, max_gap: u32, circles: Option<&mut Vec<lsd::Circle>>) -> Result<SmallVec<Line<f32>, 32>, Self::Error> {
+		let lsd_image = memory!(&self.lsd_image);
+		Ok(hough::find_lines(&*lsd_image, max_gap, circles))
+	}
+
 	fn find_longest_line(&self, image: &Self::LSDImage, pt: Point<f32>, max_gap: f32) -> Result<(Line<f32>, f32), Self::Error> {
 		let find_line_in_image = |pt: Point<f32>, max_gap: f32, theta: f32| {
 			let (mut x, mut y) = (pt.x, pt.y);
@@ -458,4 +451,211 @@ impl Vision for CPUFallback {
 			debug::DebugView::CroppedBRQ => self.cropped_brq.borrow().convert(),
 		}))
 	}
-}
\ No newline at end of file
+}
+
+/// Runs a sample screenshot through the backend up to the marker mask, the same way the vision thread does
+#[cfg(test)]
+fn test_mask_sample(sample: &[u8]) -> CPUFallback {
+	let frame = image::load_from_memory(sample).unwrap().into_bgra8();
+	let frame = image::ImageBuffer::from_raw(frame.width(), frame.height(), frame.into_raw().into_boxed_slice()).unwrap();
+
+	let mut cpu = CPUFallback::init().unwrap();
+	cpu.load_frame(frame).unwrap();
+	cpu.crop_to_map(false).unwrap().expect("crop_to_map failed");
+	cpu.isolate_map_markers().unwrap();
+	cpu.mask_marker_lines().unwrap();
+	cpu
+}
+
+#[test]
+fn test_find_marker_lines_rejects_circles() {
+	let (centre, radius) = (Point::new(300.0, 220.0), 120.0);
+	let (arc_centre, arc_radius) = (Point::new(100.0, 440.0), 140.0);
+	let (line_start, line_end) = (Point::new(20.0, 30.0), Point::new(560.0, 70.0));
+
+	let image = image::GrayImage::from_fn(600, 400, |x, y| {
+		let (x, y) = (x as f32, y as f32);
+		let from = |centre: Point<f32>| ((x - centre.x).powi(2) + (y - centre.y).powi(2)).sqrt();
+
+		// A range ring, the arc in front of the player, and a marker line
+		let on_ring = (from(centre) - radius).abs() <= 1.5;
+		let on_arc = (from(arc_centre) - arc_radius).abs() <= 1.5 && y < arc_centre.y - 80.0;
+		let t = (x - line_start.x) / (line_end.x - line_start.x);
+		let on_line = (0.0..=1.0).contains(&t) && (line_start.y + (line_end.y - line_start.y) * t - y).abs() <= 1.5;
+
+		image::Luma([if on_ring || on_arc || on_line { 255 } else { 0 }])
+	});
+
+	let mut cpu = CPUFallback::init().unwrap();
+	cpu.lsd_image = image.into();
+
+	let mut circles = Vec::new();
+	let lines = cpu.find_marker_lines(15, Some(&mut circles)).unwrap();
+
+	assert_eq!(lines.len(), 1, "{lines:?}");
+	let line = lines[0];
+	for end in [line_start, line_end] {
+		assert!(line.p0.distance_sqr(&end).min(line.p1.distance_sqr(&end)) < 25.0, "{line:?}");
+	}
+
+	assert_eq!(circles.len(), 2, "{circles:?}");
+	for (centre, radius) in [(centre, radius), (arc_centre, arc_radius)] {
+		assert!(
+			circles.iter().any(|circle| circle.centre.distance_sqr(&centre) < 4.0 && (circle.radius - radius).abs() < 2.0),
+			"{circles:?}"
+		);
+	}
+}
+
+#[test]
+fn test_find_marker_lines_false_positives() {
+	// (sample, marker lines on it, range rings on it, false positives we still let through)
+	let samples = [
+		("full.jpg", &include_bytes!("../../vision-common/samples/full.jpg")[..], 0, 1, 0),
+		("fullmapsnow.jpg", include_bytes!("../../vision-common/samples/fullmapsnow.jpg"), 0, 1, 0),
+		("quickmap.jpg", include_bytes!("../../vision-common/samples/quickmap.jpg"), 0, 1, 0),
+		// The icon in the middle of the ring isn't a circle
+		("snowpoints.png", include_bytes!("../../vision-common/samples/snowpoints.png"), 1, 1, 1),
+		// At this resolution the ring is only drawn as dots
+		("full_1600x1024.png", include_bytes!("../../vision-common/samples/full_1600x1024.png"), 0, 1, 0),
+		("point.png", include_bytes!("../../vision-common/samples/point.png"), 1, 0, 0),
+		("points.png", include_bytes!("../../vision-common/samples/points.png"), 4, 0, 0),
+		("points_intersect.png", include_bytes!("../../vision-common/samples/points_intersect.png"), 7, 0, 0),
+	];
+
+	for (name, sample, expected_lines, expected_circles, allowed_false_positives) in samples {
+		let mut circles = Vec::new();
+		let lines = test_mask_sample(sample).find_marker_lines(15, Some(&mut circles)).unwrap();
+
+		assert!(lines.len() >= expected_lines, "{name}: lost marker lines {lines:?}");
+		assert!(lines.len() - expected_lines <= allowed_false_positives, "{name}: false positives {lines:?}");
+		assert_eq!(circles.len(), expected_circles, "{name}: {circles:?}");
+	}
+}
+
+/// (sample, marker lines on it as `[x0, y0, x1, y1]` in map pixels, false positives the Hough transform still lets through)
+#[cfg(test)]
+type TestHoughSample = (&'static str, &'static [u8], &'static [[f32; 4]], usize);
+
+/// The marker lines are labelled by hand off of each sample's marker mask
+#[cfg(test)]
+const TEST_HOUGH_SAMPLES: [TestHoughSample; 12] = [
+	("full.jpg", include_bytes!("../../vision-common/samples/full.jpg"), &[], 0),
+	("fullmapsnow.jpg", include_bytes!("../../vision-common/samples/fullmapsnow.jpg"), &[], 0),
+	("quickmap.jpg", include_bytes!("../../vision-common/samples/quickmap.jpg"), &[], 0),
+	("snowpoints.png", include_bytes!("../../vision-common/samples/snowpoints.png"), &[[260.0, 498.0, 1083.0, 1006.0]], 0),
+	// The range ring is only drawn as dots at this resolution, and a few of them line up
+	("full_1600x1024.png", include_bytes!("../../vision-common/samples/full_1600x1024.png"), &[], 1),
+	// The line carries on to its icon at (847, 270), but it's too faint to mask past (723, 427), and it has a gap longer than max_gap in it
+	("fullmap.jpg", include_bytes!("../../vision-common/samples/fullmap.jpg"), &[[258.0, 996.0, 723.0, 427.0]], 0),
+	// The second line is found in two pieces, either side of where the first crosses it
+	(
+		"lol.png",
+		include_bytes!("../../vision-common/samples/lol.png"),
+		&[[720.0, 559.0, 1092.0, 877.0], [718.0, 699.0, 961.0, 622.0]],
+		0,
+	),
+	("point.png", include_bytes!("../../vision-common/samples/point.png"), &[[719.0, 558.0, 1092.0, 877.0]], 0),
+	(
+		"point_far.png",
+		include_bytes!("../../vision-common/samples/point_far.png"),
+		&[[644.0, 124.0, 286.0, 253.0], [719.0, 558.0, 1092.0, 877.0]],
+		0,
+	),
+	(
+		"points.png",
+		include_bytes!("../../vision-common/samples/points.png"),
+		&[
+			[1077.0, 153.0, 304.0, 619.0],
+			[275.0, 211.0, 441.0, 354.0],
+			[943.0, 538.0, 921.0, 747.0],
+			[569.0, 840.0, 836.0, 956.0],
+		],
+		0,
+	),
+	(
+		"points_intersect.png",
+		include_bytes!("../../vision-common/samples/points_intersect.png"),
+		&[
+			[1077.0, 153.0, 304.0, 619.0],
+			[275.0, 211.0, 441.0, 354.0],
+			[943.0, 538.0, 921.0, 747.0],
+			[569.0, 840.0, 836.0, 956.0],
+			[793.0, 171.0, 1061.0, 416.0],
+			[531.0, 388.0, 662.0, 551.0],
+			[688.0, 404.0, 486.0, 525.0],
+		],
+		0,
+	),
+	("in_mortar.png", include_bytes!("../../vision-common/samples/in_mortar.png"), &[[670.0, 537.0, 674.0, 648.0]], 0),
+];
+
+#[test]
+fn test_hough_find_lines_samples() {
+	// Whether a line that was found lies along a labelled one. Lines broken up by long gaps or crossings are found in pieces.
+	let lies_along = |found: &Line<f32>, line: &Line<f32>| {
+		let (dx, dy) = (line.p1.x - line.p0.x, line.p1.y - line.p0.y);
+		let length = (dx * dx + dy * dy).sqrt();
+		[found.p0, found.p1].into_iter().all(|pt| {
+			let (x, y) = (pt.x - line.p0.x, pt.y - line.p0.y);
+			let along = (x * dx + y * dy) / length;
+			let across = (x * dy - y * dx).abs() / length;
+			across <= 5.0 && (-10.0..=length + 10.0).contains(&along)
+		})
+	};
+
+	for (name, sample, labelled, allowed_false_positives) in TEST_HOUGH_SAMPLES {
+		let hough = test_mask_sample(sample).find_marker_lines_hough(15, None).unwrap();
+		let labelled = labelled
+			.iter()
+			.map(|&[x0, y0, x1, y1]| Line::new(Point::new(x0, y0), Point::new(x1, y1)))
+			.collect::<Vec<_>>();
+
+		// Both ends of every labelled line are found, within 10px
+		for line in labelled.iter() {
+			let pieces = hough.iter().filter(|found| lies_along(found, line)).collect::<Vec<_>>();
+			for end in [line.p0, line.p1] {
+				assert!(
+					pieces.iter().any(|found| found.p0.distance_sqr(&end) < 100.0 || found.p1.distance_sqr(&end) < 100.0),
+					"{name}: lost the end of marker line {line:?} at {end:?}\n{hough:?}"
+				);
+			}
+		}
+
+		let false_positives = hough.iter().filter(|found| !labelled.iter().any(|line| lies_along(found, line))).count();
+		assert!(false_positives <= allowed_false_positives, "{name}: false positives {hough:?}");
+	}
+}
+
+/// How long each way of finding marker lines takes on the samples
+///
+/// Only means anything in release mode: `cargo test --release -p smh-vision-cpu -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn bench_hough_find_lines_against_sweep() {
+	const ITERATIONS: u32 = 5;
+
+	let (mut sweep_total, mut hough_total) = (std::time::Duration::ZERO, std::time::Duration::ZERO);
+	for (name, sample, ..) in TEST_HOUGH_SAMPLES {
+		let cpu = test_mask_sample(sample);
+
+		let start = std::time::Instant::now();
+		for _ in 0..ITERATIONS {
+			cpu.find_marker_lines(15, None).unwrap();
+		}
+		let sweep = start.elapsed() / ITERATIONS;
+
+		let start = std::time::Instant::now();
+		for _ in 0..ITERATIONS {
+			cpu.find_marker_lines_hough(15, None).unwrap();
+		}
+		let hough = start.elapsed() / ITERATIONS;
+
+		println!("{name:>20}: sweep {sweep:?}, hough {hough:?}");
+
+		sweep_total += sweep;
+		hough_total += hough;
+	}
+
+	println!("{:>20}: sweep {sweep_total:?}, hough {hough_total:?}", "total");
+}
@@ -12,7 +12,12 @@ smh_vision_common::export_dylib_wrapper!(smh_vision_gpu => cuda::CudaInstance);
 
 #[allow(unused)]
 use smh_vision_common::open_image;
-use smh_vision_common::{consts::*, prelude::*, Vision};
+use smh_vision_common::{
+	consts::*,
+	prelude::*,
+	screen_state::{self, ScreenState},
+	Vision,
+};
 
 use bytemuck::Zeroable;
 use cust::{
@@ -20,7 +25,7 @@ use cust::{
 	error::CudaError,
 	launch,
 	memory::{
-		AsyncCopyDestination, CopyDestination, DeviceBox, DeviceBuffer, DeviceCopy, DevicePointer, DeviceSlice, LockedBuffer, UnifiedBuffer,
+		AsyncCopyDestination, CopyDestination, DeviceBuffer, DeviceCopy, DevicePointer, DeviceSlice, LockedBuffer, UnifiedBuffer,
 		UnifiedPointer
 	},
 	prelude::*,
@@ -33,7 +38,6 @@ use gpuimage::*;
 struct GpuMemory {
 	frame: GpuImage<u8, DeviceBuffer<u8>, image::Bgra<u8>>,
 
-	red_pixels: DeviceBox<u32>,
 	ui_map: GpuImage<u8, DeviceBuffer<u8>, image::Rgba<u8>>,
 	cropped_map: GpuImage<u8, DeviceBuffer<u8>, image::Rgb<u8>>,
 	cropped_brq: GpuImage<u8, DeviceBuffer<u8>, image::Rgb<u8>>,
@@ -57,14 +61,11 @@ struct GpuMemory {
 	scales_stream: Stream,
 }
 impl GpuMemory {
-	fn new(dimensions: (u32, u32)) -> Result<Self, AnyError> {
+	fn new(dimensions: (u32, u32), screen_state: ScreenState) -> Result<Self, AnyError> {
 		unsafe {
 			// let map_icon_size: u32 = 22;
 
-			let [_, _, w, h] = MAP_BOUNDS.into_absolute([dimensions.0, dimensions.1]);
-
-			// Map fills remaining space
-			let w = dimensions.0 - w;
+			let [_, _, w, h] = screen_state.map_bounds([dimensions.0, dimensions.1]);
 
 			// brq = bottom right quadrant
 			let brq_w = w / 2;
@@ -80,7 +81,6 @@ impl GpuMemory {
 
 				frame: GpuImage::uninitialized(dimensions.0, dimensions.1, 4)?,
 
-				red_pixels: DeviceBox::uninitialized()?,
 				ui_map: GpuImage::uninitialized(w, h, 4)?,
 				cropped_map: GpuImage::uninitialized(w, h, 3)?,
 				cropped_brq: GpuImage::uninitialized(brq_w, brq_h, 3)?,
@@ -107,7 +107,11 @@ impl GpuMemory {
 struct GpuVisionState {
 	cpu_frame: Arc<VisionFrame>,
 
+	/// Which map, if any, is open in the current frame
+	screen_state: Option<ScreenState>,
+
 	dimensions: (u32, u32),
+	buffers_for: Option<ScreenState>,
 	memory: Option<GpuMemory>,
 
 	// map_marker_size: u32,
@@ -115,11 +119,17 @@ struct GpuVisionState {
 }
 impl GpuVisionState {
 	#[inline]
-	fn update(&mut self, dimensions: (u32, u32)) -> Result<&mut GpuMemory, AnyError> {
-		if self.memory.is_none() || self.dimensions != dimensions {
+	fn update(&mut self, dimensions: (u32, u32), screen_state: Option<ScreenState>) -> Result<&mut GpuMemory, AnyError> {
+		self.screen_state = screen_state;
+
+		// Keep the buffers we have if no map is open, they'll be resized for whichever map is opened next
+		let buffers_for = screen_state.or(self.buffers_for).unwrap_or(ScreenState::Deployment);
+
+		if self.memory.is_none() || self.dimensions != dimensions || self.buffers_for != Some(buffers_for) {
 			self.memory = None;
-			self.memory = Some(GpuMemory::new(dimensions)?);
+			self.memory = Some(GpuMemory::new(dimensions, buffers_for)?);
 			self.dimensions = dimensions;
+			self.buffers_for = Some(buffers_for);
 		}
 		Ok(unsafe { self.memory.as_mut().unwrap_unchecked() })
 	}
@@ -166,7 +176,7 @@ impl Vision for CudaInstance {
 
 	fn load_frame(&mut self, frame: VisionFrame) -> Result<(), Self::Error> {
 		// preallocate buffers
-		let memory = self.state.update(frame.dimensions())?;
+		let memory = self.state.update(frame.dimensions(), screen_state::classify(&frame))?;
 
 		unsafe {
 			let stream = &memory.crop_to_map_streams.0;
@@ -179,8 +189,6 @@ impl Vision for CudaInstance {
 			}
 
 			// reset state
-			memory.red_pixels.async_copy_from(&0, stream)?;
-
 			// memory.marked_map_marker_pixels_count.async_copy_from(&0, stream)?;
 			// memory.map_marker_template_matches_sad.borrow_mut().fill(GPUTemplateMatch::zeroed());
 
@@ -215,39 +223,13 @@ impl Vision for CudaInstance {
 		let cropped_map = memory!(&self.cropped_map);
 		let cropped_brq = memory!(&self.cropped_brq);
 
-		let device_red_pixels = memory!(&self.red_pixels);
-		let mut red_pixels = 0;
-
-		unsafe {
-			// Find the red "Close Deployment" button
-			// TODO: support the quick map as well
-			let [x, y, w, h] = CLOSE_DEPLOYMENT_BUTTON_BOUNDS.into_absolute([frame.width, frame.height]);
-
-			let (grid, block) = gpu_2d_kernel![<<<[w, h], (16, 16)>>>];
-			launch!(
-				self.count_close_deployment_button_red_pixels<<<grid, block, 4, stream_a>>>(
-					frame.as_device_ptr(),
-					frame.width,
-					x, y, w, h,
-					device_red_pixels.as_device_ptr()
-				)
-			)?;
-
-			device_red_pixels.async_copy_to(&mut red_pixels, stream_a)?;
-
-			stream_a.synchronize()?;
-
-			let red_pixels = red_pixels as f32 / (w * h) as f32;
-			if red_pixels < CLOSE_DEPLOYMENT_BUTTON_RED_PIXEL_THRESHOLD {
-				return Ok(None);
-			}
-		}
-
-		let [x, y, w, h] = MAP_BOUNDS.into_absolute([frame.width, frame.height]);
+		// The screen state is classified when the frame is loaded, so that the buffers can be sized for it
+		let screen_state = match self.state.screen_state {
+			Some(screen_state) => screen_state,
+			None => return Ok(None),
+		};
 
-		// Map fills remaining space
-		let w = frame.width - w;
-		let x = frame.width - x - w;
+		let [x, y, w, h] = screen_state.map_bounds([frame.width, frame.height]);
 
 		// brq = bottom right quadrant
 		let brq_w = w / 2;
@@ -474,18 +456,24 @@ impl Vision for CudaInstance {
 		Ok(())
 	}
 
-	fn find_marker_lines(&self, max_gap: u32) -> Result<SmallVec<Line<f32>, 32>, Self::Error> {
+	fn find_marker_lines(&self, max_gap: u32, circles: Option<&mut Vec<lsd::Circle>>) -> Result<SmallVec<Line<f32>, 32>, Self::Error> {
 		let lsd_image = memory!(&self.lsd_image).borrow();
 		let lsd_image_host = lsd_image.as_host_ref();
 
 		lsd::find_lines(
 			&lsd_image_host,
 			max_gap,
+			circles,
 			#[inline]
 			|_, pt, max_gap| self.find_longest_line(&*lsd_image, pt, max_gap),
 		)
 	}
 
+	fn find_marker_lines_hough(&self, max_gap: u32, circles: Option<&mut Vec<lsd::Circle>>) -> Result<SmallVec<Line<f32>, 32>, Self::Error> {
+		let lsd_image = memory!(&self.lsd_image).borrow();
+		Ok(hough::find_lines(&lsd_image.as_host_ref(), max_gap, circles))
+	}
+
 	fn find_longest_line(&self, image: &Self::LSDImage, pt: Point<f32>, max_gap: f32) -> Result<(Line<f32>, f32), Self::Error> {
 		let stream = memory!(&self.markers_stream);
 
@@ -592,7 +580,7 @@ fn test_gpu_computer_vision() {
 					cuda.isolate_map_markers().expect("isolate_map_markers failed");
 					// cuda.filter_map_marker_icons().expect("filter_map_marker_icons failed");
 					cuda.mask_marker_lines().expect("mask_marker_lines failed");
-					cuda.find_marker_lines(22).expect("find_marker_lines failed")
+					cuda.find_marker_lines(22, None).expect("find_marker_lines failed")
 				},
 			);
 
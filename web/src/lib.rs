@@ -1,7 +1,7 @@
 use futures_util::{SinkExt, StreamExt};
 use image::EncodableLayout;
-use smh_heightmap_ripper::Heightmap;
-use smh_util::{anyhow, async_channel, image, log, FromBytesSlice, Rect};
+use smh_heightmap_ripper::{contours::ContourLine, registration::HeightmapTransform, Heightmap};
+use smh_util::{anyhow, async_channel, image, log, FromBytesSlice};
 use std::{
 	net::{Ipv4Addr, SocketAddr, SocketAddrV4},
 	sync::Arc,
@@ -33,6 +33,9 @@ mod ws;
 pub enum Interaction {
 	AddCustomMarker([[f32; 2]; 2]),
 	DeleteCustomMarker(u32),
+
+	/// Grid references of the gun and target, as typed by the user
+	AddGridMarker { gun: Box<str>, target: Box<str> },
 }
 impl Interaction {
 	pub fn deserialize(data: &[u8]) -> Option<Self> {
@@ -63,6 +66,24 @@ impl Interaction {
 
 				Some(Interaction::DeleteCustomMarker(u32::from_le_bytes_slice(&data[0..4])))
 			}
+			3 => {
+				// Two length prefixed UTF-8 strings
+				fn read_str(data: &mut &[u8]) -> Option<Box<str>> {
+					let len = u32::from_le_bytes_slice(data.get(0..4)?) as usize;
+					let str = data.get(4..4 + len)?;
+					*data = &data[4 + len..];
+					core::str::from_utf8(str).ok().map(Box::from)
+				}
+
+				let mut data = data;
+				match (read_str(&mut data), read_str(&mut data)) {
+					(Some(gun), Some(target)) if data.is_empty() => Some(Interaction::AddGridMarker { gun, target }),
+					_ => {
+						log::warn!("Invalid grid marker data");
+						None
+					}
+				}
+			}
 			_ => {
 				log::warn!("Unknown interaction type: {interaction}");
 				None
@@ -139,23 +160,38 @@ events! {
 		}
 	},
 
-	Markers { markers: Box<[[[f32; 2]; 2]]>, custom: bool } => {
+	Markers { markers: Box<[[[f32; 2]; 2]]>, fireteams: Box<[u8]>, commands: Box<[u8]>, ids: Box<[u32]>, custom: bool } => {
 		size => {
-			((core::mem::size_of::<[f32; 2]>() * 2) * markers.len()) + core::mem::size_of::<u32>() + 1
+			((core::mem::size_of::<[f32; 2]>() * 2) * markers.len()) + core::mem::size_of::<u32>() + 1 +
+			core::mem::size_of::<u32>() + fireteams.len() +
+			core::mem::size_of::<u32>() + commands.len() +
+			core::mem::size_of::<u32>() + (core::mem::size_of::<u32>() * ids.len())
 		},
 
 		serialize => {
 			buf.write_all(&[*custom as u8])?;
 			buf.write_all(&u32::to_le_bytes(markers.len() as u32))?;
 			markers.iter().flat_map(|[p0, p1]| p0.iter().chain(p1).copied()).try_for_each(|xy| buf.write_all(&f32::to_le_bytes(xy)))?;
+
+			// Either empty or one per marker
+			buf.write_all(&u32::to_le_bytes(fireteams.len() as u32))?;
+			buf.write_all(fireteams)?;
+
+			// Either empty or one per marker, `0` if there's no command marker
+			buf.write_all(&u32::to_le_bytes(commands.len() as u32))?;
+			buf.write_all(commands)?;
+
+			// Either empty or one per marker, and the same for as long as the marker is on the map
+			buf.write_all(&u32::to_le_bytes(ids.len() as u32))?;
+			ids.iter().try_for_each(|id| buf.write_all(&u32::to_le_bytes(*id)))?;
 		}
 	},
 
-	UpdateState { meters_to_px_ratio: Option<f64>, minimap_bounds: Option<Rect<u32>> } => {
+	UpdateState { meters_to_px_ratio: Option<f64>, minimap_bounds: Option<[[f64; 2]; 2]> } => {
 		size => {
 			core::mem::size_of::<f64>() +
 			if minimap_bounds.is_some() {
-				1 + (core::mem::size_of::<u32>() * 4)
+				1 + (core::mem::size_of::<f64>() * 4)
 			} else {
 				1
 			}
@@ -163,12 +199,13 @@ events! {
 		serialize => {
 			buf.write_all(&f64::to_le_bytes(meters_to_px_ratio.unwrap_or(0.0)))?;
 
-			if let Some(minimap_bounds) = minimap_bounds {
+			// Zooming the in-game map in puts the minimap's bounds outside of the map image, so they're not necessarily whole or positive
+			if let Some([[left, top], [right, bottom]]) = minimap_bounds {
 				buf.write_all(&[1])?;
-				buf.write_all(&u32::to_le_bytes(minimap_bounds.left))?;
-				buf.write_all(&u32::to_le_bytes(minimap_bounds.right))?;
-				buf.write_all(&u32::to_le_bytes(minimap_bounds.top))?;
-				buf.write_all(&u32::to_le_bytes(minimap_bounds.bottom))?;
+				buf.write_all(&f64::to_le_bytes(*left))?;
+				buf.write_all(&f64::to_le_bytes(*right))?;
+				buf.write_all(&f64::to_le_bytes(*top))?;
+				buf.write_all(&f64::to_le_bytes(*bottom))?;
 			} else {
 				buf.write_all(&[0])?;
 			}
@@ -205,10 +242,82 @@ events! {
 		}
 	},
 
-	HeightmapFitToMinimap { fit_to_minimap: bool } => {
-		size => 1,
+	Contours { contours: Option<Arc<[ContourLine]>> } => {
+		size => {
+			if let Some(contours) = contours {
+				1 + core::mem::size_of::<u32>() + contours.iter().map(|contour| {
+					1 + core::mem::size_of::<f32>() + core::mem::size_of::<u32>() + (contour.points.len() * core::mem::size_of::<[f32; 2]>())
+				}).sum::<usize>()
+			} else {
+				1
+			}
+		},
+		serialize => {
+			if let Some(contours) = contours {
+				buf.write_all(&[1])?;
+				buf.write_all(&u32::to_le_bytes(contours.len() as u32))?;
+
+				for contour in contours.iter() {
+					buf.write_all(&[contour.index as u8])?;
+					buf.write_all(&f32::to_le_bytes(contour.elevation as f32))?;
+					buf.write_all(&u32::to_le_bytes(contour.points.len() as u32))?;
+					contour.points.iter().flatten().copied().try_for_each(|xy| buf.write_all(&f32::to_le_bytes(xy)))?;
+				}
+			} else {
+				buf.write_all(&[0])?;
+			}
+		}
+	},
+
+	HeightmapTransform { transform: Option<HeightmapTransform> } => {
+		size => {
+			if transform.is_some() {
+				1 + (core::mem::size_of::<f64>() * 4)
+			} else {
+				1
+			}
+		},
+		serialize => {
+			if let Some(transform) = transform {
+				buf.write_all(&[1])?;
+				transform.offset.iter().chain(transform.scale.iter()).try_for_each(|v| buf.write_all(&f64::to_le_bytes(*v)))?;
+			} else {
+				buf.write_all(&[0])?;
+			}
+		}
+	},
+
+	// The map pixel at the top left of the grid, and meters per map pixel along each axis
+	Grid { grid: Option<[[f64; 2]; 2]> } => {
+		size => {
+			if grid.is_some() {
+				1 + (core::mem::size_of::<f64>() * 4)
+			} else {
+				1
+			}
+		},
 		serialize => {
-			buf.write_all(&[*fit_to_minimap as u8])?;
+			if let Some(grid) = grid {
+				buf.write_all(&[1])?;
+				grid.iter().flatten().try_for_each(|v| buf.write_all(&f64::to_le_bytes(*v)))?;
+			} else {
+				buf.write_all(&[0])?;
+			}
+		}
+	},
+
+	// Why a grid marker sent by a web client couldn't be placed, if it couldn't
+	GridMarkerPlaced { error: Option<Box<str>> } => {
+		size => {
+			1 + error.as_ref().map(|error| error.len()).unwrap_or(0)
+		},
+		serialize => {
+			if let Some(error) = error {
+				buf.write_all(&[0])?;
+				buf.write_all(error.as_bytes())?;
+			} else {
+				buf.write_all(&[1])?;
+			}
 		}
 	}
 }
@@ -217,11 +326,23 @@ events! {
 pub struct EventData {
 	pub map: Arc<image::RgbaImage>,
 	pub computer_vision_markers: Box<[[[f32; 2]; 2]]>,
+
+	/// Which fireteam each computer vision marker belongs to, `0` if we couldn't tell
+	pub computer_vision_fireteams: Box<[u8]>,
+
+	/// The squad leader's command marker at the end of each computer vision marker, `0` if there isn't one
+	pub computer_vision_commands: Box<[u8]>,
+
+	/// Stable IDs of the computer vision markers
+	pub computer_vision_ids: Box<[u32]>,
+
 	pub custom_markers: Box<[[[f32; 2]; 2]]>,
 	pub meters_to_px_ratio: Option<f64>,
-	pub minimap_bounds: Option<Rect<u32>>,
+	pub minimap_bounds: Option<[[f64; 2]; 2]>,
 	pub heightmap: Option<smh_heightmap_ripper::Heightmap>,
-	pub heightmap_fit_to_minimap: bool,
+	pub contours: Option<Arc<[ContourLine]>>,
+	pub heightmap_transform: Option<HeightmapTransform>,
+	pub grid: Option<[[f64; 2]; 2]>,
 }
 
 pub struct WebServer {
@@ -368,11 +489,14 @@ async fn server(
 			event = event_rx.recv() => match event {
 				Some(event) => {
 					match &*event {
-						Event::Markers { custom, markers } => {
+						Event::Markers { custom, markers, fireteams, commands, ids } => {
 							if *custom {
 								event_data.custom_markers = markers.clone();
 							} else {
 								event_data.computer_vision_markers = markers.clone();
+								event_data.computer_vision_fireteams = fireteams.clone();
+								event_data.computer_vision_commands = commands.clone();
+								event_data.computer_vision_ids = ids.clone();
 							}
 						},
 						Event::Map { map } => event_data.map = map.clone(),
@@ -383,8 +507,14 @@ async fn server(
 						Event::Heightmap { heightmap } => {
 							event_data.heightmap = heightmap.clone();
 						},
-						Event::HeightmapFitToMinimap { fit_to_minimap } => {
-							event_data.heightmap_fit_to_minimap = *fit_to_minimap;
+						Event::Contours { contours } => {
+							event_data.contours = contours.clone();
+						},
+						Event::HeightmapTransform { transform } => {
+							event_data.heightmap_transform = *transform;
+						},
+						Event::Grid { grid } => {
+							event_data.grid = *grid;
 						},
 
 						_ => {}
@@ -41,18 +41,28 @@ pub(super) async fn accept_ws_connection(
 		}
 
 		if !event_data.computer_vision_markers.is_empty() {
-			w.send(Binary(Event::Markers { custom: false, markers: event_data.computer_vision_markers }.serialize())).await?;
+			w.send(Binary(Event::Markers { custom: false, markers: event_data.computer_vision_markers, fireteams: event_data.computer_vision_fireteams, commands: event_data.computer_vision_commands, ids: event_data.computer_vision_ids }.serialize())).await?;
 		}
 
 		if !event_data.custom_markers.is_empty() {
-			w.send(Binary(Event::Markers { custom: true, markers: event_data.custom_markers }.serialize())).await?;
+			w.send(Binary(Event::Markers { custom: true, markers: event_data.custom_markers, fireteams: Box::default(), commands: Box::default(), ids: Box::default() }.serialize())).await?;
 		}
 
 		if let Some(ref heightmap) = event_data.heightmap {
 			w.send(Binary(Event::Heightmap { heightmap: Some(heightmap.clone()) }.serialize())).await?;
 		}
 
-		w.send(Binary(Event::HeightmapFitToMinimap { fit_to_minimap: event_data.heightmap_fit_to_minimap }.serialize())).await?;
+		if let Some(contours) = event_data.contours {
+			w.send(Binary(Event::Contours { contours: Some(contours) }.serialize())).await?;
+		}
+
+		if let Some(transform) = event_data.heightmap_transform {
+			w.send(Binary(Event::HeightmapTransform { transform: Some(transform) }.serialize())).await?;
+		}
+
+		if let Some(grid) = event_data.grid {
+			w.send(Binary(Event::Grid { grid: Some(grid) }.serialize())).await?;
+		}
 	}
 
 	loop {
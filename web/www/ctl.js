@@ -267,4 +267,55 @@ document.addEventListener('mouseout', function(e) {
 		drag_debounce = false;
 		draw_ctl_markers(e);
 	}
-});
\ No newline at end of file
+});
+// Places a marker from typed grid references, e.g. when the SL calls out a grid on comms without marking it
+// The app parses them and tells us whether it could place the marker
+var grid_marker_placed;
+{
+	var grid_form = document.getElementById('grid');
+	var grid_gun = document.getElementById('grid-gun');
+	var grid_target = document.getElementById('grid-target');
+	var grid_error = document.getElementById('grid-error');
+
+	grid_marker_placed = function(error) {
+		if (error === null) {
+			grid_error.textContent = '';
+			grid_target.value = '';
+		} else {
+			grid_error.textContent = error;
+		}
+	};
+
+	grid_form.addEventListener('submit', function(e) {
+		e.preventDefault();
+
+		var encoder = new TextEncoder();
+		var gun = encoder.encode(grid_gun.value);
+		var target = encoder.encode(grid_target.value);
+
+		ws_interaction(
+			WS_INTERACTION_ADD_GRID_MARKER,
+			function() {
+				return 4 + gun.length + 4 + target.length;
+			},
+			function(offset, data) {
+				var bytes = new Uint8Array(data.buffer);
+
+				data.setUint32(offset, gun.length, true);
+				bytes.set(gun, offset += 4);
+
+				data.setUint32(offset += gun.length, target.length, true);
+				bytes.set(target, offset += 4);
+			}
+		);
+	});
+}
+
+// Only shows one fireteam's markers, for when each fireteam has its own mortar
+{
+	var fireteam_filter_select = document.getElementById('fireteam-filter');
+	fireteam_filter_select.addEventListener('change', function() {
+		fireteam_filter = Number(fireteam_filter_select.value);
+		draw_markers();
+	});
+}
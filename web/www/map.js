@@ -12,11 +12,26 @@ var overlay = overlay_canvas.getContext('2d');
 var meters_to_px_ratio = null;
 var minimap_viewport = null;
 var heightmap = null;
-var heightmap_fit_to_minimap = false;
+var heightmap_transform = null;
+var grid = null;
+var contours = null;
+
+var CONTOUR_COLOR = 'rgba(217, 140, 51, 0.55)';
+var CONTOUR_INDEX_COLOR = 'rgba(217, 140, 51, 0.85)';
 
 var CUSTOM_MARKER_COLOR = [255, 0.0, 255];
 var MEASURE_MARKER_COLOR = [255, 0.0, 0.0];
 
+// The in-game marker colours from consts.toml, indexed by the fireteam ids the server sends (0 = unknown)
+var FIRETEAM_COLORS = [null, [64, 255, 0], [192, 117, 217], [93, 232, 181]];
+
+// The squad leader's command markers, indexed by the ids the server sends (0 = none)
+var COMMAND_NAMES = [null, 'Attack', 'Build', 'Defend', 'Move', 'Observe'];
+var COMMAND_ATTACK = 1;
+
+// Only draw computer vision markers of this fireteam, or all of them if 0
+var fireteam_filter = 0;
+
 var set_status;
 {
 	var status_node = document.getElementById('status');
@@ -106,42 +121,75 @@ function inverse_map_xy(xy) {
 	];
 }
 
-function calc_alt_delta(p0, p1) {
-	if (!heightmap || !minimap_viewport) {
-		return null;
+// The app sends how heightmap texels map onto the minimap, where minimap coordinates are fractions of the minimap's size
+function map_to_heightmap_xy(xy) {
+	var x = (xy[0] - minimap_viewport.left) / (minimap_viewport.right - minimap_viewport.left);
+	var y = (xy[1] - minimap_viewport.top) / (minimap_viewport.bottom - minimap_viewport.top);
+	return [(x - heightmap_transform.offset[0]) / heightmap_transform.scale[0], (y - heightmap_transform.offset[1]) / heightmap_transform.scale[1]];
+}
+
+function heightmap_to_map_xy(xy) {
+	return [
+		minimap_viewport.left + (heightmap_transform.offset[0] + xy[0] * heightmap_transform.scale[0]) * (minimap_viewport.right - minimap_viewport.left),
+		minimap_viewport.top + (heightmap_transform.offset[1] + xy[1] * heightmap_transform.scale[1]) * (minimap_viewport.bottom - minimap_viewport.top)
+	];
+}
+
+// The app sends the grid it's using, so the grid references here match its own
+function map_to_grid_ref(xy, depth) {
+	return grid_ref_from_meters([(xy[0] - grid.origin[0]) * grid.meters_per_px[0], (xy[1] - grid.origin[1]) * grid.meters_per_px[1]], depth);
+}
+
+// Labels each end of the marker with its grid reference, just past the end of the line
+function draw_grid_refs(ctx, marker) {
+	if (!grid) {
+		return;
 	}
 
-	var map_viewport = get_map_viewport();
+	var ends = [
+		[[marker.p0x, marker.p0y], [marker.p1x, marker.p1y]],
+		[[marker.p1x, marker.p1y], [marker.p0x, marker.p0y]]
+	];
 
-	var p0 = translate_map_xy(p0);
-	var p1 = translate_map_xy(p1);
+	ctx.save();
+	ctx.textAlign = 'center';
+	ctx.textBaseline = 'middle';
 
-	var offset;
-	if (heightmap_fit_to_minimap) {
-		offset = [0, 0];
-	} else {
-		var hm_scale_factor_w = (minimap_viewport.right - minimap_viewport.left) / (heightmap.width + heightmap.offset[0]);
-		var hm_scale_factor_h = (minimap_viewport.bottom - minimap_viewport.top) / (heightmap.height + heightmap.offset[1]);
-		offset = [heightmap.offset[0] * hm_scale_factor_w * map_viewport.scale_factor_w, heightmap.offset[1] * hm_scale_factor_h * map_viewport.scale_factor_h];
+	for (var i = 0; i < ends.length; i++) {
+		var point = ends[i][0];
+		var other = ends[i][1];
+
+		var grid_ref = map_to_grid_ref(point, DEFAULT_KEYPAD_DEPTH);
+		if (!grid_ref) {
+			continue;
+		}
+
+		var text = format_grid_ref(grid_ref);
+		var text_width = ctx.measureText(text).width;
+
+		var dx = point[0] - other[0];
+		var dy = point[1] - other[1];
+		var length = Math.max(Math.sqrt(dx ** 2 + dy ** 2), 1);
+		var distance = 6 + text_width / 2;
+
+		ctx.fillText(text, point[0] + dx / length * distance, point[1] + dy / length * distance);
 	}
 
-	var fitted_minimap_viewport = {
-		left: translate_map_x(minimap_viewport.left) + offset[0],
-		top: translate_map_y(minimap_viewport.top) + offset[1],
-		right: translate_map_x(minimap_viewport.right),
-		bottom: translate_map_y(minimap_viewport.bottom),
-	};
+	ctx.restore();
+}
 
-	var p0_xf = (p0[0] - fitted_minimap_viewport.left) / (fitted_minimap_viewport.right - fitted_minimap_viewport.left);
-	var p0_yf = (p0[1] - fitted_minimap_viewport.top) / (fitted_minimap_viewport.bottom - fitted_minimap_viewport.top);
+function calc_alt_delta(p0, p1) {
+	if (!heightmap || !heightmap_transform || !minimap_viewport) {
+		return null;
+	}
 
-	var p1_xf = (p1[0] - fitted_minimap_viewport.left) / (fitted_minimap_viewport.right - fitted_minimap_viewport.left);
-	var p1_yf = (p1[1] - fitted_minimap_viewport.top) / (fitted_minimap_viewport.bottom - fitted_minimap_viewport.top);
+	var p0 = map_to_heightmap_xy(p0);
+	var p1 = map_to_heightmap_xy(p1);
 
-	var p0_x = p0_xf * heightmap.width;
-	var p0_y = p0_yf * heightmap.height;
-	var p1_x = p1_xf * heightmap.width;
-	var p1_y = p1_yf * heightmap.height;
+	var p0_x = p0[0];
+	var p0_y = p0[1];
+	var p1_x = p1[0];
+	var p1_y = p1[1];
 
 	// The heightmap can be used to calculate a more accurate length than eyeballing the map scales
 	var meters = Math.sqrt(((p0_x - p1_x) ** 2) + ((p0_y - p1_y) ** 2));
@@ -164,9 +212,57 @@ function calc_alt_delta(p0, p1) {
 	}
 }
 
+function draw_contours(ctx) {
+	if (!contours || !heightmap || !heightmap_transform || !minimap_viewport) {
+		return;
+	}
+
+	var top_left = heightmap_to_map_xy([0, 0]);
+	var bottom_right = heightmap_to_map_xy([heightmap.width, heightmap.height]);
+
+	var left = top_left[0];
+	var top = top_left[1];
+	var scale_x = (bottom_right[0] - left) / heightmap.width;
+	var scale_y = (bottom_right[1] - top) / heightmap.height;
+
+	for (var i = 0; i < contours.length; i++) {
+		var contour = contours[i];
+		var points = contour.points;
+
+		ctx.lineWidth = contour.index ? 2 : 1;
+		ctx.strokeStyle = contour.index ? CONTOUR_INDEX_COLOR : CONTOUR_COLOR;
+		ctx.beginPath();
+		ctx.moveTo(left + points[0] * scale_x, top + points[1] * scale_y);
+		for (var j = 2; j < points.length; j += 2) {
+			ctx.lineTo(left + points[j] * scale_x, top + points[j + 1] * scale_y);
+		}
+		ctx.stroke();
+	}
+}
+
 var computer_vision_markers = [];
 var custom_markers = [];
 function draw_marker(ctx, marker, color) {
+	// Move, build etc. markers aren't something to fire on, so they're just labelled
+	if (marker.command > COMMAND_ATTACK) {
+		ctx.save();
+		ctx.lineWidth = 1;
+		ctx.setLineDash([6, 4]);
+		ctx.strokeStyle = 'rgb(' + color[0] + ',' + color[1] + ',' + color[2] + ')';
+		ctx.beginPath();
+		ctx.moveTo(marker.p0x, marker.p0y);
+		ctx.lineTo(marker.p1x, marker.p1y);
+		ctx.stroke();
+
+		ctx.font = '600 0.75em \'Inter\', sans-serif';
+		ctx.textAlign = 'center';
+		ctx.textBaseline = 'top';
+		ctx.fillStyle = ctx.strokeStyle;
+		ctx.fillText(COMMAND_NAMES[marker.command], marker.p1x, marker.p1y + 8);
+		ctx.restore();
+		return;
+	}
+
 	ctx.lineWidth = 2;
 	ctx.strokeStyle = 'rgb(' + color[0] + ',' + color[1] + ',' + color[2] + ')';
 	ctx.beginPath();
@@ -197,6 +293,8 @@ function draw_marker(ctx, marker, color) {
 		return;
 	}
 
+	draw_grid_refs(ctx, marker);
+
 	var angle = Math.atan2(marker.p0y - marker.p1y, marker.p0x - marker.p1x);
 
 	var bearing_fwd = angle * 180 / Math.PI;
@@ -343,10 +441,18 @@ function draw_marker(ctx, marker, color) {
 function draw_markers() {
 	overlay.clearRect(0, 0, overlay_canvas.width, overlay_canvas.height);
 
+	draw_contours(overlay);
+
 	for (var i = 0; i < computer_vision_markers.length; i++) {
-		var f = (i + 1) / computer_vision_markers.length;
-		var color = [(1 - f) * 255, f * 255, 0];
-		draw_marker(overlay, computer_vision_markers[i], color);
+		var marker = computer_vision_markers[i];
+		if (fireteam_filter !== 0 && marker.fireteam !== fireteam_filter) {
+			continue;
+		}
+
+		// Tracked markers keep their colour between updates
+		var f = marker.id !== null ? (marker.id * 0.618034) % 1 : (i + 1) / computer_vision_markers.length;
+		var color = FIRETEAM_COLORS[marker.fireteam] || [(1 - f) * 255, f * 255, 0];
+		draw_marker(overlay, marker, color);
 	}
 	for (var i = 0; i < custom_markers.length; i++) {
 		draw_marker(overlay, custom_markers[i], CUSTOM_MARKER_COLOR);
@@ -440,9 +546,33 @@ function map_event(event, data) {
 					p0y: markers.getFloat32(offset += 4, true),
 					p1x: markers.getFloat32(offset += 4, true),
 					p1y: markers.getFloat32(offset += 4, true),
+					fireteam: 0,
+					command: 0,
+					id: null
 				});
 			}
 
+			var fireteams_len = markers.getUint32(offset += 4, true);
+			offset += 4;
+			for (var i = 0; i < fireteams_len && i < out.length; i++) {
+				out[i].fireteam = markers.getUint8(offset + i);
+			}
+			offset += fireteams_len;
+
+			var commands_len = markers.getUint32(offset, true);
+			offset += 4;
+			for (var i = 0; i < commands_len && i < out.length; i++) {
+				out[i].command = markers.getUint8(offset + i);
+			}
+			offset += commands_len;
+
+			// Stable across updates for as long as the marker is on the map
+			var ids_len = markers.getUint32(offset, true);
+			offset += 4;
+			for (var i = 0; i < ids_len && i < out.length; i++) {
+				out[i].id = markers.getUint32(offset + (i * 4), true);
+			}
+
 			draw_markers();
 			break;
 
@@ -456,10 +586,10 @@ function map_event(event, data) {
 
 			if (data.getUint8(8) === 1) {
 				minimap_viewport = {
-					left: data.getUint32(9, true),
-					right: data.getUint32(13, true),
-					top: data.getUint32(17, true),
-					bottom: data.getUint32(21, true)
+					left: data.getFloat64(9, true),
+					right: data.getFloat64(17, true),
+					top: data.getFloat64(25, true),
+					bottom: data.getFloat64(33, true)
 				};
 			} else {
 				minimap_viewport = null;
@@ -490,8 +620,69 @@ function map_event(event, data) {
 			draw_markers();
 			break;
 
-		case WS_EVENT_FIT_HEIGHTMAP_TO_MINIMAP: // FitHeightmapToMinimap
-			heightmap_fit_to_minimap = new DataView(data).getUint8(0) === 1;
+		case WS_EVENT_HEIGHTMAP_TRANSFORM: // HeightmapTransform
+			var data = new DataView(data);
+			if (data.getUint8(0) === 1) {
+				heightmap_transform = {
+					offset: [data.getFloat64(1, true), data.getFloat64(9, true)],
+					scale: [data.getFloat64(17, true), data.getFloat64(25, true)]
+				};
+			} else {
+				heightmap_transform = null;
+			}
+
+			draw_markers();
+			break;
+
+		case WS_EVENT_GRID: // Grid
+			var data = new DataView(data);
+			if (data.getUint8(0) === 1) {
+				grid = {
+					origin: [data.getFloat64(1, true), data.getFloat64(9, true)],
+					meters_per_px: [data.getFloat64(17, true), data.getFloat64(25, true)]
+				};
+			} else {
+				grid = null;
+			}
+
+			draw_markers();
+			break;
+
+		case WS_EVENT_GRID_MARKER_PLACED: // GridMarkerPlaced
+			if (new DataView(data).getUint8(0) === 1) {
+				grid_marker_placed(null);
+			} else {
+				grid_marker_placed(new TextDecoder().decode(data.slice(1)));
+			}
+			break;
+
+		case WS_EVENT_CONTOURS: // Contours
+			var data = new DataView(data);
+			var offset = 0;
+			if (data.getUint8(offset) === 1) {
+				contours = [];
+
+				var len = data.getUint32(offset += 1, true);
+				offset += 4;
+
+				for (var i = 0; i < len; i++) {
+					var index = data.getUint8(offset) === 1;
+					var elevation = data.getFloat32(offset += 1, true);
+					var points_len = data.getUint32(offset += 4, true);
+					offset += 4;
+
+					var points = new Array(points_len * 2);
+					for (var j = 0; j < points.length; j++) {
+						points[j] = data.getFloat32(offset, true);
+						offset += 4;
+					}
+
+					contours.push({ index: index, elevation: elevation, points: points });
+				}
+			} else {
+				contours = null;
+			}
+
 			draw_markers();
 			break;
 
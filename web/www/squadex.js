@@ -12,4 +12,63 @@ function milliradians_from_meters(meters, alt_delta) {
 	var p1 = Math.sqrt(VELOCITY ** 4 - GRAVITY * (GRAVITY * meters ** 2 + 2 * alt_delta * VELOCITY ** 2));
 	var a1 = Math.atan((VELOCITY ** 2 + p1) / (GRAVITY * meters));
 	return rad_to_deg(a1) / (360.0 / 6400.0);
-}
\ No newline at end of file
+}
+var GRID_SIZE = 300.0;
+var MAX_KEYPAD_DEPTH = 4;
+var DEFAULT_KEYPAD_DEPTH = 2;
+
+// Size of a grid square (depth 0) or keypad at the given depth, in meters
+function grid_cell_size(depth) {
+	return GRID_SIZE / (3 ** depth);
+}
+
+function keypad_from_cell(x, y) {
+	return (2 - y) * 3 + x + 1;
+}
+
+function grid_column_letters(column) {
+	var letters = '';
+	column += 1;
+	while (column > 0) {
+		column -= 1;
+		letters = String.fromCharCode(65 + (column % 26)) + letters;
+		column = Math.floor(column / 26);
+	}
+	return letters;
+}
+
+// Meters are offsets from the top left of the map
+function grid_ref_from_meters(meters, depth) {
+	var x = meters[0];
+	var y = meters[1];
+	if (!(x >= 0 && y >= 0 && isFinite(x) && isFinite(y))) {
+		return null;
+	}
+
+	var grid_ref = {
+		column: Math.floor(x / GRID_SIZE),
+		row: Math.floor(y / GRID_SIZE),
+		keypads: []
+	};
+
+	x %= GRID_SIZE;
+	y %= GRID_SIZE;
+	for (var level = 1; level <= Math.min(depth, MAX_KEYPAD_DEPTH); level++) {
+		var size = grid_cell_size(level);
+		var cx = Math.min(Math.floor(x / size), 2);
+		var cy = Math.min(Math.floor(y / size), 2);
+		grid_ref.keypads.push(keypad_from_cell(cx, cy));
+		x -= cx * size;
+		y -= cy * size;
+	}
+
+	return grid_ref;
+}
+
+function format_grid_ref(grid_ref) {
+	var text = grid_column_letters(grid_ref.column) + (grid_ref.row + 1);
+	for (var i = 0; i < grid_ref.keypads.length; i++) {
+		text += '-' + grid_ref.keypads[i];
+	}
+	return text;
+}
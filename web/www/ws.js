@@ -2,10 +2,14 @@ var WS_EVENT_MAP_FRAME = 1;
 var WS_EVENT_MARKERS = 2;
 var WS_EVENT_UPDATE_STATE = 3;
 var WS_EVENT_HEIGHTMAP = 4;
-var WS_EVENT_FIT_HEIGHTMAP_TO_MINIMAP = 5;
+var WS_EVENT_CONTOURS = 5;
+var WS_EVENT_HEIGHTMAP_TRANSFORM = 6;
+var WS_EVENT_GRID = 7;
+var WS_EVENT_GRID_MARKER_PLACED = 8;
 
 var WS_INTERACTION_ADD_CUSTOM_MARKER = 1;
 var WS_INTERACTION_DELETE_CUSTOM_MARKER = 2;
+var WS_INTERACTION_ADD_GRID_MARKER = 3;
 
 var ws;
 